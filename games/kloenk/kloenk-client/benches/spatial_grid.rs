@@ -0,0 +1,61 @@
+use cgmath::{InnerSpace, Point2, Vector2};
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use kloenk::state::entity::EntityAllocator;
+use kloenk::state::spatial_grid::SpatialGrid;
+use std::hint::black_box;
+
+const CELL_SIZE: f32 = 2.0; // Same as the game state
+const ENTITY_COUNTS: [usize; 3] = [10_000, 50_000, 100_000];
+const ENTITY_SIZE: f32 = 1.0;
+const SPACING: f32 = 2.0; // Roughly one entity every two units, like the world we have now
+
+// Spread evenly but not on a grid (R2 sequence), the same every run
+fn filled_grid(entity_count: usize) -> (SpatialGrid, f32) {
+    let world_size = (entity_count as f32).sqrt() * SPACING;
+    let mut allocator = EntityAllocator::new();
+    let mut grid = SpatialGrid::new(CELL_SIZE);
+    for i in 0..entity_count {
+        let x = (i as f64 * 0.754_877_666_246_692_7).fract() as f32 * world_size;
+        let z = (i as f64 * 0.569_840_290_998_053_3).fract() as f32 * world_size;
+        let min = Point2::new(x, z);
+        grid.update(
+            allocator.allocate(),
+            min,
+            min + Vector2::new(ENTITY_SIZE, ENTITY_SIZE),
+        );
+    }
+    (grid, world_size)
+}
+
+fn spatial_grid_queries(criterion: &mut Criterion) {
+    for entity_count in ENTITY_COUNTS {
+        let (grid, world_size) = filled_grid(entity_count);
+        let center = Point2::new(world_size / 2.0, world_size / 2.0);
+
+        // About what is checked when picking up items or moving
+        let half_area = Vector2::new(5.0, 5.0);
+        criterion.bench_with_input(
+            BenchmarkId::new("query_area", entity_count),
+            &grid,
+            |bencher, grid| {
+                bencher.iter(|| grid.query_area(black_box(center - half_area), center + half_area))
+            },
+        );
+        criterion.bench_with_input(
+            BenchmarkId::new("query_point", entity_count),
+            &grid,
+            |bencher, grid| bencher.iter(|| grid.query_point(black_box(center))),
+        );
+        // From outside of the world across all of it, the worst a mouse ray can do
+        let origin = Point2::new(-world_size, -world_size * 0.9);
+        let direction = Vector2::new(1.0, 1.0).normalize();
+        criterion.bench_with_input(
+            BenchmarkId::new("query_ray", entity_count),
+            &grid,
+            |bencher, grid| bencher.iter(|| grid.query_ray(black_box(origin), direction)),
+        );
+    }
+}
+
+criterion_group!(benches, spatial_grid_queries);
+criterion_main!(benches);
@@ -12,20 +12,30 @@ use winit::dpi::LogicalSize;
 use winit::event_loop::{ActiveEventLoop, ControlFlow};
 use winit::window::{Cursor, CustomCursor, Fullscreen, Icon, Window, WindowId};
 
+use crate::application::monitor_change_system::MonitorChangeSystem;
 use crate::state::game_state::GameState;
+use crate::state::recording::{InputRecorder, InputRecording, InputReplayer};
+use crate::state::save_game::SaveGame;
+use crate::state::time::{DEFAULT_TICKS_PER_SECOND, Time};
 use crate::state::ui_state::UIState;
 use crate::state::update_state::UpdateState;
+use crate::state::viewport::Viewport;
 use crate::systems::game_system::GameSystem;
 use winit::keyboard::KeyCode;
 
-use crate::application::update_tick_handler_native::UpdateTickHandler;
-use crate::application::FontAsset;
+use crate::application::clock_native::InstantClock;
+use crate::application::update_tick_handler::UpdateTickHandler;
+use crate::application::{AssetLoader, FontAsset, SaveStorage, load_game_state};
+use crate::net::WebSocketTransport;
+use crate::net::client::NetworkClient;
+use crate::net::connection::Connection;
 use crate::render::model_loader::ModelLoader;
 use crate::render::renderer::Renderer;
 use hydrox::{load_binary, AudioSystem, Sound};
 
 pub struct Engine {
-    pub update_tick_handler: UpdateTickHandler,
+    pub update_tick_handler: UpdateTickHandler<InstantClock>,
+    pub time: Time,
 
     pub game_state: GameState,
     pub ui_state: UIState,
@@ -35,6 +45,13 @@ pub struct Engine {
     pub window: Arc<Window>, // TODO Is the only reason for having this in engine to access inner size? although that might still be valid reason. dont want to copy the data
     pub renderer: Renderer,
     pub audio_system: AudioSystem,
+
+    // Recording and replaying input is only done natively, set through KLOENK_RECORD and KLOENK_REPLAY with a file path
+    pub input_recorder: Option<(String, InputRecorder)>,
+    pub input_replayer: Option<InputReplayer>,
+    pub save_storage: SaveStorage,
+    // Only set when playing online, by pointing KLOENK_SERVER to the server address. KLOENK_NAME sets the player name
+    pub network_client: Option<NetworkClient<WebSocketTransport>>,
 }
 
 pub enum State {
@@ -64,19 +81,60 @@ impl ApplicationHandler for Application {
                 State::Initialized(engine) => {
                     while engine.update_tick_handler.should_update() {
                         engine.renderer.updating();
+                        let mut viewport = Viewport::from(engine.window.inner_size());
+                        if let Some(input_replayer) = &mut engine.input_replayer {
+                            input_replayer.before_update(&mut viewport, &mut engine.input_handler);
+                        }
+                        if let Some((_, input_recorder)) = &mut engine.input_recorder {
+                            input_recorder.before_update(&viewport, &engine.input_handler);
+                        }
+                        if let Some(network_client) = &mut engine.network_client {
+                            network_client.receive(
+                                &engine.time,
+                                &mut engine.game_state,
+                                &mut engine.ui_state,
+                            );
+                        }
+                        // Window specific input is handled here, game logic only needs the viewport
+                        MonitorChangeSystem::update_monitor(&engine.input_handler, &engine.window);
                         GameSystem::update(
-                            &engine.window,
+                            &viewport,
+                            &engine.time,
                             &mut engine.game_state,
                             &mut engine.ui_state,
                             &mut engine.input_handler,
                             &mut engine.frame_state,
                             &mut engine.audio_system,
                         );
+                        if let Some((_, input_recorder)) = &mut engine.input_recorder {
+                            input_recorder.after_update(&engine.game_state);
+                        }
+                        if let Some(input_replayer) = &mut engine.input_replayer
+                            && let Err(_divergence) =
+                                input_replayer.after_update(&engine.game_state)
+                        {
+                            #[cfg(feature = "debug-logging")]
+                            log::error!("Stopped replaying: {_divergence}");
+                            engine.input_replayer = None;
+                        }
+                        if let Some(network_client) = &mut engine.network_client {
+                            network_client.send(&engine.frame_state);
+                        }
                         engine.renderer.updated(
                             &engine.window,
                             &mut engine.frame_state,
                             &mut engine.game_state,
                         );
+                        engine.time.advance();
+                        // The world of a server is not ours to save
+                        if SaveGame::should_autosave(&engine.time)
+                            && engine.input_replayer.is_none()
+                            && engine.network_client.is_none()
+                        {
+                            engine
+                                .save_storage
+                                .save(&SaveGame::serialize(&engine.game_state));
+                        }
                         engine.update_tick_handler.updated();
                     }
                     engine.window.request_redraw();
@@ -157,9 +215,7 @@ impl ApplicationHandler for Application {
                 if let Some(texture_id) = &primitive.texture_definition {
                     // TODO check if not already loaded first
                     let image_texture_asset = pollster::block_on(
-                        crate::application::asset_loader::AssetLoader::load_image_asset(
-                            &texture_id.file_name,
-                        ),
+                        AssetLoader::load_image_asset(&texture_id.file_name),
                     );
                     renderer.load_material_to_memory(&image_texture_asset);
                 }
@@ -180,15 +236,57 @@ impl ApplicationHandler for Application {
             },
         );
 
+        let world = pollster::block_on(AssetLoader::load_world_definition("world.json"));
+        let quests = pollster::block_on(AssetLoader::load_quests("quests.json"));
+        let dialogues = pollster::block_on(AssetLoader::load_dialogues(
+            "dialogues.json",
+            &world,
+            &quests,
+        ));
+
+        let time = Time::new(DEFAULT_TICKS_PER_SECOND);
+        let input_recorder = std::env::var("KLOENK_RECORD")
+            .ok()
+            .map(|path| (path, InputRecorder::new(DEFAULT_TICKS_PER_SECOND)));
+        let input_replayer = std::env::var("KLOENK_REPLAY").ok().map(|path| {
+            let recording_data = std::fs::read(&path)
+                .unwrap_or_else(|error| panic!("Recording {path} could not be read: {error}"));
+            let recording = InputRecording::from_json(&recording_data)
+                .unwrap_or_else(|error| panic!("Failed to load recording {path}: {error}"));
+            InputReplayer::new(recording)
+        });
+        let network_client = std::env::var("KLOENK_SERVER").ok().map(|address| {
+            let player_name = std::env::var("KLOENK_NAME").unwrap_or_default();
+            NetworkClient::new(Connection::new(
+                WebSocketTransport::new(&address),
+                &player_name,
+            ))
+        });
+        let save_storage = SaveStorage::new();
+        // Recordings start from the world definition, continuing from a save would make a replay diverge
+        let game_state = if input_recorder.is_none() && input_replayer.is_none() {
+            load_game_state(&save_storage, &world, &quests)
+        } else {
+            GameState::new(&world)
+        };
         self.application_state = State::Initialized(Box::new(Engine {
             renderer,
-            update_tick_handler: UpdateTickHandler::new(),
-            game_state: GameState::new(),
-            ui_state: UIState::new(),
+            update_tick_handler: UpdateTickHandler::new(InstantClock::new(), time.delta()),
+            time,
+            game_state,
+            ui_state: UIState {
+                dialogues,
+                quests,
+                ..UIState::new()
+            },
             input_handler: Input::new(),
             frame_state: UpdateState::new(),
             window: window.clone(),
             audio_system,
+            input_recorder,
+            input_replayer,
+            save_storage,
+            network_client,
         }));
         window.set_visible(true); // Not sure why, but cannot draw (just on windows? not tested elsewhere) without the window being visible -> set_visible also implicit seems to start requesting redraws
     }
@@ -204,8 +302,9 @@ impl ApplicationHandler for Application {
         };
 
         match event {
-            WindowEvent::CloseRequested
-            | WindowEvent::KeyboardInput {
+            WindowEvent::CloseRequested => event_loop.exit(),
+            // While typing escape stops typing instead
+            WindowEvent::KeyboardInput {
                 event:
                 KeyEvent {
                     physical_key: PhysicalKey::Code(KeyCode::Escape),
@@ -213,18 +312,27 @@ impl ApplicationHandler for Application {
                     ..
                 },
                 ..
-            } => event_loop.exit(),
+            } if !engine.input_handler.is_typing => event_loop.exit(),
             // TODO ModifiersChanged? like shift ctrl. is this more for typing?
             WindowEvent::KeyboardInput {
                 event:
                 KeyEvent {
                     physical_key: PhysicalKey::Code(key),
                     state,
+                    text,
                     ..
                 },
                 ..
             } => {
                 engine.input_handler.update(key, state);
+                if state == ElementState::Pressed
+                    && let Some(text) = text
+                {
+                    engine.input_handler.process_text(&text);
+                }
+                if cfg!(debug_assertions) && state == ElementState::Pressed {
+                    engine.update_tick_handler.handle_debug_key(key);
+                }
             }
             WindowEvent::MouseInput { state, button, .. } => {
                 engine.input_handler.process_mouse_button(button, state);
@@ -245,12 +353,10 @@ impl ApplicationHandler for Application {
             }
             // TODO handle window going out of focus/out of view (occluded)
             WindowEvent::RedrawRequested => {
-                // TODO Wondering if we should do something with extrapolation: lag / MS_PER_UPDATE. Maybe only for animations etc
-                // https://gameprogrammingpatterns.com/game-loop.html: The renderer knows each game object and its current velocity. doubt it
-                // maybe an option?
-                // maybe only perform if off by some amount like 2ms?
-                // maybe we do run an update() on a cloned version? not the real state. slow updat
-                match engine.renderer.render(&engine.window) {
+                // Interpolating between the last two ticks instead of extrapolating: https://gameprogrammingpatterns.com/game-loop.html
+                // Means we render up to one tick behind, but we never show a position the game state did not reach
+                let alpha = engine.update_tick_handler.alpha();
+                match engine.renderer.render(&engine.window, alpha) {
                     Ok(()) => {}
                     Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
                         engine.renderer.resize(engine.window.inner_size());
@@ -290,6 +396,18 @@ impl ApplicationHandler for Application {
     fn exiting(&mut self, _: &ActiveEventLoop) {
         #[cfg(feature = "debug-logging")]
         log::debug!("Exiting");
+        let State::Initialized(engine) = &self.application_state else {
+            return;
+        };
+        if engine.input_replayer.is_none() && engine.network_client.is_none() {
+            engine
+                .save_storage
+                .save(&SaveGame::serialize(&engine.game_state));
+        }
+        if let Some((path, input_recorder)) = &engine.input_recorder {
+            std::fs::write(path, input_recorder.recording().to_json())
+                .unwrap_or_else(|error| panic!("Recording {path} could not be written: {error}"));
+        }
     }
 
     fn memory_warning(&mut self, _: &ActiveEventLoop) {
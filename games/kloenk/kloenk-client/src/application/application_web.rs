@@ -14,21 +14,30 @@ use wasm_bindgen::closure::Closure;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::spawn_local;
 use winit::dpi::LogicalSize;
-use winit::event::StartCause;
+use winit::event::{ElementState, StartCause};
 use winit::event_loop::ActiveEventLoop;
 use winit::window::{CustomCursor, Window, WindowId};
 
-use crate::application::update_tick_handler_web::UpdateTickHandler;
+use crate::application::clock_web::PerformanceClock;
+use crate::application::monitor_change_system::MonitorChangeSystem;
+use crate::application::update_tick_handler::UpdateTickHandler;
 use crate::application::Asset::{Audio, Color, Font, Texture, Vertices};
-use crate::application::{AssetLoader, FontAsset, ImageAsset};
+use crate::application::{AssetLoader, FontAsset, ImageAsset, SaveStorage, load_game_state};
+use crate::net::WebSocketTransport;
+use crate::net::client::NetworkClient;
+use crate::net::connection::Connection;
 use crate::render::model::ColorDefinition;
 use crate::render::model_loader::ModelLoader;
 use crate::render::primitive_vertices_manager::PrimitiveVertices;
 use crate::render::renderer::Renderer;
 use crate::state::game_state::GameState;
+use crate::state::save_game::SaveGame;
+use crate::state::time::{DEFAULT_TICKS_PER_SECOND, Time};
 use crate::state::ui_state::UIState;
 use crate::state::update_state::UpdateState;
+use crate::state::viewport::Viewport;
 use crate::systems::game_system::GameSystem;
+use web_sys::UrlSearchParams;
 use winit::keyboard::KeyCode;
 
 // TODO define engine same as for native, needs to be used as same interface
@@ -39,8 +48,12 @@ pub struct Engine {
     pub input_handler: Input,
     pub frame_state: UpdateState,
     pub window: Arc<Window>,
-    pub framerate_handler: UpdateTickHandler,
+    pub framerate_handler: UpdateTickHandler<PerformanceClock>,
+    pub time: Time,
     pub audio_system: AudioSystem,
+    pub save_storage: SaveStorage,
+    // Only set when playing online, by opening the page with ?server=host:port. ?name= sets the player name
+    pub network_client: Option<NetworkClient<WebSocketTransport>>,
 }
 
 // On web, AudioSystem is loaded after user has used a gesture. This is to get rid of this warning in Chrome:
@@ -127,6 +140,21 @@ impl Application {
     // }
 }
 
+fn connect_from_page_url() -> Option<NetworkClient<WebSocketTransport>> {
+    let search = web_sys::window()
+        .expect("Window should exist")
+        .location()
+        .search()
+        .ok()?;
+    let parameters = UrlSearchParams::new_with_str(&search).ok()?;
+    let address = parameters.get("server")?;
+    let player_name = parameters.get("name").unwrap_or_default();
+    Some(NetworkClient::new(Connection::new(
+        WebSocketTransport::new(&address),
+        &player_name,
+    )))
+}
+
 pub enum State {
     Uninitialized,
     Initializing,
@@ -144,19 +172,41 @@ impl ApplicationHandler<CustomEvent> for Application {
                 State::Initialized(engine) => {
                     while engine.framerate_handler.should_update() {
                         engine.renderer.updating();
+                        if let Some(network_client) = &mut engine.network_client {
+                            network_client.receive(
+                                &engine.time,
+                                &mut engine.game_state,
+                                &mut engine.ui_state,
+                            );
+                        }
+                        // Window specific input is handled here, game logic only needs the viewport
+                        MonitorChangeSystem::update_monitor(&engine.input_handler, &engine.window);
                         GameSystem::update(
-                            &engine.window,
+                            &Viewport::from(engine.window.inner_size()),
+                            &engine.time,
                             &mut engine.game_state,
                             &mut engine.ui_state,
                             &mut engine.input_handler,
                             &mut engine.frame_state,
                             &mut engine.audio_system,
                         );
+                        if let Some(network_client) = &mut engine.network_client {
+                            network_client.send(&engine.frame_state);
+                        }
                         engine.renderer.updated(
                             &engine.window,
                             &mut engine.frame_state,
                             &mut engine.game_state,
                         );
+                        engine.time.advance();
+                        // The world of a server is not ours to save
+                        if SaveGame::should_autosave(&engine.time)
+                            && engine.network_client.is_none()
+                        {
+                            engine
+                                .save_storage
+                                .save(&SaveGame::serialize(&engine.game_state));
+                        }
                         engine.framerate_handler.updated();
                     }
                     engine.window.request_redraw();
@@ -241,15 +291,27 @@ impl ApplicationHandler<CustomEvent> for Application {
 
         spawn_local(async move {
             let renderer = renderer_future.await;
+            let world = AssetLoader::load_world_definition("world.json").await;
+            let quests = AssetLoader::load_quests("quests.json").await;
+            let dialogues = AssetLoader::load_dialogues("dialogues.json", &world, &quests).await;
+            let time = Time::new(DEFAULT_TICKS_PER_SECOND);
+            let save_storage = SaveStorage::new();
             let engine = Engine {
                 renderer,
-                game_state: GameState::new(),
-                ui_state: UIState::new(),
+                game_state: load_game_state(&save_storage, &world, &quests),
+                ui_state: UIState {
+                    dialogues,
+                    quests,
+                    ..UIState::new()
+                },
                 input_handler: Input::new(),
                 frame_state: UpdateState::new(),
                 audio_system: AudioSystem::new_load_later(),
-                framerate_handler: UpdateTickHandler::new(),
+                framerate_handler: UpdateTickHandler::new(PerformanceClock::new(), time.delta()),
+                time,
                 window,
+                save_storage,
+                network_client: connect_from_page_url(),
             };
 
             event_loop_proxy
@@ -414,11 +476,20 @@ impl ApplicationHandler<CustomEvent> for Application {
                 KeyEvent {
                     physical_key: PhysicalKey::Code(key),
                     state,
+                    text,
                     ..
                 },
                 ..
             } => {
                 engine.input_handler.update(key, state);
+                if state == ElementState::Pressed
+                    && let Some(text) = text
+                {
+                    engine.input_handler.process_text(&text);
+                }
+                if cfg!(debug_assertions) && state == ElementState::Pressed {
+                    engine.framerate_handler.handle_debug_key(key);
+                }
 
                 // Loading audio only after user has gestured on web
                 // Thought of callback or observer pattern but that honestly seems way too complex compared to this.
@@ -450,7 +521,8 @@ impl ApplicationHandler<CustomEvent> for Application {
                 engine.input_handler.process_scroll(&delta);
             }
             WindowEvent::RedrawRequested => {
-                match engine.renderer.render(&engine.window) {
+                let alpha = engine.framerate_handler.alpha();
+                match engine.renderer.render(&engine.window, alpha) {
                     Ok(()) => {}
                     Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
                         engine.renderer.resize(engine.window.inner_size());
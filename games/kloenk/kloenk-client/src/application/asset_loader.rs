@@ -1,3 +1,6 @@
+use crate::state::world_definition::WorldDefinition;
+use crate::systems::dialogue_manager::DialogueManager;
+use crate::systems::quest_manager::QuestManager;
 use ddsfile::{Dds, DxgiFormat, FourCC, Header, Header10};
 use hydrox::load_binary;
 
@@ -35,6 +38,43 @@ impl AssetLoader {
         Self::load_dds(image_path, &data)
     }
 
+    // Designers edit the world file by hand, so we want to fail with the reason instead of a generic unwrap
+    pub async fn load_world_definition(world_path: &str) -> WorldDefinition {
+        let data = load_binary(world_path)
+            .await
+            .unwrap_or_else(|_| panic!("World file {world_path} could not be found"));
+        WorldDefinition::from_json(&data)
+            .unwrap_or_else(|error| panic!("Failed to load world file {world_path}: {error}"))
+    }
+
+    // After the world and the quests, the dialogues refer to both
+    pub async fn load_dialogues(
+        dialogue_path: &str,
+        world: &WorldDefinition,
+        quests: &QuestManager,
+    ) -> DialogueManager {
+        let data = load_binary(dialogue_path)
+            .await
+            .unwrap_or_else(|_| panic!("Dialogue file {dialogue_path} could not be found"));
+        let dialogues = DialogueManager::from_json(&data).unwrap_or_else(|error| {
+            panic!("Failed to load dialogue file {dialogue_path}: {error}")
+        });
+        dialogues
+            .check_references(world, quests)
+            .unwrap_or_else(|error| {
+                panic!("Failed to load dialogue file {dialogue_path}: {error}")
+            });
+        dialogues
+    }
+
+    pub async fn load_quests(quest_path: &str) -> QuestManager {
+        let data = load_binary(quest_path)
+            .await
+            .unwrap_or_else(|_| panic!("Quest file {quest_path} could not be found"));
+        QuestManager::from_json(&data)
+            .unwrap_or_else(|error| panic!("Failed to load quest file {quest_path}: {error}"))
+    }
+
     fn load_dds(image_name: &str, dds_bytes: &[u8]) -> ImageAsset {
         let dds = Dds::read(dds_bytes).unwrap(); // Maybe retry? How can this fail? Bytes are already in memory...
         let format = detect_format(&dds.header, dds.header10.as_ref());
@@ -0,0 +1,21 @@
+use crate::application::update_tick_handler::Clock;
+use std::time::{Duration, Instant};
+
+// https://old.reddit.com/r/rust/comments/1nbrwj4/hotpath_a_simple_rust_profiler_that_shows_exactly/  I'd advise against using stds Instant for measuring performance
+pub struct InstantClock {
+    start: Instant,
+}
+
+impl InstantClock {
+    pub fn new() -> Self {
+        InstantClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for InstantClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
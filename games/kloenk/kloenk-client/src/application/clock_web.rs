@@ -0,0 +1,24 @@
+use crate::application::update_tick_handler::Clock;
+use std::time::Duration;
+use web_sys::Performance;
+
+// Instant is not available on wasm, performance.now() is in milliseconds since the page loaded
+pub struct PerformanceClock {
+    performance: Performance,
+}
+
+impl PerformanceClock {
+    pub fn new() -> Self {
+        let performance = web_sys::window()
+            .expect("window should exist")
+            .performance()
+            .expect("performance should be available");
+        PerformanceClock { performance }
+    }
+}
+
+impl Clock for PerformanceClock {
+    fn now(&self) -> Duration {
+        Duration::from_secs_f64(self.performance.now() / 1000.0)
+    }
+}
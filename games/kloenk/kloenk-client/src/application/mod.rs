@@ -13,11 +13,61 @@ pub use application_web::*;
 
 mod asset_loader;
 #[cfg(not(target_family = "wasm"))]
-#[path = "update_tick_handler_native.rs"]
-mod update_tick_handler_native;
+#[path = "clock_native.rs"]
+mod clock_native;
 
 #[cfg(target_family = "wasm")]
-#[path = "update_tick_handler_web.rs"]
-mod update_tick_handler_web;
+#[path = "clock_web.rs"]
+mod clock_web;
+
+mod monitor_change_system;
+#[cfg(not(target_family = "wasm"))]
+#[path = "save_storage_native.rs"]
+mod save_storage_native;
+
+#[cfg(target_family = "wasm")]
+#[path = "save_storage_web.rs"]
+mod save_storage_web;
+
+mod update_tick_handler;
+
+#[cfg(not(target_family = "wasm"))]
+use save_storage_native::SaveStorage;
+#[cfg(target_family = "wasm")]
+use save_storage_web::SaveStorage;
 
 pub use asset_loader::*;
+
+use crate::state::audio::AudioSink;
+use crate::state::game_state::GameState;
+use crate::state::save_game::SaveGame;
+use crate::state::world_definition::WorldDefinition;
+use crate::systems::quest_manager::QuestManager;
+use crate::systems::quest_system::QuestSystem;
+use hydrox::AudioSystem;
+
+impl AudioSink for AudioSystem {
+    fn play_sound(&mut self, sound: &str) {
+        AudioSystem::play_sound(self, sound);
+    }
+}
+
+// Continue where the player left off. A save we cannot read is ignored, it gets overwritten on the next autosave
+fn load_game_state(
+    save_storage: &SaveStorage,
+    world: &WorldDefinition,
+    quests: &QuestManager,
+) -> GameState {
+    match save_storage.load().map(|save| SaveGame::deserialize(&save)) {
+        Some(Ok(mut game_state)) => {
+            QuestSystem::fit_progress(&mut game_state, quests);
+            game_state
+        }
+        Some(Err(_error)) => {
+            #[cfg(feature = "debug-logging")]
+            log::error!("Failed to load save, starting a new game: {_error}");
+            GameState::new(world)
+        }
+        None => GameState::new(world),
+    }
+}
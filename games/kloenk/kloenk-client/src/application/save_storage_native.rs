@@ -0,0 +1,33 @@
+use std::path::PathBuf;
+
+const SAVE_FILE: &str = "save.json";
+
+// Saves next to where the game is started from. KLOENK_SAVE can point somewhere else
+pub struct SaveStorage {
+    path: PathBuf,
+}
+
+impl SaveStorage {
+    pub fn new() -> Self {
+        let path = std::env::var("KLOENK_SAVE").unwrap_or_else(|_| SAVE_FILE.to_owned());
+        SaveStorage {
+            path: PathBuf::from(path),
+        }
+    }
+
+    pub fn load(&self) -> Option<Vec<u8>> {
+        std::fs::read(&self.path).ok()
+    }
+
+    // Losing a save is bad, but not worth crashing the game over
+    pub fn save(&self, data: &[u8]) {
+        // Write next to the old save first, so quitting halfway through does not leave a broken save
+        let temporary_path = self.path.with_extension("json.tmp");
+        if let Err(_error) = std::fs::write(&temporary_path, data)
+            .and_then(|()| std::fs::rename(&temporary_path, &self.path))
+        {
+            #[cfg(feature = "debug-logging")]
+            log::error!("Failed to save to {}: {_error}", self.path.display());
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use web_sys::Storage;
+
+const SAVE_KEY: &str = "kloenk_save";
+
+// Local storage only holds strings, which is fine as the save is json
+pub struct SaveStorage {
+    local_storage: Option<Storage>,
+}
+
+impl SaveStorage {
+    pub fn new() -> Self {
+        // Can be unavailable, for example when the user blocks site data. We just play without saving then
+        let local_storage = web_sys::window()
+            .expect("Window should exist")
+            .local_storage()
+            .ok()
+            .flatten();
+        SaveStorage { local_storage }
+    }
+
+    pub fn load(&self) -> Option<Vec<u8>> {
+        let local_storage = self.local_storage.as_ref()?;
+        local_storage
+            .get_item(SAVE_KEY)
+            .ok()
+            .flatten()
+            .map(String::into_bytes)
+    }
+
+    pub fn save(&self, data: &[u8]) {
+        let Some(local_storage) = &self.local_storage else {
+            return;
+        };
+        let data = String::from_utf8_lossy(data);
+        if let Err(_error) = local_storage.set_item(SAVE_KEY, &data) {
+            #[cfg(feature = "debug-logging")]
+            log::error!("Failed to save to local storage: {_error:?}");
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use std::time::Duration;
+use winit::keyboard::KeyCode;
+
+// After a backgrounded tab or a breakpoint we would otherwise try to run all missed ticks at once, making the next frame even slower (spiral of death)
+// Anything past this is dropped: the game just runs slower for a moment
+pub const MAX_CATCH_UP_TICKS: u32 = 5;
+const MIN_TIME_SCALE: f32 = 0.125;
+const MAX_TIME_SCALE: f32 = 4.0;
+
+// Monotonic time since some fixed point. Platforms measure time differently, and tests can pass in their own
+pub trait Clock {
+    fn now(&self) -> Duration;
+}
+
+// Time probably needs to be retrieved from server in order to match ticks
+pub struct UpdateTickHandler<C: Clock> {
+    clock: C,
+    tick_duration: Duration,
+    last_update_time: Duration,
+    accumulated_time: Duration,
+    ticks_this_frame: u32,
+    time_scale: f32,
+    is_paused: bool,
+    pending_steps: u32,
+    is_stepping: bool,
+}
+
+impl<C: Clock> UpdateTickHandler<C> {
+    pub fn new(clock: C, tick_duration: Duration) -> Self {
+        assert!(!tick_duration.is_zero(), "Tick duration should be positive");
+        let last_update_time = clock.now();
+        UpdateTickHandler {
+            clock,
+            tick_duration,
+            last_update_time,
+            accumulated_time: Duration::ZERO,
+            ticks_this_frame: 0,
+            time_scale: 1.0,
+            is_paused: false,
+            pending_steps: 0,
+            is_stepping: false,
+        }
+    }
+
+    // Called in a loop every frame until it returns false, with updated() after every tick
+    pub fn should_update(&mut self) -> bool {
+        let now = self.clock.now();
+        let elapsed = now.saturating_sub(self.last_update_time);
+        self.last_update_time = now;
+
+        if self.is_paused {
+            if self.pending_steps > 0 {
+                self.pending_steps -= 1;
+                self.is_stepping = true;
+                return true;
+            }
+            return false;
+        }
+
+        self.accumulated_time += elapsed.mul_f32(self.time_scale);
+        if self.accumulated_time < self.tick_duration {
+            self.ticks_this_frame = 0;
+            return false;
+        }
+
+        if self.ticks_this_frame >= MAX_CATCH_UP_TICKS {
+            // Keep the part of a tick we were already into, so rendering does not jump back
+            self.accumulated_time = Duration::from_nanos(
+                (self.accumulated_time.as_nanos() % self.tick_duration.as_nanos()) as u64,
+            );
+            self.ticks_this_frame = 0;
+            return false;
+        }
+        true
+    }
+
+    pub fn updated(&mut self) {
+        if self.is_stepping {
+            // Steps do not take from the accumulated time, that is frozen while paused
+            self.is_stepping = false;
+            return;
+        }
+        self.accumulated_time = self.accumulated_time.saturating_sub(self.tick_duration);
+        self.ticks_this_frame += 1;
+    }
+
+    // How far we are between the last tick and the next one, used to interpolate rendering
+    pub fn alpha(&self) -> f32 {
+        (self.accumulated_time.as_secs_f32() / self.tick_duration.as_secs_f32()).clamp(0.0, 1.0)
+    }
+
+    pub fn pause(&mut self) {
+        self.is_paused = true;
+    }
+
+    // Time spent paused is not caught up on
+    pub fn resume(&mut self) {
+        self.is_paused = false;
+        self.pending_steps = 0;
+        self.last_update_time = self.clock.now();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.is_paused
+    }
+
+    // Runs a single tick on the next frame. Only does something while paused
+    pub fn step(&mut self) {
+        if self.is_paused {
+            self.pending_steps += 1;
+        }
+    }
+
+    // Slow motion below 1.0, fast forward above. The game itself still advances a fixed delta per tick, we just run fewer or more ticks
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    // Debug controls, only hooked up in debug builds
+    pub fn handle_debug_key(&mut self, key: KeyCode) {
+        match key {
+            KeyCode::F5 => {
+                if self.is_paused() {
+                    self.resume();
+                } else {
+                    self.pause();
+                }
+            }
+            KeyCode::F6 => self.step(),
+            KeyCode::F7 => self.set_time_scale(self.time_scale() / 2.0),
+            KeyCode::F8 => self.set_time_scale(self.time_scale() * 2.0),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    const TICK: Duration = Duration::from_millis(10);
+
+    // Only moves when the test says so
+    #[derive(Clone, Default)]
+    struct FakeClock {
+        now: Rc<Cell<Duration>>,
+    }
+
+    impl FakeClock {
+        fn advance(&self, duration: Duration) {
+            self.now.set(self.now.get() + duration);
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Duration {
+            self.now.get()
+        }
+    }
+
+    fn handler() -> (FakeClock, UpdateTickHandler<FakeClock>) {
+        let clock = FakeClock::default();
+        (clock.clone(), UpdateTickHandler::new(clock, TICK))
+    }
+
+    // Advances the clock and runs one frame the way the application does, giving how many ticks it ran
+    fn frame(
+        clock: &FakeClock,
+        handler: &mut UpdateTickHandler<FakeClock>,
+        duration: Duration,
+    ) -> u32 {
+        clock.advance(duration);
+        let mut ticks = 0;
+        while handler.should_update() {
+            handler.updated();
+            ticks += 1;
+            assert!(ticks <= 1000, "frame never ended");
+        }
+        ticks
+    }
+
+    fn assert_alpha(handler: &UpdateTickHandler<FakeClock>, expected: f32) {
+        let alpha = handler.alpha();
+        assert!(
+            (alpha - expected).abs() < 0.001,
+            "alpha {alpha} is not {expected}"
+        );
+    }
+
+    #[test]
+    fn runs_a_tick_per_tick_duration_and_keeps_the_rest() {
+        let (clock, mut handler) = handler();
+
+        assert_eq!(frame(&clock, &mut handler, Duration::from_millis(5)), 0);
+        assert_alpha(&handler, 0.5);
+        assert_eq!(frame(&clock, &mut handler, Duration::from_millis(5)), 1);
+        assert_alpha(&handler, 0.0);
+        assert_eq!(frame(&clock, &mut handler, Duration::from_millis(35)), 3);
+        assert_alpha(&handler, 0.5);
+        assert_eq!(frame(&clock, &mut handler, Duration::from_millis(5)), 1);
+    }
+
+    #[test]
+    fn a_long_stall_only_catches_up_a_few_ticks() {
+        let (clock, mut handler) = handler();
+
+        let ticks = frame(&clock, &mut handler, Duration::from_millis(10_005));
+        assert_eq!(ticks, MAX_CATCH_UP_TICKS);
+        // The rest of the stall is dropped, apart from how far we were into the next tick
+        assert_alpha(&handler, 0.5);
+        assert_eq!(frame(&clock, &mut handler, Duration::ZERO), 0);
+        assert_eq!(frame(&clock, &mut handler, Duration::from_millis(5)), 1);
+        assert_eq!(frame(&clock, &mut handler, TICK), 1);
+    }
+
+    #[test]
+    fn paused_runs_no_ticks_and_does_not_catch_up_after() {
+        let (clock, mut handler) = handler();
+        assert_eq!(frame(&clock, &mut handler, Duration::from_millis(5)), 0);
+
+        handler.pause();
+        for _ in 0..10 {
+            assert_eq!(frame(&clock, &mut handler, Duration::from_secs(1)), 0);
+        }
+        assert_alpha(&handler, 0.5);
+
+        handler.resume();
+        assert_eq!(frame(&clock, &mut handler, Duration::ZERO), 0);
+        assert_eq!(frame(&clock, &mut handler, Duration::from_millis(5)), 1);
+    }
+
+    #[test]
+    fn step_runs_exactly_one_tick_while_paused() {
+        let (clock, mut handler) = handler();
+        handler.pause();
+
+        handler.step();
+        assert_eq!(frame(&clock, &mut handler, Duration::from_secs(1)), 1);
+        assert_eq!(frame(&clock, &mut handler, Duration::from_secs(1)), 0);
+
+        handler.step();
+        handler.step();
+        assert_eq!(frame(&clock, &mut handler, Duration::ZERO), 2);
+        assert_eq!(frame(&clock, &mut handler, Duration::ZERO), 0);
+
+        // Steps left over when resuming are dropped, and stepping does nothing while running
+        handler.step();
+        handler.resume();
+        handler.step();
+        assert_eq!(frame(&clock, &mut handler, Duration::ZERO), 0);
+        assert_eq!(frame(&clock, &mut handler, TICK), 1);
+    }
+
+    #[test]
+    fn time_scale_changes_how_many_ticks_run() {
+        let (clock, mut handler) = handler();
+        let ticks_in = |handler: &mut UpdateTickHandler<FakeClock>| {
+            (0..100).map(|_| frame(&clock, handler, TICK)).sum::<u32>()
+        };
+
+        assert_eq!(ticks_in(&mut handler), 100);
+        handler.set_time_scale(2.0);
+        assert_eq!(ticks_in(&mut handler), 200);
+        handler.set_time_scale(0.5);
+        assert_eq!(ticks_in(&mut handler), 50);
+
+        handler.set_time_scale(100.0);
+        assert_eq!(handler.time_scale(), MAX_TIME_SCALE);
+        handler.set_time_scale(0.0);
+        assert_eq!(handler.time_scale(), MIN_TIME_SCALE);
+    }
+
+    #[test]
+    fn debug_keys_pause_step_and_change_speed() {
+        let (clock, mut handler) = handler();
+
+        handler.handle_debug_key(KeyCode::F8);
+        assert_eq!(handler.time_scale(), 2.0);
+        handler.handle_debug_key(KeyCode::F7);
+        handler.handle_debug_key(KeyCode::F7);
+        assert_eq!(handler.time_scale(), 0.5);
+
+        handler.handle_debug_key(KeyCode::F5);
+        assert!(handler.is_paused());
+        handler.handle_debug_key(KeyCode::F6);
+        assert_eq!(frame(&clock, &mut handler, Duration::from_secs(1)), 1);
+        handler.handle_debug_key(KeyCode::F5);
+        assert!(!handler.is_paused());
+    }
+}
@@ -0,0 +1,140 @@
+use kloenk::headless::{DEFAULT_VIEWPORT, HeadlessGame, InputScript};
+use kloenk::state::recording::{InputRecorder, InputRecording};
+use kloenk::state::time::DEFAULT_TICKS_PER_SECOND;
+use kloenk::state::world_definition::WorldDefinition;
+use kloenk::systems::dialogue_manager::DialogueManager;
+use kloenk::systems::quest_manager::QuestManager;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage:
+  kloenk-headless <script.json> [--record <recording.json>] [--world <world.json>] [--dialogues <dialogues.json>] [--quests <quests.json>]
+  kloenk-headless --replay <recording.json> [--world <world.json>] [--dialogues <dialogues.json>] [--quests <quests.json>]";
+
+fn main() -> ExitCode {
+    let mut script_path = None;
+    let mut record_path = None;
+    let mut replay_path = None;
+    let mut world_path = "assets/world.json".to_owned();
+    let mut dialogue_path = "assets/dialogues.json".to_owned();
+    let mut quest_path = "assets/quests.json".to_owned();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" | "--replay" | "--world" | "--dialogues" | "--quests" => {
+                let Some(path) = args.next() else {
+                    return usage();
+                };
+                match arg.as_str() {
+                    "--record" => record_path = Some(path),
+                    "--replay" => replay_path = Some(path),
+                    "--dialogues" => dialogue_path = path,
+                    "--quests" => quest_path = path,
+                    _ => world_path = path,
+                }
+            }
+            _ if script_path.is_none() && !arg.starts_with("--") => script_path = Some(arg),
+            _ => return usage(),
+        }
+    }
+
+    let world_data = std::fs::read(&world_path)
+        .unwrap_or_else(|error| panic!("World file {world_path} could not be read: {error}"));
+    let world = WorldDefinition::from_json(&world_data)
+        .unwrap_or_else(|error| panic!("Failed to load world file {world_path}: {error}"));
+    let dialogue_data = std::fs::read(&dialogue_path)
+        .unwrap_or_else(|error| panic!("Dialogue file {dialogue_path} could not be read: {error}"));
+    let dialogues = DialogueManager::from_json(&dialogue_data)
+        .unwrap_or_else(|error| panic!("Failed to load dialogue file {dialogue_path}: {error}"));
+    let quest_data = std::fs::read(&quest_path)
+        .unwrap_or_else(|error| panic!("Quest file {quest_path} could not be read: {error}"));
+    let quests = QuestManager::from_json(&quest_data)
+        .unwrap_or_else(|error| panic!("Failed to load quest file {quest_path}: {error}"));
+    dialogues
+        .check_references(&world, &quests)
+        .unwrap_or_else(|error| panic!("Failed to load dialogue file {dialogue_path}: {error}"));
+    let mut game = HeadlessGame::new(&world, DEFAULT_VIEWPORT);
+    game.ui_state.dialogues = dialogues;
+    game.ui_state.quests = quests;
+
+    match (script_path, replay_path) {
+        (Some(script_path), None) => {
+            let script_data = std::fs::read(&script_path)
+                .unwrap_or_else(|error| panic!("Script {script_path} could not be read: {error}"));
+            let script = match InputScript::from_json(&script_data) {
+                Ok(script) => script,
+                Err(error) => {
+                    eprintln!("Failed to load script {script_path}: {error}");
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            if record_path.is_some() {
+                game.recorder = Some(InputRecorder::new(DEFAULT_TICKS_PER_SECOND));
+            }
+            if let Err(error) = game.run_script(&script) {
+                eprintln!("Failed to run script {script_path}: {error}");
+                return ExitCode::FAILURE;
+            }
+            if let (Some(record_path), Some(recorder)) = (record_path, &game.recorder) {
+                std::fs::write(&record_path, recorder.recording().to_json()).unwrap_or_else(
+                    |error| panic!("Recording {record_path} could not be written: {error}"),
+                );
+            }
+        }
+        (None, Some(replay_path)) => {
+            let recording_data = std::fs::read(&replay_path).unwrap_or_else(|error| {
+                panic!("Recording {replay_path} could not be read: {error}")
+            });
+            let recording = match InputRecording::from_json(&recording_data) {
+                Ok(recording) => recording,
+                Err(error) => {
+                    eprintln!("Failed to load recording {replay_path}: {error}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            if let Err(divergence) = game.replay(recording) {
+                eprintln!("Replay of {replay_path} failed: {divergence}");
+                return ExitCode::FAILURE;
+            }
+        }
+        _ => return usage(),
+    }
+
+    print_summary(&game);
+    ExitCode::SUCCESS
+}
+
+fn usage() -> ExitCode {
+    eprintln!("{USAGE}");
+    ExitCode::FAILURE
+}
+
+// Summary of the end state, so scripted runs can be compared
+fn print_summary(game: &HeadlessGame) {
+    let player = game
+        .game_state
+        .get_entity("player")
+        .expect("Player should exist");
+    println!("Ticks: {}", game.time.tick());
+    if let Some(position) = game.game_state.get_position(player) {
+        println!(
+            "Player position: {:.3} {:.3} {:.3}",
+            position.x, position.y, position.z
+        );
+    }
+    let mut inventory: Vec<String> = game
+        .game_state
+        .get_in_storages(player)
+        .keys()
+        .map(|item| {
+            game.game_state
+                .get_name(*item)
+                .map_or_else(|| item.to_string(), str::to_owned)
+        })
+        .collect();
+    inventory.sort();
+    println!("Inventory: {}", inventory.join(", "));
+    println!("Action text: {}", game.ui_state.action_text);
+    println!("Sounds: {}", game.audio.played_sounds.join(", "));
+    println!("State hash: {}", game.game_state.state_hash());
+}
@@ -1,8 +1,8 @@
+use crate::net::connection::ConnectionState;
 use crate::state::input::Input;
 use crate::state::ui_state::{RenderCommand, UIElement, UIState, UserAction};
+use crate::state::viewport::Viewport;
 use cgmath::Point2;
-use std::sync::Arc;
-use winit::window::Window;
 
 pub struct Gui {
     pub render_commands: Vec<RenderCommand>,
@@ -45,12 +45,12 @@ impl Gui {
 
     pub fn button_handle(
         &mut self,
-        window: &Arc<Window>,
+        viewport: &Viewport,
         mut ui_element: UIElement,
         input: &Input,
     ) -> UserAction {
         // let element_contains = ui_element.contains(input.mouse_position_ui, window);
-        let element_contains = ui_element.contains(input.mouse_position_ui, window);
+        let element_contains = ui_element.contains(input.mouse_position_ui, viewport);
         if element_contains && input.left_mouse_clicked.is_toggled_on() {
             return UserAction::LeftClick;
         }
@@ -97,5 +97,26 @@ impl Gui {
             &ui_state.selected_text,
             [0.8, 0.8, 0.0],
         );
+
+        if let Some(connection_text) = Self::connection_text(&ui_state.connection_state) {
+            self.text_render(
+                1000,
+                UIElement::new_rect(Point2::new(0.875, 0.05), Point2::new(0.1, 0.025)),
+                &connection_text,
+                [0.8, 0.8, 0.0],
+            );
+        }
+    }
+
+    // Only shown when something is up, there is no need to tell the player all is fine
+    fn connection_text(connection_state: &ConnectionState) -> Option<String> {
+        match connection_state {
+            ConnectionState::Offline | ConnectionState::Connected => None,
+            ConnectionState::Connecting => Some("Connecting...".to_owned()),
+            ConnectionState::Reconnecting { attempt } => {
+                Some(format!("Connection lost, reconnecting (attempt {attempt})"))
+            }
+            ConnectionState::Rejected { reason } => Some(reason.clone()),
+        }
     }
 }
@@ -0,0 +1,224 @@
+use crate::net::client::NetworkClient;
+use crate::net::transport::Transport;
+use crate::state::audio::RecordedAudio;
+use crate::state::game_state::GameState;
+use crate::state::input::Input;
+use crate::state::recording::{InputRecorder, InputRecording, InputReplayer, ReplayDivergence};
+use crate::state::time::{DEFAULT_TICKS_PER_SECOND, Time};
+use crate::state::ui_state::UIState;
+use crate::state::update_state::UpdateState;
+use crate::state::viewport::Viewport;
+use crate::state::world_definition::WorldDefinition;
+use crate::systems::game_system::GameSystem;
+use serde::Deserialize;
+use winit::dpi::PhysicalPosition;
+use winit::event::{ElementState, MouseButton};
+use winit::keyboard::KeyCode;
+
+pub const DEFAULT_VIEWPORT: Viewport = Viewport {
+    width: 1920,
+    height: 1080,
+};
+
+// Runs the game systems without window, GPU or audio device. Used for tests and to simulate on a server
+pub struct HeadlessGame {
+    pub viewport: Viewport,
+    pub time: Time,
+    pub game_state: GameState,
+    pub ui_state: UIState,
+    pub input: Input,
+    pub frame_state: UpdateState,
+    pub audio: RecordedAudio,
+    pub recorder: Option<InputRecorder>,
+    pub network_client: Option<NetworkClient<Box<dyn Transport>>>, // Plays against a server, over a socket or through the loopback in tests
+}
+
+// Input given by a script instead of a player. Keys use the winit key code names, mouse positions are in pixels of the viewport
+// Input only affects the game on the next tick, so wait at least one tick between pressing and releasing
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ScriptStep {
+    Press { key: String },
+    Release { key: String },
+    // Characters as the keyboard layout would give them, only reach the game while typing
+    Type { text: String },
+    MoveMouse { x: f64, y: f64 },
+    MousePress { button: ScriptMouseButton },
+    MouseRelease { button: ScriptMouseButton },
+    Wait { ticks: u32 },
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptMouseButton {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InputScript {
+    pub viewport: Option<Viewport>,
+    pub steps: Vec<ScriptStep>,
+}
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Json(serde_json::Error),
+    UnknownKey(String),
+}
+
+impl std::fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScriptError::Json(error) => write!(f, "invalid script: {error}"),
+            ScriptError::UnknownKey(key) => write!(f, "unknown key {key}"),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl From<serde_json::Error> for ScriptError {
+    fn from(error: serde_json::Error) -> Self {
+        ScriptError::Json(error)
+    }
+}
+
+impl InputScript {
+    pub fn from_json(bytes: &[u8]) -> Result<InputScript, ScriptError> {
+        let script: InputScript = serde_json::from_slice(bytes)?;
+        // Fail before running anything instead of halfway through the script
+        for step in &script.steps {
+            if let ScriptStep::Press { key } | ScriptStep::Release { key } = step {
+                Self::parse_key(key)?;
+            }
+        }
+        Ok(script)
+    }
+
+    // Only the keys the game listens to
+    fn parse_key(key: &str) -> Result<KeyCode, ScriptError> {
+        let key_code = match key {
+            "KeyW" => KeyCode::KeyW,
+            "KeyA" => KeyCode::KeyA,
+            "KeyS" => KeyCode::KeyS,
+            "KeyD" => KeyCode::KeyD,
+            "KeyE" => KeyCode::KeyE,
+            "KeyI" => KeyCode::KeyI,
+            "KeyL" => KeyCode::KeyL,
+            "KeyM" => KeyCode::KeyM,
+            "KeyR" => KeyCode::KeyR,
+            "ShiftLeft" => KeyCode::ShiftLeft,
+            "ArrowUp" => KeyCode::ArrowUp,
+            "ArrowDown" => KeyCode::ArrowDown,
+            "ArrowLeft" => KeyCode::ArrowLeft,
+            "ArrowRight" => KeyCode::ArrowRight,
+            "Enter" => KeyCode::Enter,
+            "Escape" => KeyCode::Escape,
+            "Backspace" => KeyCode::Backspace,
+            "Delete" => KeyCode::Delete,
+            "Home" => KeyCode::Home,
+            "End" => KeyCode::End,
+            "PageUp" => KeyCode::PageUp,
+            "PageDown" => KeyCode::PageDown,
+            _ => return Err(ScriptError::UnknownKey(key.to_owned())),
+        };
+        Ok(key_code)
+    }
+}
+
+impl HeadlessGame {
+    pub fn new(world: &WorldDefinition, viewport: Viewport) -> Self {
+        HeadlessGame {
+            viewport,
+            time: Time::new(DEFAULT_TICKS_PER_SECOND),
+            game_state: GameState::new(world),
+            ui_state: UIState::new(),
+            input: Input::new(),
+            frame_state: UpdateState::new(),
+            audio: RecordedAudio::default(),
+            recorder: None,
+            network_client: None,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.before_update(&self.viewport, &self.input);
+        }
+        if let Some(network_client) = &mut self.network_client {
+            network_client.receive(&self.time, &mut self.game_state, &mut self.ui_state);
+        }
+        GameSystem::update(
+            &self.viewport,
+            &self.time,
+            &mut self.game_state,
+            &mut self.ui_state,
+            &mut self.input,
+            &mut self.frame_state,
+            &mut self.audio,
+        );
+        if let Some(network_client) = &mut self.network_client {
+            network_client.send(&self.frame_state);
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.after_update(&self.game_state);
+        }
+        self.time.advance();
+    }
+
+    pub fn run_ticks(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.tick();
+        }
+    }
+
+    pub fn run_script(&mut self, script: &InputScript) -> Result<(), ScriptError> {
+        if let Some(viewport) = script.viewport {
+            self.viewport = viewport;
+        }
+        for step in &script.steps {
+            match step {
+                ScriptStep::Press { key } => self
+                    .input
+                    .update(InputScript::parse_key(key)?, ElementState::Pressed),
+                ScriptStep::Release { key } => self
+                    .input
+                    .update(InputScript::parse_key(key)?, ElementState::Released),
+                ScriptStep::Type { text } => self.input.process_text(text),
+                ScriptStep::MoveMouse { x, y } => self.input.process_mouse_movement(
+                    PhysicalPosition::new(*x, *y),
+                    self.viewport.width,
+                    self.viewport.height,
+                ),
+                ScriptStep::MousePress { button } => self
+                    .input
+                    .process_mouse_button(Self::mouse_button(*button), ElementState::Pressed),
+                ScriptStep::MouseRelease { button } => self
+                    .input
+                    .process_mouse_button(Self::mouse_button(*button), ElementState::Released),
+                ScriptStep::Wait { ticks } => self.run_ticks(*ticks),
+            }
+        }
+        Ok(())
+    }
+
+    // Should start from a fresh game, the recording starts at the first tick
+    pub fn replay(&mut self, recording: InputRecording) -> Result<(), ReplayDivergence> {
+        let mut replayer = InputReplayer::new(recording);
+        self.time = Time::new(replayer.ticks_per_second());
+        while !replayer.is_finished() {
+            replayer.before_update(&mut self.viewport, &mut self.input);
+            self.tick();
+            replayer.after_update(&self.game_state)?;
+        }
+        Ok(())
+    }
+
+    fn mouse_button(button: ScriptMouseButton) -> MouseButton {
+        match button {
+            ScriptMouseButton::Left => MouseButton::Left,
+            ScriptMouseButton::Right => MouseButton::Right,
+        }
+    }
+}
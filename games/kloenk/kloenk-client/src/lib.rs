@@ -0,0 +1,11 @@
+#[cfg(feature = "graphics")]
+mod application;
+mod gui;
+#[cfg(feature = "headless")]
+pub mod headless;
+pub mod net;
+mod render;
+#[cfg(feature = "graphics")]
+pub mod run;
+pub mod state;
+pub mod systems;
@@ -0,0 +1,577 @@
+use crate::net::connection::{Connection, ConnectionState, TrafficStats};
+use crate::net::prediction::MovementPrediction;
+use crate::net::protocol::{PlayerAction, ServerMessage};
+use crate::net::snapshot::{ReplicatedEntity, SnapshotDecoder};
+use crate::net::transport::Transport;
+use crate::state::components::Rotation;
+use crate::state::entity::EntityId;
+use crate::state::game_state::GameState;
+use crate::state::save_game::SavedEntity;
+use crate::state::time::Time;
+use crate::state::ui_state::{DialogueState, MenuState, UIState};
+use crate::state::update_state::UpdateState;
+use crate::systems::command_handle_system::CommandHandleSystem;
+
+// Plays the game against a server. We only hear about entities near our player. The server state replaces ours, except for the movement of our own player which is predicted
+pub struct NetworkClient<T: Transport> {
+    connection: Connection<T>,
+    prediction: MovementPrediction,
+    snapshots: SnapshotDecoder,
+    player: Option<EntityId>, // Known once the server welcomed us
+}
+
+impl<T: Transport> NetworkClient<T> {
+    pub fn new(connection: Connection<T>) -> Self {
+        NetworkClient {
+            connection,
+            prediction: MovementPrediction::new(),
+            snapshots: SnapshotDecoder::new(),
+            player: None,
+        }
+    }
+
+    pub fn connection_state(&self) -> &ConnectionState {
+        self.connection.state()
+    }
+
+    pub fn prediction(&self) -> &MovementPrediction {
+        &self.prediction
+    }
+
+    pub fn traffic(&self) -> TrafficStats {
+        self.connection.traffic()
+    }
+
+    // Before the game systems run, so they work on the latest state of the server
+    pub fn receive(&mut self, time: &Time, game_state: &mut GameState, ui_state: &mut UIState) {
+        for message in self.connection.update(time.elapsed()) {
+            match message {
+                ServerMessage::Welcome {
+                    player,
+                    tick,
+                    entities,
+                    ..
+                } => {
+                    self.snapshots.start(tick, &entities);
+                    let Some(new_game_state) = Self::load_server_entities(entities, player) else {
+                        continue;
+                    };
+                    self.connection.acknowledge(tick);
+                    *game_state = new_game_state;
+                    self.player = Some(player);
+                    self.prediction = MovementPrediction::new(); // The server starts counting again for every connection
+                    // Entities in the old state are gone
+                    ui_state.menu_state = MenuState::Closed;
+                    ui_state.dialogue_state = DialogueState::Closed;
+                }
+                ServerMessage::Snapshot {
+                    tick,
+                    last_processed_input,
+                    delta,
+                } => {
+                    let Some(player) = self.player else {
+                        continue;
+                    };
+                    match self.snapshots.decode(tick, delta) {
+                        Ok(snapshot) => {
+                            self.connection.acknowledge(tick);
+                            // Changes first, items dropped by an entity that is gone should not go with it
+                            Self::apply_changes(game_state, player, snapshot.changes);
+                            Self::apply_despawns(game_state, player, snapshot.despawns);
+                            Self::apply_spawns(game_state, player, snapshot.spawns);
+                            self.reconcile(time, game_state, player, last_processed_input);
+                        }
+                        // Not acknowledged, so the server keeps building on what we do have
+                        Err(_error) => {
+                            #[cfg(feature = "debug-logging")]
+                            log::error!("Failed to decode snapshot: {_error}");
+                        }
+                    }
+                }
+                ServerMessage::ActionEffects { effects } => {
+                    CommandHandleSystem::show_action_effects(ui_state, &effects);
+                }
+                ServerMessage::Chat { message } => ui_state.chat.push(message),
+                // TODO open dialogues from the server
+                ServerMessage::DialogueStarted { .. } | ServerMessage::Rejected { .. } => {}
+            }
+        }
+
+        if self.connection.state() == &ConnectionState::Connected
+            && let Some(player) = self.player
+        {
+            self.prediction.smooth(game_state, player);
+        }
+        if ui_state.connection_state != *self.connection.state() {
+            ui_state.connection_state = self.connection.state().clone();
+        }
+    }
+
+    // After the game systems ran, with the movement they already applied this tick
+    pub fn send(&mut self, frame_state: &UpdateState) {
+        if self.player.is_none() || self.connection.state() != &ConnectionState::Connected {
+            return;
+        }
+        let action = self.prediction.predicted(
+            frame_state.movement_input,
+            frame_state.item_transactions.clone(),
+        );
+        self.connection.send(action);
+        for (channel, text) in &frame_state.chat_messages {
+            self.connection.send(PlayerAction::Chat {
+                channel: channel.clone(),
+                text: text.clone(),
+            });
+        }
+        for npc in &frame_state.talked_to {
+            self.connection.send(PlayerAction::Talk { npc: *npc });
+        }
+        for choice in &frame_state.dialogue_choices {
+            self.connection.send(PlayerAction::DialogueChoice {
+                npc: choice.npc,
+                node_id: choice.node_id.clone(),
+                choice: choice.choice,
+            });
+        }
+    }
+
+    // Our player goes by the name the game systems look for
+    fn load_server_entities(entities: Vec<SavedEntity>, player: EntityId) -> Option<GameState> {
+        let mut game_state = GameState::empty();
+        for entity in entities {
+            game_state.load_saved_entity(entity);
+        }
+        if !game_state.is_alive(player) {
+            #[cfg(feature = "debug-logging")]
+            log::error!("Server state does not contain our player");
+            return None;
+        }
+        game_state.set_name(player, "player");
+        Some(game_state)
+    }
+
+    // Entities that went out of view, or are gone on the server. Items stored in them go with them
+    fn apply_despawns(game_state: &mut GameState, player: EntityId, despawns: Vec<EntityId>) {
+        for entity in despawns {
+            if entity != player {
+                game_state.despawn(entity);
+            }
+        }
+    }
+
+    // Entities that came into view. The camera stays ours
+    fn apply_spawns(game_state: &mut GameState, player: EntityId, spawns: Vec<SavedEntity>) {
+        for spawn in spawns {
+            if spawn.entity == player || game_state.camera_components.contains_key(&spawn.entity) {
+                continue;
+            }
+            game_state.load_saved_entity(spawn);
+        }
+    }
+
+    // Our player moves by prediction, only the rest of it is taken over
+    fn apply_changes(
+        game_state: &mut GameState,
+        player: EntityId,
+        changes: Vec<(EntityId, ReplicatedEntity)>,
+    ) {
+        for (entity, replicated) in changes {
+            if !game_state.is_alive(entity) {
+                continue;
+            }
+            if entity != player {
+                match replicated.position {
+                    Some(position) => game_state.create_position(entity, position.to_position()),
+                    None => game_state.remove_position(entity),
+                }
+                if let Some(degrees_y) = replicated.rotation_degrees_y {
+                    game_state.set_rotation(entity, Rotation { degrees_y });
+                }
+            }
+            match replicated.in_storage {
+                Some(in_storage) => {
+                    game_state.in_storage_components.insert(entity, in_storage);
+                }
+                None => game_state.remove_in_storage(entity),
+            }
+            match replicated.health {
+                Some(health) => {
+                    game_state.health_components.insert(entity, health);
+                }
+                None => {
+                    game_state.health_components.remove(&entity);
+                }
+            }
+            match replicated.flags {
+                Some(flags) => {
+                    game_state.flag_components.insert(entity, flags);
+                }
+                None => {
+                    game_state.flag_components.remove(&entity);
+                }
+            }
+            match replicated.quests {
+                Some(quests) => {
+                    game_state.quest_components.insert(entity, quests);
+                }
+                None => {
+                    game_state.quest_components.remove(&entity);
+                }
+            }
+        }
+    }
+
+    // Every snapshot, also when our player did not change: the server might have applied more of our inputs
+    fn reconcile(
+        &mut self,
+        time: &Time,
+        game_state: &mut GameState,
+        player: EntityId,
+        last_processed_input: Option<u32>,
+    ) {
+        let Some(replicated) = self
+            .snapshots
+            .latest()
+            .and_then(|snapshot| snapshot.get(&player))
+        else {
+            return;
+        };
+        if let Some(position) = replicated.position {
+            let rotation = replicated
+                .rotation_degrees_y
+                .map(|degrees_y| Rotation { degrees_y });
+            self.prediction.reconcile(
+                time,
+                game_state,
+                player,
+                last_processed_input,
+                position.to_position(),
+                rotation,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::loopback::{LoopbackServer, LoopbackTransport};
+    use crate::net::protocol::ClientMessage;
+    use crate::net::snapshot::SnapshotEncoder;
+    use crate::state::audio::SilentAudio;
+    use crate::state::time::DEFAULT_TICKS_PER_SECOND;
+    use crate::state::world_definition::WorldDefinition;
+    use crate::systems::movement_system::{MovementInput, MovementSystem};
+    use cgmath::{MetricSpace, Point3};
+    use std::collections::{HashSet, VecDeque};
+
+    const VIEW_DISTANCE: f32 = 8.0; // Plenty of floor to walk around on
+    const POSITION_PRECISION: f32 = 0.01; // Snapshots round positions
+    const MAX_CORRECTION: f32 = 0.05; // Two ticks of running
+    const STAND_STILL: MovementInput = MovementInput {
+        direction: None,
+        is_running: false,
+        max_distance: None,
+    };
+    const RUN_EAST: MovementInput = MovementInput {
+        direction: Some(std::f32::consts::FRAC_PI_2),
+        is_running: true,
+        max_distance: None,
+    };
+
+    // The server side, played by hand. Applies one move per tick like the real server, and sends the area around the player as snapshots
+    // Messages can be held back for a number of ticks each way, and some moves and snapshots can be lost
+    struct TestServer {
+        loopback: LoopbackServer,
+        latency_ticks: u64,
+        lose_one_in: Option<u64>,
+        incoming: VecDeque<(u64, ClientMessage)>, // With the tick they arrive in
+        outgoing: VecDeque<(u64, ServerMessage)>,
+        time: Time,
+        game_state: GameState,
+        player: EntityId,
+        in_view: HashSet<EntityId>,
+        snapshots: SnapshotEncoder,
+        inputs: VecDeque<(u32, MovementInput)>,
+        last_processed_input: Option<u32>,
+    }
+
+    impl TestServer {
+        fn new(loopback: LoopbackServer) -> TestServer {
+            let world = WorldDefinition::from_json(include_bytes!("../../assets/world.json"))
+                .expect("World should load");
+            let game_state = GameState::new(&world);
+            let player = game_state
+                .get_entity("player")
+                .expect("World should have a player");
+            let start = *game_state.get_position(player).unwrap();
+            let in_view = game_state
+                .position_components
+                .iter()
+                .filter(|(_, position)| position.distance(start) < VIEW_DISTANCE)
+                .map(|(entity, _)| *entity)
+                .collect();
+            TestServer {
+                loopback,
+                latency_ticks: 0,
+                lose_one_in: None,
+                incoming: VecDeque::new(),
+                outgoing: VecDeque::new(),
+                time: Time::new(DEFAULT_TICKS_PER_SECOND),
+                game_state,
+                player,
+                in_view,
+                snapshots: SnapshotEncoder::new(),
+                inputs: VecDeque::new(),
+                last_processed_input: None,
+            }
+        }
+
+        fn position(&self) -> Point3<f32> {
+            *self.game_state.get_position(self.player).unwrap()
+        }
+
+        fn is_lost(&self, number: u64) -> bool {
+            self.lose_one_in
+                .is_some_and(|lose_one_in| number % lose_one_in == lose_one_in - 1)
+        }
+
+        fn handle(&mut self, message: ClientMessage) {
+            match message {
+                ClientMessage::Hello { .. } => self.welcome(),
+                ClientMessage::Action(PlayerAction::Move {
+                    sequence, movement, ..
+                }) => {
+                    if !self.is_lost(u64::from(sequence)) {
+                        self.inputs.push_back((sequence, movement));
+                    }
+                }
+                ClientMessage::Ack { tick } => self.snapshots.acknowledge(tick),
+                ClientMessage::Action(_) => {}
+            }
+        }
+
+        // Every connection starts over, like it does on the real server
+        fn welcome(&mut self) {
+            self.inputs.clear();
+            self.last_processed_input = None;
+            let entities = self
+                .snapshots
+                .start(self.time.tick(), &self.game_state, &self.in_view);
+            self.send(ServerMessage::Welcome {
+                player: self.player,
+                tick: self.time.tick(),
+                ticks_per_second: DEFAULT_TICKS_PER_SECOND,
+                entities,
+            });
+        }
+
+        fn send(&mut self, message: ServerMessage) {
+            let arrives_in = self.time.tick() + self.latency_ticks;
+            self.outgoing.push_back((arrives_in, message));
+        }
+
+        fn tick(&mut self) {
+            let tick = self.time.tick();
+            while let Some(message) = self.loopback.receive() {
+                self.incoming
+                    .push_back((tick + self.latency_ticks, message));
+            }
+            while let Some((_, message)) = self
+                .incoming
+                .pop_front_if(|(arrives_in, _)| *arrives_in <= tick)
+            {
+                self.handle(message);
+            }
+            if let Some((sequence, movement)) = self.inputs.pop_front() {
+                MovementSystem::move_character(
+                    &self.time,
+                    &mut self.game_state,
+                    self.player,
+                    &movement,
+                    &mut SilentAudio {},
+                );
+                self.last_processed_input = Some(sequence);
+            }
+            self.time.advance();
+            if self.loopback.is_connected() {
+                let delta =
+                    self.snapshots
+                        .encode(self.time.tick(), &self.game_state, &self.in_view);
+                let snapshot = ServerMessage::Snapshot {
+                    tick: self.time.tick(),
+                    last_processed_input: self.last_processed_input,
+                    delta,
+                };
+                if !self.is_lost(self.time.tick()) {
+                    self.send(snapshot);
+                }
+            }
+            while let Some((_, message)) = self
+                .outgoing
+                .pop_front_if(|(arrives_in, _)| *arrives_in <= self.time.tick())
+            {
+                self.loopback.send(&message);
+            }
+        }
+    }
+
+    // A game tick with only the movement system running
+    struct TestClient {
+        network_client: NetworkClient<LoopbackTransport>,
+        time: Time,
+        game_state: GameState,
+        ui_state: UIState,
+        frame_state: UpdateState,
+    }
+
+    impl TestClient {
+        fn tick(&mut self, movement: MovementInput) {
+            self.network_client
+                .receive(&self.time, &mut self.game_state, &mut self.ui_state);
+            if let Some(player) = self.game_state.get_entity("player") {
+                MovementSystem::move_character(
+                    &self.time,
+                    &mut self.game_state,
+                    player,
+                    &movement,
+                    &mut SilentAudio {},
+                );
+            }
+            self.frame_state.movement_input = movement;
+            self.network_client.send(&self.frame_state);
+            self.time.advance();
+        }
+
+        fn position(&self) -> Point3<f32> {
+            let player = self.game_state.get_entity("player").unwrap();
+            *self.game_state.get_position(player).unwrap()
+        }
+
+        fn is_connected(&self) -> bool {
+            self.network_client.connection_state() == &ConnectionState::Connected
+        }
+    }
+
+    fn connect() -> (TestClient, TestServer) {
+        let (transport, loopback) = LoopbackTransport::pair();
+        let client = TestClient {
+            network_client: NetworkClient::new(Connection::new(transport, "Alice")),
+            time: Time::new(DEFAULT_TICKS_PER_SECOND),
+            game_state: GameState::empty(),
+            ui_state: UIState::new(),
+            frame_state: UpdateState::new(),
+        };
+        (client, TestServer::new(loopback))
+    }
+
+    fn play(client: &mut TestClient, server: &mut TestServer, movement: MovementInput, ticks: u32) {
+        for _ in 0..ticks {
+            client.tick(movement);
+            server.tick();
+        }
+    }
+
+    fn play_until_connected(client: &mut TestClient, server: &mut TestServer) {
+        for _ in 0..DEFAULT_TICKS_PER_SECOND * 2 {
+            if client.is_connected() {
+                return;
+            }
+            play(client, server, STAND_STILL, 1);
+        }
+        panic!("Did not connect");
+    }
+
+    #[test]
+    fn welcome_starts_prediction_over() {
+        let (mut client, mut server) = connect();
+        play(&mut client, &mut server, STAND_STILL, 2);
+        assert!(client.is_connected());
+        assert_eq!(client.ui_state.connection_state, ConnectionState::Connected);
+
+        // The server goes away before applying any of these
+        for _ in 0..60 {
+            client.tick(RUN_EAST);
+        }
+        assert!(client.position().distance(server.position()) > 0.5);
+        server.loopback.disconnect();
+        while server.loopback.receive().is_some() {} // Lost with the connection
+        client.tick(STAND_STILL);
+        assert!(!client.is_connected());
+
+        play_until_connected(&mut client, &mut server);
+        assert!(client.position().distance(server.position()) < POSITION_PRECISION);
+
+        // Inputs from before are forgotten, so they are not replayed on top of what the server says and numbering starts over
+        play(&mut client, &mut server, STAND_STILL, 10);
+        assert_eq!(server.last_processed_input, Some(10)); // Also one from the tick we were welcomed in
+        assert!(client.position().distance(server.position()) < POSITION_PRECISION);
+        assert_eq!(
+            client.network_client.prediction().correction_distance(),
+            0.0
+        );
+    }
+
+    fn run(direction: f32) -> MovementInput {
+        MovementInput {
+            direction: Some(direction),
+            ..RUN_EAST
+        }
+    }
+
+    // Runs a square around the start, a second per side, and gives the largest correction on the way
+    fn run_square(client: &mut TestClient, server: &mut TestServer) -> f32 {
+        let mut max_correction: f32 = 0.0;
+        for side in 0..4 {
+            let direction = side as f32 * std::f32::consts::FRAC_PI_2;
+            for _ in 0..DEFAULT_TICKS_PER_SECOND {
+                play(client, server, run(direction), 1);
+                let correction = client.network_client.prediction().correction_distance();
+                max_correction = max_correction.max(correction);
+            }
+        }
+        max_correction
+    }
+
+    fn connect_with(latency_ticks: u64, lose_one_in: Option<u64>) -> (TestClient, TestServer) {
+        let (mut client, mut server) = connect();
+        server.latency_ticks = latency_ticks;
+        server.lose_one_in = lose_one_in;
+        play_until_connected(&mut client, &mut server);
+        (client, server)
+    }
+
+    #[test]
+    fn latency_alone_needs_no_corrections() {
+        let (mut client, mut server) = connect_with(6, None); // 100 ms each way
+
+        assert_eq!(run_square(&mut client, &mut server), 0.0);
+        play(&mut client, &mut server, STAND_STILL, 20);
+        assert!(client.position().distance(server.position()) < POSITION_PRECISION);
+    }
+
+    #[test]
+    fn corrections_for_lost_moves_stay_small_and_settle() {
+        let (mut client, mut server) = connect_with(6, Some(7));
+
+        // Every lost move puts the server a tick of running behind, that should be all
+        let max_correction = run_square(&mut client, &mut server);
+        assert!(max_correction > 0.0, "Nothing was lost");
+        assert!(
+            max_correction < MAX_CORRECTION,
+            "Corrected by {max_correction}"
+        );
+
+        play(
+            &mut client,
+            &mut server,
+            STAND_STILL,
+            DEFAULT_TICKS_PER_SECOND,
+        );
+        assert_eq!(
+            client.network_client.prediction().correction_distance(),
+            0.0
+        );
+        assert!(client.position().distance(server.position()) < POSITION_PRECISION);
+    }
+}
@@ -0,0 +1,330 @@
+use crate::net::protocol::{
+    ClientMessage, PROTOCOL_VERSION, PlayerAction, ProtocolError, ServerMessage,
+};
+use crate::net::transport::{Transport, TransportEvent};
+use std::time::Duration;
+
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_millis(500);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ConnectionState {
+    // Not playing online
+    Offline,
+    Connecting,
+    // The server welcomed us
+    Connected,
+    Reconnecting { attempt: u32 },
+    // The server does not want us, trying again would not change that
+    Rejected { reason: String },
+}
+
+// What went over a connection, to keep an eye on how much bandwidth the game uses
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct TrafficStats {
+    pub messages_sent: u64,
+    pub bytes_sent: u64,
+    pub messages_received: u64,
+    pub bytes_received: u64,
+}
+
+impl TrafficStats {
+    pub fn record_sent(&mut self, bytes: usize) {
+        self.messages_sent += 1;
+        self.bytes_sent += bytes as u64;
+    }
+
+    pub fn record_received(&mut self, bytes: usize) {
+        self.messages_received += 1;
+        self.bytes_received += bytes as u64;
+    }
+}
+
+// Keeps a connection to the server up. When it drops we try again, waiting longer after every failed attempt so a server that is down is not flooded
+pub struct Connection<T: Transport> {
+    transport: T,
+    player_name: String,
+    state: ConnectionState,
+    failed_attempts: u32,
+    reconnect_at: Duration,
+    traffic: TrafficStats, // Over all connection attempts
+}
+
+impl<T: Transport> Connection<T> {
+    pub fn new(mut transport: T, player_name: &str) -> Self {
+        transport.connect();
+        Connection {
+            transport,
+            player_name: player_name.to_owned(),
+            state: ConnectionState::Connecting,
+            failed_attempts: 0,
+            reconnect_at: Duration::ZERO,
+            traffic: TrafficStats::default(),
+        }
+    }
+
+    pub fn state(&self) -> &ConnectionState {
+        &self.state
+    }
+
+    pub fn traffic(&self) -> TrafficStats {
+        self.traffic
+    }
+
+    // Actions done while not connected are lost, the server would not know what to do with them after reconnecting anyway
+    pub fn send(&mut self, action: PlayerAction) {
+        if self.state == ConnectionState::Connected {
+            self.send_message(&ClientMessage::Action(action));
+        }
+    }
+
+    // Tells the server which snapshots we have, so it can send the next ones as changes to them
+    pub fn acknowledge(&mut self, tick: u64) {
+        if self.state == ConnectionState::Connected {
+            self.send_message(&ClientMessage::Ack { tick });
+        }
+    }
+
+    fn send_message(&mut self, message: &ClientMessage) {
+        let bytes = message.encode();
+        self.traffic.record_sent(bytes.len());
+        self.transport.send(bytes);
+    }
+
+    // Called every tick with the current time. Returns everything the server sent since the last update
+    pub fn update(&mut self, now: Duration) -> Vec<ServerMessage> {
+        if matches!(self.state, ConnectionState::Reconnecting { .. }) && now >= self.reconnect_at {
+            self.state = ConnectionState::Connecting;
+            self.transport.connect();
+        }
+
+        let mut messages = Vec::new();
+        for event in self.transport.poll() {
+            match event {
+                TransportEvent::Opened => {
+                    let hello = ClientMessage::Hello {
+                        protocol_version: PROTOCOL_VERSION,
+                        name: self.player_name.clone(),
+                    };
+                    self.send_message(&hello);
+                }
+                TransportEvent::Message(bytes) => match self.decode(&bytes) {
+                    Ok(ServerMessage::Rejected { reason }) => {
+                        #[cfg(feature = "debug-logging")]
+                        log::warn!("Server rejected us: {reason}");
+                        self.transport.close();
+                        self.state = ConnectionState::Rejected { reason };
+                        break;
+                    }
+                    Ok(message) => {
+                        if let ServerMessage::Welcome { .. } = message {
+                            self.state = ConnectionState::Connected;
+                            self.failed_attempts = 0;
+                        }
+                        messages.push(message);
+                    }
+                    Err(_error) => {
+                        // Probably a server on another version. Reconnecting is fine, it will send a rejection if so
+                        #[cfg(feature = "debug-logging")]
+                        log::error!("Server sent a message we do not understand: {_error}");
+                        self.transport.close();
+                        self.schedule_reconnect(now);
+                        break;
+                    }
+                },
+                TransportEvent::Closed => {
+                    self.schedule_reconnect(now);
+                    break;
+                }
+            }
+        }
+        messages
+    }
+
+    fn decode(&mut self, bytes: &[u8]) -> Result<ServerMessage, ProtocolError> {
+        self.traffic.record_received(bytes.len());
+        ServerMessage::decode(bytes)
+    }
+
+    fn schedule_reconnect(&mut self, now: Duration) {
+        self.failed_attempts += 1;
+        let delay = INITIAL_RECONNECT_DELAY
+            .saturating_mul(1 << (self.failed_attempts - 1).min(16))
+            .min(MAX_RECONNECT_DELAY);
+        self.reconnect_at = now + delay;
+        self.state = ConnectionState::Reconnecting {
+            attempt: self.failed_attempts,
+        };
+        #[cfg(feature = "debug-logging")]
+        log::info!(
+            "Lost connection to server, reconnecting in {} seconds",
+            delay.as_secs_f32()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::loopback::{LoopbackServer, LoopbackTransport};
+    use crate::state::game_state::GameState;
+    use crate::systems::movement_system::MovementInput;
+
+    const PLAYER_NAME: &str = "Alice";
+    const STEP: Duration = Duration::from_millis(100);
+
+    fn connect() -> (Connection<LoopbackTransport>, LoopbackServer) {
+        let (transport, server) = LoopbackTransport::pair();
+        (Connection::new(transport, PLAYER_NAME), server)
+    }
+
+    fn welcome() -> ServerMessage {
+        ServerMessage::Welcome {
+            player: GameState::empty().spawn(),
+            tick: 0,
+            ticks_per_second: 60,
+            entities: Vec::new(),
+        }
+    }
+
+    fn action() -> PlayerAction {
+        PlayerAction::Move {
+            sequence: 0,
+            movement: MovementInput::default(),
+            item_transactions: Vec::new(),
+        }
+    }
+
+    fn assert_hello(server: &mut LoopbackServer) {
+        match server.receive() {
+            Some(ClientMessage::Hello {
+                protocol_version,
+                name,
+            }) => {
+                assert_eq!(protocol_version, PROTOCOL_VERSION);
+                assert_eq!(name, PLAYER_NAME);
+            }
+            other => panic!("Expected a hello, got {other:?}"),
+        }
+    }
+
+    // Updates every step until the connection is in the expected state, giving the time that happened
+    fn update_until(
+        connection: &mut Connection<LoopbackTransport>,
+        now: &mut Duration,
+        state: &ConnectionState,
+    ) -> Duration {
+        let give_up_at = *now + MAX_RECONNECT_DELAY;
+        while *now <= give_up_at {
+            connection.update(*now);
+            if connection.state() == state {
+                return *now;
+            }
+            *now += STEP;
+        }
+        panic!("Still {:?} instead of {state:?}", connection.state());
+    }
+
+    #[test]
+    fn says_hello_and_is_connected_once_welcomed() {
+        let (mut connection, mut server) = connect();
+        assert_eq!(connection.state(), &ConnectionState::Connecting);
+
+        connection.update(Duration::ZERO);
+        assert_hello(&mut server);
+        connection.send(action());
+        assert!(
+            server.receive().is_none(),
+            "Actions before the welcome are dropped"
+        );
+
+        server.send(&welcome());
+        let messages = connection.update(STEP);
+        assert!(matches!(messages[..], [ServerMessage::Welcome { .. }]));
+        assert_eq!(connection.state(), &ConnectionState::Connected);
+        connection.send(action());
+        assert!(matches!(
+            server.receive(),
+            Some(ClientMessage::Action(PlayerAction::Move { .. }))
+        ));
+    }
+
+    #[test]
+    fn refused_connections_are_retried_less_and_less_often() {
+        let (transport, mut server) = LoopbackTransport::pair();
+        server.set_accepting(false);
+        let mut connection = Connection::new(transport, PLAYER_NAME);
+
+        let mut now = Duration::ZERO;
+        let mut attempts_at = Vec::new();
+        for attempt in 1..=9 {
+            let state = ConnectionState::Reconnecting { attempt };
+            attempts_at.push(update_until(&mut connection, &mut now, &state));
+        }
+        let delays: Vec<Duration> = attempts_at
+            .windows(2)
+            .map(|attempts| attempts[1] - attempts[0])
+            .collect();
+        let expected_seconds = [0.5, 1.0, 2.0, 4.0, 8.0, 16.0, 30.0, 30.0];
+        assert_eq!(
+            delays,
+            expected_seconds.map(Duration::from_secs_f32).to_vec()
+        );
+        assert!(!server.is_connected());
+
+        // Once the server is back we get in on the next attempt, and a later drop starts over with a short wait
+        server.set_accepting(true);
+        update_until(&mut connection, &mut now, &ConnectionState::Connecting);
+        assert_hello(&mut server);
+        server.send(&welcome());
+        connection.update(now);
+        assert_eq!(connection.state(), &ConnectionState::Connected);
+
+        let disconnected_at = now;
+        server.disconnect();
+        connection.update(now);
+        assert_eq!(
+            connection.state(),
+            &ConnectionState::Reconnecting { attempt: 1 }
+        );
+        let reconnected_at = update_until(&mut connection, &mut now, &ConnectionState::Connecting);
+        assert_eq!(reconnected_at - disconnected_at, INITIAL_RECONNECT_DELAY);
+        assert_hello(&mut server);
+    }
+
+    #[test]
+    fn rejected_connections_are_not_retried() {
+        let (mut connection, mut server) = connect();
+        connection.update(Duration::ZERO);
+        assert_hello(&mut server);
+
+        let reason = "Wrong version".to_owned();
+        server.send(&ServerMessage::Rejected {
+            reason: reason.clone(),
+        });
+        assert!(connection.update(STEP).is_empty());
+        assert!(!server.is_connected());
+
+        connection.update(MAX_RECONNECT_DELAY * 2);
+        assert_eq!(connection.state(), &ConnectionState::Rejected { reason });
+        assert!(server.receive().is_none());
+    }
+
+    #[test]
+    fn messages_we_do_not_understand_lead_to_a_reconnect() {
+        let (mut connection, mut server) = connect();
+        connection.update(Duration::ZERO);
+        assert_hello(&mut server);
+
+        server.send_bytes(vec![255; 4]);
+        connection.update(STEP);
+        assert!(!server.is_connected());
+        assert_eq!(
+            connection.state(),
+            &ConnectionState::Reconnecting { attempt: 1 }
+        );
+        connection.update(STEP + INITIAL_RECONNECT_DELAY);
+        assert_hello(&mut server);
+        assert_eq!(connection.traffic().messages_received, 1);
+    }
+}
@@ -0,0 +1,106 @@
+use crate::net::protocol::{ClientMessage, ServerMessage};
+use crate::net::transport::{Transport, TransportEvent};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+#[derive(Default)]
+struct LoopbackChannel {
+    is_accepting: bool,
+    is_open: bool,
+    to_server: VecDeque<Vec<u8>>,
+    to_client: Vec<TransportEvent>,
+}
+
+// In-process transport for tests. The server end is driven by hand, no sockets or threads involved
+pub struct LoopbackTransport {
+    channel: Rc<RefCell<LoopbackChannel>>,
+}
+
+pub struct LoopbackServer {
+    channel: Rc<RefCell<LoopbackChannel>>,
+}
+
+impl LoopbackTransport {
+    // The server accepts connections until told otherwise
+    pub fn pair() -> (LoopbackTransport, LoopbackServer) {
+        let channel = Rc::new(RefCell::new(LoopbackChannel {
+            is_accepting: true,
+            ..LoopbackChannel::default()
+        }));
+        (
+            LoopbackTransport {
+                channel: channel.clone(),
+            },
+            LoopbackServer { channel },
+        )
+    }
+}
+
+impl Transport for LoopbackTransport {
+    fn connect(&mut self) {
+        let mut channel = self.channel.borrow_mut();
+        channel.to_server.clear();
+        channel.to_client.clear();
+        channel.is_open = channel.is_accepting;
+        let event = if channel.is_open {
+            TransportEvent::Opened
+        } else {
+            TransportEvent::Closed
+        };
+        channel.to_client.push(event);
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        std::mem::take(&mut self.channel.borrow_mut().to_client)
+    }
+
+    fn send(&mut self, bytes: Vec<u8>) {
+        let mut channel = self.channel.borrow_mut();
+        if channel.is_open {
+            channel.to_server.push_back(bytes);
+        }
+    }
+
+    fn close(&mut self) {
+        let mut channel = self.channel.borrow_mut();
+        channel.is_open = false;
+        channel.to_client.clear();
+    }
+}
+
+impl LoopbackServer {
+    // Only affects new connections, like a server going down without closing its sockets
+    pub fn set_accepting(&mut self, is_accepting: bool) {
+        self.channel.borrow_mut().is_accepting = is_accepting;
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.channel.borrow().is_open
+    }
+
+    pub fn receive(&mut self) -> Option<ClientMessage> {
+        let bytes = self.channel.borrow_mut().to_server.pop_front()?;
+        Some(ClientMessage::decode(&bytes).expect("Client should send valid messages"))
+    }
+
+    pub fn send(&mut self, message: &ServerMessage) {
+        self.send_bytes(message.encode());
+    }
+
+    // For sending messages the client should not understand
+    pub fn send_bytes(&mut self, bytes: Vec<u8>) {
+        let mut channel = self.channel.borrow_mut();
+        if channel.is_open {
+            channel.to_client.push(TransportEvent::Message(bytes));
+        }
+    }
+
+    pub fn disconnect(&mut self) {
+        let mut channel = self.channel.borrow_mut();
+        if channel.is_open {
+            channel.is_open = false;
+            channel.to_client.push(TransportEvent::Closed);
+        }
+    }
+}
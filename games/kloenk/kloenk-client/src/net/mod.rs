@@ -0,0 +1,19 @@
+pub mod client;
+pub mod connection;
+pub mod loopback;
+pub mod prediction;
+pub mod protocol;
+pub mod snapshot;
+pub mod transport;
+#[cfg(not(target_family = "wasm"))]
+#[path = "websocket_transport_native.rs"]
+mod websocket_transport_native;
+
+#[cfg(target_family = "wasm")]
+#[path = "websocket_transport_web.rs"]
+mod websocket_transport_web;
+
+#[cfg(not(target_family = "wasm"))]
+pub use websocket_transport_native::WebSocketTransport;
+#[cfg(target_family = "wasm")]
+pub use websocket_transport_web::WebSocketTransport;
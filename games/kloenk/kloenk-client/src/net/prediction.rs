@@ -0,0 +1,126 @@
+use crate::net::protocol::PlayerAction;
+use crate::state::audio::SilentAudio;
+use crate::state::components::Rotation;
+use crate::state::entity::EntityId;
+use crate::state::game_state::GameState;
+use crate::state::time::Time;
+use crate::systems::item_transaction_system::ItemTransaction;
+use crate::systems::movement_system::{MovementInput, MovementSystem};
+use cgmath::{InnerSpace, Point3, Vector3, Zero};
+use std::collections::VecDeque;
+
+const MAX_UNACKNOWLEDGED_INPUTS: usize = 600; // Ten seconds of input. When the server is further behind than that, something else is wrong
+const CORRECTION_PER_TICK: f32 = 0.2; // Part of the remaining correction applied every tick
+const MIN_CORRECTION_DISTANCE: f32 = 0.001;
+const SNAP_CORRECTION_DISTANCE: f32 = 1.0; // Gliding over a larger distance looks worse than just jumping there
+
+// Moves the player right away instead of waiting for the server, then corrects when the server disagrees
+// https://www.gabrielgambetta.com/client-side-prediction-server-reconciliation.html
+pub struct MovementPrediction {
+    next_sequence: u32,
+    unacknowledged_inputs: VecDeque<(u32, MovementInput)>,
+    correction: Vector3<f32>,
+}
+
+impl Default for MovementPrediction {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MovementPrediction {
+    pub fn new() -> Self {
+        MovementPrediction {
+            next_sequence: 0,
+            unacknowledged_inputs: VecDeque::new(),
+            correction: Vector3::zero(),
+        }
+    }
+
+    // The movement system already applied the input locally. Returns the action telling the server to do the same
+    pub fn predicted(
+        &mut self,
+        movement: MovementInput,
+        item_transactions: Vec<ItemTransaction>,
+    ) -> PlayerAction {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.unacknowledged_inputs.push_back((sequence, movement));
+        if self.unacknowledged_inputs.len() > MAX_UNACKNOWLEDGED_INPUTS {
+            self.unacknowledged_inputs.pop_front();
+        }
+        PlayerAction::Move {
+            sequence,
+            movement,
+            item_transactions,
+        }
+    }
+
+    // Starts from where the server says the player was after the last input it applied, and replays the inputs it has not applied yet
+    // Where that ends up is where we should have been. The difference with where we are is made up over the next ticks by smooth
+    pub fn reconcile(
+        &mut self,
+        time: &Time,
+        game_state: &mut GameState,
+        player: EntityId,
+        last_processed_input: Option<u32>,
+        server_position: Point3<f32>,
+        server_rotation: Option<Rotation>,
+    ) {
+        if let Some(last_processed_input) = last_processed_input {
+            while self
+                .unacknowledged_inputs
+                .front()
+                .is_some_and(|(sequence, _)| *sequence <= last_processed_input)
+            {
+                self.unacknowledged_inputs.pop_front();
+            }
+        }
+        let Some(predicted_position) = game_state.get_position(player).copied() else {
+            return;
+        };
+
+        game_state.create_position(player, server_position);
+        if let Some(server_rotation) = server_rotation {
+            game_state.set_rotation(player, server_rotation);
+        }
+        for (_, movement) in &self.unacknowledged_inputs {
+            MovementSystem::move_character(time, game_state, player, movement, &mut SilentAudio {});
+        }
+
+        let corrected_position = *game_state
+            .get_position(player)
+            .expect("Player position should exist");
+        let correction = corrected_position - predicted_position;
+        let distance = correction.magnitude();
+        if (MIN_CORRECTION_DISTANCE..=SNAP_CORRECTION_DISTANCE).contains(&distance) {
+            // Stay where the player sees themselves for now
+            game_state.create_position(player, predicted_position);
+            self.correction = correction;
+        } else {
+            self.correction = Vector3::zero();
+        }
+    }
+
+    // Called every tick, moves the player a part of the way to where they should be
+    pub fn smooth(&mut self, game_state: &mut GameState, player: EntityId) {
+        if self.correction.is_zero() {
+            return;
+        }
+        let Some(position) = game_state.get_position(player).copied() else {
+            return;
+        };
+        let step = if self.correction.magnitude() < MIN_CORRECTION_DISTANCE {
+            self.correction
+        } else {
+            self.correction * CORRECTION_PER_TICK
+        };
+        self.correction -= step;
+        game_state.create_position(player, position + step);
+    }
+
+    // How far the shown position is from the position the server confirmed plus our inputs since
+    pub fn correction_distance(&self) -> f32 {
+        self.correction.magnitude()
+    }
+}
@@ -0,0 +1,137 @@
+use crate::net::snapshot::SnapshotDelta;
+use crate::state::chat::{ChatChannel, ChatMessage};
+use crate::state::entity::EntityId;
+use crate::state::save_game::SavedEntity;
+use crate::state::update_state::ActionEffect;
+use crate::systems::item_transaction_system::ItemTransaction;
+use crate::systems::movement_system::MovementInput;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+// Bump on any change to the messages below. Client and server have to be on the same version, older clients are turned away
+pub const PROTOCOL_VERSION: u32 = 10;
+pub const DEFAULT_SERVER_ADDRESS: &str = "127.0.0.1:7878";
+pub const MAX_CHAT_MESSAGE_LENGTH: usize = 200;
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024; // Messages come from the network, do not let a length in there make us allocate everything
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ClientMessage {
+    Hello { protocol_version: u32, name: String },
+    Action(PlayerAction),
+    // Received the snapshot of this tick, the server sends the next ones as changes to it
+    Ack { tick: u64 },
+}
+
+// Everything a player can do in the world. The server checks all of it, the client is not trusted
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum PlayerAction {
+    // Sent every tick, numbered so the server can tell in snapshots which ones it applied. The server applies one per tick
+    // Item transactions go along, so they happen at the same point in the movement as they did for the player
+    Move {
+        sequence: u32,
+        movement: MovementInput,
+        item_transactions: Vec<ItemTransaction>,
+    },
+    Examine {
+        entity: EntityId,
+    },
+    Talk {
+        npc: EntityId,
+    },
+    // The server checks length and language, and decides who hears it
+    Chat {
+        channel: ChatChannel,
+        text: String,
+    },
+    // Only sent for choices with effects. The server checks the conditions of the choice itself
+    DialogueChoice {
+        npc: EntityId,
+        node_id: String,
+        choice: usize,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ServerMessage {
+    // Holds only what the player can see, everything else comes in through snapshots once it is near
+    Welcome {
+        player: EntityId,
+        tick: u64,
+        ticks_per_second: u32,
+        entities: Vec<SavedEntity>,
+    },
+    Rejected {
+        reason: String,
+    },
+    // Sent every tick, with what changed in view of the receiving player. Includes the last move the server applied for them
+    Snapshot {
+        tick: u64,
+        last_processed_input: Option<u32>,
+        delta: SnapshotDelta,
+    },
+    ActionEffects {
+        effects: Vec<ActionEffect>,
+    },
+    DialogueStarted {
+        npc: EntityId,
+        dialogue_id: String,
+    },
+    Chat {
+        message: ChatMessage,
+    },
+}
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    Decode(bincode::error::DecodeError),
+    TrailingBytes(usize),
+}
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtocolError::Decode(error) => write!(f, "invalid message: {error}"),
+            ProtocolError::TrailingBytes(count) => {
+                write!(f, "message has {count} bytes left after decoding")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl ClientMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        encode(self)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, ProtocolError> {
+        decode(bytes)
+    }
+}
+
+impl ServerMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        encode(self)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, ProtocolError> {
+        decode(bytes)
+    }
+}
+
+// Every WebSocket message holds exactly one of these, so there is no need for length prefixes
+fn encode<T: Serialize>(message: &T) -> Vec<u8> {
+    bincode::serde::encode_to_vec(message, bincode::config::standard())
+        .expect("Message should be serializable")
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, ProtocolError> {
+    let config = bincode::config::standard().with_limit::<MAX_MESSAGE_SIZE>();
+    let (message, read) =
+        bincode::serde::decode_from_slice(bytes, config).map_err(ProtocolError::Decode)?;
+    if read != bytes.len() {
+        return Err(ProtocolError::TrailingBytes(bytes.len() - read));
+    }
+    Ok(message)
+}
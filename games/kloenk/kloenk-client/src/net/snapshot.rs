@@ -0,0 +1,834 @@
+use crate::state::components::{Flags, Health, InStorage, QuestLog};
+use crate::state::entity::EntityId;
+use crate::state::game_state::GameState;
+use crate::state::save_game::SavedEntity;
+use cgmath::Point3;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+const POSITION_STEPS_PER_UNIT: f32 = 1024.0; // Rounding stays well below the smallest correction of movement prediction
+const MAX_SNAPSHOT_HISTORY: usize = 120; // Two seconds. A client that takes longer to acknowledge gets full snapshots
+
+// What one player knows about the world at a tick
+pub type SnapshotState = BTreeMap<EntityId, ReplicatedEntity>;
+
+// Rounded to a grid, so moving a little gives small numbers, which take few bytes to send
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct QuantizedPosition {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl QuantizedPosition {
+    pub fn new(position: Point3<f32>) -> Self {
+        QuantizedPosition {
+            x: (position.x * POSITION_STEPS_PER_UNIT).round() as i32,
+            y: (position.y * POSITION_STEPS_PER_UNIT).round() as i32,
+            z: (position.z * POSITION_STEPS_PER_UNIT).round() as i32,
+        }
+    }
+
+    pub fn to_position(self) -> Point3<f32> {
+        Point3::new(
+            self.x as f32 / POSITION_STEPS_PER_UNIT,
+            self.y as f32 / POSITION_STEPS_PER_UNIT,
+            self.z as f32 / POSITION_STEPS_PER_UNIT,
+        )
+    }
+}
+
+// The components of an entity that change while playing, as the client gets to see them
+#[derive(Clone, PartialEq, Debug)]
+pub struct ReplicatedEntity {
+    pub position: Option<QuantizedPosition>,
+    pub rotation_degrees_y: Option<f32>,
+    pub in_storage: Option<InStorage>,
+    pub health: Option<Health>,
+    pub flags: Option<Flags>,
+    pub quests: Option<QuestLog>,
+}
+
+impl ReplicatedEntity {
+    pub fn from_game_state(game_state: &GameState, entity: EntityId) -> Self {
+        ReplicatedEntity {
+            position: game_state
+                .get_position(entity)
+                .map(|position| QuantizedPosition::new(*position)),
+            rotation_degrees_y: game_state
+                .get_rotation(entity)
+                .map(|rotation| rotation.degrees_y),
+            in_storage: game_state.in_storage_components.get(&entity).cloned(),
+            health: game_state.health_components.get(&entity).cloned(),
+            flags: game_state.flag_components.get(&entity).cloned(),
+            quests: game_state.quest_components.get(&entity).cloned(),
+        }
+    }
+
+    pub fn from_saved(saved: &SavedEntity) -> Self {
+        ReplicatedEntity {
+            position: saved.position.map(QuantizedPosition::new),
+            rotation_degrees_y: saved.rotation.as_ref().map(|rotation| rotation.degrees_y),
+            in_storage: saved.in_storage.clone(),
+            health: saved.health.clone(),
+            flags: saved.flags.clone(),
+            quests: saved.quests.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Change<T> {
+    Unchanged,
+    Set(T),
+    Removed,
+}
+
+impl<T: Clone + PartialEq> Change<T> {
+    fn between(old: &Option<T>, new: &Option<T>) -> Self {
+        match new {
+            _ if old == new => Change::Unchanged,
+            Some(new) => Change::Set(new.clone()),
+            None => Change::Removed,
+        }
+    }
+
+    fn apply(&self, value: &mut Option<T>) {
+        match self {
+            Change::Unchanged => {}
+            Change::Set(new) => *value = Some(new.clone()),
+            Change::Removed => *value = None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum PositionChange {
+    Unchanged,
+    // Relative to the position in the baseline
+    Moved { x: i32, y: i32, z: i32 },
+    // The entity had no position in the baseline, such as an item dropped from the inventory
+    Placed(QuantizedPosition),
+    Removed,
+}
+
+// Only the fields that differ from the baseline
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EntityDelta {
+    pub entity: EntityId,
+    pub position: PositionChange,
+    pub rotation_degrees_y: Change<f32>,
+    pub in_storage: Change<InStorage>,
+    pub health: Change<Health>,
+    pub flags: Change<Flags>,
+    pub quests: Change<QuestLog>,
+}
+
+impl EntityDelta {
+    fn between(entity: EntityId, old: &ReplicatedEntity, new: &ReplicatedEntity) -> Option<Self> {
+        if old == new {
+            return None;
+        }
+        let position = match (old.position, new.position) {
+            (old, new) if old == new => PositionChange::Unchanged,
+            (Some(old), Some(new)) => PositionChange::Moved {
+                x: new.x.wrapping_sub(old.x),
+                y: new.y.wrapping_sub(old.y),
+                z: new.z.wrapping_sub(old.z),
+            },
+            (_, Some(new)) => PositionChange::Placed(new),
+            (_, None) => PositionChange::Removed,
+        };
+        Some(EntityDelta {
+            entity,
+            position,
+            rotation_degrees_y: Change::between(&old.rotation_degrees_y, &new.rotation_degrees_y),
+            in_storage: Change::between(&old.in_storage, &new.in_storage),
+            health: Change::between(&old.health, &new.health),
+            flags: Change::between(&old.flags, &new.flags),
+            quests: Change::between(&old.quests, &new.quests),
+        })
+    }
+
+    fn apply(&self, replicated: &mut ReplicatedEntity) {
+        match self.position {
+            PositionChange::Unchanged => {}
+            PositionChange::Moved { x, y, z } => {
+                if let Some(position) = &mut replicated.position {
+                    position.x = position.x.wrapping_add(x);
+                    position.y = position.y.wrapping_add(y);
+                    position.z = position.z.wrapping_add(z);
+                }
+            }
+            PositionChange::Placed(position) => replicated.position = Some(position),
+            PositionChange::Removed => replicated.position = None,
+        }
+        self.rotation_degrees_y
+            .apply(&mut replicated.rotation_degrees_y);
+        self.in_storage.apply(&mut replicated.in_storage);
+        self.health.apply(&mut replicated.health);
+        self.flags.apply(&mut replicated.flags);
+        self.quests.apply(&mut replicated.quests);
+    }
+}
+
+// The world in view of a player, as changes to a snapshot the client acknowledged. Without a baseline everything in view is a spawn
+// Entities that came into view since the baseline are sent whole, the client might not have them
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SnapshotDelta {
+    pub baseline_tick: Option<u64>,
+    pub despawns: Vec<EntityId>,
+    pub spawns: Vec<SavedEntity>,
+    pub changes: Vec<EntityDelta>,
+}
+
+// What changed compared to the snapshot decoded before, which is what the game state of the client holds
+pub struct DecodedSnapshot {
+    pub despawns: Vec<EntityId>,
+    pub spawns: Vec<SavedEntity>,
+    pub changes: Vec<(EntityId, ReplicatedEntity)>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    MissingBaseline(u64),
+    UnknownEntity(EntityId),
+    EntityOutOfRange(EntityId),
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::MissingBaseline(tick) => {
+                write!(f, "snapshot builds on tick {tick}, which we do not have")
+            }
+            SnapshotError::UnknownEntity(entity) => {
+                write!(
+                    f,
+                    "snapshot changes entity {entity}, which is not in its baseline"
+                )
+            }
+            SnapshotError::EntityOutOfRange(entity) => {
+                write!(f, "snapshot spawns entity {entity}, past the entity limit")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+// Server side, one per player. Remembers what was sent until the client acknowledges it
+pub struct SnapshotEncoder {
+    sent: VecDeque<(u64, SnapshotState)>, // Oldest first, nothing older than the last acknowledged snapshot
+    acknowledged_tick: Option<u64>,
+    in_view_since: HashMap<EntityId, u64>,
+}
+
+impl Default for SnapshotEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SnapshotEncoder {
+    pub fn new() -> Self {
+        SnapshotEncoder {
+            sent: VecDeque::new(),
+            acknowledged_tick: None,
+            in_view_since: HashMap::new(),
+        }
+    }
+
+    // The entities a client starts out with, which counts as the first snapshot
+    pub fn start(
+        &mut self,
+        tick: u64,
+        game_state: &GameState,
+        entities: &HashSet<EntityId>,
+    ) -> Vec<SavedEntity> {
+        *self = Self::new();
+        let delta = self.encode(tick, game_state, entities);
+        delta.spawns
+    }
+
+    // Ticks we no longer have, or never sent, are ignored
+    pub fn acknowledge(&mut self, tick: u64) {
+        if self
+            .acknowledged_tick
+            .is_some_and(|acknowledged| tick <= acknowledged)
+            || !self.sent.iter().any(|(sent_tick, _)| *sent_tick == tick)
+        {
+            return;
+        }
+        self.acknowledged_tick = Some(tick);
+        self.sent.retain(|(sent_tick, _)| *sent_tick >= tick);
+    }
+
+    pub fn encode(
+        &mut self,
+        tick: u64,
+        game_state: &GameState,
+        entities: &HashSet<EntityId>,
+    ) -> SnapshotDelta {
+        let state: SnapshotState = entities
+            .iter()
+            .map(|entity| {
+                (
+                    *entity,
+                    ReplicatedEntity::from_game_state(game_state, *entity),
+                )
+            })
+            .collect();
+        self.in_view_since
+            .retain(|entity, _| state.contains_key(entity));
+        for entity in state.keys() {
+            self.in_view_since.entry(*entity).or_insert(tick);
+        }
+
+        let baseline = self.acknowledged_tick.and_then(|acknowledged_tick| {
+            self.sent
+                .iter()
+                .find(|(sent_tick, _)| *sent_tick == acknowledged_tick)
+        });
+        let mut delta = SnapshotDelta {
+            baseline_tick: baseline.map(|(baseline_tick, _)| *baseline_tick),
+            despawns: Vec::new(),
+            spawns: Vec::new(),
+            changes: Vec::new(),
+        };
+        if let Some((_, baseline)) = baseline {
+            delta.despawns = baseline
+                .keys()
+                .filter(|entity| !state.contains_key(entity))
+                .copied()
+                .collect();
+        }
+        for (entity, replicated) in &state {
+            let baseline_entity = baseline.and_then(|(baseline_tick, baseline)| {
+                baseline
+                    .get(entity)
+                    .filter(|_| self.in_view_since[entity] <= *baseline_tick)
+            });
+            match baseline_entity {
+                Some(old) => delta
+                    .changes
+                    .extend(EntityDelta::between(*entity, old, replicated)),
+                None => delta
+                    .spawns
+                    .push(Self::saved_entity(game_state, *entity, replicated)),
+            }
+        }
+
+        self.sent.push_back((tick, state));
+        if self.sent.len() > MAX_SNAPSHOT_HISTORY
+            && let Some((dropped_tick, _)) = self.sent.pop_front()
+            && self.acknowledged_tick == Some(dropped_tick)
+        {
+            self.acknowledged_tick = None;
+        }
+        delta
+    }
+
+    // Rounded like the rest of the snapshot, so the client ends up with exactly what we think it has
+    fn saved_entity(
+        game_state: &GameState,
+        entity: EntityId,
+        replicated: &ReplicatedEntity,
+    ) -> SavedEntity {
+        let mut saved = game_state.to_saved_entity(entity);
+        saved.position = replicated.position.map(QuantizedPosition::to_position);
+        saved
+    }
+}
+
+// Client side. Keeps the snapshots the server might still build on
+pub struct SnapshotDecoder {
+    received: VecDeque<(u64, SnapshotState)>, // Newest last
+}
+
+impl Default for SnapshotDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SnapshotDecoder {
+    pub fn new() -> Self {
+        SnapshotDecoder {
+            received: VecDeque::new(),
+        }
+    }
+
+    pub fn start(&mut self, tick: u64, entities: &[SavedEntity]) {
+        let state = entities
+            .iter()
+            .map(|saved| (saved.entity, ReplicatedEntity::from_saved(saved)))
+            .collect();
+        self.received = VecDeque::from([(tick, state)]);
+    }
+
+    pub fn latest(&self) -> Option<&SnapshotState> {
+        self.received.back().map(|(_, state)| state)
+    }
+
+    pub fn decode(
+        &mut self,
+        tick: u64,
+        delta: SnapshotDelta,
+    ) -> Result<DecodedSnapshot, SnapshotError> {
+        let mut state = match delta.baseline_tick {
+            None => SnapshotState::new(),
+            Some(baseline_tick) => self
+                .received
+                .iter()
+                .find(|(received_tick, _)| *received_tick == baseline_tick)
+                .map(|(_, baseline)| baseline.clone())
+                .ok_or(SnapshotError::MissingBaseline(baseline_tick))?,
+        };
+        if let Some(spawn) = delta
+            .spawns
+            .iter()
+            .find(|spawn| !spawn.entity.is_within_limit())
+        {
+            return Err(SnapshotError::EntityOutOfRange(spawn.entity));
+        }
+        for entity in &delta.despawns {
+            state.remove(entity);
+        }
+        for spawn in &delta.spawns {
+            state.insert(spawn.entity, ReplicatedEntity::from_saved(spawn));
+        }
+        for change in &delta.changes {
+            let replicated = state
+                .get_mut(&change.entity)
+                .ok_or(SnapshotError::UnknownEntity(change.entity))?;
+            change.apply(replicated);
+        }
+
+        let empty_state = SnapshotState::new();
+        let previous = self.latest().unwrap_or(&empty_state);
+        let despawns = previous
+            .keys()
+            .filter(|entity| !state.contains_key(entity))
+            .copied()
+            .collect();
+        let spawned: HashSet<EntityId> = delta.spawns.iter().map(|spawn| spawn.entity).collect();
+        let changes = state
+            .iter()
+            .filter(|(entity, replicated)| {
+                !spawned.contains(entity) && previous.get(entity) != Some(replicated)
+            })
+            .map(|(entity, replicated)| (*entity, replicated.clone()))
+            .collect();
+
+        // The server acknowledges in order, it will not build on anything older than this baseline again
+        if let Some(baseline_tick) = delta.baseline_tick {
+            self.received
+                .retain(|(received_tick, _)| *received_tick >= baseline_tick);
+        }
+        self.received.push_back((tick, state));
+        if self.received.len() > MAX_SNAPSHOT_HISTORY {
+            self.received.pop_front();
+        }
+        Ok(DecodedSnapshot {
+            despawns,
+            spawns: delta.spawns,
+            changes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::components::Rotation;
+    use proptest::prelude::*;
+
+    #[derive(Clone, Debug)]
+    enum Event {
+        Spawn {
+            x: f32,
+            z: f32,
+        },
+        // Entities are picked by index into the ones that exist, wrapping around
+        Despawn(usize),
+        Move {
+            entity: usize,
+            x: f32,
+            z: f32,
+        },
+        Turn {
+            entity: usize,
+            degrees_y: f32,
+        },
+        Hurt {
+            entity: usize,
+            hitpoints: u32,
+        },
+        Store {
+            entity: usize,
+            storage: usize,
+            column: u8,
+            row: u8,
+        },
+        Drop {
+            entity: usize,
+            x: f32,
+            z: f32,
+        },
+        ToggleView(usize), // Goes out of view, or comes back into it
+    }
+
+    #[derive(Clone, Debug)]
+    struct Tick {
+        events: Vec<Event>,
+        is_delivered: bool,
+        is_acknowledged: bool,
+    }
+
+    struct TestServer {
+        game_state: GameState,
+        entities: Vec<EntityId>,
+        hidden: HashSet<EntityId>,
+        encoder: SnapshotEncoder,
+    }
+
+    impl TestServer {
+        fn new(entities: usize) -> TestServer {
+            let mut server = TestServer {
+                game_state: GameState::empty(),
+                entities: Vec::new(),
+                hidden: HashSet::new(),
+                encoder: SnapshotEncoder::new(),
+            };
+            for index in 0..entities {
+                server.apply(&Event::Spawn {
+                    x: index as f32,
+                    z: 0.0,
+                });
+            }
+            server
+        }
+
+        fn pick(&self, index: usize) -> Option<EntityId> {
+            (!self.entities.is_empty()).then(|| self.entities[index % self.entities.len()])
+        }
+
+        fn apply(&mut self, event: &Event) {
+            match *event {
+                Event::Spawn { x, z } => {
+                    let entity = self.game_state.spawn();
+                    self.game_state
+                        .create_position(entity, Point3::new(x, 0.0, z));
+                    self.entities.push(entity);
+                }
+                Event::Despawn(index) => {
+                    if let Some(entity) = self.pick(index) {
+                        // Stored items go with it
+                        self.game_state.despawn(entity);
+                        let game_state = &self.game_state;
+                        self.entities.retain(|entity| game_state.is_alive(*entity));
+                    }
+                }
+                Event::Move { entity, x, z } => {
+                    if let Some(entity) = self.pick(entity)
+                        && let Some(position) = self.game_state.get_position(entity).copied()
+                    {
+                        let moved = Point3::new(position.x + x, position.y, position.z + z);
+                        self.game_state.create_position(entity, moved);
+                    }
+                }
+                Event::Turn { entity, degrees_y } => {
+                    if let Some(entity) = self.pick(entity) {
+                        self.game_state.set_rotation(entity, Rotation { degrees_y });
+                    }
+                }
+                Event::Hurt { entity, hitpoints } => {
+                    if let Some(entity) = self.pick(entity) {
+                        self.game_state.health_components.insert(
+                            entity,
+                            Health {
+                                hitpoints,
+                                max_hitpoints: 100,
+                            },
+                        );
+                    }
+                }
+                Event::Store {
+                    entity,
+                    storage,
+                    column,
+                    row,
+                } => {
+                    if let (Some(entity), Some(storage)) = (self.pick(entity), self.pick(storage))
+                        && entity != storage
+                    {
+                        self.game_state.remove_position(entity);
+                        self.game_state.in_storage_components.insert(
+                            entity,
+                            InStorage {
+                                storage_entity: storage,
+                                position_x: column,
+                                position_y: row,
+                                is_rotated: false,
+                            },
+                        );
+                    }
+                }
+                Event::Drop { entity, x, z } => {
+                    if let Some(entity) = self.pick(entity) {
+                        self.game_state.remove_in_storage(entity);
+                        self.game_state
+                            .create_position(entity, Point3::new(x, 0.0, z));
+                    }
+                }
+                Event::ToggleView(index) => {
+                    if let Some(entity) = self.pick(index)
+                        && !self.hidden.remove(&entity)
+                    {
+                        self.hidden.insert(entity);
+                    }
+                }
+            }
+        }
+
+        fn in_view(&self) -> HashSet<EntityId> {
+            self.entities
+                .iter()
+                .filter(|entity| !self.hidden.contains(entity))
+                .copied()
+                .collect()
+        }
+
+        // What the client should end up with
+        fn state(&self) -> SnapshotState {
+            self.in_view()
+                .into_iter()
+                .map(|entity| {
+                    (
+                        entity,
+                        ReplicatedEntity::from_game_state(&self.game_state, entity),
+                    )
+                })
+                .collect()
+        }
+
+        fn start(&mut self, tick: u64) -> Vec<SavedEntity> {
+            let in_view = self.in_view();
+            self.encoder.start(tick, &self.game_state, &in_view)
+        }
+
+        fn encode(&mut self, tick: u64) -> SnapshotDelta {
+            let in_view = self.in_view();
+            self.encoder.encode(tick, &self.game_state, &in_view)
+        }
+    }
+
+    // Holds what the game state of the client would, applying decoded snapshots in the same order as the network client
+    struct TestClient {
+        decoder: SnapshotDecoder,
+        state: SnapshotState,
+    }
+
+    impl TestClient {
+        fn new(tick: u64, entities: &[SavedEntity]) -> TestClient {
+            let mut decoder = SnapshotDecoder::new();
+            decoder.start(tick, entities);
+            TestClient {
+                decoder,
+                state: entities
+                    .iter()
+                    .map(|saved| (saved.entity, ReplicatedEntity::from_saved(saved)))
+                    .collect(),
+            }
+        }
+
+        fn receive(&mut self, tick: u64, delta: SnapshotDelta) {
+            let decoded = self
+                .decoder
+                .decode(tick, delta)
+                .expect("Snapshot should decode");
+            for (entity, replicated) in decoded.changes {
+                if let Some(known) = self.state.get_mut(&entity) {
+                    *known = replicated;
+                }
+            }
+            for entity in decoded.despawns {
+                self.state.remove(&entity);
+            }
+            for spawn in decoded.spawns {
+                self.state
+                    .insert(spawn.entity, ReplicatedEntity::from_saved(&spawn));
+            }
+        }
+
+        fn assert_matches(&self, server: &TestServer) {
+            let expected = server.state();
+            assert_eq!(self.decoder.latest(), Some(&expected));
+            assert_eq!(self.state, expected);
+        }
+    }
+
+    fn connect(server: &mut TestServer) -> TestClient {
+        let entities = server.start(0);
+        server.encoder.acknowledge(0);
+        TestClient::new(0, &entities)
+    }
+
+    fn position() -> impl Strategy<Value = f32> {
+        -50.0_f32..50.0
+    }
+
+    fn event() -> impl Strategy<Value = Event> {
+        prop_oneof![
+            (position(), position()).prop_map(|(x, z)| Event::Spawn { x, z }),
+            any::<usize>().prop_map(Event::Despawn),
+            (any::<usize>(), -1.0_f32..1.0, -1.0_f32..1.0).prop_map(|(entity, x, z)| Event::Move {
+                entity,
+                x,
+                z
+            }),
+            (any::<usize>(), -180.0_f32..180.0)
+                .prop_map(|(entity, degrees_y)| Event::Turn { entity, degrees_y }),
+            (any::<usize>(), 0_u32..100)
+                .prop_map(|(entity, hitpoints)| Event::Hurt { entity, hitpoints }),
+            (any::<usize>(), any::<usize>(), any::<u8>(), any::<u8>()).prop_map(
+                |(entity, storage, column, row)| Event::Store {
+                    entity,
+                    storage,
+                    column,
+                    row
+                }
+            ),
+            (any::<usize>(), position(), position()).prop_map(|(entity, x, z)| Event::Drop {
+                entity,
+                x,
+                z
+            }),
+            any::<usize>().prop_map(Event::ToggleView),
+        ]
+    }
+
+    fn tick() -> impl Strategy<Value = Tick> {
+        (
+            prop::collection::vec(event(), 0..4),
+            prop::bool::weighted(0.8),
+            prop::bool::weighted(0.5),
+        )
+            .prop_map(|(events, is_delivered, is_acknowledged)| Tick {
+                events,
+                is_delivered,
+                is_acknowledged,
+            })
+    }
+
+    proptest! {
+        // Lost snapshots and acknowledgements only mean larger snapshots, the client always ends up with what the server has
+        #[test]
+        fn decoding_gives_what_the_server_has(
+            entities in 0_usize..10,
+            ticks in prop::collection::vec(tick(), 1..200),
+        ) {
+            let mut server = TestServer::new(entities);
+            let mut client = connect(&mut server);
+            for (tick, Tick { events, is_delivered, is_acknowledged }) in (1..).zip(ticks) {
+                for event in &events {
+                    server.apply(event);
+                }
+                let delta = server.encode(tick);
+                if is_delivered {
+                    client.receive(tick, delta);
+                    client.assert_matches(&server);
+                    if is_acknowledged {
+                        server.encoder.acknowledge(tick);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn without_acknowledgements_for_too_long_everything_is_sent_again() {
+        let mut server = TestServer::new(3);
+        let mut client = connect(&mut server);
+        let history = MAX_SNAPSHOT_HISTORY as u64;
+        for tick in 1..=history + 1 {
+            server.apply(&Event::Move {
+                entity: tick as usize,
+                x: 0.1,
+                z: 0.0,
+            });
+            let delta = server.encode(tick);
+            if tick <= history {
+                assert_eq!(delta.baseline_tick, Some(0));
+                assert!(delta.spawns.is_empty());
+            } else {
+                // The acknowledged snapshot is no longer kept
+                assert_eq!(delta.baseline_tick, None);
+                assert_eq!(delta.spawns.len(), 3);
+            }
+            client.receive(tick, delta);
+            client.assert_matches(&server);
+        }
+
+        // Which gets things going again once it is acknowledged
+        server.encoder.acknowledge(history + 1);
+        let delta = server.encode(history + 2);
+        assert_eq!(delta.baseline_tick, Some(history + 1));
+        client.receive(history + 2, delta);
+        client.assert_matches(&server);
+    }
+
+    #[test]
+    fn entities_back_in_view_since_the_baseline_are_sent_whole() {
+        let mut server = TestServer::new(2);
+        let mut client = connect(&mut server);
+        let entity = server.entities[0];
+
+        // Gone for the client, but not for the baseline it has not acknowledged since
+        server.apply(&Event::ToggleView(0));
+        let delta = server.encode(1);
+        assert_eq!(delta.despawns, vec![entity]);
+        client.receive(1, delta);
+        client.assert_matches(&server);
+
+        server.apply(&Event::ToggleView(0));
+        server.apply(&Event::Move {
+            entity: 0,
+            x: 0.5,
+            z: 0.0,
+        });
+        let delta = server.encode(2);
+        assert_eq!(delta.baseline_tick, Some(0));
+        assert!(delta.spawns.iter().any(|spawn| spawn.entity == entity));
+        assert!(delta.changes.iter().all(|change| change.entity != entity));
+        client.receive(2, delta);
+        client.assert_matches(&server);
+    }
+
+    #[test]
+    fn spawns_past_the_entity_limit_are_refused() {
+        let mut server = TestServer::new(1);
+        let mut decoder = SnapshotDecoder::new();
+        let entities = server.start(0);
+        decoder.start(0, &entities);
+
+        let mut spawn = entities[0].clone();
+        spawn.entity = serde_json::from_str(&format!(
+            r#"{{"index": {}, "generation": 0}}"#,
+            crate::state::entity::MAX_ENTITIES
+        ))
+        .unwrap();
+        let delta = SnapshotDelta {
+            baseline_tick: Some(0),
+            despawns: Vec::new(),
+            spawns: vec![spawn],
+            changes: Vec::new(),
+        };
+        assert!(matches!(
+            decoder.decode(1, delta),
+            Err(SnapshotError::EntityOutOfRange(_))
+        ));
+        assert_eq!(decoder.latest().map(|state| state.len()), Some(1));
+    }
+}
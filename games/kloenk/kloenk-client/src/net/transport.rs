@@ -0,0 +1,41 @@
+#[derive(Debug, PartialEq)]
+pub enum TransportEvent {
+    Opened,
+    Message(Vec<u8>),
+    // The connection failed to open or was lost
+    Closed,
+}
+
+// Sends whole messages to the server and back. Connecting happens in the background, poll tells when it is done
+pub trait Transport {
+    // Closes any previous connection first
+    fn connect(&mut self);
+
+    // Everything that happened since the last poll, in order
+    fn poll(&mut self) -> Vec<TransportEvent>;
+
+    // Dropped when not connected
+    fn send(&mut self, bytes: Vec<u8>);
+
+    // Closing it ourselves does not lead to a Closed event
+    fn close(&mut self);
+}
+
+// So the transport can be picked at runtime, like the loopback in tests and a real socket otherwise
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn connect(&mut self) {
+        (**self).connect();
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        (**self).poll()
+    }
+
+    fn send(&mut self, bytes: Vec<u8>) {
+        (**self).send(bytes);
+    }
+
+    fn close(&mut self) {
+        (**self).close();
+    }
+}
@@ -0,0 +1,139 @@
+use crate::net::transport::{Transport, TransportEvent};
+use std::io::ErrorKind;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{Receiver, TryRecvError, channel};
+use std::thread;
+use std::time::Duration;
+use tungstenite::{Error, Message, WebSocket};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Connecting blocks, so it is done on its own thread. Once open the socket is nonblocking and read from the game loop
+pub struct WebSocketTransport {
+    address: String,
+    pending_connection: Option<Receiver<Result<WebSocket<TcpStream>, String>>>,
+    websocket: Option<WebSocket<TcpStream>>,
+}
+
+impl WebSocketTransport {
+    // Address as host:port
+    pub fn new(address: &str) -> Self {
+        WebSocketTransport {
+            address: address.to_owned(),
+            pending_connection: None,
+            websocket: None,
+        }
+    }
+
+    fn open(address: &str) -> Result<WebSocket<TcpStream>, String> {
+        let socket_address = address
+            .to_socket_addrs()
+            .map_err(|error| error.to_string())?
+            .next()
+            .ok_or_else(|| format!("{address} does not resolve to an address"))?;
+        let stream = TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT)
+            .map_err(|error| error.to_string())?;
+        stream
+            .set_read_timeout(Some(CONNECT_TIMEOUT))
+            .map_err(|error| error.to_string())?;
+        // Small messages every tick, waiting to fill up packets only adds latency
+        stream
+            .set_nodelay(true)
+            .map_err(|error| error.to_string())?;
+        let (websocket, _response) = tungstenite::client(format!("ws://{address}"), stream)
+            .map_err(|error| error.to_string())?;
+        websocket
+            .get_ref()
+            .set_read_timeout(None)
+            .and_then(|()| websocket.get_ref().set_nonblocking(true))
+            .map_err(|error| error.to_string())?;
+        Ok(websocket)
+    }
+
+    fn poll_pending_connection(&mut self, events: &mut Vec<TransportEvent>) {
+        let Some(pending_connection) = &self.pending_connection else {
+            return;
+        };
+        match pending_connection.try_recv() {
+            Ok(Ok(websocket)) => {
+                self.websocket = Some(websocket);
+                events.push(TransportEvent::Opened);
+            }
+            Ok(Err(_error)) => {
+                #[cfg(feature = "debug-logging")]
+                log::warn!("Failed to connect to {}: {_error}", self.address);
+                events.push(TransportEvent::Closed);
+            }
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => events.push(TransportEvent::Closed),
+        }
+        self.pending_connection = None;
+    }
+}
+
+impl Transport for WebSocketTransport {
+    fn connect(&mut self) {
+        self.close();
+        let (sender, receiver) = channel();
+        let address = self.address.clone();
+        thread::spawn(move || {
+            // Only fails when we stopped waiting for this connection
+            let _ = sender.send(Self::open(&address));
+        });
+        self.pending_connection = Some(receiver);
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        let mut events = Vec::new();
+        self.poll_pending_connection(&mut events);
+        let Some(websocket) = &mut self.websocket else {
+            return events;
+        };
+
+        // Whatever did not fit in the socket last time
+        let mut is_closed = match websocket.flush() {
+            Ok(()) => false,
+            Err(Error::Io(error)) => error.kind() != ErrorKind::WouldBlock,
+            Err(_) => true,
+        };
+        while !is_closed {
+            match websocket.read() {
+                Ok(Message::Binary(bytes)) => events.push(TransportEvent::Message(bytes.to_vec())),
+                Ok(Message::Close(_)) => is_closed = true,
+                Ok(_) => {} // Pings are answered by tungstenite itself
+                Err(Error::Io(error)) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => is_closed = true,
+            }
+        }
+
+        if is_closed {
+            self.websocket = None;
+            events.push(TransportEvent::Closed);
+        }
+        events
+    }
+
+    fn send(&mut self, bytes: Vec<u8>) {
+        let Some(websocket) = &mut self.websocket else {
+            return;
+        };
+        match websocket.send(Message::binary(bytes)) {
+            Ok(()) => {}
+            Err(Error::Io(error)) if error.kind() == ErrorKind::WouldBlock => {} // Queued, goes out on the next poll
+            Err(_error) => {
+                #[cfg(feature = "debug-logging")]
+                log::warn!("Failed to send to server: {_error}");
+                // Makes the next read fail, so poll reports the connection as closed
+                let _ = websocket.get_ref().shutdown(std::net::Shutdown::Both);
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        self.pending_connection = None;
+        if let Some(mut websocket) = self.websocket.take() {
+            let _ = websocket.close(None);
+            let _ = websocket.flush();
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use crate::net::transport::{Transport, TransportEvent};
+use js_sys::{ArrayBuffer, Uint8Array};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::closure::Closure;
+use web_sys::{BinaryType, MessageEvent, WebSocket};
+
+// The callbacks are called by the browser, they have to live as long as the socket
+struct OpenWebSocket {
+    websocket: WebSocket,
+    _on_open: Closure<dyn FnMut()>,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_close: Closure<dyn FnMut()>,
+}
+
+// The browser calls back whenever something happens. We keep what happened until the next poll, so the game loop handles it in order
+pub struct WebSocketTransport {
+    url: String,
+    websocket: Option<OpenWebSocket>,
+    events: Rc<RefCell<Vec<TransportEvent>>>,
+}
+
+impl WebSocketTransport {
+    // Address as host:port. Pages served over https are only allowed to use secure sockets
+    pub fn new(address: &str) -> Self {
+        let is_secure = web_sys::window()
+            .expect("Window should exist")
+            .location()
+            .protocol()
+            .is_ok_and(|protocol| protocol == "https:");
+        let scheme = if is_secure { "wss" } else { "ws" };
+        WebSocketTransport {
+            url: format!("{scheme}://{address}"),
+            websocket: None,
+            events: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+}
+
+impl Transport for WebSocketTransport {
+    fn connect(&mut self) {
+        self.close();
+        // Every socket gets its own events, so nothing from a previous connection ends up in this one
+        self.events = Rc::new(RefCell::new(Vec::new()));
+        let websocket = match WebSocket::new(&self.url) {
+            Ok(websocket) => websocket,
+            Err(_error) => {
+                #[cfg(feature = "debug-logging")]
+                log::warn!("Failed to connect to {}: {_error:?}", self.url);
+                self.events.borrow_mut().push(TransportEvent::Closed);
+                return;
+            }
+        };
+        websocket.set_binary_type(BinaryType::Arraybuffer);
+
+        let events = self.events.clone();
+        let on_open = Closure::<dyn FnMut()>::new(move || {
+            events.borrow_mut().push(TransportEvent::Opened);
+        });
+        let events = self.events.clone();
+        let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            // Text messages are not part of the protocol
+            if let Ok(buffer) = event.data().dyn_into::<ArrayBuffer>() {
+                let bytes = Uint8Array::new(&buffer).to_vec();
+                events.borrow_mut().push(TransportEvent::Message(bytes));
+            }
+        });
+        // An error is always followed by a close, so that is all we listen to
+        let events = self.events.clone();
+        let on_close = Closure::<dyn FnMut()>::new(move || {
+            events.borrow_mut().push(TransportEvent::Closed);
+        });
+        websocket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        websocket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        websocket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+
+        self.websocket = Some(OpenWebSocket {
+            websocket,
+            _on_open: on_open,
+            _on_message: on_message,
+            _on_close: on_close,
+        });
+    }
+
+    fn poll(&mut self) -> Vec<TransportEvent> {
+        let events = std::mem::take(&mut *self.events.borrow_mut());
+        if events.contains(&TransportEvent::Closed) {
+            self.close();
+        }
+        events
+    }
+
+    fn send(&mut self, bytes: Vec<u8>) {
+        let Some(open_websocket) = &self.websocket else {
+            return;
+        };
+        if open_websocket.websocket.ready_state() != WebSocket::OPEN {
+            return;
+        }
+        // Fails when the socket is closing, which we hear about through the close callback
+        let _ = open_websocket.websocket.send_with_u8_array(&bytes);
+    }
+
+    fn close(&mut self) {
+        let Some(open_websocket) = self.websocket.take() else {
+            return;
+        };
+        // The callbacks are dropped with the socket, the browser should not call them anymore
+        let websocket = open_websocket.websocket;
+        websocket.set_onopen(None);
+        websocket.set_onmessage(None);
+        websocket.set_onclose(None);
+        let _ = websocket.close();
+    }
+}
@@ -1,9 +1,9 @@
+use crate::state::viewport::Viewport;
 use cgmath::{Matrix4, Point3, SquareMatrix, Vector3, Zero};
-use std::sync::Arc;
-use winit::window::Window;
+use serde::{Deserialize, Serialize};
 
 // TODO kind of weird having all this functionality in this component. should be separated.
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Camera {
     pub target: Point3<f32>,
     pub eye: Point3<f32>,
@@ -32,11 +32,11 @@ impl Camera {
     }
 
     // TODO only for 3d and only maybe upon update?
-    pub fn update_view_projection_matrix(&mut self, window: &Arc<Window>) {
+    pub fn update_view_projection_matrix(&mut self, viewport: &Viewport) {
         let view = Matrix4::look_at_rh(self.eye, self.target, self.up);
 
         let scale = 1.0; //window.inner_size().height as f32 / DEFAULT_RESOLUTION_HEIGHT;
-        let resolution = window.inner_size().width as f32 / window.inner_size().height as f32;
+        let resolution = viewport.aspect_ratio();
         let height = scale;
         let width = scale * resolution;
         let isometric_projection =
@@ -0,0 +1,189 @@
+use cgmath::{InnerSpace, Point3, Vector3, VectorSpace};
+use std::ops::Range;
+
+// The world is only updated on fixed ticks while we might render a lot more often. We keep the transforms of the last two ticks
+// and render somewhere in between, based on how far we are towards the next tick (alpha 0.0 is the previous tick, 1.0 the current one)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub position: Point3<f32>,
+    pub scale: Vector3<f32>,
+    pub rotation_degrees_y: f32,
+}
+
+impl Transform {
+    pub fn interpolate(previous: &Transform, current: &Transform, alpha: f32) -> Transform {
+        Transform {
+            position: interpolate_point(previous.position, current.position, alpha),
+            scale: previous.scale.lerp(current.scale, alpha),
+            rotation_degrees_y: interpolate_degrees(
+                previous.rotation_degrees_y,
+                current.rotation_degrees_y,
+                alpha,
+            ),
+        }
+    }
+
+    // Entities that did not exist last tick are just drawn where they are now
+    pub fn interpolate_from(
+        previous: Option<&Transform>,
+        current: &Transform,
+        alpha: f32,
+    ) -> Transform {
+        match previous {
+            Some(previous) => Transform::interpolate(previous, current, alpha),
+            None => *current,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraTransform {
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    pub z_near: f32,
+    pub z_far: f32,
+}
+
+impl CameraTransform {
+    pub fn interpolate(
+        previous: &CameraTransform,
+        current: &CameraTransform,
+        alpha: f32,
+    ) -> CameraTransform {
+        CameraTransform {
+            eye: interpolate_point(previous.eye, current.eye, alpha),
+            target: interpolate_point(previous.target, current.target, alpha),
+            up: previous.up.lerp(current.up, alpha).normalize(),
+            z_near: current.z_near,
+            z_far: current.z_far,
+        }
+    }
+}
+
+pub fn interpolate_point(previous: Point3<f32>, current: Point3<f32>, alpha: f32) -> Point3<f32> {
+    previous + (current - previous) * alpha
+}
+
+// Turns the shortest way around, so going from 350 to 10 degrees does not spin all the way back through 180
+pub fn interpolate_degrees(previous: f32, current: f32, alpha: f32) -> f32 {
+    let difference = (current - previous).rem_euclid(360.0);
+    let shortest_difference = if difference > 180.0 {
+        difference - 360.0
+    } else {
+        difference
+    };
+    previous + shortest_difference * alpha
+}
+
+// Runs of instances whose transform is not what was written before, so only those parts of an instance buffer need to be written again
+pub fn changed_ranges(written: &[Transform], transforms: &[Transform]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (index, transform) in transforms.iter().enumerate() {
+        if written.get(index) == Some(transform) {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(range) if range.end == index => range.end += 1,
+            _ => ranges.push(index..index + 1),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(x: f32, scale: f32, rotation_degrees_y: f32) -> Transform {
+        Transform {
+            position: Point3::new(x, 1.0, -x),
+            scale: Vector3::new(scale, scale, scale),
+            rotation_degrees_y,
+        }
+    }
+
+    fn assert_degrees_eq(actual: f32, expected: f32) {
+        let difference = (actual - expected + 180.0).rem_euclid(360.0) - 180.0;
+        assert!(
+            difference.abs() < 0.001,
+            "{actual} is not {expected} degrees"
+        );
+    }
+
+    #[test]
+    fn alpha_zero_and_one_are_the_ticks_themselves() {
+        let previous = transform(1.0, 1.0, 10.0);
+        let current = transform(3.0, 2.0, 50.0);
+
+        assert_eq!(Transform::interpolate(&previous, &current, 0.0), previous);
+        assert_eq!(Transform::interpolate(&previous, &current, 1.0), current);
+
+        let halfway = Transform::interpolate(&previous, &current, 0.5);
+        assert_eq!(halfway, transform(2.0, 1.5, 30.0));
+    }
+
+    #[test]
+    fn rotation_turns_the_short_way_around() {
+        assert_degrees_eq(interpolate_degrees(359.0, 1.0, 0.5), 0.0);
+        assert_degrees_eq(interpolate_degrees(359.0, 1.0, 0.25), 359.5);
+        assert_degrees_eq(interpolate_degrees(1.0, 359.0, 0.5), 0.0);
+        assert_degrees_eq(interpolate_degrees(1.0, 359.0, 0.25), 0.5);
+        assert_degrees_eq(interpolate_degrees(350.0, -350.0, 0.5), 0.0);
+        assert_degrees_eq(interpolate_degrees(359.0, 1.0, 1.0), 1.0);
+        assert_degrees_eq(interpolate_degrees(90.0, 270.0, 0.5), 180.0);
+    }
+
+    #[test]
+    fn entities_without_a_previous_transform_stay_where_they_are() {
+        let current = transform(3.0, 2.0, 50.0);
+        for alpha in [0.0, 0.3, 1.0] {
+            assert_eq!(Transform::interpolate_from(None, &current, alpha), current);
+        }
+        let previous = transform(1.0, 1.0, 10.0);
+        assert_eq!(
+            Transform::interpolate_from(Some(&previous), &current, 0.0),
+            previous
+        );
+    }
+
+    #[test]
+    fn camera_alpha_zero_and_one_are_the_ticks_themselves() {
+        let previous = CameraTransform {
+            eye: Point3::new(0.0, 5.0, 5.0),
+            target: Point3::new(0.0, 0.0, 0.0),
+            up: Vector3::unit_y(),
+            z_near: 0.1,
+            z_far: 100.0,
+        };
+        let current = CameraTransform {
+            eye: Point3::new(2.0, 5.0, 7.0),
+            target: Point3::new(2.0, 0.0, 2.0),
+            ..previous
+        };
+
+        assert_eq!(
+            CameraTransform::interpolate(&previous, &current, 0.0),
+            previous
+        );
+        assert_eq!(
+            CameraTransform::interpolate(&previous, &current, 1.0),
+            current
+        );
+    }
+
+    #[test]
+    fn only_instances_that_changed_are_rewritten() {
+        let still = transform(1.0, 1.0, 0.0);
+        let moved = transform(2.0, 1.0, 0.0);
+
+        assert_eq!(changed_ranges(&[still; 4], &[still; 4]), vec![]);
+        assert_eq!(
+            changed_ranges(&[still; 5], &[moved, still, moved, moved, still]),
+            vec![0..1, 2..4]
+        );
+        // Instances that were not written yet always are
+        assert_eq!(changed_ranges(&[still], &[still, still, moved]), vec![1..3]);
+        assert_eq!(changed_ranges(&[], &[still, still]), vec![0..2]);
+    }
+}
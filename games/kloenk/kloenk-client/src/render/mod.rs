@@ -1,13 +1,28 @@
+// Only the camera is part of the game state, everything else needs the graphics feature
 pub mod camera;
+#[cfg(feature = "graphics")]
 mod camera_manager;
+#[cfg(feature = "graphics")]
 pub mod color_manager;
+#[cfg(feature = "graphics")]
 mod instance;
+#[cfg(feature = "graphics")]
+mod interpolation;
+#[cfg(feature = "graphics")]
 pub mod material_manager;
+#[cfg(feature = "graphics")]
 pub mod model;
+#[cfg(feature = "graphics")]
 pub mod model_loader;
+#[cfg(feature = "graphics")]
 pub mod model_manager;
+#[cfg(feature = "graphics")]
 pub mod primitive_vertices_manager;
+#[cfg(feature = "graphics")]
 mod render_context_manager;
+#[cfg(feature = "graphics")]
 pub mod renderer;
+#[cfg(feature = "graphics")]
 pub mod text_renderer;
+#[cfg(feature = "graphics")]
 pub mod texture;
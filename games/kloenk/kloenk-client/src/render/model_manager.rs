@@ -130,6 +130,11 @@ impl ModelManager {
             Vector4::new(0.2, 0.2, 0.2, 1.0),
         ));
 
+        model_manager.add_required_model(ModelLoader::load_colored_square_model(
+            "green",
+            Vector4::new(0.1, 0.4, 0.1, 1.0),
+        ));
+
         // #780606
         model_manager.add_required_model(ModelLoader::load_colored_square_model(
             "blood_red",
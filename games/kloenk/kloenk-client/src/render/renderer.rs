@@ -3,6 +3,7 @@ use crate::render::camera::Camera;
 use crate::render::camera_manager::CameraManager;
 use crate::render::color_manager::ColorManager;
 use crate::render::instance::InstanceRaw;
+use crate::render::interpolation::{changed_ranges, CameraTransform, Transform};
 use crate::render::material_manager::TextureManager;
 use crate::render::model::ColorDefinition;
 use crate::render::model_manager::ModelManager;
@@ -11,9 +12,11 @@ use crate::render::render_context_manager::RenderContextManager;
 use crate::render::text_renderer::TextWriter;
 use crate::render::texture;
 use crate::state::components::Scale;
+use crate::state::entity::EntityId;
 use crate::state::game_state::GameState;
 use crate::state::ui_state::{RenderCommand, UIElement, UIState};
 use crate::state::update_state::UpdateState;
+use crate::state::viewport::Viewport;
 use cgmath::{prelude::*, Point3, Vector3};
 use std::collections::HashMap;
 use std::iter;
@@ -22,8 +25,8 @@ use wgpu::util::DeviceExt;
 use wgpu::CompositeAlphaMode::Auto;
 use wgpu::PresentMode::{AutoVsync, Mailbox};
 use wgpu::{
-    Adapter, Buffer, CommandEncoder, Device, Features, InstanceFlags, MemoryHints, Queue,
-    RenderPass, SurfaceConfiguration, TextureView, Trace,
+    Adapter, Buffer, BufferAddress, CommandEncoder, Device, Features, InstanceFlags, MemoryHints,
+    Queue, RenderPass, SurfaceConfiguration, TextureView, Trace,
 };
 use winit::dpi::PhysicalSize;
 use winit::window::Window;
@@ -43,6 +46,10 @@ pub struct Renderer {
 
     depth_texture: texture::Depth,
     render_batches: Vec<RenderBatch>,
+    previous_transforms: HashMap<EntityId, Transform>,
+    current_transforms: HashMap<EntityId, Transform>,
+    previous_camera_3d: Option<CameraTransform>,
+    current_camera_3d: Option<CameraTransform>,
     ui_render_batches: Vec<UiRenderBatch>,
     text_writer: TextWriter,
 
@@ -66,7 +73,8 @@ impl Instance {
 struct RenderBatch {
     instance_buffer: Buffer,
     model_id: String,
-    instance_count: u32,
+    entities: Vec<EntityId>, // Instance order in the buffer, so we can rewrite the buffer with interpolated transforms
+    written: Vec<Transform>, // What is in the buffer now, so we only rewrite instances that moved
 }
 
 // maybe just like renderbatch, there should also be instances right? multiple swords in inventory lead to same draw?
@@ -174,6 +182,10 @@ impl Renderer {
             render_context_manager,
             depth_texture,
             render_batches: Vec::new(),
+            previous_transforms: HashMap::new(),
+            current_transforms: HashMap::new(),
+            previous_camera_3d: None,
+            current_camera_3d: None,
             ui_render_batches: Vec::new(),
             text_writer,
             is_first_render: true,
@@ -201,7 +213,11 @@ impl Renderer {
         }
     }
 
-    pub fn render(&mut self, window: &Arc<Window>) -> Result<(), wgpu::SurfaceError> {
+    // Alpha is how far we are between the previous and the current tick
+    pub fn render(&mut self, window: &Arc<Window>, alpha: f32) -> Result<(), wgpu::SurfaceError> {
+        self.write_interpolated_instances(alpha);
+        self.write_interpolated_camera(window, alpha);
+
         let output = self.surface.get_current_texture()?;
         let view = output.texture.create_view(&wgpu::TextureViewDescriptor {
             label: Some("Render view"),
@@ -294,7 +310,7 @@ impl Renderer {
             render_pass.draw_indexed(
                 0..primitive_vertices.num_indices,
                 0,
-                0..render_group.instance_count,
+                0..render_group.entities.len() as u32,
             );
         }
     }
@@ -338,45 +354,101 @@ impl Renderer {
         device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Instance Buffer"),
             contents: bytemuck::cast_slice(&raw_instances),
-            usage: wgpu::BufferUsages::VERTEX,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
         })
     }
 
-    fn convert_instance(
+    fn create_transform(
         position: &Point3<f32>,
         size: Option<&Scale>,
         rotation: Option<&crate::state::components::Rotation>,
-    ) -> Instance {
+    ) -> Transform {
         let scale = if let Some(size_unwrap) = size {
-            cgmath::Vector4::new(size_unwrap.x, size_unwrap.y, size_unwrap.z, 1.0)
+            Vector3::new(size_unwrap.x, size_unwrap.y, size_unwrap.z)
         } else {
-            cgmath::Vector4::new(1.0, 1.0, 1.0, 1.0)
+            Vector3::new(1.0, 1.0, 1.0)
         };
+        Transform {
+            position: *position,
+            scale,
+            rotation_degrees_y: rotation.map_or(0.0, |r| r.degrees_y),
+        }
+    }
+
+    fn convert_instance(transform: &Transform) -> Instance {
         Instance {
-            position: Vector3 {
-                x: position.x,
-                y: position.y,
-                z: position.z,
-            },
-            scale: cgmath::Matrix4::from_diagonal(scale),
+            position: transform.position.to_vec(),
+            scale: cgmath::Matrix4::from_diagonal(transform.scale.extend(1.0)),
             rotation: cgmath::Quaternion::from_axis_angle(
                 Vector3::unit_y(),
-                cgmath::Deg(rotation.map_or(0.0, |r| r.degrees_y)),
+                cgmath::Deg(transform.rotation_degrees_y),
             ),
         }
     }
 
-    pub fn create_ui_element_instance(window: &Arc<Window>, rect: &mut UIElement) -> Instance {
-        rect.update(&window.inner_size());
+    fn interpolated_transform(&self, entity: EntityId, alpha: f32) -> Transform {
+        let current = self
+            .current_transforms
+            .get(&entity)
+            .expect("Batched entities should have a transform");
+        Transform::interpolate_from(self.previous_transforms.get(&entity), current, alpha)
+    }
+
+    // Standing still is the common case, those instances are already right in the buffer
+    fn write_interpolated_instances(&mut self, alpha: f32) {
+        for batch_index in 0..self.render_batches.len() {
+            let transforms: Vec<Transform> = self.render_batches[batch_index]
+                .entities
+                .iter()
+                .map(|entity| self.interpolated_transform(*entity, alpha))
+                .collect();
+            let render_batch = &mut self.render_batches[batch_index];
+            for range in changed_ranges(&render_batch.written, &transforms) {
+                let raw_instances = transforms[range.clone()]
+                    .iter()
+                    .map(|transform| Self::convert_instance(transform).to_raw())
+                    .collect::<Vec<_>>();
+                self.queue.write_buffer(
+                    &render_batch.instance_buffer,
+                    (range.start * size_of::<InstanceRaw>()) as BufferAddress,
+                    bytemuck::cast_slice(&raw_instances),
+                );
+            }
+            render_batch.written = transforms;
+        }
+    }
+
+    fn write_interpolated_camera(&mut self, window: &Arc<Window>, alpha: f32) {
+        let Some(current) = self.current_camera_3d else {
+            return;
+        };
+        let transform = match self.previous_camera_3d {
+            Some(previous) => CameraTransform::interpolate(&previous, &current, alpha),
+            None => current,
+        };
+
+        let mut camera = Camera::new();
+        camera.eye = transform.eye;
+        camera.target = transform.target;
+        camera.up = transform.up;
+        camera.z_near = transform.z_near;
+        camera.z_far = transform.z_far;
+        camera.update_view_projection_matrix(&Viewport::from(window.inner_size()));
+        self.camera_manager
+            .update_buffer("camera_3d", &self.queue, &mut camera);
+    }
+
+    pub fn create_ui_element_instance(viewport: &Viewport, rect: &mut UIElement) -> Instance {
+        rect.update(&viewport.size());
         Instance {
             position: Vector3 {
-                x: UIState::clip_space_element_position_x(rect, window),
-                y: UIState::clip_space_element_position_y(rect, window),
+                x: UIState::clip_space_element_position_x(rect, viewport),
+                y: UIState::clip_space_element_position_y(rect, viewport),
                 z: 0.0,
             },
             scale: cgmath::Matrix4::from_diagonal(cgmath::Vector4::new(
-                UIState::convert_scale_x(rect.scaled_width, window),
-                UIState::convert_scale_y(rect.scaled_height, window),
+                UIState::convert_scale_x(rect.scaled_width, viewport),
+                UIState::convert_scale_y(rect.scaled_height, viewport),
                 1.0,
                 1.0,
             )),
@@ -384,53 +456,68 @@ impl Renderer {
         }
     }
 
+    // The square hangs down from its top left corner. Turning it around that corner puts it above the element, so we move it back down
+    pub fn create_rotated_ui_element_instance(
+        viewport: &Viewport,
+        rect: &mut UIElement,
+    ) -> Instance {
+        let mut instance = Self::create_ui_element_instance(viewport, rect);
+        instance.position.y -= UIState::convert_scale_y(rect.scaled_height, viewport);
+        instance.rotation =
+            cgmath::Quaternion::from_axis_angle(Vector3::unit_z(), cgmath::Deg(90.0));
+        instance
+    }
+
     // TODO one of the most expensive methods. Maybe just check the diff of the game state and update the batches accordingly by removing/adding to batches
     fn create_render_batches(&mut self, game_state: &GameState) {
-        let mut bind_group_entities: HashMap<String, Vec<String>> = HashMap::new();
+        let mut bind_group_entities: HashMap<String, Vec<EntityId>> = HashMap::new();
 
         // TODO Group by identical bind groups instead of by model id
         // i think we have to iterate over each primitive in the model? though theoretically we can group primitives instead if they have different properties. shared textures for different models we probably would want to render same time in order not to change bind groups again
         game_state
             .entities
             .iter()
-            .filter(|entity| game_state.get_position(entity).is_some())
-            .filter(|entity| {
-                game_state
-                    .graphics_3d_components
-                    .contains_key(entity.as_str())
-            })
+            .filter(|entity| game_state.get_position(**entity).is_some())
+            .filter(|entity| game_state.graphics_3d_components.contains_key(entity))
             .for_each(|entity| {
                 let model_id = game_state
-                    .get_graphics(entity)
+                    .get_graphics(*entity)
                     .expect("Entity contains 3d component")
                     .model_id
                     .clone();
                 bind_group_entities
                     .entry(model_id)
                     .or_default()
-                    .push(entity.clone());
+                    .push(*entity);
             });
 
+        self.previous_transforms = std::mem::take(&mut self.current_transforms);
+        for entity_group in bind_group_entities.values() {
+            for entity in entity_group {
+                let transform = Self::create_transform(
+                    game_state.get_position(*entity).unwrap(),
+                    game_state.get_size(*entity),
+                    game_state.get_rotation(*entity),
+                );
+                self.current_transforms.insert(*entity, transform);
+            }
+        }
+
         // TODO what is the difference again between groups and batch? naming?
         let mut render_batches: Vec<RenderBatch> = Vec::new();
         for (model_id, entity_group) in bind_group_entities.drain() {
-            let instance_group: Vec<Instance> = entity_group
-                .into_iter()
-                .map(|entity| {
-                    let size = game_state.get_size(&entity);
-                    let rotation = game_state.get_rotation(&entity);
-                    Self::convert_instance(
-                        game_state.get_position(&entity).unwrap(),
-                        size,
-                        rotation,
-                    )
-                })
+            let written: Vec<Transform> = entity_group
+                .iter()
+                .map(|entity| self.current_transforms[entity])
                 .collect();
+            let instance_group: Vec<Instance> =
+                written.iter().map(Self::convert_instance).collect();
             let instance_buffer = Self::create_instance_buffer(&self.device, &instance_group);
             let render_batch = RenderBatch {
                 instance_buffer,
                 model_id,
-                instance_count: instance_group.len() as u32,
+                entities: entity_group,
+                written,
             };
             render_batches.push(render_batch);
         }
@@ -439,7 +526,7 @@ impl Renderer {
 
     fn update_camera_data_ui(&mut self, camera: &mut Camera, window: &Arc<Window>) {
         // TODO hmm maybe only needs called on resize?
-        camera.update_view_projection_matrix(window); // TODO hmm i think camera matrix is updated in systems for 3d but for ui we do it here... one place for all.
+        camera.update_view_projection_matrix(&Viewport::from(window.inner_size())); // TODO hmm i think camera matrix is updated in systems for 3d but for ui we do it here... one place for all.
         self.camera_manager
             .update_buffer("camera_2d", &self.queue, camera);
     }
@@ -520,16 +607,27 @@ impl Renderer {
     ) {
         self.create_render_batches(game_state);
 
+        let camera_3d = game_state
+            .get_entity("camera_3d")
+            .expect("Camera should exist");
         let camera = game_state
-            .camera_components
-            .get_mut("camera_3d")
+            .get_camera(camera_3d)
             .expect("Camera components should exist");
-        self.camera_manager
-            .update_buffer("camera_3d", &self.queue, camera);
+        // The buffer itself is written at render time, in between this tick and the previous one
+        self.previous_camera_3d = self.current_camera_3d.take();
+        self.current_camera_3d = Some(CameraTransform {
+            eye: camera.eye,
+            target: camera.target,
+            up: camera.up,
+            z_near: camera.z_near,
+            z_far: camera.z_far,
+        });
 
+        let camera_ui = game_state
+            .get_entity("camera_ui")
+            .expect("Camera should exist");
         let camera = game_state
-            .camera_components
-            .get_mut("camera_ui")
+            .get_camera_mut(camera_ui)
             .expect("Camera components should exist");
         self.update_camera_data_ui(camera, window);
 
@@ -537,7 +635,9 @@ impl Renderer {
             .gui
             .render_commands
             .sort_by_key(|render_command| match render_command {
-                RenderCommand::Model { layer, .. } | RenderCommand::Text { layer, .. } => *layer,
+                RenderCommand::Model { layer, .. }
+                | RenderCommand::Text { layer, .. }
+                | RenderCommand::RotatedModel { layer, .. } => *layer,
             });
 
         // TODO not true batches, all with 1 instance
@@ -549,7 +649,10 @@ impl Renderer {
                     ui_element,
                     model_id,
                 } => {
-                    let element_instance = Self::create_ui_element_instance(window, ui_element);
+                    let element_instance = Self::create_ui_element_instance(
+                        &Viewport::from(window.inner_size()),
+                        ui_element,
+                    );
                     let instance_buffer =
                         Self::create_instance_buffer(&self.device, &[element_instance]); // TODO pretty expensive method call, can be done earlier. Don't generate buffers during rendering
                     ui_render_batches.push(UiRenderBatch {
@@ -557,6 +660,22 @@ impl Renderer {
                         instance_buffer,
                     })
                 }
+                RenderCommand::RotatedModel {
+                    layer: _layer,
+                    ui_element,
+                    model_id,
+                } => {
+                    let element_instance = Self::create_rotated_ui_element_instance(
+                        &Viewport::from(window.inner_size()),
+                        ui_element,
+                    );
+                    let instance_buffer =
+                        Self::create_instance_buffer(&self.device, &[element_instance]);
+                    ui_render_batches.push(UiRenderBatch {
+                        model_id: model_id.to_owned(),
+                        instance_buffer,
+                    })
+                }
                 RenderCommand::Text {
                     layer: _layer,
                     rect,
@@ -0,0 +1,22 @@
+// Systems only tell which sound should play. The application passes the real audio system, headless runs just keep a list
+pub trait AudioSink {
+    fn play_sound(&mut self, sound: &str);
+}
+
+#[derive(Default)]
+pub struct RecordedAudio {
+    pub played_sounds: Vec<String>,
+}
+
+impl AudioSink for RecordedAudio {
+    fn play_sound(&mut self, sound: &str) {
+        self.played_sounds.push(sound.to_owned());
+    }
+}
+
+// For movement that should not make a sound, like the server moving players or replaying movement the player already heard
+pub struct SilentAudio {}
+
+impl AudioSink for SilentAudio {
+    fn play_sound(&mut self, _sound: &str) {}
+}
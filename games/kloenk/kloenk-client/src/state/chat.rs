@@ -0,0 +1,124 @@
+use crate::net::protocol::MAX_CHAT_MESSAGE_LENGTH;
+use crate::state::text_field::TextField;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+const MAX_CHAT_HISTORY: usize = 100;
+
+// Who hears a message
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ChatChannel {
+    Local, // Players nearby
+    Global,
+    Whisper { player: String },
+}
+
+impl ChatChannel {
+    pub fn prompt(&self) -> String {
+        match self {
+            ChatChannel::Local => "Say: ".to_owned(),
+            ChatChannel::Global => "Global: ".to_owned(),
+            ChatChannel::Whisper { player } => format!("To {player}: "),
+        }
+    }
+}
+
+// Where a received message came from. Whispers we sent come back to us, so we see them in the history as well
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ChatSource {
+    Local { from: String },
+    Global { from: String },
+    WhisperFrom { from: String },
+    WhisperTo { to: String },
+    Notice, // From the game itself, like an unknown command
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub source: ChatSource,
+    pub text: String,
+}
+
+impl ChatMessage {
+    pub fn notice(text: &str) -> Self {
+        ChatMessage {
+            source: ChatSource::Notice,
+            text: text.to_owned(),
+        }
+    }
+
+    pub fn display_text(&self) -> String {
+        match &self.source {
+            ChatSource::Local { from } => format!("{from}: {}", self.text),
+            ChatSource::Global { from } => format!("[Global] {from}: {}", self.text),
+            ChatSource::WhisperFrom { from } => format!("[From {from}] {}", self.text),
+            ChatSource::WhisperTo { to } => format!("[To {to}] {}", self.text),
+            ChatSource::Notice => self.text.clone(),
+        }
+    }
+}
+
+// The recent messages, and what is being typed
+pub struct ChatLog {
+    messages: VecDeque<ChatMessage>,
+    scroll_offset: usize, // Messages hidden below the visible ones, 0 shows the newest
+    pub channel: ChatChannel, // Used for messages typed without a command
+    pub input: TextField,
+}
+
+impl Default for ChatLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChatLog {
+    pub fn new() -> Self {
+        ChatLog {
+            messages: VecDeque::new(),
+            scroll_offset: 0,
+            channel: ChatChannel::Local,
+            input: TextField::new(MAX_CHAT_MESSAGE_LENGTH),
+        }
+    }
+
+    pub fn push(&mut self, message: ChatMessage) {
+        self.messages.push_back(message);
+        if self.messages.len() > MAX_CHAT_HISTORY {
+            self.messages.pop_front();
+        }
+        // Someone reading back should not have the history move under them
+        if self.scroll_offset > 0 {
+            self.scroll_offset = (self.scroll_offset + 1).min(self.messages.len() - 1);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.messages.clear();
+        self.scroll_offset = 0;
+    }
+
+    pub fn messages(&self) -> &VecDeque<ChatMessage> {
+        &self.messages
+    }
+
+    // Positive goes back in history
+    pub fn scroll(&mut self, lines: isize) {
+        let max_offset = self.messages.len().saturating_sub(1);
+        self.scroll_offset = self
+            .scroll_offset
+            .saturating_add_signed(lines)
+            .min(max_offset);
+    }
+
+    // One message per line, oldest first
+    pub fn visible_text(&self, lines: usize) -> String {
+        let end = self.messages.len() - self.scroll_offset;
+        let start = end.saturating_sub(lines);
+        self.messages
+            .range(start..end)
+            .map(ChatMessage::display_text)
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
@@ -1,69 +1,237 @@
-use cgmath::Point3;
-
-pub type Entity = String;
+use crate::state::entity::EntityId;
+use cgmath::{Deg, Point3, Quaternion, Rotation3, Vector3};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Graphics3D {
     pub model_id: String,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Graphics2D {
     pub material_id: String,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Storable {
     pub shape: ItemShape,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Storage {
     pub number_of_rows: u8,
     pub number_of_columns: u8,
 }
 
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct InStorage {
-    pub storage_entity: Entity,
+    pub storage_entity: EntityId,
     pub position_x: u8,
     pub position_y: u8,
+    #[serde(default)]
+    pub is_rotated: bool, // Lying sideways, so it takes up its shape turned a quarter
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ItemShape {
     pub width: u8,
     pub height: u8,
 }
 
+impl ItemShape {
+    pub fn rotated(&self, is_rotated: bool) -> ItemShape {
+        if is_rotated {
+            ItemShape {
+                width: self.height,
+                height: self.width,
+            }
+        } else {
+            self.clone()
+        }
+    }
+
+    // Turning it would not change anything
+    pub fn is_square(&self) -> bool {
+        self.width == self.height
+    }
+}
+
+// World space axis aligned bounding box, computed from the collider of an entity
 #[derive(Debug)]
 pub struct Hitbox {
     pub box_corner_min: Point3<f32>,
     pub box_corner_max: Point3<f32>,
 }
 
+// Shape in entity local space, before scale and rotation are applied. Stays on the entity when it has no position (for example while in storage)
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Collider {
+    Box {
+        min_offset: Vector3<f32>,
+        max_offset: Vector3<f32>,
+    },
+    Cylinder {
+        radius: f32,
+        height: f32,
+    }, // Standing upright on the entity position, so rotation around y does not change it. Used for characters
+}
+
+#[derive(Debug)]
+pub enum WorldCollider {
+    Box(Hitbox),
+    Cylinder {
+        base: Point3<f32>,
+        radius: f32,
+        height: f32,
+    },
+}
+
+impl Collider {
+    pub fn place_in_world(
+        &self,
+        position: Point3<f32>,
+        scale: Option<&Scale>,
+        rotation: Option<&Rotation>,
+    ) -> WorldCollider {
+        let scale = scale.map_or(Vector3::new(1.0, 1.0, 1.0), |scale| {
+            Vector3::new(scale.x, scale.y, scale.z)
+        });
+        match self {
+            Collider::Box {
+                min_offset,
+                max_offset,
+            } => {
+                // Same rotation as the model gets in the renderer
+                let rotation = Quaternion::from_axis_angle(
+                    Vector3::unit_y(),
+                    Deg(rotation.map_or(0.0, |rotation| rotation.degrees_y)),
+                );
+                let mut box_corner_min = Point3::new(f32::MAX, f32::MAX, f32::MAX);
+                let mut box_corner_max = Point3::new(f32::MIN, f32::MIN, f32::MIN);
+                for corner in 0..8 {
+                    let local_corner = Vector3::new(
+                        if corner & 1 == 0 {
+                            min_offset.x
+                        } else {
+                            max_offset.x
+                        } * scale.x,
+                        if corner & 2 == 0 {
+                            min_offset.y
+                        } else {
+                            max_offset.y
+                        } * scale.y,
+                        if corner & 4 == 0 {
+                            min_offset.z
+                        } else {
+                            max_offset.z
+                        } * scale.z,
+                    );
+                    let world_corner = position + rotation * local_corner;
+                    for dimension in 0..3 {
+                        box_corner_min[dimension] =
+                            box_corner_min[dimension].min(world_corner[dimension]);
+                        box_corner_max[dimension] =
+                            box_corner_max[dimension].max(world_corner[dimension]);
+                    }
+                }
+                WorldCollider::Box(Hitbox {
+                    box_corner_min,
+                    box_corner_max,
+                })
+            }
+            Collider::Cylinder { radius, height } => WorldCollider::Cylinder {
+                base: position,
+                radius: radius * scale.x.max(scale.z),
+                height: height * scale.y,
+            },
+        }
+    }
+}
+
+impl WorldCollider {
+    pub fn bounds(&self) -> Hitbox {
+        match self {
+            WorldCollider::Box(hitbox) => Hitbox {
+                box_corner_min: hitbox.box_corner_min,
+                box_corner_max: hitbox.box_corner_max,
+            },
+            WorldCollider::Cylinder {
+                base,
+                radius,
+                height,
+            } => Hitbox {
+                box_corner_min: Point3::new(base.x - radius, base.y, base.z - radius),
+                box_corner_max: Point3::new(base.x + radius, base.y + height, base.z + radius),
+            },
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Health {
     pub hitpoints: u32,
     pub max_hitpoints: u32,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct CameraTarget {
     pub distance: f32,
     pub rotation_x_degrees: f32, // Spherical coordinates
     pub rotation_y_degrees: f32,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Scale {
     pub x: f32,
     pub y: f32,
     pub z: f32,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Rotation {
     pub degrees_y: f32,
 }
 
+// Set by clicking somewhere in the world, walked by the movement system until keyboard input takes over
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MovementPath {
+    pub waypoints: VecDeque<Point3<f32>>,
+    pub on_arrival: Option<PathInteraction>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum PathInteraction {
+    Pickup { item: EntityId },
+    Talk { npc: EntityId },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Description {
     pub text: String,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Dialogue {
-    pub dialogue_id: Entity,
+    pub dialogue_id: String,
+}
+
+// What a player did that dialogues remember, like who they talked to or what they were asked to do
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct Flags {
+    pub flags: BTreeSet<String>,
+}
+
+// The quests a player started, by quest id
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct QuestLog {
+    pub quests: BTreeMap<String, QuestProgress>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+pub struct QuestProgress {
+    pub stage: usize, // Index into the stages of the quest, stays at the last one once completed
+    // For the objectives of the current stage, filled in as they are reached
+    pub objective_counts: Vec<u32>,
+    pub is_completed: bool,
 }
@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Far more than any world holds. Ids come from save files and the server as well, a bad one should not make us allocate gigabytes
+pub const MAX_ENTITIES: u32 = 1 << 20;
+
+// Index is reused after a despawn, generation tells apart the old and new entity living at that index
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+pub struct EntityId {
+    index: u32,
+    generation: u32,
+}
+
+impl EntityId {
+    pub fn is_within_limit(&self) -> bool {
+        self.index < MAX_ENTITIES
+    }
+}
+
+impl fmt::Display for EntityId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EntityAllocator {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free_indices: Vec<u32>, // Can hold indices that got taken by allocate_at since, those are skipped
+}
+
+impl Default for EntityAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EntityAllocator {
+    pub fn new() -> Self {
+        Self {
+            generations: Vec::new(),
+            alive: Vec::new(),
+            free_indices: Vec::new(),
+        }
+    }
+
+    pub fn allocate(&mut self) -> EntityId {
+        while let Some(index) = self.free_indices.pop() {
+            let slot = index as usize;
+            if self.alive[slot] {
+                continue;
+            }
+            self.alive[slot] = true;
+            return EntityId {
+                index,
+                generation: self.generations[slot],
+            };
+        }
+
+        let index = u32::try_from(self.generations.len())
+            .ok()
+            .filter(|index| *index < MAX_ENTITIES)
+            .expect("Ran out of entity indices");
+        self.generations.push(0);
+        self.alive.push(true);
+        EntityId {
+            index,
+            generation: 0,
+        }
+    }
+
+    // For mirroring the entities of a server, which decides the ids. Despawn whatever lives at that index first, see alive_at
+    // Returns false for ids past MAX_ENTITIES, in which case nothing changes
+    pub fn allocate_at(&mut self, entity: EntityId) -> bool {
+        if !entity.is_within_limit() {
+            return false;
+        }
+        let slot = entity.index as usize;
+        while self.generations.len() <= slot {
+            let index =
+                u32::try_from(self.generations.len()).expect("Index should be within limit");
+            self.generations.push(0);
+            self.alive.push(false);
+            self.free_indices.push(index);
+        }
+        self.generations[slot] = entity.generation;
+        self.alive[slot] = true;
+        true
+    }
+
+    // Returns false on a stale or already freed handle, in which case nothing changes
+    pub fn free(&mut self, entity: EntityId) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+
+        let slot = entity.index as usize;
+        self.alive[slot] = false;
+        self.generations[slot] = self.generations[slot].wrapping_add(1);
+        self.free_indices.push(entity.index);
+        true
+    }
+
+    // The entity living at the same index as the given one, which can be another generation of it
+    pub fn alive_at(&self, entity: EntityId) -> Option<EntityId> {
+        let slot = entity.index as usize;
+        (slot < self.alive.len() && self.alive[slot]).then(|| EntityId {
+            index: entity.index,
+            generation: self.generations[slot],
+        })
+    }
+
+    pub fn is_alive(&self, entity: EntityId) -> bool {
+        let slot = entity.index as usize;
+        slot < self.generations.len()
+            && self.alive[slot]
+            && self.generations[slot] == entity.generation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity(index: u32, generation: u32) -> EntityId {
+        EntityId { index, generation }
+    }
+
+    #[test]
+    fn freed_indices_come_back_with_the_next_generation() {
+        let mut allocator = EntityAllocator::new();
+        let first = allocator.allocate();
+        let second = allocator.allocate();
+        assert_eq!((first, second), (entity(0, 0), entity(1, 0)));
+
+        assert!(allocator.free(first));
+        let reused = allocator.allocate();
+        assert_eq!(reused, entity(0, 1));
+        assert!(allocator.is_alive(reused));
+        assert!(allocator.is_alive(second));
+        assert_eq!(allocator.allocate(), entity(2, 0));
+    }
+
+    #[test]
+    fn stale_handles_are_not_alive_and_cannot_be_freed() {
+        let mut allocator = EntityAllocator::new();
+        let old = allocator.allocate();
+        assert!(allocator.free(old));
+        assert!(!allocator.is_alive(old));
+        assert!(!allocator.free(old), "Freed twice");
+
+        let new = allocator.allocate();
+        assert!(!allocator.is_alive(old));
+        assert!(!allocator.free(old), "Stale handle freed the new entity");
+        assert!(allocator.is_alive(new));
+        assert_eq!(allocator.alive_at(old), Some(new));
+
+        // Freeing twice did not put the index on the free list twice
+        assert_eq!(allocator.allocate(), entity(1, 0));
+    }
+
+    #[test]
+    fn never_allocated_handles_are_not_alive() {
+        let mut allocator = EntityAllocator::new();
+        allocator.allocate();
+        assert!(!allocator.is_alive(entity(0, 1)));
+        assert!(!allocator.is_alive(entity(5, 0)));
+        assert_eq!(allocator.alive_at(entity(5, 0)), None);
+        assert!(!allocator.free(entity(5, 0)));
+    }
+
+    #[test]
+    fn allocating_at_an_index_keeps_the_ones_before_it_free() {
+        let mut allocator = EntityAllocator::new();
+        assert!(allocator.allocate_at(entity(3, 7)));
+        assert!(allocator.is_alive(entity(3, 7)));
+        assert!(!allocator.is_alive(entity(3, 0)));
+
+        let mut allocated: Vec<EntityId> = (0..3).map(|_| allocator.allocate()).collect();
+        allocated.sort();
+        assert_eq!(allocated, [entity(0, 0), entity(1, 0), entity(2, 0)]);
+        assert_eq!(allocator.allocate(), entity(4, 0));
+    }
+
+    #[test]
+    fn indices_taken_by_allocating_at_them_are_skipped() {
+        let mut allocator = EntityAllocator::new();
+        let first = allocator.allocate();
+        allocator.allocate();
+        allocator.free(first);
+        assert!(allocator.allocate_at(entity(0, 4)));
+
+        assert_eq!(allocator.allocate(), entity(2, 0));
+        assert!(allocator.free(entity(0, 4)));
+        assert_eq!(allocator.allocate(), entity(0, 5));
+    }
+
+    #[test]
+    fn ids_past_the_limit_are_refused() {
+        let mut allocator = EntityAllocator::new();
+        assert!(!allocator.allocate_at(entity(MAX_ENTITIES, 0)));
+        assert!(!allocator.allocate_at(entity(u32::MAX, 0)));
+        assert!(!allocator.is_alive(entity(u32::MAX, 0)));
+        assert_eq!(allocator.allocate(), entity(0, 0));
+
+        assert!(allocator.allocate_at(entity(MAX_ENTITIES - 1, 0)));
+        assert!(allocator.is_alive(entity(MAX_ENTITIES - 1, 0)));
+    }
+}
@@ -1,447 +1,405 @@
-use cgmath::num_traits::ToPrimitive;
-
 use crate::render::camera::Camera;
 use crate::state::components::{
-    CameraTarget, Description, Dialogue, Entity, Graphics2D, Graphics3D, Health, Hitbox, InStorage,
-    ItemShape, Rotation, Scale, Storable, Storage,
+    CameraTarget, Collider, Description, Dialogue, Flags, Graphics2D, Graphics3D, Health, Hitbox,
+    InStorage, ItemShape, MovementPath, QuestLog, Rotation, Scale, Storable, Storage,
+    WorldCollider,
 };
-use cgmath::{ElementWise, Point3};
+use crate::state::entity::{EntityAllocator, EntityId};
+use crate::state::save_game::{SavedEntity, SavedGameState};
+use crate::state::spatial_grid::SpatialGrid;
+use crate::state::world_definition::ColliderDefinition;
+use crate::state::world_definition::{EntityDefinition, WorldDefinition};
+use cgmath::{Point2, Point3, Vector2, Vector3};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 
-pub const ROUGHLY_CAMERA_DISTANCE: f32 = 200_000.;
+const SPATIAL_GRID_CELL_SIZE: f32 = 2.0;
+pub const SURFACE_TILE_HALF_SIZE: f32 = 0.5;
 
 pub struct GameState {
-    pub entities: Vec<Entity>,
-    pub graphics_3d_components: HashMap<Entity, Graphics3D>,
-    pub graphics_2d_components: HashMap<Entity, Graphics2D>,
-    pub position_components: HashMap<Entity, Point3<f32>>,
-    pub surface_components: HashSet<Entity>,
-    pub size_components: HashMap<Entity, Scale>,
-    pub rotation_components: HashMap<Entity, Rotation>,
-    pub hitbox_components: HashMap<Entity, Hitbox>,
-    pub health_components: HashMap<Entity, Health>,
-    pub camera_components: HashMap<Entity, Camera>,
-    pub camera_target_components: HashMap<Entity, CameraTarget>,
-    pub storable_components: HashMap<Entity, Storable>,
-    pub storage_components: HashMap<Entity, Storage>,
-    pub in_storage_components: HashMap<Entity, InStorage>,
-    pub description_components: HashMap<Entity, Description>,
-    pub dialogue_components: HashMap<Entity, Dialogue>,
+    entity_allocator: EntityAllocator,
+    spatial_grid: SpatialGrid, // Kept in sync by the methods changing position, rotation or collider, so do not write to those maps directly
+    entity_names: HashMap<String, EntityId>, // Only for the few entities we need to find by name, such as the player
+    name_components: HashMap<EntityId, String>,
+
+    pub entities: Vec<EntityId>,
+    pub kind_components: HashMap<EntityId, String>, // The template an entity was made from, or the surface grid of a tile, so content can refer to all swords at once
+    pub graphics_3d_components: HashMap<EntityId, Graphics3D>,
+    pub graphics_2d_components: HashMap<EntityId, Graphics2D>,
+    pub position_components: HashMap<EntityId, Point3<f32>>,
+    pub surface_components: HashSet<EntityId>,
+    pub size_components: HashMap<EntityId, Scale>,
+    pub rotation_components: HashMap<EntityId, Rotation>,
+    pub collider_components: HashMap<EntityId, Collider>,
+    pub health_components: HashMap<EntityId, Health>,
+    pub camera_components: HashMap<EntityId, Camera>,
+    pub camera_target_components: HashMap<EntityId, CameraTarget>,
+    pub storable_components: HashMap<EntityId, Storable>,
+    pub storage_components: HashMap<EntityId, Storage>,
+    pub in_storage_components: HashMap<EntityId, InStorage>,
+    pub description_components: HashMap<EntityId, Description>,
+    pub dialogue_components: HashMap<EntityId, Dialogue>,
+    pub path_components: HashMap<EntityId, MovementPath>,
+    pub flag_components: HashMap<EntityId, Flags>,
+    pub quest_components: HashMap<EntityId, QuestLog>,
 }
 
 impl GameState {}
 
 impl GameState {
-    pub fn new() -> Self {
-        let mut entities = Vec::new();
-        let mut graphics_3d_components = HashMap::new();
-        let mut graphics_2d_components = HashMap::new();
-        let mut position_components = HashMap::new();
-        let mut surface_components = HashSet::new();
-        let mut size_components = HashMap::new();
-        let mut rotation_components = HashMap::new();
-        let mut hitbox_components = HashMap::new();
-        let mut health_components = HashMap::new();
-        let mut camera_components = HashMap::new();
-        let mut camera_target_components = HashMap::new();
-        let mut storable_components = HashMap::new();
-        let mut storage_components = HashMap::new();
-        let in_storage_components = HashMap::new();
-        let mut description_components = HashMap::new();
-        let mut dialogue_components = HashMap::new();
-
-        Self::load_player(
-            &mut entities,
-            &mut graphics_3d_components,
-            &mut position_components,
-            &mut rotation_components,
-            &mut hitbox_components,
-            &mut health_components,
-            &mut camera_target_components,
-            &mut storage_components,
-            &mut description_components,
-        );
-        Self::load_npc(
-            &mut entities,
-            &mut graphics_3d_components,
-            &mut position_components,
-            &mut hitbox_components,
-            &mut description_components,
-            &mut dialogue_components,
-        );
-        Self::load_camera_3d(&mut entities, &mut camera_components);
-        Self::load_camera_ui(&mut entities, &mut camera_components);
-
-        Self::load_shield(
-            &mut entities,
-            &mut graphics_3d_components,
-            &mut graphics_2d_components,
-            &mut position_components,
-            &mut size_components,
-            &mut hitbox_components,
-            &mut storable_components,
-            &mut description_components,
-        );
-        Self::load_swords(
-            &mut entities,
-            &mut graphics_3d_components,
-            &mut graphics_2d_components,
-            &mut position_components,
-            &mut size_components,
-            &mut hitbox_components,
-            &mut storable_components,
-            &mut description_components,
-        );
-        Self::load_tiles(
-            &mut entities,
-            &mut graphics_3d_components,
-            &mut position_components,
-            &mut surface_components,
-        );
-        Self::load_tree(
-            &mut entities,
-            &mut graphics_3d_components,
-            &mut position_components,
-            &mut hitbox_components,
-            &mut description_components,
-        );
+    pub fn new(world: &WorldDefinition) -> Self {
+        let mut game_state = Self::empty();
+        for entity_definition in &world.entities {
+            game_state.load_entity(entity_definition);
+        }
+        game_state.load_camera_3d();
+        game_state.load_camera_ui();
+
+        game_state
+    }
 
+    // Without even a camera. Only useful when entities are added right after, such as when the server sends them
+    pub fn empty() -> Self {
         Self {
-            entities,
-            graphics_3d_components,
-            graphics_2d_components,
-            position_components,
-            surface_components,
-            size_components,
-            rotation_components,
-            hitbox_components,
-            health_components,
-            camera_components,
-            camera_target_components,
-            storable_components,
-            storage_components,
-            in_storage_components,
-            description_components,
-            dialogue_components,
+            entity_allocator: EntityAllocator::new(),
+            spatial_grid: SpatialGrid::new(SPATIAL_GRID_CELL_SIZE),
+            entity_names: HashMap::new(),
+            name_components: HashMap::new(),
+
+            entities: Vec::new(),
+            kind_components: HashMap::new(),
+            graphics_3d_components: HashMap::new(),
+            graphics_2d_components: HashMap::new(),
+            position_components: HashMap::new(),
+            surface_components: HashSet::new(),
+            size_components: HashMap::new(),
+            rotation_components: HashMap::new(),
+            collider_components: HashMap::new(),
+            health_components: HashMap::new(),
+            camera_components: HashMap::new(),
+            camera_target_components: HashMap::new(),
+            storable_components: HashMap::new(),
+            storage_components: HashMap::new(),
+            in_storage_components: HashMap::new(),
+            description_components: HashMap::new(),
+            dialogue_components: HashMap::new(),
+            path_components: HashMap::new(),
+            flag_components: HashMap::new(),
+            quest_components: HashMap::new(),
         }
     }
 
-    fn load_tree(
-        entities: &mut Vec<Entity>,
-        graphics_3d_components: &mut HashMap<String, Graphics3D>,
-        position_components: &mut HashMap<String, Point3<f32>>,
-        hitbox_components: &mut HashMap<String, Hitbox>,
-        description_components: &mut HashMap<String, Description>,
-    ) {
-        let tree = "tree".to_owned();
-        entities.push(tree.clone());
-
-        let tree_graphics = Graphics3D {
-            model_id: tree.clone(),
-        };
-        graphics_3d_components.insert(tree.clone(), tree_graphics);
-
-        let tree_position = Point3 {
-            x: 2.0,
-            y: 1.0,
-            z: -3.0,
-        };
-
-        position_components.insert(tree.clone(), tree_position);
+    pub fn to_saved(&self) -> SavedGameState {
+        SavedGameState {
+            entity_allocator: self.entity_allocator.clone(),
+            entities: self.entities.clone(),
+            name_components: Self::to_sorted(&self.name_components),
+            kind_components: Self::to_sorted(&self.kind_components),
+            graphics_3d_components: Self::to_sorted(&self.graphics_3d_components),
+            graphics_2d_components: Self::to_sorted(&self.graphics_2d_components),
+            position_components: Self::to_sorted(&self.position_components),
+            surface_components: {
+                let mut surfaces: Vec<EntityId> = self.surface_components.iter().copied().collect();
+                surfaces.sort();
+                surfaces
+            },
+            size_components: Self::to_sorted(&self.size_components),
+            rotation_components: Self::to_sorted(&self.rotation_components),
+            collider_components: Self::to_sorted(&self.collider_components),
+            health_components: Self::to_sorted(&self.health_components),
+            camera_components: Self::to_sorted(&self.camera_components),
+            camera_target_components: Self::to_sorted(&self.camera_target_components),
+            storable_components: Self::to_sorted(&self.storable_components),
+            storage_components: Self::to_sorted(&self.storage_components),
+            in_storage_components: Self::to_sorted(&self.in_storage_components),
+            description_components: Self::to_sorted(&self.description_components),
+            dialogue_components: Self::to_sorted(&self.dialogue_components),
+            path_components: Self::to_sorted(&self.path_components),
+            flag_components: Self::to_sorted(&self.flag_components),
+            quest_components: Self::to_sorted(&self.quest_components),
+        }
+    }
 
-        let tree_hitbox_min = tree_position.sub_element_wise(Point3::new(0.51, 0.51, 0.51));
-        let tree_hitbox_max = tree_position.add_element_wise(Point3::new(0.51, 0.51, 0.51));
-        let tree_hitbox = Hitbox {
-            box_corner_min: tree_hitbox_min,
-            box_corner_max: tree_hitbox_max,
+    pub fn from_saved(saved: SavedGameState) -> Self {
+        let mut game_state = Self {
+            entity_allocator: saved.entity_allocator,
+            spatial_grid: SpatialGrid::new(SPATIAL_GRID_CELL_SIZE),
+            entity_names: saved
+                .name_components
+                .iter()
+                .map(|(entity, name)| (name.clone(), *entity))
+                .collect(),
+            name_components: saved.name_components.into_iter().collect(),
+
+            entities: saved.entities,
+            kind_components: saved.kind_components.into_iter().collect(),
+            graphics_3d_components: saved.graphics_3d_components.into_iter().collect(),
+            graphics_2d_components: saved.graphics_2d_components.into_iter().collect(),
+            position_components: saved.position_components.into_iter().collect(),
+            surface_components: saved.surface_components.into_iter().collect(),
+            size_components: saved.size_components.into_iter().collect(),
+            rotation_components: saved.rotation_components.into_iter().collect(),
+            collider_components: saved.collider_components.into_iter().collect(),
+            health_components: saved.health_components.into_iter().collect(),
+            camera_components: saved.camera_components.into_iter().collect(),
+            camera_target_components: saved.camera_target_components.into_iter().collect(),
+            storable_components: saved.storable_components.into_iter().collect(),
+            storage_components: saved.storage_components.into_iter().collect(),
+            in_storage_components: saved.in_storage_components.into_iter().collect(),
+            description_components: saved.description_components.into_iter().collect(),
+            dialogue_components: saved.dialogue_components.into_iter().collect(),
+            path_components: saved.path_components.into_iter().collect(),
+            flag_components: saved.flag_components.into_iter().collect(),
+            quest_components: saved.quest_components.into_iter().collect(),
         };
-        hitbox_components.insert(tree.clone(), tree_hitbox);
 
-        description_components.insert(
-            tree.clone(),
-            Description {
-                text: "Tree of life".to_owned(),
-            },
-        );
+        for entity in game_state.entities.clone() {
+            game_state.update_spatial_grid(entity);
+        }
+        game_state
     }
 
-    fn load_tiles(
-        entities: &mut Vec<Entity>,
-        graphics_3d_components: &mut HashMap<String, Graphics3D>,
-        position_components: &mut HashMap<String, Point3<f32>>,
-        surface_components: &mut HashSet<String>,
-    ) {
-        let plane_longitude_minimum: i8 = -10;
-        let plane_longitude_maximum: i8 = 10;
-        let plane_latitude_minimum: i8 = -10;
-        let plane_latitude_maximum: i8 = 10;
-        for x in plane_longitude_minimum..plane_longitude_maximum {
-            for z in plane_latitude_minimum..plane_latitude_maximum {
-                let plane = "plane".to_owned() + &x.to_string() + &z.to_string();
-                entities.push(plane.clone());
-
-                let plane_graphics = Graphics3D {
-                    model_id: "grass".to_owned(),
-                };
-                graphics_3d_components.insert(plane.clone(), plane_graphics);
-
-                let plane_position = Point3 {
-                    x: f32::from(x),
-                    y: 0.0,
-                    z: f32::from(z),
-                };
-                position_components.insert(plane.clone(), plane_position);
-
-                surface_components.insert(plane.clone());
-            }
+    pub fn to_saved_entity(&self, entity: EntityId) -> SavedEntity {
+        SavedEntity {
+            entity,
+            name: self.name_components.get(&entity).cloned(),
+            kind: self.kind_components.get(&entity).cloned(),
+            graphics_3d: self.graphics_3d_components.get(&entity).cloned(),
+            graphics_2d: self.graphics_2d_components.get(&entity).cloned(),
+            position: self.position_components.get(&entity).copied(),
+            is_surface: self.surface_components.contains(&entity),
+            size: self.size_components.get(&entity).cloned(),
+            rotation: self.rotation_components.get(&entity).cloned(),
+            collider: self.collider_components.get(&entity).cloned(),
+            health: self.health_components.get(&entity).cloned(),
+            camera: self.camera_components.get(&entity).cloned(),
+            camera_target: self.camera_target_components.get(&entity).cloned(),
+            storable: self.storable_components.get(&entity).cloned(),
+            storage: self.storage_components.get(&entity).cloned(),
+            in_storage: self.in_storage_components.get(&entity).cloned(),
+            description: self.description_components.get(&entity).cloned(),
+            dialogue: self.dialogue_components.get(&entity).cloned(),
+            path: self.path_components.get(&entity).cloned(),
+            flags: self.flag_components.get(&entity).cloned(),
+            quests: self.quest_components.get(&entity).cloned(),
         }
     }
 
-    #[allow(clippy::too_many_arguments)]
-    fn load_swords(
-        entities: &mut Vec<Entity>,
-        graphics_3d_components: &mut HashMap<String, Graphics3D>,
-        graphics_2d_components: &mut HashMap<String, Graphics2D>,
-        position_components: &mut HashMap<String, Point3<f32>>,
-        size_components: &mut HashMap<String, Scale>,
-        hitbox_components: &mut HashMap<String, Hitbox>,
-        storable_components: &mut HashMap<String, Storable>,
-        description_components: &mut HashMap<String, Description>,
-    ) {
-        let sword = "sword".to_owned();
-        for i in 1..71 {
-            let sword_id = sword.clone() + &i.to_string();
-            entities.push(sword_id.clone());
-
-            let sword_graphics = Graphics3D {
-                model_id: sword.clone(),
-            };
-            graphics_3d_components.insert(sword_id.clone(), sword_graphics);
-
-            let sword_graphics_inventory = Graphics2D {
-                material_id: "sword_inventory".to_owned(),
-            };
-            graphics_2d_components.insert(sword_id.clone(), sword_graphics_inventory);
-
-            let sword_position = Point3 {
-                x: i.to_f32().unwrap() + 0.1,
-                y: 0.75,
-                z: i.to_f32().unwrap() + 0.1,
-            };
-            position_components.insert(sword_id.clone(), sword_position);
-
-            let size = Scale {
-                x: 0.5,
-                y: 0.5,
-                z: 0.5,
-            };
-            size_components.insert(sword_id.clone(), size);
-
-            let sword_hitbox_min = sword_position.sub_element_wise(Point3::new(0.26, 0.26, 0.26));
-            let sword_hitbox_max = sword_position.add_element_wise(Point3::new(0.26, 0.26, 0.26));
-            let sword_hitbox = Hitbox {
-                box_corner_min: sword_hitbox_min,
-                box_corner_max: sword_hitbox_max,
-            };
-            hitbox_components.insert(sword_id.clone(), sword_hitbox);
+    // Keeps the id the entity had where it was saved. An entity already living under that id gets all its components replaced
+    // Another generation living at the same index is despawned, otherwise its components and name would end up on this entity
+    // Ids past MAX_ENTITIES are not loaded
+    pub fn load_saved_entity(&mut self, saved: SavedEntity) {
+        let entity = saved.entity;
+        if !self.is_alive(entity) {
+            if let Some(old_entity) = self.entity_allocator.alive_at(entity) {
+                self.despawn(old_entity);
+            }
+            if !self.entity_allocator.allocate_at(entity) {
+                #[cfg(feature = "debug-logging")]
+                log::warn!("Not loading entity {entity}, its index is too large");
+                return;
+            }
+            self.entities.push(entity);
+        }
+        match &saved.name {
+            Some(name) => self.set_name(entity, name),
+            None => {
+                if let Some(name) = self.name_components.remove(&entity) {
+                    self.entity_names.remove(&name);
+                }
+            }
+        }
 
-            let sword_storable = Storable {
-                shape: ItemShape {
-                    width: 1,
-                    height: 1,
-                },
-            };
-            storable_components.insert(sword_id.clone(), sword_storable);
-            description_components.insert(
-                sword_id.clone(),
-                Description {
-                    text: "Sword of Tungstenator".to_owned(),
-                },
-            );
+        Self::set_component(&mut self.kind_components, entity, saved.kind);
+        Self::set_component(&mut self.graphics_3d_components, entity, saved.graphics_3d);
+        Self::set_component(&mut self.graphics_2d_components, entity, saved.graphics_2d);
+        Self::set_component(&mut self.position_components, entity, saved.position);
+        if saved.is_surface {
+            self.surface_components.insert(entity);
+        } else {
+            self.surface_components.remove(&entity);
         }
+        Self::set_component(&mut self.size_components, entity, saved.size);
+        Self::set_component(&mut self.rotation_components, entity, saved.rotation);
+        Self::set_component(&mut self.collider_components, entity, saved.collider);
+        Self::set_component(&mut self.health_components, entity, saved.health);
+        Self::set_component(&mut self.camera_components, entity, saved.camera);
+        Self::set_component(
+            &mut self.camera_target_components,
+            entity,
+            saved.camera_target,
+        );
+        Self::set_component(&mut self.storable_components, entity, saved.storable);
+        Self::set_component(&mut self.storage_components, entity, saved.storage);
+        Self::set_component(&mut self.in_storage_components, entity, saved.in_storage);
+        Self::set_component(&mut self.description_components, entity, saved.description);
+        Self::set_component(&mut self.dialogue_components, entity, saved.dialogue);
+        Self::set_component(&mut self.path_components, entity, saved.path);
+        Self::set_component(&mut self.flag_components, entity, saved.flags);
+        Self::set_component(&mut self.quest_components, entity, saved.quests);
+        self.update_spatial_grid(entity);
     }
 
-    #[allow(clippy::too_many_arguments)]
-    fn load_shield(
-        entities: &mut Vec<Entity>,
-        graphics_3d_components: &mut HashMap<Entity, Graphics3D>,
-        graphics_2d_components: &mut HashMap<Entity, Graphics2D>,
-        position_components: &mut HashMap<Entity, Point3<f32>>,
-        size_components: &mut HashMap<Entity, Scale>,
-        hitbox_components: &mut HashMap<Entity, Hitbox>,
-        storable_components: &mut HashMap<Entity, Storable>,
-        description_components: &mut HashMap<String, Description>,
+    fn set_component<T>(
+        components: &mut HashMap<EntityId, T>,
+        entity: EntityId,
+        component: Option<T>,
     ) {
-        let shield_id = "shield".to_owned();
-        entities.push(shield_id.clone());
-        let shield_graphics = Graphics3D {
-            model_id: shield_id.clone(),
+        match component {
+            Some(component) => components.insert(entity, component),
+            None => components.remove(&entity),
         };
-        graphics_3d_components.insert(shield_id.clone(), shield_graphics);
-
-        let shield_graphics_inventory = Graphics2D {
-            material_id: "shield_inventory".to_owned(),
-        };
-        graphics_2d_components.insert(shield_id.clone(), shield_graphics_inventory);
-
-        let shield_position = Point3 {
-            x: -2.8,
-            y: 0.75,
-            z: -2.7,
-        };
-        position_components.insert(shield_id.clone(), shield_position);
+    }
 
-        let size = Scale {
-            x: 0.5,
-            y: 0.5,
-            z: 0.5,
-        };
-        size_components.insert(shield_id.clone(), size);
+    fn to_sorted<T: Clone>(components: &HashMap<EntityId, T>) -> Vec<(EntityId, T)> {
+        let mut sorted: Vec<(EntityId, T)> = components
+            .iter()
+            .map(|(entity, component)| (*entity, component.clone()))
+            .collect();
+        sorted.sort_by_key(|(entity, _)| *entity);
+        sorted
+    }
 
-        let shield_hitbox_min = shield_position.sub_element_wise(Point3::new(0.26, 0.26, 0.26));
-        let shield_hitbox_max = shield_position.add_element_wise(Point3::new(0.26, 0.26, 0.26));
-        let shield_hitbox = Hitbox {
-            box_corner_min: shield_hitbox_min,
-            box_corner_max: shield_hitbox_max,
+    // World definition is validated on load, so we can assume ids are unique and shapes are valid
+    pub fn load_entity(&mut self, definition: &EntityDefinition) -> EntityId {
+        let entity = match &definition.id {
+            Some(name) => self.spawn_named(name),
+            None => self.spawn(),
         };
-        hitbox_components.insert(shield_id.clone(), shield_hitbox);
 
-        let shield_storable = Storable {
-            shape: ItemShape {
-                width: 1,
-                height: 2,
-            },
-        };
-        storable_components.insert(shield_id.clone(), shield_storable);
-        description_components.insert(
-            shield_id.clone(),
-            Description {
-                text: "Shield of Hydrogax".to_owned(),
-            },
-        );
-    }
+        if let Some(template) = &definition.template {
+            self.kind_components.insert(entity, template.clone());
+        }
 
-    #[allow(clippy::too_many_arguments)]
-    fn load_player(
-        entities: &mut Vec<Entity>,
-        graphics_3d_components: &mut HashMap<Entity, Graphics3D>,
-        position_components: &mut HashMap<Entity, Point3<f32>>,
-        rotation_components: &mut HashMap<Entity, Rotation>,
-        hitbox_components: &mut HashMap<Entity, Hitbox>,
-        health_components: &mut HashMap<Entity, Health>,
-        camera_target_components: &mut HashMap<Entity, CameraTarget>,
-        storage_components: &mut HashMap<Entity, Storage>,
-        description_components: &mut HashMap<String, Description>,
-    ) {
-        let player = "player".to_owned();
-        entities.push(player.clone());
+        if let Some(model_id) = &definition.graphics_3d {
+            self.graphics_3d_components.insert(
+                entity,
+                Graphics3D {
+                    model_id: model_id.clone(),
+                },
+            );
+        }
 
-        let player_graphics = Graphics3D {
-            model_id: "Gozer".to_owned(), // Matches mesh name in gltf
-        };
-        graphics_3d_components.insert(player.clone(), player_graphics);
+        if let Some(material_id) = &definition.graphics_2d {
+            self.graphics_2d_components.insert(
+                entity,
+                Graphics2D {
+                    material_id: material_id.clone(),
+                },
+            );
+        }
 
-        let player_position = Point3 {
-            x: 0.0,
-            y: 0.5,
-            z: 0.0,
-        };
-        position_components.insert(player.clone(), player_position);
+        if let Some(position) = definition.position {
+            self.position_components
+                .insert(entity, Point3::from(position));
+        }
 
-        let rotation = Rotation { degrees_y: 50.0 };
-        rotation_components.insert(player.clone(), rotation);
+        if let Some(collider) = &definition.collider {
+            let collider = match *collider {
+                ColliderDefinition::Box {
+                    min_offset,
+                    max_offset,
+                } => Collider::Box {
+                    min_offset: Vector3::from(min_offset),
+                    max_offset: Vector3::from(max_offset),
+                },
+                ColliderDefinition::Cylinder { radius, height } => {
+                    Collider::Cylinder { radius, height }
+                }
+            };
+            self.collider_components.insert(entity, collider);
+        }
 
-        let player_hitbox_min = player_position.sub_element_wise(Point3::new(0.1, 0.0, 0.1));
-        let player_hitbox_max = player_position.add_element_wise(Point3::new(0.1, 1.8, 0.1));
-        let player_hitbox = Hitbox {
-            box_corner_min: player_hitbox_min,
-            box_corner_max: player_hitbox_max,
-        };
-        hitbox_components.insert(player.clone(), player_hitbox);
+        if definition.surface {
+            self.surface_components.insert(entity);
+        }
 
-        health_components.insert(
-            player.clone(),
-            Health {
-                hitpoints: 100,
-                max_hitpoints: 100,
-            },
-        );
+        if let Some(scale) = definition.scale {
+            self.size_components.insert(
+                entity,
+                Scale {
+                    x: scale[0],
+                    y: scale[1],
+                    z: scale[2],
+                },
+            );
+        }
 
-        let camera_target = CameraTarget {
-            distance: f32::sqrt(ROUGHLY_CAMERA_DISTANCE / 3.0),
-            rotation_x_degrees: 225.0,
-            rotation_y_degrees: 315.0,
-        };
-        camera_target_components.insert(player.clone(), camera_target);
+        if let Some(degrees_y) = definition.rotation_y_degrees {
+            self.rotation_components
+                .insert(entity, Rotation { degrees_y });
+        }
 
-        let player_storage = Storage {
-            number_of_rows: 8,
-            number_of_columns: 8,
-        };
-        storage_components.insert(player.clone(), player_storage);
-        description_components.insert(
-            player.clone(),
-            Description {
-                text: "That's me!".to_owned(),
-            },
-        );
-    }
+        if let Some(health) = &definition.health {
+            self.health_components.insert(
+                entity,
+                Health {
+                    hitpoints: health.hitpoints,
+                    max_hitpoints: health.max_hitpoints,
+                },
+            );
+        }
 
-    fn load_npc(
-        entities: &mut Vec<Entity>,
-        graphics_3d_components: &mut HashMap<Entity, Graphics3D>,
-        position_components: &mut HashMap<Entity, Point3<f32>>,
-        hitbox_components: &mut HashMap<Entity, Hitbox>,
-        description_components: &mut HashMap<String, Description>,
-        dialogue_components: &mut HashMap<String, Dialogue>,
-    ) {
-        let npc = "Dennis".to_owned();
-        entities.push(npc.clone());
+        if let Some(camera_target) = &definition.camera_target {
+            self.camera_target_components.insert(
+                entity,
+                CameraTarget {
+                    distance: camera_target.distance,
+                    rotation_x_degrees: camera_target.rotation_x_degrees,
+                    rotation_y_degrees: camera_target.rotation_y_degrees,
+                },
+            );
+        }
 
-        let player_graphics = Graphics3D {
-            model_id: "Gozer".to_owned(), // matches name in gltf mesh
-        };
-        graphics_3d_components.insert(npc.clone(), player_graphics);
+        if let Some(shape) = &definition.storable {
+            self.storable_components.insert(
+                entity,
+                Storable {
+                    shape: ItemShape {
+                        width: shape.width,
+                        height: shape.height,
+                    },
+                },
+            );
+        }
 
-        let player_position = Point3 {
-            x: -3.0,
-            y: 0.5,
-            z: 2.0,
-        };
-        position_components.insert(npc.clone(), player_position);
+        if let Some(storage) = &definition.storage {
+            self.storage_components.insert(
+                entity,
+                Storage {
+                    number_of_rows: storage.number_of_rows,
+                    number_of_columns: storage.number_of_columns,
+                },
+            );
+        }
 
-        let npc_hitbox_min = player_position.sub_element_wise(Point3::new(0.1, 0.0, 0.1));
-        let npc_hitbox_max = player_position.add_element_wise(Point3::new(0.1, 1.8, 0.1));
-        let npc_hitbox = Hitbox {
-            box_corner_min: npc_hitbox_min,
-            box_corner_max: npc_hitbox_max,
-        };
-        hitbox_components.insert(npc.clone(), npc_hitbox);
+        if let Some(text) = &definition.description {
+            self.description_components
+                .insert(entity, Description { text: text.clone() });
+        }
 
-        description_components.insert(
-            npc.clone(),
-            Description {
-                text: "Dennis is a menace.".to_owned(),
-            },
-        );
+        if let Some(dialogue_id) = &definition.dialogue {
+            self.dialogue_components.insert(
+                entity,
+                Dialogue {
+                    dialogue_id: dialogue_id.clone(),
+                },
+            );
+        }
 
-        dialogue_components.insert(
-            npc.clone(),
-            Dialogue {
-                dialogue_id: "dennis_intro".to_owned(),
-            },
-        );
+        self.update_spatial_grid(entity);
+        entity
     }
 
-    fn load_camera_3d(entities: &mut Vec<Entity>, camera_components: &mut HashMap<String, Camera>) {
-        let camera_id = "camera_3d".to_owned();
-        entities.push(camera_id.clone());
+    fn load_camera_3d(&mut self) {
+        let camera_id = self.spawn_named("camera_3d");
         let camera_component = Camera::new();
-        camera_components.insert(camera_id.clone(), camera_component);
+        self.camera_components.insert(camera_id, camera_component);
     }
 
-    fn load_camera_ui(entities: &mut Vec<Entity>, camera_components: &mut HashMap<String, Camera>) {
-        let camera_id = "camera_ui".to_owned();
+    fn load_camera_ui(&mut self) {
+        let camera_id = self.spawn_named("camera_ui");
         let mut camera_component = Camera::new();
         camera_component.eye = Point3 {
             x: 0.0,
@@ -457,98 +415,438 @@ impl GameState {
 
         camera_component.z_near = -1.0;
         camera_component.z_far = 1.0;
-        entities.push(camera_id.clone());
-        camera_components.insert(camera_id.clone(), camera_component);
+        self.camera_components.insert(camera_id, camera_component);
     }
 
-    pub fn get_graphics(&self, entity: &str) -> Option<&Graphics3D> {
-        self.graphics_3d_components.get(entity)
+    pub fn spawn(&mut self) -> EntityId {
+        let entity = self.entity_allocator.allocate();
+        self.entities.push(entity);
+        entity
     }
 
-    #[allow(dead_code)]
-    pub fn get_graphics_inventory(&self, entity: &str) -> Option<&Graphics2D> {
-        self.graphics_2d_components.get(entity)
+    // Names are unique: spawning a second entity with the same name takes over the name
+    pub fn spawn_named(&mut self, name: &str) -> EntityId {
+        let entity = self.spawn();
+        self.set_name(entity, name);
+        entity
+    }
+
+    // Replaces the name the entity had, if any
+    pub fn set_name(&mut self, entity: EntityId, name: &str) {
+        if let Some(old_name) = self.name_components.remove(&entity) {
+            self.entity_names.remove(&old_name);
+        }
+        if let Some(previous) = self.entity_names.insert(name.to_owned(), entity) {
+            self.name_components.remove(&previous);
+        }
+        self.name_components.insert(entity, name.to_owned());
     }
 
-    // Note: On a position change, also consider updating the hitbox
-    pub fn create_position(&mut self, entity: &str, position: Point3<f32>) {
-        self.position_components.insert(entity.to_owned(), position);
+    // Removes the entity from every component store. Items stored inside the entity are despawned with it, otherwise they would point to a storage that no longer exists
+    // Systems should not call this in the middle of an update, but request it through EntityCommand so it gets applied at the end of the tick
+    // Stale handles are ignored
+    pub fn despawn(&mut self, entity: EntityId) -> bool {
+        if !self.entity_allocator.free(entity) {
+            return false;
+        }
+
+        self.entities.retain(|e| *e != entity);
+        self.spatial_grid.remove(entity);
+        if let Some(name) = self.name_components.remove(&entity) {
+            self.entity_names.remove(&name);
+        }
+
+        self.kind_components.remove(&entity);
+        self.graphics_3d_components.remove(&entity);
+        self.graphics_2d_components.remove(&entity);
+        self.position_components.remove(&entity);
+        self.surface_components.remove(&entity);
+        self.size_components.remove(&entity);
+        self.rotation_components.remove(&entity);
+        self.collider_components.remove(&entity);
+        self.health_components.remove(&entity);
+        self.camera_components.remove(&entity);
+        self.camera_target_components.remove(&entity);
+        self.storable_components.remove(&entity);
+        self.storage_components.remove(&entity);
+        self.in_storage_components.remove(&entity);
+        self.description_components.remove(&entity);
+        self.dialogue_components.remove(&entity);
+        self.path_components.remove(&entity);
+        self.flag_components.remove(&entity);
+        self.quest_components.remove(&entity);
+
+        let stored_items: Vec<EntityId> = self.get_in_storages(entity).into_keys().collect();
+        for stored_item in stored_items {
+            self.despawn(stored_item);
+        }
+        true
     }
 
-    pub fn get_position(&self, entity: &str) -> Option<&Point3<f32>> {
-        self.position_components.get(entity)
+    pub fn is_alive(&self, entity: EntityId) -> bool {
+        self.entity_allocator.is_alive(entity)
+    }
+
+    pub fn get_entity(&self, name: &str) -> Option<EntityId> {
+        self.entity_names.get(name).copied()
+    }
+
+    pub fn get_name(&self, entity: EntityId) -> Option<&str> {
+        self.name_components.get(&entity).map(String::as_str)
+    }
+
+    pub fn get_graphics(&self, entity: EntityId) -> Option<&Graphics3D> {
+        self.graphics_3d_components.get(&entity)
     }
 
     #[allow(dead_code)]
-    pub fn get_position_mut(&mut self, entity: &str) -> Option<&mut Point3<f32>> {
-        self.position_components.get_mut(entity)
+    pub fn get_graphics_inventory(&self, entity: EntityId) -> Option<&Graphics2D> {
+        self.graphics_2d_components.get(&entity)
     }
 
-    // Note: On a position change, also consider updating the hitbox
-    pub fn remove_position(&mut self, to_remove: &str) {
-        self.position_components.remove(to_remove);
+    pub fn create_position(&mut self, entity: EntityId, position: Point3<f32>) {
+        self.position_components.insert(entity, position);
+        self.update_spatial_grid(entity);
     }
 
-    pub fn get_size(&self, entity: &str) -> Option<&Scale> {
-        self.size_components.get(entity)
+    pub fn get_position(&self, entity: EntityId) -> Option<&Point3<f32>> {
+        self.position_components.get(&entity)
     }
 
-    pub fn get_rotation(&self, entity: &str) -> Option<&Rotation> {
-        self.rotation_components.get(entity)
+    pub fn remove_position(&mut self, to_remove: EntityId) {
+        self.position_components.remove(&to_remove);
+        self.spatial_grid.remove(to_remove);
     }
 
-    pub fn create_hitbox(&mut self, entity: &str, hitbox: Hitbox) {
-        self.hitbox_components.insert(entity.to_owned(), hitbox);
+    pub fn get_size(&self, entity: EntityId) -> Option<&Scale> {
+        self.size_components.get(&entity)
     }
 
-    pub fn get_hitbox(&self, entity: &str) -> Option<&Hitbox> {
-        self.hitbox_components.get(entity)
+    pub fn get_rotation(&self, entity: EntityId) -> Option<&Rotation> {
+        self.rotation_components.get(&entity)
     }
 
-    pub fn remove_hitbox(&mut self, to_remove: &str) {
-        self.hitbox_components.remove(to_remove);
+    pub fn set_rotation(&mut self, entity: EntityId, rotation: Rotation) {
+        self.rotation_components.insert(entity, rotation);
+        self.update_spatial_grid(entity); // Rotating a box collider changes its bounds
     }
 
-    pub fn get_camera_target(&self, entity: &str) -> Option<&CameraTarget> {
-        self.camera_target_components.get(entity)
+    // Only entities placed in the world have a hitbox
+    pub fn get_hitbox(&self, entity: EntityId) -> Option<Hitbox> {
+        self.get_world_collider(entity)
+            .map(|collider| collider.bounds())
     }
 
-    pub fn get_camera_target_mut(&mut self, entity: &str) -> Option<&mut CameraTarget> {
-        self.camera_target_components.get_mut(entity)
+    pub fn get_world_collider(&self, entity: EntityId) -> Option<WorldCollider> {
+        let position = self.get_position(entity)?;
+        self.get_world_collider_at(entity, *position)
     }
 
-    // #[allow(dead_code)]
-    // pub fn get_camera(&self, entity: &str) -> Option<&Camera> {
-    //     self.camera_components.get(entity)
-    // }
+    // Where the collider would be if the entity was moved to the given position
+    pub fn get_world_collider_at(
+        &self,
+        entity: EntityId,
+        position: Point3<f32>,
+    ) -> Option<WorldCollider> {
+        self.collider_components.get(&entity).map(|collider| {
+            collider.place_in_world(position, self.get_size(entity), self.get_rotation(entity))
+        })
+    }
 
-    pub fn get_camera_mut(&mut self, entity: &str) -> Option<&mut Camera> {
-        self.camera_components.get_mut(entity)
+    pub fn has_flag(&self, entity: EntityId, flag: &str) -> bool {
+        self.flag_components
+            .get(&entity)
+            .is_some_and(|flags| flags.flags.contains(flag))
     }
 
-    pub fn get_storage(&self, entity: &str) -> Option<&Storage> {
-        self.storage_components.get(entity)
+    pub fn set_flag(&mut self, entity: EntityId, flag: &str) {
+        self.flag_components
+            .entry(entity)
+            .or_default()
+            .flags
+            .insert(flag.to_owned());
     }
 
-    pub fn create_in_storage(&mut self, storage_entity: &str, to_store: &str, spot: (u8, u8)) {
+    // The component goes once the last flag does, so an entity that never had flags looks the same
+    pub fn clear_flag(&mut self, entity: EntityId, flag: &str) {
+        if let Some(flags) = self.flag_components.get_mut(&entity) {
+            flags.flags.remove(flag);
+            if flags.flags.is_empty() {
+                self.flag_components.remove(&entity);
+            }
+        }
+    }
+
+    pub fn get_camera_target(&self, entity: EntityId) -> Option<&CameraTarget> {
+        self.camera_target_components.get(&entity)
+    }
+
+    pub fn get_camera_target_mut(&mut self, entity: EntityId) -> Option<&mut CameraTarget> {
+        self.camera_target_components.get_mut(&entity)
+    }
+
+    pub fn get_camera(&self, entity: EntityId) -> Option<&Camera> {
+        self.camera_components.get(&entity)
+    }
+
+    pub fn get_camera_mut(&mut self, entity: EntityId) -> Option<&mut Camera> {
+        self.camera_components.get_mut(&entity)
+    }
+
+    pub fn get_storage(&self, entity: EntityId) -> Option<&Storage> {
+        self.storage_components.get(&entity)
+    }
+
+    pub fn create_in_storage(
+        &mut self,
+        storage_entity: EntityId,
+        to_store: EntityId,
+        spot: (u8, u8),
+    ) {
+        self.create_in_storage_rotated(storage_entity, to_store, spot, false);
+    }
+
+    pub fn create_in_storage_rotated(
+        &mut self,
+        storage_entity: EntityId,
+        to_store: EntityId,
+        spot: (u8, u8),
+        is_rotated: bool,
+    ) {
         let in_storage_component = InStorage {
-            storage_entity: storage_entity.to_owned(),
+            storage_entity,
             position_x: spot.0,
             position_y: spot.1,
+            is_rotated,
         };
         self.in_storage_components
-            .insert(to_store.to_owned(), in_storage_component);
+            .insert(to_store, in_storage_component);
     }
 
-    pub fn remove_in_storage(&mut self, entity: &str) {
-        self.in_storage_components.remove(entity);
+    pub fn remove_in_storage(&mut self, entity: EntityId) {
+        self.in_storage_components.remove(&entity);
     }
 
-    #[allow(dead_code)]
-    pub fn get_in_storages(&self, storage_entity: &str) -> HashMap<&Entity, &InStorage> {
+    pub fn get_in_storages(&self, storage_entity: EntityId) -> HashMap<EntityId, &InStorage> {
         self.in_storage_components
             .iter()
-            .filter(|(_, in_storage)| in_storage.storage_entity == *storage_entity)
+            .filter(|(_, in_storage)| in_storage.storage_entity == storage_entity)
+            .map(|(entity, in_storage)| (*entity, in_storage))
             .collect()
     }
+
+    // Entities without a position are not in the world, so not in the grid either
+    fn update_spatial_grid(&mut self, entity: EntityId) {
+        let Some(position) = self.get_position(entity) else {
+            self.spatial_grid.remove(entity);
+            return;
+        };
+
+        let (min, max) = if let Some(hitbox) = self.get_hitbox(entity) {
+            (
+                Point2::new(hitbox.box_corner_min.x, hitbox.box_corner_min.z),
+                Point2::new(hitbox.box_corner_max.x, hitbox.box_corner_max.z),
+            )
+        } else if self.surface_components.contains(&entity) {
+            let half_tile = Vector2::new(SURFACE_TILE_HALF_SIZE, SURFACE_TILE_HALF_SIZE);
+            let center = Point2::new(position.x, position.z);
+            (center - half_tile, center + half_tile)
+        } else {
+            let point = Point2::new(position.x, position.z);
+            (point, point)
+        };
+        self.spatial_grid.update(entity, min, max);
+    }
+
+    // Spatial queries only look at the xz plane and return candidates: callers still do their exact check on the result
+    pub fn get_entities_in_area(&self, min: Point2<f32>, max: Point2<f32>) -> Vec<EntityId> {
+        self.spatial_grid.query_area(min, max)
+    }
+
+    pub fn get_entities_at(&self, point: Point2<f32>) -> Vec<EntityId> {
+        self.spatial_grid.query_point(point)
+    }
+
+    pub fn get_entities_along_ray(
+        &self,
+        origin: Point2<f32>,
+        direction: Vector2<f32>,
+    ) -> Vec<EntityId> {
+        self.spatial_grid.query_ray(origin, direction)
+    }
+
+    // Summary of everything the simulation changes, used to check that a replay ends up where the recording did
+    // Maps iterate in a random order, so we go over the entities in the order they were created. Floats are hashed by their bits
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for entity in &self.entities {
+            entity.hash(&mut hasher);
+            self.position_components
+                .get(entity)
+                .map(|position| [position.x, position.y, position.z].map(f32::to_bits))
+                .hash(&mut hasher);
+            self.rotation_components
+                .get(entity)
+                .map(|rotation| rotation.degrees_y.to_bits())
+                .hash(&mut hasher);
+            self.in_storage_components
+                .get(entity)
+                .map(|in_storage| {
+                    (
+                        in_storage.storage_entity,
+                        in_storage.position_x,
+                        in_storage.position_y,
+                        in_storage.is_rotated,
+                    )
+                })
+                .hash(&mut hasher);
+            self.health_components
+                .get(entity)
+                .map(|health| (health.hitpoints, health.max_hitpoints))
+                .hash(&mut hasher);
+            self.camera_target_components
+                .get(entity)
+                .map(|camera_target| {
+                    [
+                        camera_target.distance,
+                        camera_target.rotation_x_degrees,
+                        camera_target.rotation_y_degrees,
+                    ]
+                    .map(f32::to_bits)
+                })
+                .hash(&mut hasher);
+            self.path_components
+                .get(entity)
+                .map(|path| path.waypoints.len())
+                .hash(&mut hasher);
+            self.flag_components
+                .get(entity)
+                .map(|flags| &flags.flags)
+                .hash(&mut hasher);
+            self.quest_components
+                .get(entity)
+                .map(|quest_log| &quest_log.quests)
+                .hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::components::Description;
+
+    #[test]
+    fn names_point_to_the_entity_that_has_them() {
+        let mut game_state = GameState::empty();
+        let player = game_state.spawn_named("player");
+        assert_eq!(game_state.get_entity("player"), Some(player));
+        assert_eq!(game_state.get_name(player), Some("player"));
+
+        game_state.set_name(player, "hero");
+        assert_eq!(game_state.get_entity("player"), None);
+        assert_eq!(game_state.get_entity("hero"), Some(player));
+
+        // Taking over a name leaves the other entity without one
+        let other = game_state.spawn_named("hero");
+        assert_eq!(game_state.get_entity("hero"), Some(other));
+        assert_eq!(game_state.get_name(player), None);
+
+        assert!(game_state.despawn(other));
+        assert_eq!(game_state.get_entity("hero"), None);
+        assert!(!game_state.despawn(other));
+        let reused = game_state.spawn();
+        assert_eq!(game_state.get_name(reused), None);
+        assert!(game_state.is_alive(player));
+    }
+
+    #[test]
+    fn entities_past_the_limit_are_not_loaded() {
+        let mut source = GameState::empty();
+        let entity = source.spawn_named("far_away");
+        let mut saved = source.to_saved_entity(entity);
+        saved.entity = serde_json::from_str(&format!(
+            r#"{{"index": {}, "generation": 0}}"#,
+            crate::state::entity::MAX_ENTITIES
+        ))
+        .unwrap();
+
+        let mut game_state = GameState::empty();
+        game_state.load_saved_entity(saved);
+
+        assert!(game_state.entities.is_empty());
+        assert_eq!(game_state.get_entity("far_away"), None);
+        assert_eq!(game_state.spawn(), entity);
+    }
+
+    #[test]
+    fn loading_an_entity_over_another_generation_despawns_the_old_one() {
+        // Where the entity comes from, its index was used before
+        let mut source = GameState::empty();
+        let old_entity = source.spawn();
+        source.despawn(old_entity);
+        let entity = source.spawn();
+        source.create_position(entity, Point3::new(5.0, 0.0, 5.0));
+
+        let mut game_state = GameState::empty();
+        let stale = game_state.spawn_named("stale");
+        assert_eq!(stale, old_entity);
+        game_state.description_components.insert(
+            stale,
+            Description {
+                text: "Stale".to_owned(),
+            },
+        );
+        game_state.create_position(stale, Point3::new(1.0, 0.0, 1.0));
+        let stored_item = game_state.spawn();
+        game_state.create_in_storage(stale, stored_item, (0, 0));
+
+        game_state.load_saved_entity(source.to_saved_entity(entity));
+
+        assert!(game_state.is_alive(entity));
+        assert!(!game_state.is_alive(stale));
+        assert!(!game_state.is_alive(stored_item));
+        assert_eq!(game_state.entities, vec![entity]);
+        assert_eq!(game_state.get_entity("stale"), None);
+        assert_eq!(game_state.get_name(entity), None);
+        assert!(!game_state.description_components.contains_key(&entity));
+        assert!(game_state.get_entities_at(Point2::new(1.0, 1.0)).is_empty());
+        assert_eq!(
+            game_state.get_entities_at(Point2::new(5.0, 5.0)),
+            vec![entity]
+        );
+        // The allocator hands out new indices again instead of the loaded one
+        let next = game_state.spawn();
+        assert!(game_state.is_alive(entity) && next != entity);
+    }
+
+    #[test]
+    fn loading_an_entity_again_replaces_its_components() {
+        let mut game_state = GameState::empty();
+        let entity = game_state.spawn_named("sword");
+        game_state.description_components.insert(
+            entity,
+            Description {
+                text: "Sword".to_owned(),
+            },
+        );
+        game_state.create_position(entity, Point3::new(1.0, 0.0, 1.0));
+        let mut saved = game_state.to_saved_entity(entity);
+        saved.name = Some("old sword".to_owned());
+        saved.description = None;
+        saved.position = Some(Point3::new(3.0, 0.0, 3.0));
+
+        game_state.load_saved_entity(saved);
+
+        assert_eq!(game_state.entities, vec![entity]);
+        assert_eq!(game_state.get_entity("sword"), None);
+        assert_eq!(game_state.get_entity("old sword"), Some(entity));
+        assert!(!game_state.description_components.contains_key(&entity));
+        assert!(game_state.get_entities_at(Point2::new(1.0, 1.0)).is_empty());
+        assert_eq!(
+            game_state.get_entities_at(Point2::new(3.0, 3.0)),
+            vec![entity]
+        );
+    }
 }
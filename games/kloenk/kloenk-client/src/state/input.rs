@@ -1,9 +1,10 @@
 use cgmath::Point2;
+use serde::{Deserialize, Serialize};
 use winit::dpi::PhysicalPosition;
 use winit::event::{ElementState, MouseButton, MouseScrollDelta};
 use winit::keyboard::KeyCode;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct KeyPress {
     pub is_pressed: bool,
     pub was_pressed: bool,
@@ -19,13 +20,31 @@ impl KeyPress {
         !self.was_pressed && self.is_pressed
     }
 
+    pub fn is_toggled_off(&self) -> bool {
+        self.was_pressed && !self.is_pressed
+    }
+
     pub fn update_end_frame(&mut self) {
         self.was_pressed = self.is_pressed;
     }
 }
 
+// Keys that edit text, in the order they were pressed. Held keys repeat
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TextInput {
+    Text(String),
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+}
+
 // TODO do we need to wait for a frame? Can we not do an update inbetween frame?
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Input {
     pub w_pressed: KeyPress,
     pub s_pressed: KeyPress,
@@ -35,6 +54,10 @@ pub struct Input {
     pub i_pressed: KeyPress,
     pub e_pressed: KeyPress,
     pub m_pressed: KeyPress,
+    #[serde(default)]
+    pub l_pressed: KeyPress,
+    #[serde(default)]
+    pub r_pressed: KeyPress,
 
     pub up_pressed: KeyPress,
     pub down_pressed: KeyPress,
@@ -42,9 +65,17 @@ pub struct Input {
     pub right_pressed: KeyPress,
 
     pub enter_pressed: KeyPress,
+    #[serde(default)]
+    pub escape_pressed: KeyPress,
 
     pub left_shift_pressed: KeyPress,
 
+    // While typing, pressed keys go to text_input instead of the game
+    #[serde(default)]
+    pub is_typing: bool,
+    #[serde(default)]
+    pub text_input: Vec<TextInput>,
+
     pub mouse_position_ndc: Point2<f32>,
     pub mouse_position_ui: Point2<f32>,
     pub right_mouse_clicked: KeyPress,
@@ -53,6 +84,12 @@ pub struct Input {
     pub scrolled_amount: f32,
 }
 
+impl Default for Input {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Input {
     pub fn new() -> Self {
         Input {
@@ -64,6 +101,8 @@ impl Input {
             i_pressed: KeyPress::default(),
             e_pressed: KeyPress::default(),
             m_pressed: KeyPress::default(),
+            l_pressed: KeyPress::default(),
+            r_pressed: KeyPress::default(),
 
             up_pressed: KeyPress::default(),
             down_pressed: KeyPress::default(),
@@ -71,9 +110,13 @@ impl Input {
             right_pressed: KeyPress::default(),
 
             enter_pressed: KeyPress::default(),
+            escape_pressed: KeyPress::default(),
 
             left_shift_pressed: KeyPress::default(),
 
+            is_typing: false,
+            text_input: Vec::new(),
+
             mouse_position_ndc: Point2::new(0.0, 0.0),
             mouse_position_ui: Point2::new(0.5, 0.5),
             right_mouse_clicked: KeyPress::default(),
@@ -85,6 +128,11 @@ impl Input {
 
     pub fn update(&mut self, keycode: KeyCode, state: ElementState) {
         let is_pressed = state == ElementState::Pressed;
+        // Releases still go through, so keys held down before typing do not get stuck
+        if self.is_typing && is_pressed && !matches!(keycode, KeyCode::Enter | KeyCode::Escape) {
+            self.text_input.extend(Self::text_editing_key(keycode));
+            return;
+        }
 
         match keycode {
             KeyCode::KeyW => {
@@ -115,6 +163,14 @@ impl Input {
                 self.m_pressed.set_press_state(is_pressed);
             }
 
+            KeyCode::KeyL => {
+                self.l_pressed.set_press_state(is_pressed);
+            }
+
+            KeyCode::KeyR => {
+                self.r_pressed.set_press_state(is_pressed);
+            }
+
             KeyCode::ShiftLeft => {
                 self.left_shift_pressed.set_press_state(is_pressed);
             }
@@ -139,10 +195,42 @@ impl Input {
                 self.enter_pressed.set_press_state(is_pressed);
             }
 
+            KeyCode::Escape => {
+                self.escape_pressed.set_press_state(is_pressed);
+            }
+
             _ => {}
         }
     }
 
+    // The characters a key press typed, as given by the keyboard layout
+    pub fn process_text(&mut self, text: &str) {
+        if !self.is_typing {
+            return;
+        }
+        let text: String = text
+            .chars()
+            .filter(|character| !character.is_control())
+            .collect();
+        if !text.is_empty() {
+            self.text_input.push(TextInput::Text(text));
+        }
+    }
+
+    fn text_editing_key(keycode: KeyCode) -> Option<TextInput> {
+        match keycode {
+            KeyCode::Backspace => Some(TextInput::Backspace),
+            KeyCode::Delete => Some(TextInput::Delete),
+            KeyCode::ArrowLeft => Some(TextInput::Left),
+            KeyCode::ArrowRight => Some(TextInput::Right),
+            KeyCode::Home => Some(TextInput::Home),
+            KeyCode::End => Some(TextInput::End),
+            KeyCode::PageUp => Some(TextInput::PageUp),
+            KeyCode::PageDown => Some(TextInput::PageDown),
+            _ => None,
+        }
+    }
+
     pub fn update_end_frame(&mut self) {
         self.w_pressed.update_end_frame();
         self.s_pressed.update_end_frame();
@@ -151,14 +239,18 @@ impl Input {
         self.i_pressed.update_end_frame();
         self.e_pressed.update_end_frame();
         self.m_pressed.update_end_frame();
+        self.l_pressed.update_end_frame();
+        self.r_pressed.update_end_frame();
         self.up_pressed.update_end_frame();
         self.down_pressed.update_end_frame();
         self.left_pressed.update_end_frame();
         self.right_pressed.update_end_frame();
         self.enter_pressed.update_end_frame();
+        self.escape_pressed.update_end_frame();
         self.left_shift_pressed.update_end_frame();
         self.right_mouse_clicked.update_end_frame();
         self.left_mouse_clicked.update_end_frame();
+        self.text_input.clear();
     }
 
     pub fn process_mouse_button(&mut self, button: MouseButton, state: ElementState) {
@@ -1,5 +1,15 @@
+pub mod audio;
+pub mod chat;
 pub mod components;
+pub mod entity;
 pub mod update_state;
 pub mod game_state;
 pub mod input;
+pub mod recording;
+pub mod save_game;
+pub mod spatial_grid;
+pub mod text_field;
+pub mod time;
 pub mod ui_state;
+pub mod viewport;
+pub mod world_definition;
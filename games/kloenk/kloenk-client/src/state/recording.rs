@@ -0,0 +1,170 @@
+use crate::state::game_state::GameState;
+use crate::state::input::Input;
+use crate::state::viewport::Viewport;
+use serde::{Deserialize, Serialize};
+
+// Bump when the recorded input changes shape, old recordings cannot be replayed anymore after that
+pub const RECORDING_VERSION: u32 = 1;
+
+// A played session: the input at the start of every tick and the resulting game state hash
+// Replaying the input on the same world and build should give the same hash every tick
+#[derive(Serialize, Deserialize)]
+pub struct InputRecording {
+    pub version: u32,
+    pub ticks_per_second: u32,
+    pub ticks: Vec<RecordedTick>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecordedTick {
+    pub viewport: Viewport, // UI hit checks depend on the window size, which can change during a session
+    pub input: Input,
+    pub state_hash: u64,
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Json(serde_json::Error),
+    UnsupportedVersion(u32),
+}
+
+impl std::fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordingError::Json(error) => write!(f, "invalid recording: {error}"),
+            RecordingError::UnsupportedVersion(version) => write!(
+                f,
+                "recording has version {version}, only version {RECORDING_VERSION} is supported"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+impl From<serde_json::Error> for RecordingError {
+    fn from(error: serde_json::Error) -> Self {
+        RecordingError::Json(error)
+    }
+}
+
+impl InputRecording {
+    pub fn from_json(bytes: &[u8]) -> Result<InputRecording, RecordingError> {
+        let recording: InputRecording = serde_json::from_slice(bytes)?;
+        if recording.version != RECORDING_VERSION {
+            return Err(RecordingError::UnsupportedVersion(recording.version));
+        }
+        Ok(recording)
+    }
+
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("Recording should be serializable")
+    }
+}
+
+// Input is changed by the update itself (toggles are reset at the end), so take the snapshot before updating
+pub struct InputRecorder {
+    recording: InputRecording,
+    input_before_update: Option<(Viewport, Input)>,
+}
+
+impl InputRecorder {
+    pub fn new(ticks_per_second: u32) -> Self {
+        InputRecorder {
+            recording: InputRecording {
+                version: RECORDING_VERSION,
+                ticks_per_second,
+                ticks: Vec::new(),
+            },
+            input_before_update: None,
+        }
+    }
+
+    pub fn before_update(&mut self, viewport: &Viewport, input: &Input) {
+        self.input_before_update = Some((*viewport, input.clone()));
+    }
+
+    pub fn after_update(&mut self, game_state: &GameState) {
+        let (viewport, input) = self
+            .input_before_update
+            .take()
+            .expect("Input should be recorded before the update");
+        self.recording.ticks.push(RecordedTick {
+            viewport,
+            input,
+            state_hash: game_state.state_hash(),
+        });
+    }
+
+    pub fn recording(&self) -> &InputRecording {
+        &self.recording
+    }
+}
+
+#[derive(Debug)]
+pub struct ReplayDivergence {
+    pub tick: usize,
+    pub expected_hash: u64,
+    pub actual_hash: u64,
+}
+
+impl std::fmt::Display for ReplayDivergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "game state differs from the recording at tick {}: expected hash {}, got {}",
+            self.tick, self.expected_hash, self.actual_hash
+        )
+    }
+}
+
+impl std::error::Error for ReplayDivergence {}
+
+// Feeds recorded input back in place of the live input, tick by tick
+pub struct InputReplayer {
+    recording: InputRecording,
+    next_tick: usize,
+}
+
+impl InputReplayer {
+    pub fn new(recording: InputRecording) -> Self {
+        InputReplayer {
+            recording,
+            next_tick: 0,
+        }
+    }
+
+    pub fn ticks_per_second(&self) -> u32 {
+        self.recording.ticks_per_second
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next_tick >= self.recording.ticks.len()
+    }
+
+    // Overwrites the viewport and input with the recorded ones. Does nothing once the recording has ended
+    pub fn before_update(&mut self, viewport: &mut Viewport, input: &mut Input) {
+        if let Some(recorded_tick) = self.recording.ticks.get(self.next_tick) {
+            *viewport = recorded_tick.viewport;
+            *input = recorded_tick.input.clone();
+        }
+    }
+
+    pub fn after_update(&mut self, game_state: &GameState) -> Result<(), ReplayDivergence> {
+        let Some(recorded_tick) = self.recording.ticks.get(self.next_tick) else {
+            return Ok(());
+        };
+        let tick = self.next_tick;
+        self.next_tick += 1;
+
+        let actual_hash = game_state.state_hash();
+        if actual_hash != recorded_tick.state_hash {
+            return Err(ReplayDivergence {
+                tick,
+                expected_hash: recorded_tick.state_hash,
+                actual_hash,
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,339 @@
+use crate::render::camera::Camera;
+use crate::state::components::{
+    CameraTarget, Collider, Description, Dialogue, Flags, Graphics2D, Graphics3D, Health,
+    InStorage, MovementPath, QuestLog, Rotation, Scale, Storable, Storage,
+};
+use crate::state::entity::{EntityAllocator, EntityId};
+use crate::state::game_state::GameState;
+use crate::state::time::Time;
+use cgmath::Point3;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Bump when the saved game state changes shape, and add a migration for the previous version
+pub const SAVE_VERSION: u32 = 3;
+pub const AUTOSAVE_INTERVAL_SECONDS: u64 = 30;
+
+// Index 0 upgrades a version 1 save to version 2, index 1 a version 2 save to version 3 and so on
+// Migrations work on the json, as the structs of older versions no longer exist
+const MIGRATIONS: &[fn(&mut Value)] = &[add_flag_components, add_kind_and_quest_components];
+const _: () = assert!(MIGRATIONS.len() + 1 == SAVE_VERSION as usize);
+
+// Nobody had flags before version 2
+fn add_flag_components(save_file: &mut Value) {
+    save_file["game_state"]["flag_components"] = Value::Array(Vec::new());
+}
+
+// Which template an entity was made from is lost, so quest objectives only find items in older saves by their id
+fn add_kind_and_quest_components(save_file: &mut Value) {
+    save_file["game_state"]["kind_components"] = Value::Array(Vec::new());
+    save_file["game_state"]["quest_components"] = Value::Array(Vec::new());
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveFile {
+    version: u32,
+    game_state: SavedGameState,
+}
+
+// Everything in the game state that cannot be derived from the rest. The spatial grid and name lookup are rebuilt on load
+// Component maps are stored as lists sorted by entity: json only has string keys, and this way the same state always gives the same file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedGameState {
+    pub entity_allocator: EntityAllocator,
+    pub entities: Vec<EntityId>,
+    pub name_components: Vec<(EntityId, String)>,
+    pub kind_components: Vec<(EntityId, String)>,
+    pub graphics_3d_components: Vec<(EntityId, Graphics3D)>,
+    pub graphics_2d_components: Vec<(EntityId, Graphics2D)>,
+    pub position_components: Vec<(EntityId, Point3<f32>)>,
+    pub surface_components: Vec<EntityId>,
+    pub size_components: Vec<(EntityId, Scale)>,
+    pub rotation_components: Vec<(EntityId, Rotation)>,
+    pub collider_components: Vec<(EntityId, Collider)>,
+    pub health_components: Vec<(EntityId, Health)>,
+    pub camera_components: Vec<(EntityId, Camera)>,
+    pub camera_target_components: Vec<(EntityId, CameraTarget)>,
+    pub storable_components: Vec<(EntityId, Storable)>,
+    pub storage_components: Vec<(EntityId, Storage)>,
+    pub in_storage_components: Vec<(EntityId, InStorage)>,
+    pub description_components: Vec<(EntityId, Description)>,
+    pub dialogue_components: Vec<(EntityId, Dialogue)>,
+    pub path_components: Vec<(EntityId, MovementPath)>,
+    pub flag_components: Vec<(EntityId, Flags)>,
+    pub quest_components: Vec<(EntityId, QuestLog)>,
+}
+
+// A single entity with all of its components, for sending the world to players piece by piece
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedEntity {
+    pub entity: EntityId,
+    pub name: Option<String>,
+    pub kind: Option<String>,
+    pub graphics_3d: Option<Graphics3D>,
+    pub graphics_2d: Option<Graphics2D>,
+    pub position: Option<Point3<f32>>,
+    pub is_surface: bool,
+    pub size: Option<Scale>,
+    pub rotation: Option<Rotation>,
+    pub collider: Option<Collider>,
+    pub health: Option<Health>,
+    pub camera: Option<Camera>,
+    pub camera_target: Option<CameraTarget>,
+    pub storable: Option<Storable>,
+    pub storage: Option<Storage>,
+    pub in_storage: Option<InStorage>,
+    pub description: Option<Description>,
+    pub dialogue: Option<Dialogue>,
+    pub path: Option<MovementPath>,
+    pub flags: Option<Flags>,
+    pub quests: Option<QuestLog>,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Json(serde_json::Error),
+    MissingVersion,
+    UnsupportedVersion(u32),
+}
+
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveError::Json(error) => write!(f, "invalid save: {error}"),
+            SaveError::MissingVersion => write!(f, "save has no version"),
+            SaveError::UnsupportedVersion(version) => write!(
+                f,
+                "save has version {version}, only up to version {SAVE_VERSION} is supported"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(error: serde_json::Error) -> Self {
+        SaveError::Json(error)
+    }
+}
+
+pub struct SaveGame {}
+
+impl SaveGame {
+    pub fn serialize(game_state: &GameState) -> Vec<u8> {
+        let save_file = SaveFile {
+            version: SAVE_VERSION,
+            game_state: game_state.to_saved(),
+        };
+        serde_json::to_vec(&save_file).expect("Game state should be serializable")
+    }
+
+    // A save replaces the whole world: entities added to the world definition after saving are not in it
+    pub fn deserialize(bytes: &[u8]) -> Result<GameState, SaveError> {
+        let mut save_file: Value = serde_json::from_slice(bytes)?;
+        let version = save_file
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or(SaveError::MissingVersion)?;
+        let version = u32::try_from(version).unwrap_or(u32::MAX);
+        if version == 0 || version > SAVE_VERSION {
+            return Err(SaveError::UnsupportedVersion(version));
+        }
+
+        for migration in &MIGRATIONS[version as usize - 1..] {
+            migration(&mut save_file);
+        }
+        save_file["version"] = Value::from(SAVE_VERSION);
+
+        let save_file: SaveFile = serde_json::from_value(save_file)?;
+        Ok(GameState::from_saved(save_file.game_state))
+    }
+
+    pub fn should_autosave(time: &Time) -> bool {
+        let interval_ticks = AUTOSAVE_INTERVAL_SECONDS * u64::from(time.ticks_per_second());
+        time.tick() > 0 && time.tick().is_multiple_of(interval_ticks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::components::{MovementPath, PathInteraction, QuestProgress};
+    use crate::state::world_definition::WorldDefinition;
+    use std::collections::{BTreeMap, VecDeque};
+
+    const ADDED_IN_VERSION_2: &[&str] = &["flag_components"];
+    const ADDED_IN_VERSION_3: &[&str] = &["kind_components", "quest_components"];
+
+    // The world from the assets, played for a bit so every component map has something in it
+    fn played_game_state() -> GameState {
+        let world = WorldDefinition::from_json(include_bytes!("../../assets/world.json"))
+            .expect("World should load");
+        let mut game_state = GameState::new(&world);
+        let player = game_state
+            .get_entity("player")
+            .expect("Player should exist");
+        let dennis = game_state
+            .get_entity("Dennis")
+            .expect("Dennis should exist");
+        let shield = game_state
+            .get_entity("shield")
+            .expect("Shield should exist");
+
+        game_state.remove_position(shield);
+        game_state.create_in_storage_rotated(player, shield, (1, 2), true);
+        game_state.set_flag(player, "met_dennis");
+        game_state.quest_components.insert(
+            player,
+            QuestLog {
+                quests: BTreeMap::from([(
+                    "find_shield".to_owned(),
+                    QuestProgress {
+                        stage: 1,
+                        objective_counts: vec![2],
+                        is_completed: false,
+                    },
+                )]),
+            },
+        );
+        game_state.path_components.insert(
+            player,
+            MovementPath {
+                waypoints: VecDeque::from([Point3::new(1.5, 0.5, 2.5)]),
+                on_arrival: Some(PathInteraction::Talk { npc: dennis }),
+            },
+        );
+
+        // A reused index, so the allocator has a generation other than 0 to keep
+        let sword = game_state.get_entity("sword1").expect("Sword should exist");
+        game_state.despawn(sword);
+        let dropped = game_state.spawn_named("dropped");
+        game_state.create_position(dropped, Point3::new(0.5, 0.5, 0.5));
+        game_state
+    }
+
+    fn saved_json(game_state: &GameState) -> Value {
+        serde_json::to_value(game_state.to_saved()).expect("Game state should be serializable")
+    }
+
+    // A save as an older version wrote it, which did not have these components yet
+    fn old_save(game_state: &GameState, version: u32, missing: &[&str]) -> Vec<u8> {
+        let mut save_file: Value =
+            serde_json::from_slice(&SaveGame::serialize(game_state)).expect("Save should be json");
+        save_file["version"] = Value::from(version);
+        let saved_game_state = save_file["game_state"]
+            .as_object_mut()
+            .expect("Game state should be an object");
+        for key in missing {
+            assert!(
+                saved_game_state.remove(*key).is_some(),
+                "{key} is not saved"
+            );
+        }
+        serde_json::to_vec(&save_file).expect("Save should be serializable")
+    }
+
+    fn assert_same_state(loaded: &GameState, expected: &GameState) {
+        assert_eq!(loaded.state_hash(), expected.state_hash());
+        assert_eq!(saved_json(loaded), saved_json(expected));
+    }
+
+    #[test]
+    fn played_game_state_has_every_component() {
+        let saved = saved_json(&played_game_state());
+        let saved = saved.as_object().expect("Game state should be an object");
+        for (key, value) in saved {
+            if let Some(list) = value.as_array() {
+                assert!(
+                    !list.is_empty(),
+                    "nothing in {key}, so loading it is not tested"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn saving_and_loading_gives_the_same_state() {
+        let mut game_state = played_game_state();
+
+        let mut loaded =
+            SaveGame::deserialize(&SaveGame::serialize(&game_state)).expect("Save should load");
+
+        assert_same_state(&loaded, &game_state);
+        // Lookups that are rebuilt instead of saved
+        let player = game_state
+            .get_entity("player")
+            .expect("Player should exist");
+        assert_eq!(loaded.get_entity("player"), Some(player));
+        let position = loaded
+            .get_position(player)
+            .expect("Player should have a position");
+        assert!(
+            loaded
+                .get_entities_at(cgmath::Point2::new(position.x, position.z))
+                .contains(&player)
+        );
+        // The allocator continues where it was, so new entities do not take the id of a saved one
+        assert_eq!(loaded.spawn(), game_state.spawn());
+    }
+
+    #[test]
+    fn saving_twice_gives_the_same_file() {
+        let game_state = played_game_state();
+        let loaded =
+            SaveGame::deserialize(&SaveGame::serialize(&game_state)).expect("Save should load");
+        assert_eq!(
+            SaveGame::serialize(&loaded),
+            SaveGame::serialize(&game_state)
+        );
+    }
+
+    #[test]
+    fn version_1_saves_load_without_flags_kinds_and_quests() {
+        let game_state = played_game_state();
+        let missing = [ADDED_IN_VERSION_2, ADDED_IN_VERSION_3].concat();
+
+        let loaded = SaveGame::deserialize(&old_save(&game_state, 1, &missing))
+            .expect("Version 1 save should load");
+
+        let mut expected = game_state;
+        expected.flag_components.clear();
+        expected.kind_components.clear();
+        expected.quest_components.clear();
+        assert_same_state(&loaded, &expected);
+    }
+
+    #[test]
+    fn version_2_saves_load_without_kinds_and_quests() {
+        let game_state = played_game_state();
+
+        let loaded = SaveGame::deserialize(&old_save(&game_state, 2, ADDED_IN_VERSION_3))
+            .expect("Version 2 save should load");
+
+        let mut expected = game_state;
+        expected.kind_components.clear();
+        expected.quest_components.clear();
+        assert_same_state(&loaded, &expected);
+    }
+
+    #[test]
+    fn unknown_versions_are_not_loaded() {
+        let game_state = played_game_state();
+        for version in [0, SAVE_VERSION + 1, u32::MAX] {
+            let result = SaveGame::deserialize(&old_save(&game_state, version, &[]));
+            assert!(
+                matches!(result, Err(SaveError::UnsupportedVersion(v)) if v == version),
+                "version {version} loaded"
+            );
+        }
+        assert!(matches!(
+            SaveGame::deserialize(br#"{"game_state":{}}"#),
+            Err(SaveError::MissingVersion)
+        ));
+        assert!(matches!(
+            SaveGame::deserialize(b"not json"),
+            Err(SaveError::Json(_))
+        ));
+    }
+}
@@ -0,0 +1,426 @@
+use crate::state::entity::EntityId;
+use cgmath::{Point2, Vector2};
+use std::collections::{HashMap, HashSet};
+
+// Uniform grid over the xz plane. Our world is mostly flat, so height is left to the caller to check
+// Entities are stored in every cell their bounds overlap, queries return candidates that still need an exact check
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<EntityId>>,
+    entity_cells: HashMap<EntityId, CellRange>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct CellRange {
+    min: (i32, i32),
+    max: (i32, i32),
+}
+
+impl CellRange {
+    fn cells(self) -> impl Iterator<Item = (i32, i32)> {
+        (self.min.0..=self.max.0).flat_map(move |x| (self.min.1..=self.max.1).map(move |z| (x, z)))
+    }
+}
+
+impl SpatialGrid {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            entity_cells: HashMap::new(),
+        }
+    }
+
+    // Inserts or moves the entity. Cheap when the entity stays within the same cells, which is the common case when walking
+    pub fn update(&mut self, entity: EntityId, min: Point2<f32>, max: Point2<f32>) {
+        let range = self.cell_range(min, max);
+        if let Some(previous_range) = self.entity_cells.get(&entity) {
+            if *previous_range == range {
+                return;
+            }
+            self.remove(entity);
+        }
+
+        for cell in range.cells() {
+            self.cells.entry(cell).or_default().push(entity);
+        }
+        self.entity_cells.insert(entity, range);
+    }
+
+    pub fn remove(&mut self, entity: EntityId) {
+        let Some(range) = self.entity_cells.remove(&entity) else {
+            return;
+        };
+
+        for cell in range.cells() {
+            if let Some(cell_entities) = self.cells.get_mut(&cell) {
+                cell_entities.retain(|e| *e != entity);
+                if cell_entities.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+    }
+
+    pub fn query_area(&self, min: Point2<f32>, max: Point2<f32>) -> Vec<EntityId> {
+        let mut seen = HashSet::new();
+        self.cell_range(min, max)
+            .cells()
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .filter(|entity| seen.insert(**entity))
+            .copied()
+            .collect()
+    }
+
+    pub fn query_point(&self, point: Point2<f32>) -> Vec<EntityId> {
+        self.query_area(point, point)
+    }
+
+    // Walks the cells the ray passes over (Amanatides & Woo), entities come out roughly in order along the ray
+    // Stops once the ray leaves the area that contains any entity
+    pub fn query_ray(&self, origin: Point2<f32>, direction: Vector2<f32>) -> Vec<EntityId> {
+        let Some((occupied_min, occupied_max)) = self.occupied_cells() else {
+            return Vec::new();
+        };
+
+        let mut seen = HashSet::new();
+        let mut found = Vec::new();
+        let mut visit = |cell: (i32, i32)| {
+            if let Some(cell_entities) = self.cells.get(&cell) {
+                found.extend(cell_entities.iter().filter(|entity| seen.insert(**entity)));
+            }
+        };
+
+        // A ray looking straight down only covers the cell below it
+        if direction.x == 0.0 && direction.y == 0.0 {
+            visit(self.cell(origin));
+            return found;
+        }
+
+        // Move the start onto the occupied area, the orthographic camera ray starts far outside of it
+        let area_min = Point2::new(occupied_min.0 as f32, occupied_min.1 as f32) * self.cell_size;
+        let area_max =
+            Point2::new((occupied_max.0 + 1) as f32, (occupied_max.1 + 1) as f32) * self.cell_size;
+        let Some((t_enter, t_exit)) = Self::clip_ray(origin, direction, area_min, area_max) else {
+            return found;
+        };
+        let start = origin + direction * t_enter;
+
+        let mut cell = self.cell(start);
+        let step = (
+            if direction.x >= 0.0 { 1 } else { -1 },
+            if direction.y >= 0.0 { 1 } else { -1 },
+        );
+        let t_delta = Vector2::new(
+            (self.cell_size / direction.x).abs(),
+            (self.cell_size / direction.y).abs(),
+        );
+        let next_boundary = |cell_index: i32, step: i32| {
+            (if step > 0 { cell_index + 1 } else { cell_index }) as f32 * self.cell_size
+        };
+        let mut t_max = Vector2::new(
+            if direction.x == 0.0 {
+                f32::INFINITY
+            } else {
+                t_enter + (next_boundary(cell.0, step.0) - start.x) / direction.x
+            },
+            if direction.y == 0.0 {
+                f32::INFINITY
+            } else {
+                t_enter + (next_boundary(cell.1, step.1) - start.y) / direction.y
+            },
+        );
+
+        loop {
+            visit(cell);
+            if t_max.x.min(t_max.y) > t_exit {
+                return found;
+            }
+            if t_max.x < t_max.y {
+                cell.0 += step.0;
+                t_max.x += t_delta.x;
+            } else {
+                cell.1 += step.1;
+                t_max.y += t_delta.y;
+            }
+        }
+    }
+
+    fn cell(&self, point: Point2<f32>) -> (i32, i32) {
+        (
+            (point.x / self.cell_size).floor() as i32,
+            (point.y / self.cell_size).floor() as i32,
+        )
+    }
+
+    fn cell_range(&self, min: Point2<f32>, max: Point2<f32>) -> CellRange {
+        CellRange {
+            min: self.cell(min),
+            max: self.cell(max),
+        }
+    }
+
+    fn occupied_cells(&self) -> Option<((i32, i32), (i32, i32))> {
+        let mut cells = self.cells.keys();
+        let first = *cells.next()?;
+        Some(cells.fold((first, first), |(min, max), cell| {
+            (
+                (min.0.min(cell.0), min.1.min(cell.1)),
+                (max.0.max(cell.0), max.1.max(cell.1)),
+            )
+        }))
+    }
+
+    // Slab test on the xz plane, returns the ray parameters where it enters and leaves the area
+    fn clip_ray(
+        origin: Point2<f32>,
+        direction: Vector2<f32>,
+        area_min: Point2<f32>,
+        area_max: Point2<f32>,
+    ) -> Option<(f32, f32)> {
+        let mut t_enter = 0.0_f32;
+        let mut t_exit = f32::INFINITY;
+        for dimension in 0..2 {
+            if direction[dimension] == 0.0 {
+                if origin[dimension] < area_min[dimension]
+                    || origin[dimension] > area_max[dimension]
+                {
+                    return None;
+                }
+                continue;
+            }
+            let t1 = (area_min[dimension] - origin[dimension]) / direction[dimension];
+            let t2 = (area_max[dimension] - origin[dimension]) / direction[dimension];
+            t_enter = t_enter.max(t1.min(t2));
+            t_exit = t_exit.min(t1.max(t2));
+        }
+        (t_enter <= t_exit).then_some((t_enter, t_exit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::entity::EntityAllocator;
+    use proptest::prelude::*;
+
+    const CELL_SIZE: f32 = 2.0;
+    const MARGIN: f32 = 0.001; // Rays only have to find what they clearly hit, floats decide the rest
+
+    type Bounds = (Point2<f32>, Point2<f32>);
+
+    fn build(bounds: &[Bounds]) -> (SpatialGrid, Vec<(EntityId, Bounds)>) {
+        let mut allocator = EntityAllocator::new();
+        let mut grid = SpatialGrid::new(CELL_SIZE);
+        let entities = bounds
+            .iter()
+            .map(|(min, max)| {
+                let entity = allocator.allocate();
+                grid.update(entity, *min, *max);
+                (entity, (*min, *max))
+            })
+            .collect();
+        (grid, entities)
+    }
+
+    fn overlaps(bounds: &Bounds, min: Point2<f32>, max: Point2<f32>) -> bool {
+        bounds.0.x <= max.x && min.x <= bounds.1.x && bounds.0.y <= max.y && min.y <= bounds.1.y
+    }
+
+    // Slab test against the bounds shrunk by the margin
+    fn ray_hits(bounds: &Bounds, origin: Point2<f32>, direction: Vector2<f32>) -> bool {
+        let (mut t_enter, mut t_exit) = (0.0_f32, f32::INFINITY);
+        for dimension in 0..2 {
+            let min = bounds.0[dimension] + MARGIN;
+            let max = bounds.1[dimension] - MARGIN;
+            if direction[dimension] == 0.0 {
+                if origin[dimension] < min || origin[dimension] > max {
+                    return false;
+                }
+                continue;
+            }
+            let t1 = (min - origin[dimension]) / direction[dimension];
+            let t2 = (max - origin[dimension]) / direction[dimension];
+            t_enter = t_enter.max(t1.min(t2));
+            t_exit = t_exit.min(t1.max(t2));
+        }
+        t_enter <= t_exit
+    }
+
+    fn assert_unique(found: &[EntityId]) {
+        let unique: HashSet<&EntityId> = found.iter().collect();
+        assert_eq!(unique.len(), found.len(), "{found:?} has duplicates");
+    }
+
+    fn bounds_strategy() -> impl Strategy<Value = Bounds> {
+        (-40.0_f32..40.0, -40.0_f32..40.0, 0.0_f32..5.0, 0.0_f32..5.0)
+            .prop_map(|(x, z, width, depth)| (Point2::new(x, z), Point2::new(x + width, z + depth)))
+    }
+
+    proptest! {
+        #[test]
+        fn query_area_finds_everything_overlapping(
+            bounds in prop::collection::vec(bounds_strategy(), 0..60),
+            area in bounds_strategy(),
+        ) {
+            let (grid, entities) = build(&bounds);
+            let found = grid.query_area(area.0, area.1);
+            assert_unique(&found);
+            for (entity, entity_bounds) in &entities {
+                if overlaps(entity_bounds, area.0, area.1) {
+                    prop_assert!(found.contains(entity));
+                }
+            }
+            // Candidates share a cell with the area, so they are never more than a cell away
+            let near_min = area.0 - Vector2::new(CELL_SIZE, CELL_SIZE);
+            let near_max = area.1 + Vector2::new(CELL_SIZE, CELL_SIZE);
+            for (entity, entity_bounds) in &entities {
+                if found.contains(entity) {
+                    prop_assert!(overlaps(entity_bounds, near_min, near_max));
+                }
+            }
+        }
+
+        #[test]
+        fn query_point_finds_everything_containing_it(
+            bounds in prop::collection::vec(bounds_strategy(), 0..60),
+            x in -45.0_f32..45.0,
+            z in -45.0_f32..45.0,
+        ) {
+            let (grid, entities) = build(&bounds);
+            let point = Point2::new(x, z);
+            let found = grid.query_point(point);
+            assert_unique(&found);
+            for (entity, entity_bounds) in &entities {
+                if overlaps(entity_bounds, point, point) {
+                    prop_assert!(found.contains(entity));
+                }
+            }
+        }
+
+        #[test]
+        fn query_ray_finds_everything_it_hits(
+            bounds in prop::collection::vec(bounds_strategy(), 0..60),
+            x in -100.0_f32..100.0,
+            z in -100.0_f32..100.0,
+            angle in 0.0_f32..std::f32::consts::TAU,
+        ) {
+            let (grid, entities) = build(&bounds);
+            let origin = Point2::new(x, z);
+            let direction = Vector2::new(angle.cos(), angle.sin());
+            let found = grid.query_ray(origin, direction);
+            assert_unique(&found);
+            for (entity, entity_bounds) in &entities {
+                if ray_hits(entity_bounds, origin, direction) {
+                    prop_assert!(found.contains(entity), "{entity} at {entity_bounds:?} missed");
+                }
+            }
+        }
+
+        #[test]
+        fn moved_and_removed_entities_are_found_where_they_are_now(
+            bounds in prop::collection::vec(bounds_strategy(), 1..40),
+            moves in prop::collection::vec((any::<prop::sample::Index>(), prop::option::of(bounds_strategy())), 0..40),
+        ) {
+            let (mut grid, mut entities) = build(&bounds);
+            for (index, new_bounds) in moves {
+                let (entity, current) = &mut entities[index.index(bounds.len())];
+                match new_bounds {
+                    Some(new_bounds) => {
+                        grid.update(*entity, new_bounds.0, new_bounds.1);
+                        *current = new_bounds;
+                    }
+                    None => {
+                        grid.remove(*entity);
+                        *current = (Point2::new(f32::NAN, f32::NAN), Point2::new(f32::NAN, f32::NAN));
+                    }
+                }
+            }
+            let everywhere = grid.query_area(Point2::new(-50.0, -50.0), Point2::new(50.0, 50.0));
+            assert_unique(&everywhere);
+            for (entity, entity_bounds) in &entities {
+                // Removed entities have no bounds to overlap with
+                prop_assert_eq!(everywhere.contains(entity), !entity_bounds.0.x.is_nan());
+                if !entity_bounds.0.x.is_nan() {
+                    prop_assert!(grid.query_point(entity_bounds.0).contains(entity));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ray_along_cell_boundary_finds_both_sides() {
+        // Both touch x = 4, the boundary between the cells at x 1 and 2
+        let left = (Point2::new(3.0, 10.0), Point2::new(4.0, 11.0));
+        let right = (Point2::new(4.0, -11.0), Point2::new(5.0, -10.0));
+        let (grid, entities) = build(&[left, right]);
+        for direction in [Vector2::new(0.0, 1.0), Vector2::new(0.0, -1.0)] {
+            let origin = Point2::new(4.0, -direction.y * 100.0);
+            let found = grid.query_ray(origin, direction);
+            assert!(
+                found.contains(&entities[0].0),
+                "left missed going {direction:?}"
+            );
+            assert!(
+                found.contains(&entities[1].0),
+                "right missed going {direction:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn ray_along_negative_cell_boundary_finds_both_sides() {
+        // Both touch z = -2
+        let below = (Point2::new(-7.0, -3.0), Point2::new(-6.0, -2.0));
+        let above = (Point2::new(6.0, -2.0), Point2::new(7.0, -1.0));
+        let (grid, entities) = build(&[below, above]);
+        for direction in [Vector2::new(1.0, 0.0), Vector2::new(-1.0, 0.0)] {
+            let origin = Point2::new(-direction.x * 100.0, -2.0);
+            let found = grid.query_ray(origin, direction);
+            assert!(found.contains(&entities[0].0));
+            assert!(found.contains(&entities[1].0));
+        }
+    }
+
+    #[test]
+    fn ray_through_cell_corners_finds_what_it_passes() {
+        let on_the_line = (Point2::new(4.5, 4.5), Point2::new(5.5, 5.5));
+        let touching_a_corner = (Point2::new(8.0, 6.0), Point2::new(9.0, 8.0));
+        let next_to_the_line = (Point2::new(0.5, 6.5), Point2::new(1.5, 7.5));
+        let (grid, entities) = build(&[on_the_line, touching_a_corner, next_to_the_line]);
+        let found = grid.query_ray(Point2::new(-100.0, -100.0), Vector2::new(1.0, 1.0));
+        assert!(found.contains(&entities[0].0));
+        assert!(found.contains(&entities[1].0));
+        assert!(!found.contains(&entities[2].0));
+    }
+
+    #[test]
+    fn ray_comes_out_in_order_along_it() {
+        let (grid, entities) = build(&[
+            (Point2::new(20.0, 0.5), Point2::new(21.0, 1.5)),
+            (Point2::new(-10.0, 0.5), Point2::new(-9.0, 1.5)),
+            (Point2::new(5.0, 0.5), Point2::new(6.0, 1.5)),
+        ]);
+        let found = grid.query_ray(Point2::new(-100.0, 1.0), Vector2::new(1.0, 0.0));
+        assert_eq!(found, vec![entities[1].0, entities[2].0, entities[0].0]);
+    }
+
+    #[test]
+    fn ray_pointing_away_or_straight_down() {
+        let (grid, entities) = build(&[(Point2::new(0.5, 0.5), Point2::new(1.5, 1.5))]);
+        assert!(
+            grid.query_ray(Point2::new(10.0, 10.0), Vector2::new(1.0, 0.0))
+                .is_empty()
+        );
+        assert_eq!(
+            grid.query_ray(Point2::new(1.0, 1.0), Vector2::new(0.0, 0.0)),
+            vec![entities[0].0]
+        );
+        assert!(
+            SpatialGrid::new(CELL_SIZE)
+                .query_ray(Point2::new(0.0, 0.0), Vector2::new(1.0, 0.0))
+                .is_empty()
+        );
+    }
+}
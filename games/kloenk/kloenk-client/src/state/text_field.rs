@@ -0,0 +1,72 @@
+use crate::state::input::TextInput;
+
+// Single line of text being typed. The cursor counts characters, not bytes, so it never ends up inside a character
+pub struct TextField {
+    text: String,
+    cursor: usize,
+    max_length: usize,
+}
+
+impl TextField {
+    pub fn new(max_length: usize) -> Self {
+        TextField {
+            text: String::new(),
+            cursor: 0,
+            max_length,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    // Empties the field, returning what was typed
+    pub fn take(&mut self) -> String {
+        self.cursor = 0;
+        std::mem::take(&mut self.text)
+    }
+
+    // Keys that are not about editing, like scrolling, are left for the caller
+    pub fn apply(&mut self, text_input: &TextInput) {
+        let length = self.text.chars().count();
+        match text_input {
+            TextInput::Text(text) => {
+                let room = self.max_length.saturating_sub(length);
+                let inserted: String = text.chars().take(room).collect();
+                let index = self.byte_index(self.cursor);
+                self.text.insert_str(index, &inserted);
+                self.cursor += inserted.chars().count();
+            }
+            TextInput::Backspace => {
+                if self.cursor > 0 {
+                    self.cursor -= 1;
+                    self.text.remove(self.byte_index(self.cursor));
+                }
+            }
+            TextInput::Delete => {
+                if self.cursor < length {
+                    self.text.remove(self.byte_index(self.cursor));
+                }
+            }
+            TextInput::Left => self.cursor = self.cursor.saturating_sub(1),
+            TextInput::Right => self.cursor = (self.cursor + 1).min(length),
+            TextInput::Home => self.cursor = 0,
+            TextInput::End => self.cursor = length,
+            TextInput::PageUp | TextInput::PageDown => {}
+        }
+    }
+
+    // The text with a bar where the next character goes
+    pub fn display_text(&self) -> String {
+        let mut display_text = self.text.clone();
+        display_text.insert(self.byte_index(self.cursor), '|');
+        display_text
+    }
+
+    fn byte_index(&self, cursor: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(cursor)
+            .map_or(self.text.len(), |(index, _)| index)
+    }
+}
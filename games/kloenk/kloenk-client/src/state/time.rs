@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+pub const DEFAULT_TICKS_PER_SECOND: u32 = 60;
+
+// Simulation time. Advances by exactly one fixed delta per tick, no matter how long the tick took in real time
+pub struct Time {
+    ticks_per_second: u32,
+    delta: Duration,
+    elapsed: Duration,
+    tick: u64,
+}
+
+impl Time {
+    pub fn new(ticks_per_second: u32) -> Self {
+        assert!(ticks_per_second > 0, "Tick rate should be positive");
+        Self {
+            ticks_per_second,
+            delta: Duration::from_secs(1) / ticks_per_second,
+            elapsed: Duration::ZERO,
+            tick: 0,
+        }
+    }
+
+    pub fn advance(&mut self) {
+        self.elapsed += self.delta;
+        self.tick += 1;
+    }
+
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    // Speeds are defined per second, multiply them by this to get the change for this tick
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn ticks_per_second(&self) -> u32 {
+        self.ticks_per_second
+    }
+}
@@ -1,10 +1,13 @@
-use crate::state::components::Entity;
+use crate::net::connection::ConnectionState;
+use crate::state::chat::ChatLog;
+use crate::state::entity::EntityId;
 use crate::state::ui_state::MenuState::Closed;
+use crate::state::viewport::Viewport;
+use crate::systems::dialogue_manager::DialogueManager;
+use crate::systems::quest_manager::QuestManager;
 use cgmath::{EuclideanSpace, Point2, Vector2};
 use std::collections::HashMap;
-use std::sync::Arc;
 use winit::dpi::PhysicalSize;
-use winit::window::Window;
 
 pub struct UIWindow {
     pub is_visible: bool,
@@ -29,6 +32,12 @@ pub enum RenderCommand {
         text: String,
         color: [f32; 3],
     },
+    // Turned a quarter counterclockwise, for items lying sideways in the inventory. The element is the turned rect
+    RotatedModel {
+        layer: u32,
+        ui_element: UIElement,
+        model_id: String,
+    },
 }
 
 pub enum UserAction {
@@ -129,10 +138,10 @@ impl UIElement {
     // TODO how does this work for ui elements with parents?
     // TODO can this be window-agnostic somehow?
     // TODO probably dont need to pass window: just pass cursor point in pixels (still needed for update though...)
-    pub fn contains(&mut self, cursor_point: Point2<f32>, window: &Arc<Window>) -> bool {
-        self.update(&window.inner_size()); // TODO probably dont update here
-        let cursor_x = cursor_point.x * window.inner_size().width as f32;
-        let cursor_y = cursor_point.y * window.inner_size().height as f32;
+    pub fn contains(&mut self, cursor_point: Point2<f32>, viewport: &Viewport) -> bool {
+        self.update(&viewport.size()); // TODO probably dont update here
+        let cursor_x = cursor_point.x * viewport.width as f32;
+        let cursor_y = cursor_point.y * viewport.height as f32;
         cursor_x >= self.scaled_anchor_x + self.scaled_x
             && cursor_x < self.scaled_anchor_x + self.scaled_x + self.scaled_width
             && cursor_y >= self.scaled_anchor_y + self.scaled_y
@@ -234,8 +243,9 @@ pub enum DialogueState {
     Closed,
     Npc {
         render_position: Point2<f32>,
-        npc_entity_id: Entity,
+        npc_entity_id: EntityId,
         dialogue_id: String,
+        node_id: String,
     },
 }
 
@@ -244,11 +254,11 @@ pub enum MenuState {
     Closed,
     WorldAction {
         render_position: Point2<f32>,
-        item: Entity,
+        item: EntityId,
     },
     InventoryAction {
         render_position: Point2<f32>,
-        item: Entity,
+        item: EntityId,
     },
 }
 
@@ -261,6 +271,16 @@ pub enum InputState {
 
 impl InputState {}
 
+// An inventory item held under the cursor while the mouse button is down
+pub enum DragState {
+    Idle,
+    Item {
+        item: EntityId,
+        is_rotated: bool,
+        grab_offset: Vector2<f32>, // From the top left of the item to the cursor, in cells
+    },
+}
+
 pub struct UIState {
     pub windows: HashMap<String, UIWindow>,
 
@@ -269,7 +289,19 @@ pub struct UIState {
 
     pub menu_state: MenuState,
     pub dialogue_state: DialogueState,
+    pub drag_state: DragState,
     pub input_state: InputState,
+    pub connection_state: ConnectionState,
+    pub chat: ChatLog,
+    // Content rather than state, but only the UI uses them. The quests also to follow progress when playing alone
+    pub dialogues: DialogueManager,
+    pub quests: QuestManager,
+}
+
+impl Default for UIState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl UIState {
@@ -284,45 +316,56 @@ impl UIState {
 
         let chat_window = UIWindow::new(
             false,
-            UIElement::new_rect(Point2::new(0.25, 0.7), Point2::new(0.2, 0.1)),
+            UIElement::new_rect(Point2::new(0.4, 0.84), Point2::new(0.2, 0.14)),
         );
         windows.insert("chat".to_owned(), chat_window);
 
+        let quest_window = UIWindow::new(
+            false,
+            UIElement::new_rect(Point2::new(0.2, 0.4), Point2::new(0.17, 0.18)),
+        );
+        windows.insert("quests".to_owned(), quest_window);
+
         UIState {
             windows,
             menu_state: Closed,
             dialogue_state: DialogueState::Closed,
+            drag_state: DragState::Idle,
             input_state: InputState::Normal,
+            connection_state: ConnectionState::Offline,
+            chat: ChatLog::new(),
+            dialogues: DialogueManager::new(),
+            quests: QuestManager::new(),
             action_text: String::new(),
             selected_text: String::new(),
         }
     }
 
     // Reuse camera method for calculation ? TODO
-    pub fn clip_space_element_position_x(ui_element: &UIElement, window: &Arc<Window>) -> f32 {
+    pub fn clip_space_element_position_x(ui_element: &UIElement, viewport: &Viewport) -> f32 {
         let scale = 1.0;
-        let resolution = window.inner_size().width as f32 / window.inner_size().height as f32;
+        let resolution = viewport.aspect_ratio();
         let viewport_half_width = scale * resolution;
-        -viewport_half_width + Self::convert_scale_x(ui_element.scaled_anchor_x + ui_element.scaled_x, window)
+        -viewport_half_width + Self::convert_scale_x(ui_element.scaled_anchor_x + ui_element.scaled_x, viewport)
     }
 
-    pub fn clip_space_element_position_y(ui_element: &UIElement, window: &Arc<Window>) -> f32 {
+    pub fn clip_space_element_position_y(ui_element: &UIElement, viewport: &Viewport) -> f32 {
         let viewport_half_height = 1.0;
-        viewport_half_height - Self::convert_scale_y(ui_element.scaled_anchor_y + ui_element.scaled_y, window)
+        viewport_half_height - Self::convert_scale_y(ui_element.scaled_anchor_y + ui_element.scaled_y, viewport)
     }
 
     // TODO logic probably should be in ui element not in rendering, just calculate there upon window size change. also needed for contains
-    pub fn convert_scale_x(value: f32, window: &Arc<Window>) -> f32 {
+    pub fn convert_scale_x(value: f32, viewport: &Viewport) -> f32 {
         let scale = 1.0;
-        let resolution = window.inner_size().width as f32 / window.inner_size().height as f32;
+        let resolution = viewport.aspect_ratio();
         let viewport_width = 2.0 * scale * resolution;
 
-        value / window.inner_size().width as f32 * viewport_width
+        value / viewport.width as f32 * viewport_width
     }
 
-    pub fn convert_scale_y(value: f32, window: &Arc<Window>) -> f32 {
+    pub fn convert_scale_y(value: f32, viewport: &Viewport) -> f32 {
         let viewport_half_height = 1.0;
 
-        value / window.inner_size().height as f32 * viewport_half_height * 2.0
+        value / viewport.height as f32 * viewport_half_height * 2.0
     }
 }
@@ -1,9 +1,16 @@
 use crate::gui::Gui;
-use crate::state::components::Entity;
+use crate::state::chat::ChatChannel;
+use crate::state::entity::EntityId;
+use crate::state::world_definition::EntityDefinition;
+use crate::systems::dialogue_effect_system::DialogueChoiceMade;
+use crate::systems::dialogue_manager::DialogueEffect;
+use crate::systems::item_transaction_system::ItemTransaction;
+use crate::systems::movement_system::MovementInput;
+use serde::{Deserialize, Serialize};
 
 pub struct UpdateState {
-    pub objects_on_cursor: Vec<Entity>,
-    nearest_object: Option<Entity>, //In orthographic we can't just calculate this by ray distance (all objects on plane will be same distance)
+    pub objects_on_cursor: Vec<EntityId>,
+    nearest_object: Option<EntityId>, //In orthographic we can't just calculate this by ray distance (all objects on plane will be same distance)
 
     pub handled_left_click: bool,
     pub handled_right_click: bool,
@@ -14,6 +21,21 @@ pub struct UpdateState {
 
     pub action_requests: Vec<ActionRequest>,
     pub action_effects: Vec<ActionEffect>,
+
+    pub entity_commands: Vec<EntityCommand>, // Applied at the end of the update, so systems never see an entity disappear halfway through a tick
+
+    pub movement_input: MovementInput, // How the player moved this tick, sent to the server when playing online
+    pub is_online: bool,
+    pub item_transactions: Vec<ItemTransaction>, // Only when online, waiting to be sent to the server
+    pub chat_messages: Vec<(ChatChannel, String)>, // Same, the server decides who hears them
+    pub dialogue_choices: Vec<DialogueChoiceMade>, // Same, the server applies their effects
+    pub talked_to: Vec<EntityId>,                // Same, the server keeps track of quests
+}
+
+impl Default for UpdateState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl UpdateState {
@@ -30,6 +52,15 @@ impl UpdateState {
 
             action_requests: Vec::new(),
             action_effects: Vec::new(),
+
+            entity_commands: Vec::new(),
+
+            movement_input: MovementInput::default(),
+            is_online: false,
+            item_transactions: Vec::new(),
+            chat_messages: Vec::new(),
+            dialogue_choices: Vec::new(),
+            talked_to: Vec::new(),
         }
     }
 
@@ -41,37 +72,78 @@ impl UpdateState {
         self.gui = Gui::new();
         self.action_requests = Vec::new();
         self.action_effects = Vec::new();
+        self.entity_commands = Vec::new();
+        self.movement_input = MovementInput::default();
+        self.is_online = false;
+        self.item_transactions = Vec::new();
+        self.chat_messages = Vec::new();
+        self.dialogue_choices = Vec::new();
+        self.talked_to = Vec::new();
     }
 
-    pub fn add_object_on_cursor(&mut self, object: &Entity) {
-        self.objects_on_cursor.push(object.to_owned());
+    pub fn add_object_on_cursor(&mut self, object: EntityId) {
+        self.objects_on_cursor.push(object);
     }
 
-    pub fn get_objects_on_cursor(&self) -> &Vec<Entity> {
+    pub fn get_objects_on_cursor(&self) -> &Vec<EntityId> {
         &self.objects_on_cursor
     }
 
-    pub fn set_nearest_object_on_cursor(&mut self, nearest_object: Option<Entity>) {
+    pub fn set_nearest_object_on_cursor(&mut self, nearest_object: Option<EntityId>) {
         self.nearest_object = nearest_object;
     }
 
-    pub fn get_nearest_object_on_cursor(&self) -> Option<&str> {
-        self.nearest_object.as_deref()
+    pub fn get_nearest_object_on_cursor(&self) -> Option<EntityId> {
+        self.nearest_object
     }
 }
 
 pub enum ActionRequest {
-    ItemPlacement { entity: Entity },
+    ItemPlacement {
+        entity: EntityId,
+    },
+    // Conditions were checked when the choice was shown
+    DialogueChoice {
+        choice: DialogueChoiceMade,
+        effects: Vec<DialogueEffect>,
+    },
+}
+
+pub enum EntityCommand {
+    Spawn { definition: Box<EntityDefinition> },
+    Despawn { entity: EntityId },
 }
 
+// Sent to players by the server as the result of their actions
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ActionEffect {
     PickupItemNotStorable,
     PickupNoItemInRange,
     PlaceItemNotInInventory,
     PlaceItemNonPlaceable,
+    PlaceItemOutOfRange,
     PlaceItemCollidingItem,
-    PlaceItemSucceeded,
+    PlaceItemSucceeded { item: EntityId },
     ItemSelected { found_objects_text: String },
     PickupNoInventorySpace,
     Examine { text: String },
+    NoPathFound,
+    MoveItemSpotTaken,
+    SortItemsDoNotFit,
+    TradeRecipientNotAStorage,
+    TradeRecipientIsAPlayer,
+    TradeRecipientOutOfRange,
+    TradeNoRecipientSpace,
+    TradeSucceeded,
+    DialogueChoiceUnavailable,
+    DialogueItemReceived,
+    DialogueItemNotHeld,
+    DialogueItemMissing,
+    Healed,
+    QuestStarted { quest: String },
+    // Only there for quests to follow, the player already sees these happen
+    PickupSucceeded { item: EntityId },
+    TalkedTo { npc: EntityId },
+    QuestStageCompleted { quest: String },
+    QuestCompleted { quest: String },
 }
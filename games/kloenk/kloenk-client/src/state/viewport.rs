@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use winit::dpi::PhysicalSize;
+
+// The size of the area we draw in. Game logic only needs this from the window, so it can also run without one
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "ViewportSize")]
+pub struct Viewport {
+    pub width: u32,
+    pub height: u32,
+}
+
+// Loaded viewports go through the same checks as new ones
+#[derive(Deserialize)]
+struct ViewportSize {
+    width: u32,
+    height: u32,
+}
+
+impl TryFrom<ViewportSize> for Viewport {
+    type Error = String;
+
+    fn try_from(size: ViewportSize) -> Result<Self, Self::Error> {
+        if size.width == 0 || size.height == 0 {
+            return Err(format!(
+                "viewport should be at least 1 by 1, got {} by {}",
+                size.width, size.height
+            ));
+        }
+        Ok(Viewport::new(size.width, size.height))
+    }
+}
+
+impl Viewport {
+    pub fn new(width: u32, height: u32) -> Self {
+        // Minimized windows report 0 by 0, which would lead to dividing by zero
+        Self {
+            width: width.max(1),
+            height: height.max(1),
+        }
+    }
+
+    pub fn size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(self.width, self.height)
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+}
+
+impl From<PhysicalSize<u32>> for Viewport {
+    fn from(size: PhysicalSize<u32>) -> Self {
+        Viewport::new(size.width, size.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_viewports_are_not_loaded() {
+        for json in [
+            r#"{"width":0,"height":0}"#,
+            r#"{"width":1920,"height":0}"#,
+            r#"{"width":0,"height":1080}"#,
+        ] {
+            assert!(serde_json::from_str::<Viewport>(json).is_err(), "{json}");
+        }
+    }
+
+    #[test]
+    fn saved_viewports_load_the_same() {
+        let viewport = Viewport::new(1920, 1080);
+        let json = serde_json::to_string(&viewport).unwrap();
+        assert_eq!(serde_json::from_str::<Viewport>(&json).unwrap(), viewport);
+    }
+
+    #[test]
+    fn minimized_windows_get_a_one_pixel_viewport() {
+        assert_eq!(Viewport::new(0, 0), Viewport::new(1, 1));
+        assert_eq!(
+            Viewport::from(PhysicalSize::new(0, 720)).aspect_ratio(),
+            1.0 / 720.0
+        );
+    }
+}
@@ -0,0 +1,484 @@
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// Entities in the world file either define their components directly or refer to a template and only override what differs (usually just id and position)
+#[derive(Deserialize, Clone, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct EntityDefinition {
+    pub id: Option<String>,
+    pub template: Option<String>,
+    pub position: Option<[f32; 3]>,
+    pub graphics_3d: Option<String>,
+    pub graphics_2d: Option<String>,
+    pub scale: Option<[f32; 3]>,
+    pub rotation_y_degrees: Option<f32>,
+    pub collider: Option<ColliderDefinition>,
+    pub storable: Option<ItemShapeDefinition>,
+    pub storage: Option<StorageDefinition>,
+    pub description: Option<String>,
+    pub dialogue: Option<String>,
+    pub health: Option<HealthDefinition>,
+    pub camera_target: Option<CameraTargetDefinition>,
+    #[serde(default)]
+    pub surface: bool,
+}
+
+// Shapes are in entity local space: scale and rotation of the entity are applied on top
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(tag = "shape", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ColliderDefinition {
+    Box {
+        min_offset: [f32; 3],
+        max_offset: [f32; 3],
+    },
+    Cylinder {
+        radius: f32,
+        height: f32,
+    },
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ItemShapeDefinition {
+    pub width: u8,
+    pub height: u8,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct StorageDefinition {
+    pub number_of_rows: u8,
+    pub number_of_columns: u8,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct HealthDefinition {
+    pub hitpoints: u32,
+    pub max_hitpoints: u32,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CameraTargetDefinition {
+    pub distance: f32,
+    pub rotation_x_degrees: f32,
+    pub rotation_y_degrees: f32,
+}
+
+// A rectangle of walkable tiles, one anonymous entity per tile. Ranges are [inclusive, exclusive)
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SurfaceGridDefinition {
+    pub name: String,
+    pub graphics_3d: String,
+    pub x_range: [i8; 2],
+    pub z_range: [i8; 2],
+    #[serde(default)]
+    pub y: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawWorldDefinition {
+    #[serde(default)]
+    templates: HashMap<String, EntityDefinition>,
+    #[serde(default)]
+    entities: Vec<EntityDefinition>,
+    #[serde(default)]
+    surfaces: Vec<SurfaceGridDefinition>,
+}
+
+// Resolved world: templates are applied and surface grids are expanded. Only surface tiles go without an id
+pub struct WorldDefinition {
+    pub entities: Vec<EntityDefinition>,
+}
+
+#[derive(Debug)]
+pub enum WorldDefinitionError {
+    Syntax { message: String },
+    MissingId { index: usize },
+    DuplicateId { id: String },
+    UnknownTemplate { id: String, template: String },
+    NestedTemplate { template: String },
+    TemplateWithId { template: String },
+    InvalidCollider { id: String },
+    InvalidScale { id: String },
+    InvalidItemShape { id: String },
+    InvalidStorage { id: String },
+    InvalidHealth { id: String },
+    EmptySurfaceGrid { name: String },
+}
+
+impl fmt::Display for WorldDefinitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorldDefinitionError::Syntax { message } => write!(f, "Invalid world file: {message}"),
+            WorldDefinitionError::MissingId { index } => {
+                write!(f, "Entity at index {index} has no id")
+            }
+            WorldDefinitionError::DuplicateId { id } => {
+                write!(f, "Entity id '{id}' is defined more than once")
+            }
+            WorldDefinitionError::UnknownTemplate { id, template } => {
+                write!(f, "Entity '{id}' refers to unknown template '{template}'")
+            }
+            WorldDefinitionError::NestedTemplate { template } => {
+                write!(
+                    f,
+                    "Template '{template}' refers to another template, which is not supported"
+                )
+            }
+            WorldDefinitionError::TemplateWithId { template } => {
+                write!(
+                    f,
+                    "Template '{template}' defines an id, ids belong on entities"
+                )
+            }
+            WorldDefinitionError::InvalidCollider { id } => write!(
+                f,
+                "Entity '{id}' has a collider with a min offset larger than its max offset, or without radius or height"
+            ),
+            WorldDefinitionError::InvalidScale { id } => {
+                write!(f, "Entity '{id}' has a scale that is not positive")
+            }
+            WorldDefinitionError::InvalidItemShape { id } => {
+                write!(
+                    f,
+                    "Entity '{id}' has a storable shape without width or height"
+                )
+            }
+            WorldDefinitionError::InvalidStorage { id } => {
+                write!(f, "Entity '{id}' has a storage without rows or columns")
+            }
+            WorldDefinitionError::InvalidHealth { id } => {
+                write!(f, "Entity '{id}' has more hitpoints than its max hitpoints")
+            }
+            WorldDefinitionError::EmptySurfaceGrid { name } => {
+                write!(f, "Surface grid '{name}' does not contain any tiles")
+            }
+        }
+    }
+}
+
+impl std::error::Error for WorldDefinitionError {}
+
+impl From<serde_json::Error> for WorldDefinitionError {
+    fn from(error: serde_json::Error) -> Self {
+        WorldDefinitionError::Syntax {
+            message: error.to_string(), // Includes line and column
+        }
+    }
+}
+
+impl WorldDefinition {
+    pub fn from_json(bytes: &[u8]) -> Result<WorldDefinition, WorldDefinitionError> {
+        let raw: RawWorldDefinition = serde_json::from_slice(bytes)?;
+        Self::resolve(raw)
+    }
+
+    fn resolve(raw: RawWorldDefinition) -> Result<WorldDefinition, WorldDefinitionError> {
+        for (template_id, template) in &raw.templates {
+            if template.template.is_some() {
+                return Err(WorldDefinitionError::NestedTemplate {
+                    template: template_id.clone(),
+                });
+            }
+            if template.id.is_some() {
+                return Err(WorldDefinitionError::TemplateWithId {
+                    template: template_id.clone(),
+                });
+            }
+        }
+
+        let mut entities = Vec::new();
+        for (index, entity) in raw.entities.into_iter().enumerate() {
+            let id = entity
+                .id
+                .clone()
+                .ok_or(WorldDefinitionError::MissingId { index })?;
+            let resolved = match &entity.template {
+                None => entity,
+                Some(template_id) => {
+                    let template = raw.templates.get(template_id).ok_or_else(|| {
+                        WorldDefinitionError::UnknownTemplate {
+                            id,
+                            template: template_id.clone(),
+                        }
+                    })?;
+                    Self::apply_template(entity, template)
+                }
+            };
+            entities.push(resolved);
+        }
+
+        for surface in &raw.surfaces {
+            entities.append(&mut Self::expand_surface_grid(surface)?);
+        }
+
+        let mut ids = HashSet::new();
+        for entity in &entities {
+            Self::validate_entity(entity)?;
+            if let Some(id) = &entity.id
+                && !ids.insert(id.clone())
+            {
+                return Err(WorldDefinitionError::DuplicateId { id: id.clone() });
+            }
+        }
+
+        Ok(WorldDefinition { entities })
+    }
+
+    fn apply_template(entity: EntityDefinition, template: &EntityDefinition) -> EntityDefinition {
+        let template = template.clone();
+        EntityDefinition {
+            id: entity.id,
+            template: entity.template,
+            position: entity.position.or(template.position),
+            graphics_3d: entity.graphics_3d.or(template.graphics_3d),
+            graphics_2d: entity.graphics_2d.or(template.graphics_2d),
+            scale: entity.scale.or(template.scale),
+            rotation_y_degrees: entity.rotation_y_degrees.or(template.rotation_y_degrees),
+            collider: entity.collider.or(template.collider),
+            storable: entity.storable.or(template.storable),
+            storage: entity.storage.or(template.storage),
+            description: entity.description.or(template.description),
+            dialogue: entity.dialogue.or(template.dialogue),
+            health: entity.health.or(template.health),
+            camera_target: entity.camera_target.or(template.camera_target),
+            surface: entity.surface || template.surface,
+        }
+    }
+
+    fn expand_surface_grid(
+        surface: &SurfaceGridDefinition,
+    ) -> Result<Vec<EntityDefinition>, WorldDefinitionError> {
+        if surface.x_range[0] >= surface.x_range[1] || surface.z_range[0] >= surface.z_range[1] {
+            return Err(WorldDefinitionError::EmptySurfaceGrid {
+                name: surface.name.clone(),
+            });
+        }
+
+        let mut tiles = Vec::new();
+        for x in surface.x_range[0]..surface.x_range[1] {
+            for z in surface.z_range[0]..surface.z_range[1] {
+                tiles.push(EntityDefinition {
+                    template: Some(surface.name.clone()), // Not applied, but kept like templates are so content can refer to the grid
+                    position: Some([f32::from(x), surface.y, f32::from(z)]),
+                    graphics_3d: Some(surface.graphics_3d.clone()),
+                    surface: true,
+                    ..EntityDefinition::default()
+                });
+            }
+        }
+        Ok(tiles)
+    }
+
+    fn validate_entity(entity: &EntityDefinition) -> Result<(), WorldDefinitionError> {
+        let id = entity.id.clone().unwrap_or_default();
+
+        let is_valid_collider = match &entity.collider {
+            None => true,
+            Some(ColliderDefinition::Box {
+                min_offset,
+                max_offset,
+            }) => (0..3).all(|dimension| min_offset[dimension] <= max_offset[dimension]),
+            Some(ColliderDefinition::Cylinder { radius, height }) => *radius > 0.0 && *height > 0.0,
+        };
+        if !is_valid_collider {
+            return Err(WorldDefinitionError::InvalidCollider { id });
+        }
+
+        if let Some(scale) = &entity.scale
+            && scale.iter().any(|value| *value <= 0.0)
+        {
+            return Err(WorldDefinitionError::InvalidScale { id });
+        }
+
+        if let Some(shape) = &entity.storable
+            && (shape.width == 0 || shape.height == 0)
+        {
+            return Err(WorldDefinitionError::InvalidItemShape { id });
+        }
+
+        if let Some(storage) = &entity.storage
+            && (storage.number_of_rows == 0 || storage.number_of_columns == 0)
+        {
+            return Err(WorldDefinitionError::InvalidStorage { id });
+        }
+
+        if let Some(health) = &entity.health
+            && health.hitpoints > health.max_hitpoints
+        {
+            return Err(WorldDefinitionError::InvalidHealth { id });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejection(json: &str) -> WorldDefinitionError {
+        WorldDefinition::from_json(json.as_bytes())
+            .err()
+            .expect("World should be rejected")
+    }
+
+    #[test]
+    fn the_world_of_the_game_loads() {
+        let world = WorldDefinition::from_json(include_bytes!("../../assets/world.json"))
+            .expect("World should load");
+        assert!(
+            world
+                .entities
+                .iter()
+                .any(|entity| entity.id.as_deref() == Some("player"))
+        );
+    }
+
+    #[test]
+    fn templates_are_applied_and_surface_grids_expanded() {
+        let world = WorldDefinition::from_json(
+            br#"{
+                "templates": {
+                    "sword": {"graphics_3d": "sword", "description": "A sword", "storable": {"width": 1, "height": 2}}
+                },
+                "entities": [
+                    {"id": "sword1", "template": "sword", "position": [1.0, 0.0, 1.0]},
+                    {"id": "sword2", "template": "sword", "description": "A blunt sword"}
+                ],
+                "surfaces": [
+                    {"name": "grass", "graphics_3d": "grass", "x_range": [-1, 1], "z_range": [0, 3]}
+                ]
+            }"#,
+        )
+        .expect("World should load");
+
+        assert_eq!(world.entities.len(), 2 + 6);
+        let [sword1, sword2] = [&world.entities[0], &world.entities[1]];
+        assert_eq!(sword1.graphics_3d.as_deref(), Some("sword"));
+        assert_eq!(sword1.description.as_deref(), Some("A sword"));
+        assert_eq!(sword1.position, Some([1.0, 0.0, 1.0]));
+        assert_eq!(sword2.description.as_deref(), Some("A blunt sword"));
+        assert!(sword2.storable.is_some());
+
+        let tiles = &world.entities[2..];
+        assert!(tiles.iter().all(|tile| tile.surface && tile.id.is_none()));
+        assert!(
+            tiles
+                .iter()
+                .all(|tile| tile.template.as_deref() == Some("grass"))
+        );
+        assert_eq!(tiles[0].position, Some([-1.0, 0.0, 0.0]));
+        assert_eq!(tiles[5].position, Some([0.0, 0.0, 2.0]));
+    }
+
+    #[test]
+    fn unknown_template() {
+        let error = rejection(r#"{"entities": [{"id": "sword1", "template": "sword"}]}"#);
+        assert!(matches!(
+            error,
+            WorldDefinitionError::UnknownTemplate { id, template } if id == "sword1" && template == "sword"
+        ));
+    }
+
+    #[test]
+    fn duplicate_id() {
+        let error =
+            rejection(r#"{"entities": [{"id": "sword1"}, {"id": "shield"}, {"id": "sword1"}]}"#);
+        assert!(matches!(error, WorldDefinitionError::DuplicateId { id } if id == "sword1"));
+    }
+
+    #[test]
+    fn missing_id() {
+        let error = rejection(r#"{"entities": [{"id": "sword1"}, {"position": [0.0, 0.0, 0.0]}]}"#);
+        assert!(matches!(
+            error,
+            WorldDefinitionError::MissingId { index: 1 }
+        ));
+    }
+
+    #[test]
+    fn templates_cannot_have_ids_or_templates() {
+        let error = rejection(r#"{"templates": {"sword": {"id": "sword1"}}}"#);
+        assert!(
+            matches!(error, WorldDefinitionError::TemplateWithId { template } if template == "sword")
+        );
+        let error = rejection(r#"{"templates": {"sword": {"template": "weapon"}}}"#);
+        assert!(
+            matches!(error, WorldDefinitionError::NestedTemplate { template } if template == "sword")
+        );
+    }
+
+    #[test]
+    fn empty_surface_range() {
+        for (x_range, z_range) in [("[2, 2]", "[0, 1]"), ("[0, 1]", "[3, -3]")] {
+            let json = format!(
+                r#"{{"surfaces": [{{"name": "grass", "graphics_3d": "grass", "x_range": {x_range}, "z_range": {z_range}}}]}}"#
+            );
+            assert!(matches!(
+                rejection(&json),
+                WorldDefinitionError::EmptySurfaceGrid { name } if name == "grass"
+            ));
+        }
+    }
+
+    #[test]
+    fn invalid_components() {
+        let cases = [
+            (
+                r#"{"collider": {"shape": "box", "min_offset": [0.0, 1.0, 0.0], "max_offset": [1.0, 0.0, 1.0]}}"#,
+                "Entity 'thing' has a collider with a min offset larger than its max offset, or without radius or height",
+            ),
+            (
+                r#"{"collider": {"shape": "cylinder", "radius": 0.0, "height": 1.0}}"#,
+                "Entity 'thing' has a collider with a min offset larger than its max offset, or without radius or height",
+            ),
+            (
+                r#"{"scale": [1.0, 0.0, 1.0]}"#,
+                "Entity 'thing' has a scale that is not positive",
+            ),
+            (
+                r#"{"storable": {"width": 0, "height": 1}}"#,
+                "Entity 'thing' has a storable shape without width or height",
+            ),
+            (
+                r#"{"storage": {"number_of_rows": 3, "number_of_columns": 0}}"#,
+                "Entity 'thing' has a storage without rows or columns",
+            ),
+            (
+                r#"{"health": {"hitpoints": 11, "max_hitpoints": 10}}"#,
+                "Entity 'thing' has more hitpoints than its max hitpoints",
+            ),
+        ];
+        for (components, expected) in cases {
+            // Also when the component comes from a template
+            let on_entity = format!(r#"{{"entities": [{{"id": "thing", {}]}}"#, &components[1..]);
+            let on_template = format!(
+                r#"{{"templates": {{"thing": {components}}}, "entities": [{{"id": "thing", "template": "thing"}}]}}"#
+            );
+            for json in [on_entity, on_template] {
+                assert_eq!(rejection(&json).to_string(), expected, "{json}");
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_fields_and_bad_syntax() {
+        for json in [
+            r#"{"entities": [{"id": "sword1", "colour": "red"}]}"#,
+            r#"{"entities": [{"id": "sword1", "collider": {"shape": "sphere"}}]}"#,
+            r#"{"entities": [{"id": "sword1"}"#,
+            r#"{"surfaces": [{"name": "grass", "graphics_3d": "grass", "x_range": [0, 200], "z_range": [0, 1]}]}"#,
+        ] {
+            assert!(
+                matches!(rejection(json), WorldDefinitionError::Syntax { .. }),
+                "{json}"
+            );
+        }
+    }
+}
@@ -1,33 +1,55 @@
 use crate::state::components::CameraTarget;
+use crate::state::entity::EntityId;
 use crate::state::game_state::GameState;
 use crate::state::input::Input;
+use crate::state::time::Time;
+use crate::state::viewport::Viewport;
 use cgmath::{InnerSpace, Point3, Vector3};
-use std::sync::Arc;
-use winit::window::Window;
 
 pub const MIN_CAMERA_DISTANCE: f32 = 100.0;
 pub const MAX_CAMERA_DISTANCE: f32 = 500.0;
-pub const CAMERA_MOVEMENT_SPEED: f32 = 3.0;
+pub const CAMERA_MOVEMENT_SPEED_DEGREES: f32 = 180.0; // Per second
 pub const CAMERA_BOTTOM_LIMIT: f32 = 280.0;
 pub const CAMERA_TOP_LIMIT: f32 = 350.0;
 const SCROLL_FACTOR: f32 = 0.3;
+const VIEW_HALF_HEIGHT: f32 = 1.0; // Same as the orthographic projection of the camera
+const MAX_ASPECT_RATIO: f32 = 21.0 / 9.0; // Wider screens see a bit less than they could
 
 pub struct CameraSystem {}
 
 impl CameraSystem {
     // Note: both camera and camera target can move, and therefore needs update on either of those changes. TODO could check for this
-    pub fn update_3d_camera(window: &Arc<Window>, game_state: &mut GameState, input: &mut Input) {
-        Self::setup_camera_target(game_state, input);
-        Self::setup_camera(game_state);
+    pub fn update_3d_camera(
+        viewport: &Viewport,
+        time: &Time,
+        game_state: &mut GameState,
+        input: &mut Input,
+    ) {
+        let player = game_state
+            .get_entity("player")
+            .expect("Player should exist");
+        let camera_3d = game_state
+            .get_entity("camera_3d")
+            .expect("Camera should exist");
+        Self::setup_camera_target(time, game_state, player, input);
+        Self::setup_camera(game_state, player, camera_3d);
         let camera = game_state
-            .get_camera_mut("camera_3d")
+            .get_camera_mut(camera_3d)
             .expect("Camera should exist");
-        camera.update_view_projection_matrix(window);
+        camera.update_view_projection_matrix(viewport);
         camera.update_inverse_matrix();
     }
 
-    fn setup_camera(game_state: &mut GameState) {
-        let player = "player";
+    // How far from the player anything can be on screen. The projection is orthographic, so the camera distance does not matter, only the angle:
+    // the lower the camera, the further the ground is stretched out in view
+    pub fn max_view_distance() -> f32 {
+        let lowest_elevation_sine = f32::to_radians(CAMERA_BOTTOM_LIMIT).cos();
+        let half_depth = VIEW_HALF_HEIGHT / lowest_elevation_sine;
+        let half_width = VIEW_HALF_HEIGHT * MAX_ASPECT_RATIO;
+        half_depth.hypot(half_width)
+    }
+
+    fn setup_camera(game_state: &mut GameState, player: EntityId, camera_3d: EntityId) {
         let player_position = *game_state
             .get_position(player)
             .expect("Player position should exist");
@@ -39,7 +61,7 @@ impl CameraSystem {
         let rad_y = f32::to_radians(player_camera.rotation_y_degrees);
 
         let camera = game_state
-            .get_camera_mut("camera_3d")
+            .get_camera_mut(camera_3d)
             .expect("Camera should exist");
         camera.eye = Point3 {
             x: player_position.x + player_camera.distance * rad_y.sin() * rad_x.cos(),
@@ -56,23 +78,29 @@ impl CameraSystem {
         camera.up = view_direction.cross(right).normalize();
     }
 
-    fn setup_camera_target(game_state: &mut GameState, input: &mut Input) {
-        let player_camera: &mut CameraTarget = game_state.get_camera_target_mut("player").unwrap();
+    fn setup_camera_target(
+        time: &Time,
+        game_state: &mut GameState,
+        player: EntityId,
+        input: &mut Input,
+    ) {
+        let player_camera: &mut CameraTarget = game_state.get_camera_target_mut(player).unwrap();
+        let camera_rotation = CAMERA_MOVEMENT_SPEED_DEGREES * time.delta_seconds();
 
         if input.up_pressed.is_pressed {
-            player_camera.rotation_y_degrees += CAMERA_MOVEMENT_SPEED;
+            player_camera.rotation_y_degrees += camera_rotation;
         }
 
         if input.down_pressed.is_pressed {
-            player_camera.rotation_y_degrees -= CAMERA_MOVEMENT_SPEED;
+            player_camera.rotation_y_degrees -= camera_rotation;
         }
 
         if input.right_pressed.is_pressed {
-            player_camera.rotation_x_degrees -= CAMERA_MOVEMENT_SPEED;
+            player_camera.rotation_x_degrees -= camera_rotation;
         }
 
         if input.left_pressed.is_pressed {
-            player_camera.rotation_x_degrees += CAMERA_MOVEMENT_SPEED;
+            player_camera.rotation_x_degrees += camera_rotation;
         }
 
         // We do this to keep the degrees in range of 0 to 359.99.. which modulo would not do...
@@ -1,19 +1,34 @@
-use crate::state::ui_state::{RenderCommand, UserAction};
+use crate::state::chat::{ChatChannel, ChatMessage, ChatSource};
+use crate::state::input::TextInput;
+use crate::state::ui_state::{RenderCommand, UIElement, UserAction};
+use crate::state::viewport::Viewport;
 use crate::state::{
     input::Input,
     ui_state::{InputState, UIState},
     update_state::UpdateState,
 };
-use std::sync::Arc;
-use winit::window::Window;
+use cgmath::Point2;
+
+const CHAT_VISIBLE_LINES: usize = 3;
+const HELP_TEXT: &str = "/l local, /g global, /w <player> whisper, /clear, /help. Without a message it switches channel";
+
+// What a line typed in the chat asks for
+enum ChatCommand {
+    Send { channel: ChatChannel, text: String },
+    SwitchChannel(ChatChannel),
+    Clear,
+    Help,
+    Unknown(String),
+    MissingPlayer,
+}
 
 pub struct ChatSystem {}
 
 impl ChatSystem {
     pub fn handle_chat(
-        window: &Arc<Window>,
+        viewport: &Viewport,
         ui_state: &mut UIState,
-        input: &Input,
+        input: &mut Input,
         frame_state: &mut UpdateState,
     ) {
         let mut new_input_state = None;
@@ -21,15 +36,18 @@ impl ChatSystem {
             InputState::Normal => {
                 if input.enter_pressed.is_toggled_on() {
                     new_input_state = Some(InputState::Chat);
-                    // Visible vs chatstate? hmm
                 }
             }
             InputState::Chat => {
-                if input.enter_pressed.is_toggled_on() {
-                    //Self::send_message(); Should close the chat as well?
+                // Whatever was typed before enter was pressed belongs to the message
+                Self::edit(ui_state, &input.text_input);
+                if input.escape_pressed.is_toggled_on() {
+                    ui_state.chat.input.take();
+                    new_input_state = Some(InputState::Normal);
+                } else if input.enter_pressed.is_toggled_on() {
+                    Self::send_message(ui_state, frame_state);
                     new_input_state = Some(InputState::Normal);
                 }
-                // TODO Cancel options like escape to close?
             }
         }
 
@@ -41,24 +59,126 @@ impl ChatSystem {
                 .get_mut("chat")
                 .expect("Chat window should exist");
             chat_window.is_visible = matches!(ui_state.input_state, InputState::Chat);
+            input.is_typing = chat_window.is_visible;
+        }
+
+        Self::display_chat(viewport, ui_state, input, frame_state);
+    }
+
+    fn edit(ui_state: &mut UIState, text_input: &[TextInput]) {
+        for text_input in text_input {
+            match text_input {
+                TextInput::PageUp => ui_state.chat.scroll(CHAT_VISIBLE_LINES as isize),
+                TextInput::PageDown => ui_state.chat.scroll(-(CHAT_VISIBLE_LINES as isize)),
+                _ => ui_state.chat.input.apply(text_input),
+            }
+        }
+    }
+
+    fn send_message(ui_state: &mut UIState, frame_state: &mut UpdateState) {
+        let typed = ui_state.chat.input.take();
+        let typed = typed.trim();
+        if typed.is_empty() {
+            return;
+        }
+
+        match Self::parse(typed, &ui_state.chat.channel) {
+            ChatCommand::Send { channel, text } => {
+                if frame_state.is_online {
+                    frame_state.chat_messages.push((channel, text));
+                } else {
+                    ui_state.chat.push(Self::local_echo(channel, text));
+                }
+            }
+            ChatCommand::SwitchChannel(channel) => ui_state.chat.channel = channel,
+            ChatCommand::Clear => ui_state.chat.clear(),
+            ChatCommand::Help => ui_state.chat.push(ChatMessage::notice(HELP_TEXT)),
+            ChatCommand::Unknown(command) => ui_state.chat.push(ChatMessage::notice(&format!(
+                "Unknown command /{command}, type /help for the commands"
+            ))),
+            ChatCommand::MissingPlayer => ui_state.chat.push(ChatMessage::notice(
+                "Who to whisper to? /w <player> <message>",
+            )),
         }
+    }
+
+    // Text without a command goes to the channel last switched to
+    fn parse(typed: &str, current_channel: &ChatChannel) -> ChatCommand {
+        let Some(command_line) = typed.strip_prefix('/') else {
+            return ChatCommand::Send {
+                channel: current_channel.clone(),
+                text: typed.to_owned(),
+            };
+        };
+        let (command, rest) = command_line
+            .split_once(' ')
+            .map_or((command_line, ""), |(command, rest)| (command, rest.trim()));
 
-        Self::display_chat(window, ui_state, input, frame_state);
+        let (channel, text) = match command.to_lowercase().as_str() {
+            "l" | "local" | "s" | "say" => (ChatChannel::Local, rest),
+            "g" | "global" => (ChatChannel::Global, rest),
+            "w" | "whisper" => {
+                let (player, text) = rest
+                    .split_once(' ')
+                    .map_or((rest, ""), |(player, text)| (player, text.trim()));
+                if player.is_empty() {
+                    return ChatCommand::MissingPlayer;
+                }
+                let player = player.to_owned();
+                (ChatChannel::Whisper { player }, text)
+            }
+            "clear" => return ChatCommand::Clear,
+            "help" => return ChatCommand::Help,
+            _ => return ChatCommand::Unknown(command.to_owned()),
+        };
+        if text.is_empty() {
+            return ChatCommand::SwitchChannel(channel);
+        }
+        ChatCommand::Send {
+            channel,
+            text: text.to_owned(),
+        }
+    }
+
+    // Playing alone nobody else hears it
+    fn local_echo(channel: ChatChannel, text: String) -> ChatMessage {
+        let from = "You".to_owned();
+        match channel {
+            ChatChannel::Local => ChatMessage {
+                source: ChatSource::Local { from },
+                text,
+            },
+            ChatChannel::Global => ChatMessage {
+                source: ChatSource::Global { from },
+                text,
+            },
+            ChatChannel::Whisper { .. } => {
+                ChatMessage::notice("There is nobody to whisper to while playing alone")
+            }
+        }
     }
 
+    // The history stays visible, the background and what is being typed only while chatting
     fn display_chat(
-        window: &Arc<Window>,
+        viewport: &Viewport,
         ui_state: &mut UIState,
         input: &Input,
         frame_state: &mut UpdateState,
     ) {
         let chat_window = ui_state.windows.get("chat").unwrap();
+        let history_rect = UIElement::new_rect(Point2::new(0.4, 0.81), Point2::new(0.19, 0.11));
+        frame_state.gui.text_render(
+            1000,
+            history_rect,
+            &ui_state.chat.visible_text(CHAT_VISIBLE_LINES),
+            [1.0, 1.0, 1.0],
+        );
         if !chat_window.is_visible {
             return;
         }
         match frame_state
             .gui
-            .button_handle(window, chat_window.rect, input)
+            .button_handle(viewport, chat_window.rect, input)
         {
             UserAction::None => {}
             UserAction::Hover => {}
@@ -73,10 +193,10 @@ impl ChatSystem {
         };
         frame_state.gui.render_commands.push(chat_render_command);
 
-        // TODO Interact with
-        if let InputState::Normal = ui_state.input_state {
-            #[allow(clippy::needless_return)]
-            return;
-        }
+        let input_rect = UIElement::new_rect(Point2::new(0.4, 0.95), Point2::new(0.19, 0.03));
+        let input_text = ui_state.chat.channel.prompt() + &ui_state.chat.input.display_text();
+        frame_state
+            .gui
+            .text_render(1000, input_rect, &input_text, [0.8, 0.8, 0.0]);
     }
 }
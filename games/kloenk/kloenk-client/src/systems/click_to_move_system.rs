@@ -0,0 +1,210 @@
+use crate::state::components::{MovementPath, PathInteraction};
+use crate::state::entity::EntityId;
+use crate::state::game_state::GameState;
+use crate::state::input::Input;
+use crate::state::ui_state::UIState;
+use crate::state::update_state::{ActionEffect, UpdateState};
+use crate::state::viewport::Viewport;
+use crate::systems::dialogue_system::{DIALOGUE_RANGE, DialogueSystem};
+use crate::systems::item_pickup_system::ITEM_PICKUP_RANGE;
+use crate::systems::item_transaction_system::{ItemTransaction, ItemTransactionSystem};
+use crate::systems::object_detection_system::ObjectDetectionSystem;
+use crate::systems::path_manager::PathManager;
+use crate::systems::position_manager::PositionManager;
+use cgmath::Point3;
+use std::collections::VecDeque;
+
+pub struct ClickToMoveSystem {}
+
+impl ClickToMoveSystem {
+    // Clicked items that are in range are left for the item pickup system to handle
+    pub fn handle_click_to_move(
+        viewport: &Viewport,
+        game_state: &mut GameState,
+        ui_state: &mut UIState,
+        input: &Input,
+        frame_state: &mut UpdateState,
+    ) {
+        if !input.left_mouse_clicked.is_toggled_on() || frame_state.handled_left_click {
+            return;
+        }
+
+        let player = game_state
+            .get_entity("player")
+            .expect("Player should exist");
+
+        if let Some(clicked_object) = frame_state.get_nearest_object_on_cursor() {
+            if game_state.dialogue_components.contains_key(&clicked_object) {
+                if Self::in_range(game_state, player, clicked_object, DIALOGUE_RANGE) {
+                    DialogueSystem::open_dialogue(
+                        game_state,
+                        ui_state,
+                        input,
+                        frame_state,
+                        clicked_object,
+                    );
+                } else {
+                    Self::walk_to_object(
+                        game_state,
+                        frame_state,
+                        player,
+                        clicked_object,
+                        PathInteraction::Talk {
+                            npc: clicked_object,
+                        },
+                    );
+                }
+                frame_state.handled_left_click = true;
+            } else if game_state.storable_components.contains_key(&clicked_object)
+                && !Self::in_range(game_state, player, clicked_object, ITEM_PICKUP_RANGE)
+            {
+                Self::walk_to_object(
+                    game_state,
+                    frame_state,
+                    player,
+                    clicked_object,
+                    PathInteraction::Pickup {
+                        item: clicked_object,
+                    },
+                );
+                frame_state.handled_left_click = true;
+            }
+            return;
+        }
+
+        // Clicking on an open window should not make us walk around behind it
+        if ui_state.windows.values_mut().any(|ui_window| {
+            ui_window.is_visible && ui_window.rect.contains(input.mouse_position_ui, viewport)
+        }) {
+            return;
+        }
+
+        let Some(clicked_position) = Self::find_clicked_ground(game_state, input, player) else {
+            return;
+        };
+        frame_state.handled_left_click = true;
+        match PathManager::find_path(game_state, player, clicked_position, None) {
+            Some(mut waypoints) => {
+                // Walk to where we clicked instead of the center of that tile
+                waypoints.pop();
+                waypoints.push(clicked_position);
+                game_state.path_components.insert(
+                    player,
+                    MovementPath {
+                        waypoints: VecDeque::from(waypoints),
+                        on_arrival: None,
+                    },
+                );
+            }
+            None => frame_state.action_effects.push(ActionEffect::NoPathFound),
+        }
+    }
+
+    // Runs after movement: interacts as soon as the target is in range, we do not need to walk all the way up to it
+    pub fn handle_path_arrival(
+        game_state: &mut GameState,
+        ui_state: &mut UIState,
+        input: &Input,
+        frame_state: &mut UpdateState,
+    ) {
+        let player = game_state
+            .get_entity("player")
+            .expect("Player should exist");
+        let Some(path) = game_state.path_components.get(&player) else {
+            return;
+        };
+
+        let path_finished = path.waypoints.is_empty();
+        match path.on_arrival {
+            Some(PathInteraction::Pickup { item }) => {
+                if game_state.get_position(item).is_none() {
+                    // Someone else got there first
+                    game_state.path_components.remove(&player);
+                } else if Self::in_range(game_state, player, item, ITEM_PICKUP_RANGE) {
+                    game_state.path_components.remove(&player);
+                    ItemTransactionSystem::request(
+                        game_state,
+                        frame_state,
+                        player,
+                        ItemTransaction::Pickup { item },
+                    );
+                } else if path_finished {
+                    game_state.path_components.remove(&player);
+                }
+            }
+            Some(PathInteraction::Talk { npc }) => {
+                if !game_state.is_alive(npc) {
+                    game_state.path_components.remove(&player);
+                } else if Self::in_range(game_state, player, npc, DIALOGUE_RANGE) {
+                    game_state.path_components.remove(&player);
+                    DialogueSystem::open_dialogue(game_state, ui_state, input, frame_state, npc);
+                } else if path_finished {
+                    game_state.path_components.remove(&player);
+                }
+            }
+            None => {
+                if path_finished {
+                    game_state.path_components.remove(&player);
+                }
+            }
+        }
+    }
+
+    fn walk_to_object(
+        game_state: &mut GameState,
+        frame_state: &mut UpdateState,
+        player: EntityId,
+        object: EntityId,
+        on_arrival: PathInteraction,
+    ) {
+        let object_position = *game_state
+            .get_position(object)
+            .expect("Clicked object should have a position");
+        match PathManager::find_path(game_state, player, object_position, Some(object)) {
+            Some(waypoints) => {
+                game_state.path_components.insert(
+                    player,
+                    MovementPath {
+                        waypoints: VecDeque::from(waypoints),
+                        on_arrival: Some(on_arrival),
+                    },
+                );
+            }
+            None => frame_state.action_effects.push(ActionEffect::NoPathFound),
+        }
+    }
+
+    // The player walks on a flat plane, so we intersect the cursor ray with the plane at the height of the player
+    pub fn find_clicked_ground(
+        game_state: &GameState,
+        input: &Input,
+        player: EntityId,
+    ) -> Option<Point3<f32>> {
+        let walk_height = game_state
+            .get_position(player)
+            .expect("Player position should exist")
+            .y;
+        let ray = ObjectDetectionSystem::get_cursor_ray(game_state, input);
+        if ray.direction.y.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let distance = (walk_height - ray.origin.y) / ray.direction.y;
+        if distance < 0.0 {
+            return None;
+        }
+        Some(ray.origin + ray.direction * distance)
+    }
+
+    fn in_range(game_state: &GameState, player: EntityId, object: EntityId, range: f32) -> bool {
+        match (
+            game_state.get_position(player),
+            game_state.get_position(object),
+        ) {
+            (Some(player_position), Some(object_position)) => {
+                PositionManager::in_range(player_position, object_position, range)
+            }
+            _ => false,
+        }
+    }
+}
@@ -1,4 +1,5 @@
-use crate::state::components::Hitbox;
+use crate::state::components::{Hitbox, WorldCollider};
+use cgmath::Point3;
 
 pub struct CollisionManager {}
 
@@ -25,6 +26,77 @@ impl CollisionManager {
         true
     }
 
+    pub fn check_collider_collision(
+        collider_one: &WorldCollider,
+        collider_two: &WorldCollider,
+    ) -> bool {
+        match (collider_one, collider_two) {
+            (WorldCollider::Box(box_one), WorldCollider::Box(box_two)) => {
+                Self::check_collision(box_one, box_two)
+            }
+            (
+                WorldCollider::Cylinder {
+                    base,
+                    radius,
+                    height,
+                },
+                WorldCollider::Box(bounding_box),
+            )
+            | (
+                WorldCollider::Box(bounding_box),
+                WorldCollider::Cylinder {
+                    base,
+                    radius,
+                    height,
+                },
+            ) => Self::check_cylinder_box_collision(base, *radius, *height, bounding_box),
+            (
+                WorldCollider::Cylinder {
+                    base: base_one,
+                    radius: radius_one,
+                    height: height_one,
+                },
+                WorldCollider::Cylinder {
+                    base: base_two,
+                    radius: radius_two,
+                    height: height_two,
+                },
+            ) => {
+                if base_one.y + height_one <= base_two.y || base_one.y >= base_two.y + height_two {
+                    return false;
+                }
+                let distance_x = base_one.x - base_two.x;
+                let distance_z = base_one.z - base_two.z;
+                let radius_sum = radius_one + radius_two;
+                distance_x * distance_x + distance_z * distance_z < radius_sum * radius_sum
+            }
+        }
+    }
+
+    // Compares the circle of the cylinder to the closest point of the box on the xz plane
+    fn check_cylinder_box_collision(
+        base: &Point3<f32>,
+        radius: f32,
+        height: f32,
+        bounding_box: &Hitbox,
+    ) -> bool {
+        if base.y + height <= bounding_box.box_corner_min.y
+            || base.y >= bounding_box.box_corner_max.y
+        {
+            return false;
+        }
+
+        let closest_x = base
+            .x
+            .clamp(bounding_box.box_corner_min.x, bounding_box.box_corner_max.x);
+        let closest_z = base
+            .z
+            .clamp(bounding_box.box_corner_min.z, bounding_box.box_corner_max.z);
+        let distance_x = base.x - closest_x;
+        let distance_z = base.z - closest_z;
+        distance_x * distance_x + distance_z * distance_z < radius * radius
+    }
+
     pub fn check_in_dimension(
         position1: f32,
         boundary1: f32,
@@ -1,59 +1,243 @@
 use crate::state::game_state::GameState;
-use crate::state::ui_state::UIState;
-use crate::state::update_state::{ActionEffect, ActionRequest, UpdateState};
-use crate::systems::item_placement_system::ItemPlacementSystem;
+use crate::state::ui_state::{DialogueState, MenuState, UIState};
+use crate::state::update_state::{ActionEffect, ActionRequest, EntityCommand, UpdateState};
+use crate::systems::dialogue_effect_system::DialogueEffectSystem;
+use crate::systems::item_transaction_system::{ItemTransaction, ItemTransactionSystem};
+use crate::systems::quest_system::QuestSystem;
 
 pub struct CommandHandleSystem {}
 
 impl CommandHandleSystem {
     pub fn handle_action_requests(game_state: &mut GameState, frame_state: &mut UpdateState) {
-        frame_state
-            .action_requests
-            .iter()
-            .for_each(|command| match command {
+        let player = game_state
+            .get_entity("player")
+            .expect("Player should exist");
+        for command in std::mem::take(&mut frame_state.action_requests) {
+            match command {
                 ActionRequest::ItemPlacement { entity } => {
-                    ItemPlacementSystem::place_item(
+                    ItemTransactionSystem::request(
+                        game_state,
+                        frame_state,
+                        player,
+                        ItemTransaction::Drop { item: entity },
+                    );
+                }
+                ActionRequest::DialogueChoice { choice, effects } => {
+                    DialogueEffectSystem::request(
                         game_state,
-                        &mut frame_state.action_effects,
-                        entity,
+                        frame_state,
+                        player,
+                        choice,
+                        &effects,
                     );
                 }
-            });
+            }
+        }
     }
 
-    pub fn handle_action_effects(ui_state: &mut UIState, frame_state: &mut UpdateState) {
-        frame_state
-            .action_effects
-            .iter()
-            .for_each(|command| match command {
-                ActionEffect::PickupItemNotStorable => {
-                    "That cannot be picked up.".clone_into(&mut ui_state.action_text);
-                }
-                ActionEffect::PickupNoItemInRange => {
-                    "No item found around you to pick up.".clone_into(&mut ui_state.action_text);
-                }
-                ActionEffect::PlaceItemNotInInventory => {
-                    "Tried to place an item not in your inventory, how did that happen?"
-                        .clone_into(&mut ui_state.action_text);
-                }
-                ActionEffect::PlaceItemNonPlaceable => {
-                    "Cannot place outside placeable area.".clone_into(&mut ui_state.action_text);
-                }
-                ActionEffect::PlaceItemCollidingItem => {
-                    "Found a colliding object.\nNot allowed to place there."
-                        .clone_into(&mut ui_state.action_text);
-                }
-                ActionEffect::PickupNoInventorySpace => {
-                    "There is no space left in your\ninventory to pick up this item."
-                        .clone_into(&mut ui_state.action_text);
-                }
-                ActionEffect::PlaceItemSucceeded => {
-                    "You drop the item.".clone_into(&mut ui_state.action_text);
+    pub fn handle_entity_commands(
+        game_state: &mut GameState,
+        ui_state: &mut UIState,
+        frame_state: &mut UpdateState,
+    ) {
+        Self::apply_entity_commands(game_state, std::mem::take(&mut frame_state.entity_commands));
+
+        // Do not keep windows open for entities that no longer exist
+        if let MenuState::WorldAction { item, .. } | MenuState::InventoryAction { item, .. } =
+            &ui_state.menu_state
+            && !game_state.is_alive(*item)
+        {
+            ui_state.menu_state = MenuState::Closed;
+        }
+        if let DialogueState::Npc { npc_entity_id, .. } = &ui_state.dialogue_state
+            && !game_state.is_alive(*npc_entity_id)
+        {
+            ui_state.dialogue_state = DialogueState::Closed;
+        }
+    }
+
+    // In the order they were requested. Shared with the server, which applies them at the end of its tick as well
+    pub fn apply_entity_commands(game_state: &mut GameState, entity_commands: Vec<EntityCommand>) {
+        for command in entity_commands {
+            match command {
+                EntityCommand::Spawn { definition } => {
+                    game_state.load_entity(&definition);
                 }
-                ActionEffect::ItemSelected { found_objects_text } => {
-                    found_objects_text.clone_into(&mut ui_state.selected_text);
+                EntityCommand::Despawn { entity } => {
+                    game_state.despawn(entity);
                 }
-                ActionEffect::Examine { text } => text.clone_into(&mut ui_state.action_text),
-            });
+            }
+        }
+    }
+
+    // Online the quests are followed by the server, which sends the stages done along with the other effects
+    pub fn handle_action_effects(
+        game_state: &mut GameState,
+        ui_state: &mut UIState,
+        frame_state: &mut UpdateState,
+    ) {
+        if !frame_state.is_online {
+            let player = game_state
+                .get_entity("player")
+                .expect("Player should exist");
+            let quest_effects = QuestSystem::track(
+                game_state,
+                &ui_state.quests,
+                player,
+                &frame_state.action_effects,
+            );
+            frame_state.action_effects.extend(quest_effects);
+        }
+        Self::show_action_effects(ui_state, &frame_state.action_effects);
+    }
+
+    // Effects of actions done by the server come in here as well
+    pub fn show_action_effects(ui_state: &mut UIState, action_effects: &[ActionEffect]) {
+        action_effects.iter().for_each(|command| match command {
+            ActionEffect::PickupItemNotStorable => {
+                "That cannot be picked up.".clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::PickupNoItemInRange => {
+                "No item found around you to pick up.".clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::PlaceItemNotInInventory => {
+                "Tried to place an item not in your inventory, how did that happen?"
+                    .clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::PlaceItemNonPlaceable => {
+                "Cannot place outside placeable area.".clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::PlaceItemOutOfRange => {
+                "That is too far away to put it down.".clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::PlaceItemCollidingItem => {
+                "Found a colliding object.\nNot allowed to place there."
+                    .clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::PickupNoInventorySpace => {
+                "There is no space left in your\ninventory to pick up this item."
+                    .clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::PlaceItemSucceeded { .. } => {
+                "You drop the item.".clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::ItemSelected { found_objects_text } => {
+                found_objects_text.clone_into(&mut ui_state.selected_text);
+            }
+            ActionEffect::Examine { text } => text.clone_into(&mut ui_state.action_text),
+            ActionEffect::NoPathFound => {
+                "You cannot walk there.".clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::MoveItemSpotTaken => {
+                "The item does not fit there.".clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::SortItemsDoNotFit => {
+                "Your items would not all fit sorted,\nso they stay where they are."
+                    .clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::TradeRecipientNotAStorage => {
+                "They cannot take any items.".clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::TradeRecipientIsAPlayer => {
+                "Put it down for them to pick up.".clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::TradeRecipientOutOfRange => {
+                "You need to get closer to hand that over.".clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::TradeNoRecipientSpace => {
+                "They have no space left for that item.".clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::TradeSucceeded => {
+                "You hand over the item.".clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::DialogueChoiceUnavailable => {
+                "That is not possible anymore.".clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::DialogueItemReceived => {
+                "You are handed an item.".clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::DialogueItemNotHeld => {
+                "They no longer have that to give.".clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::DialogueItemMissing => {
+                "You do not have what they asked for.".clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::Healed => {
+                "You feel better.".clone_into(&mut ui_state.action_text);
+            }
+            ActionEffect::PickupSucceeded { .. } | ActionEffect::TalkedTo { .. } => {}
+            ActionEffect::QuestStarted { quest } => {
+                ui_state.action_text = format!("New quest: {}", ui_state.quests.title(quest));
+            }
+            ActionEffect::QuestStageCompleted { quest } => {
+                ui_state.action_text = format!(
+                    "Quest updated: {}\nSee your quest log (L).",
+                    ui_state.quests.title(quest)
+                );
+            }
+            ActionEffect::QuestCompleted { quest } => {
+                ui_state.action_text = format!("Quest completed: {}", ui_state.quests.title(quest));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::world_definition::{ColliderDefinition, EntityDefinition};
+    use cgmath::Point2;
+
+    fn chest(id: &str, x: f32) -> EntityDefinition {
+        EntityDefinition {
+            id: Some(id.to_owned()),
+            position: Some([x, 0.0, 0.0]),
+            collider: Some(ColliderDefinition::Box {
+                min_offset: [-0.5, 0.0, -0.5],
+                max_offset: [0.5, 1.0, 0.5],
+            }),
+            ..EntityDefinition::default()
+        }
+    }
+
+    #[test]
+    fn spawns_and_despawns_only_happen_at_the_end_of_the_tick() {
+        let mut game_state = GameState::empty();
+        let mut ui_state = UIState::new();
+        let mut frame_state = UpdateState::new();
+        let old_chest = game_state.load_entity(&chest("old_chest", 5.0));
+
+        frame_state.entity_commands.push(EntityCommand::Spawn {
+            definition: Box::new(chest("new_chest", 0.0)),
+        });
+        frame_state
+            .entity_commands
+            .push(EntityCommand::Despawn { entity: old_chest });
+
+        // Systems running after the request still see the world as it was at the start of the tick
+        assert_eq!(game_state.get_entity("new_chest"), None);
+        assert!(game_state.get_entities_at(Point2::new(0.0, 0.0)).is_empty());
+        assert!(game_state.is_alive(old_chest));
+        assert_eq!(
+            game_state.get_entities_at(Point2::new(5.0, 0.0)),
+            vec![old_chest]
+        );
+
+        CommandHandleSystem::handle_entity_commands(
+            &mut game_state,
+            &mut ui_state,
+            &mut frame_state,
+        );
+
+        let new_chest = game_state
+            .get_entity("new_chest")
+            .expect("Chest should be spawned");
+        assert_eq!(
+            game_state.get_entities_at(Point2::new(0.0, 0.0)),
+            vec![new_chest]
+        );
+        assert!(!game_state.is_alive(old_chest));
+        assert!(game_state.get_entities_at(Point2::new(5.0, 0.0)).is_empty());
+        assert!(frame_state.entity_commands.is_empty());
     }
 }
@@ -0,0 +1,421 @@
+use crate::state::entity::EntityId;
+use crate::state::game_state::GameState;
+use crate::state::update_state::{ActionEffect, EntityCommand, UpdateState};
+use crate::systems::dialogue_manager::{DialogueCondition, DialogueEffect};
+use crate::systems::item_transaction_system::ItemTransactionSystem;
+use crate::systems::quest_system::QuestSystem;
+use crate::systems::storage_manager::StorageManager;
+use serde::{Deserialize, Serialize};
+
+// A choice picked in a conversation. The index counts all choices of the node, also the ones that were not shown
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DialogueChoiceMade {
+    pub npc: EntityId,
+    pub node_id: String,
+    pub choice: usize,
+}
+
+pub struct DialogueEffectSystem {}
+
+impl DialogueEffectSystem {
+    // Applied right away when playing alone. Online the server checks the conditions again and applies the effects
+    pub fn request(
+        game_state: &mut GameState,
+        frame_state: &mut UpdateState,
+        player: EntityId,
+        choice: DialogueChoiceMade,
+        effects: &[DialogueEffect],
+    ) {
+        if frame_state.is_online {
+            frame_state.dialogue_choices.push(choice);
+            return;
+        }
+        for effect in effects {
+            let action_effect = Self::apply(
+                game_state,
+                player,
+                choice.npc,
+                effect,
+                &mut frame_state.entity_commands,
+            );
+            frame_state.action_effects.extend(action_effect);
+        }
+    }
+
+    pub fn conditions_hold(
+        game_state: &GameState,
+        player: EntityId,
+        conditions: &[DialogueCondition],
+    ) -> bool {
+        conditions
+            .iter()
+            .all(|condition| Self::condition_holds(game_state, player, condition))
+    }
+
+    fn condition_holds(
+        game_state: &GameState,
+        player: EntityId,
+        condition: &DialogueCondition,
+    ) -> bool {
+        match condition {
+            DialogueCondition::HasItem { item } => {
+                game_state.get_entity(item).is_some_and(|item| {
+                    StorageManager::get_in_storage(game_state, player).contains(&item)
+                })
+            }
+            DialogueCondition::HealthAbove { hitpoints } => game_state
+                .health_components
+                .get(&player)
+                .is_some_and(|health| health.hitpoints > *hitpoints),
+            DialogueCondition::FlagSet { flag } => game_state.has_flag(player, flag),
+            DialogueCondition::FlagNotSet { flag } => !game_state.has_flag(player, flag),
+            DialogueCondition::QuestNotStarted { quest } => {
+                !QuestSystem::has_started(game_state, player, quest)
+            }
+            DialogueCondition::QuestCompleted { quest } => {
+                QuestSystem::has_completed(game_state, player, quest)
+            }
+        }
+    }
+
+    // An effect that cannot be applied is skipped, the player is told why. The ones after it still apply
+    pub fn apply(
+        game_state: &mut GameState,
+        player: EntityId,
+        npc: EntityId,
+        effect: &DialogueEffect,
+        entity_commands: &mut Vec<EntityCommand>,
+    ) -> Option<ActionEffect> {
+        match effect {
+            DialogueEffect::GiveItem { item } => Self::give_item(game_state, player, npc, item),
+            DialogueEffect::TakeItem { item } => {
+                Self::take_item(game_state, player, npc, item, entity_commands)
+            }
+            DialogueEffect::SetFlag { flag } => {
+                game_state.set_flag(player, flag);
+                None
+            }
+            DialogueEffect::ClearFlag { flag } => {
+                game_state.clear_flag(player, flag);
+                None
+            }
+            DialogueEffect::Heal { hitpoints } => {
+                let health = game_state.health_components.get_mut(&player)?;
+                health.hitpoints = health
+                    .hitpoints
+                    .saturating_add(*hitpoints)
+                    .min(health.max_hitpoints);
+                Some(ActionEffect::Healed)
+            }
+            // Started only once, the progress made is kept
+            DialogueEffect::StartQuest { quest } => QuestSystem::start(game_state, player, quest)
+                .then(|| ActionEffect::QuestStarted {
+                    quest: quest.clone(),
+                }),
+        }
+    }
+
+    fn give_item(
+        game_state: &mut GameState,
+        player: EntityId,
+        npc: EntityId,
+        item: &str,
+    ) -> Option<ActionEffect> {
+        // Someone else might have gotten it first
+        let Some(item) = game_state.get_entity(item).filter(|item| {
+            ItemTransactionSystem::check_in_inventory(game_state, npc, *item).is_ok()
+        }) else {
+            return Some(ActionEffect::DialogueItemNotHeld);
+        };
+        let inventory = game_state.get_storage(player)?;
+        let inventory_items = StorageManager::get_in_storage(game_state, player);
+        let Some(spot) =
+            StorageManager::find_empty_spot(game_state, inventory, &inventory_items, item)
+        else {
+            return Some(ActionEffect::PickupNoInventorySpace);
+        };
+
+        game_state.create_in_storage(player, item, spot);
+        Some(ActionEffect::DialogueItemReceived)
+    }
+
+    fn take_item(
+        game_state: &mut GameState,
+        player: EntityId,
+        npc: EntityId,
+        item: &str,
+        entity_commands: &mut Vec<EntityCommand>,
+    ) -> Option<ActionEffect> {
+        let Some(item) = game_state.get_entity(item).filter(|item| {
+            ItemTransactionSystem::check_in_inventory(game_state, player, *item).is_ok()
+        }) else {
+            return Some(ActionEffect::DialogueItemMissing);
+        };
+        let npc_spot = game_state.get_storage(npc).and_then(|storage| {
+            let npc_items = StorageManager::get_in_storage(game_state, npc);
+            StorageManager::find_empty_spot(game_state, storage, &npc_items, item)
+        });
+
+        match npc_spot {
+            Some(spot) => game_state.create_in_storage(npc, item, spot),
+            None => entity_commands.push(EntityCommand::Despawn { entity: item }),
+        }
+        Some(ActionEffect::TradeSucceeded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::world_definition::{
+        EntityDefinition, HealthDefinition, ItemShapeDefinition, StorageDefinition,
+    };
+
+    // The player holds an apple, Dennis a shield and nobody the sword. Each has room for one more item
+    fn world() -> (GameState, EntityId, EntityId) {
+        let mut game_state = GameState::empty();
+        let player = game_state.load_entity(&EntityDefinition {
+            id: Some("player".to_owned()),
+            storage: Some(StorageDefinition {
+                number_of_rows: 1,
+                number_of_columns: 2,
+            }),
+            health: Some(HealthDefinition {
+                hitpoints: 50,
+                max_hitpoints: 100,
+            }),
+            ..EntityDefinition::default()
+        });
+        let npc = game_state.load_entity(&EntityDefinition {
+            id: Some("Dennis".to_owned()),
+            storage: Some(StorageDefinition {
+                number_of_rows: 1,
+                number_of_columns: 2,
+            }),
+            ..EntityDefinition::default()
+        });
+        for (item, holder) in [
+            ("apple", Some(player)),
+            ("shield", Some(npc)),
+            ("sword", None),
+        ] {
+            let item = game_state.load_entity(&EntityDefinition {
+                id: Some(item.to_owned()),
+                storable: Some(ItemShapeDefinition {
+                    width: 1,
+                    height: 1,
+                }),
+                ..EntityDefinition::default()
+            });
+            if let Some(holder) = holder {
+                game_state.create_in_storage(holder, item, (0, 0));
+            }
+        }
+        (game_state, player, npc)
+    }
+
+    fn holds(game_state: &GameState, holder: EntityId, item: &str) -> bool {
+        let item = game_state.get_entity(item).unwrap();
+        ItemTransactionSystem::check_in_inventory(game_state, holder, item).is_ok()
+    }
+
+    fn holds_condition(
+        game_state: &GameState,
+        player: EntityId,
+        condition: DialogueCondition,
+    ) -> bool {
+        DialogueEffectSystem::conditions_hold(game_state, player, &[condition])
+    }
+
+    fn apply(
+        game_state: &mut GameState,
+        player: EntityId,
+        npc: EntityId,
+        effect: DialogueEffect,
+    ) -> (Option<ActionEffect>, Vec<EntityCommand>) {
+        let mut entity_commands = Vec::new();
+        let action_effect =
+            DialogueEffectSystem::apply(game_state, player, npc, &effect, &mut entity_commands);
+        (action_effect, entity_commands)
+    }
+
+    #[test]
+    fn items_are_only_had_when_in_the_inventory_of_the_player() {
+        let (game_state, player, _) = world();
+        let has = |item: &str| {
+            holds_condition(
+                &game_state,
+                player,
+                DialogueCondition::HasItem {
+                    item: item.to_owned(),
+                },
+            )
+        };
+        assert!(has("apple"));
+        assert!(!has("shield"), "Dennis has it");
+        assert!(!has("sword"), "Nobody has it");
+        assert!(!has("potion"), "It does not exist");
+    }
+
+    #[test]
+    fn health_has_to_be_above_the_hitpoints() {
+        let (game_state, player, npc) = world();
+        let above = |entity: EntityId, hitpoints: u32| {
+            holds_condition(
+                &game_state,
+                entity,
+                DialogueCondition::HealthAbove { hitpoints },
+            )
+        };
+        assert!(above(player, 49));
+        assert!(!above(player, 50));
+        assert!(!above(npc, 0), "Dennis has no health at all");
+    }
+
+    #[test]
+    fn flags_are_set_and_cleared_per_player() {
+        let (mut game_state, player, npc) = world();
+        let flag = || "met_dennis".to_owned();
+        assert!(holds_condition(
+            &game_state,
+            player,
+            DialogueCondition::FlagNotSet { flag: flag() }
+        ));
+        assert!(!holds_condition(
+            &game_state,
+            player,
+            DialogueCondition::FlagSet { flag: flag() }
+        ));
+
+        let set_flag = DialogueEffect::SetFlag { flag: flag() };
+        assert!(apply(&mut game_state, player, npc, set_flag).0.is_none());
+        assert!(holds_condition(
+            &game_state,
+            player,
+            DialogueCondition::FlagSet { flag: flag() }
+        ));
+        assert!(!holds_condition(
+            &game_state,
+            player,
+            DialogueCondition::FlagNotSet { flag: flag() }
+        ));
+        assert!(!game_state.has_flag(npc, &flag()));
+
+        apply(
+            &mut game_state,
+            player,
+            npc,
+            DialogueEffect::ClearFlag { flag: flag() },
+        );
+        assert!(holds_condition(
+            &game_state,
+            player,
+            DialogueCondition::FlagNotSet { flag: flag() }
+        ));
+    }
+
+    #[test]
+    fn quests_are_started_once_and_completed_by_playing_them() {
+        let (mut game_state, player, npc) = world();
+        let quest = || "find_the_shield".to_owned();
+        let not_started = DialogueCondition::QuestNotStarted { quest: quest() };
+        let completed = DialogueCondition::QuestCompleted { quest: quest() };
+        assert!(holds_condition(&game_state, player, not_started.clone()));
+        assert!(!holds_condition(&game_state, player, completed.clone()));
+
+        let start = || DialogueEffect::StartQuest { quest: quest() };
+        assert!(matches!(
+            apply(&mut game_state, player, npc, start()).0,
+            Some(ActionEffect::QuestStarted { quest }) if quest == "find_the_shield"
+        ));
+        assert!(!holds_condition(&game_state, player, not_started));
+        assert!(!holds_condition(&game_state, player, completed.clone()));
+        assert!(apply(&mut game_state, player, npc, start()).0.is_none());
+
+        game_state
+            .quest_components
+            .get_mut(&player)
+            .unwrap()
+            .quests
+            .get_mut("find_the_shield")
+            .unwrap()
+            .is_completed = true;
+        assert!(holds_condition(&game_state, player, completed));
+        assert!(
+            apply(&mut game_state, player, npc, start()).0.is_none(),
+            "Not started again"
+        );
+    }
+
+    #[test]
+    fn items_are_given_only_while_the_npc_holds_them() {
+        let (mut game_state, player, npc) = world();
+        let give = |item: &str| DialogueEffect::GiveItem {
+            item: item.to_owned(),
+        };
+        assert!(matches!(
+            apply(&mut game_state, player, npc, give("shield")).0,
+            Some(ActionEffect::DialogueItemReceived)
+        ));
+        assert!(holds(&game_state, player, "shield"));
+        for item in ["shield", "sword", "potion"] {
+            assert!(matches!(
+                apply(&mut game_state, player, npc, give(item)).0,
+                Some(ActionEffect::DialogueItemNotHeld)
+            ));
+        }
+
+        // The inventory is full now
+        let sword = game_state.get_entity("sword").unwrap();
+        game_state.create_in_storage(npc, sword, (0, 0));
+        assert!(matches!(
+            apply(&mut game_state, player, npc, give("sword")).0,
+            Some(ActionEffect::PickupNoInventorySpace)
+        ));
+        assert!(holds(&game_state, npc, "sword"));
+    }
+
+    #[test]
+    fn items_are_taken_into_the_npc_storage_or_gone() {
+        let (mut game_state, player, npc) = world();
+        let take = |item: &str| DialogueEffect::TakeItem {
+            item: item.to_owned(),
+        };
+        assert!(matches!(
+            apply(&mut game_state, player, npc, take("shield")).0,
+            Some(ActionEffect::DialogueItemMissing)
+        ));
+        let (action_effect, entity_commands) = apply(&mut game_state, player, npc, take("apple"));
+        assert!(matches!(action_effect, Some(ActionEffect::TradeSucceeded)));
+        assert!(entity_commands.is_empty());
+        assert!(holds(&game_state, npc, "apple"));
+
+        // Dennis has no room left for the sword, so it is gone at the end of the tick
+        let sword = game_state.get_entity("sword").unwrap();
+        game_state.create_in_storage(player, sword, (0, 0));
+        let (action_effect, entity_commands) = apply(&mut game_state, player, npc, take("sword"));
+        assert!(matches!(action_effect, Some(ActionEffect::TradeSucceeded)));
+        assert!(matches!(
+            entity_commands[..],
+            [EntityCommand::Despawn { entity }] if entity == sword
+        ));
+    }
+
+    #[test]
+    fn healing_stops_at_the_maximum() {
+        let (mut game_state, player, npc) = world();
+        let heal = || DialogueEffect::Heal { hitpoints: 30 };
+        let hitpoints = |game_state: &GameState| game_state.health_components[&player].hitpoints;
+        assert!(matches!(
+            apply(&mut game_state, player, npc, heal()).0,
+            Some(ActionEffect::Healed)
+        ));
+        assert_eq!(hitpoints(&game_state), 80);
+        apply(&mut game_state, player, npc, heal());
+        assert_eq!(hitpoints(&game_state), 100);
+        assert!(
+            apply(&mut game_state, npc, player, heal()).0.is_none(),
+            "Dennis has no health"
+        );
+    }
+}
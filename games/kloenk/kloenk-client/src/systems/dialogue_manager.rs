@@ -1,38 +1,471 @@
-use std::collections::HashMap;
+use crate::state::world_definition::WorldDefinition;
+use crate::systems::quest_manager::QuestManager;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
 
-#[derive(Clone)]
+// A conversation is a graph: the NPC says the line of a node, the player answers with one of its choices which leads to the next node
+// A node without choices ends the conversation
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct Dialogue {
+    pub start: String,
+    pub nodes: BTreeMap<String, DialogueNode>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DialogueNode {
+    pub line: String,
+    #[serde(default)]
+    pub choices: Vec<DialogueChoice>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DialogueChoice {
     pub text: String,
+    pub next: String,
+    #[serde(default)]
+    pub conditions: Vec<DialogueCondition>, // All have to hold for the choice to be shown
+    #[serde(default)]
+    pub effects: Vec<DialogueEffect>, // Applied in order when the choice is picked
+}
+
+// Checked against the player talking
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "condition", rename_all = "snake_case", deny_unknown_fields)]
+pub enum DialogueCondition {
+    HasItem { item: String }, // By the id the item has in the world file
+    HealthAbove { hitpoints: u32 },
+    FlagSet { flag: String },
+    FlagNotSet { flag: String },
+    QuestNotStarted { quest: String },
+    QuestCompleted { quest: String },
+}
+
+// Items are handed between the player and the NPC they talk to
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "effect", rename_all = "snake_case", deny_unknown_fields)]
+pub enum DialogueEffect {
+    GiveItem { item: String }, // From the storage of the NPC
+    TakeItem { item: String }, // Into the storage of the NPC, or gone when it has no room
+    SetFlag { flag: String },
+    ClearFlag { flag: String },
+    Heal { hitpoints: u32 },
+    StartQuest { quest: String },
+}
+
+impl DialogueNode {
+    pub fn is_end(&self) -> bool {
+        self.choices.is_empty()
+    }
+}
+
+impl DialogueChoice {
+    // The ids of the items and the quests its conditions and effects name
+    fn references(&self) -> (Vec<&str>, Vec<&str>) {
+        let mut items = Vec::new();
+        let mut quests = Vec::new();
+        for condition in &self.conditions {
+            match condition {
+                DialogueCondition::HasItem { item } => items.push(item.as_str()),
+                DialogueCondition::QuestNotStarted { quest }
+                | DialogueCondition::QuestCompleted { quest } => quests.push(quest.as_str()),
+                DialogueCondition::HealthAbove { .. }
+                | DialogueCondition::FlagSet { .. }
+                | DialogueCondition::FlagNotSet { .. } => {}
+            }
+        }
+        for effect in &self.effects {
+            match effect {
+                DialogueEffect::GiveItem { item } | DialogueEffect::TakeItem { item } => {
+                    items.push(item.as_str());
+                }
+                DialogueEffect::StartQuest { quest } => quests.push(quest.as_str()),
+                DialogueEffect::SetFlag { .. }
+                | DialogueEffect::ClearFlag { .. }
+                | DialogueEffect::Heal { .. } => {}
+            }
+        }
+        (items, quests)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawDialogues {
+    dialogues: BTreeMap<String, Dialogue>,
+}
+
+#[derive(Debug)]
+pub enum DialogueError {
+    Syntax {
+        message: String,
+    },
+    UnknownStart {
+        dialogue: String,
+        start: String,
+    },
+    DanglingLink {
+        dialogue: String,
+        node: String,
+        next: String,
+    },
+    UnreachableNode {
+        dialogue: String,
+        node: String,
+    },
+    UnknownQuest {
+        dialogue: String,
+        node: String,
+        quest: String,
+    },
+    UnknownItem {
+        dialogue: String,
+        node: String,
+        item: String,
+    },
 }
 
-impl Dialogue {}
+impl fmt::Display for DialogueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DialogueError::Syntax { message } => write!(f, "Invalid dialogue file: {message}"),
+            DialogueError::UnknownStart { dialogue, start } => {
+                write!(f, "Dialogue '{dialogue}' starts at unknown node '{start}'")
+            }
+            DialogueError::DanglingLink {
+                dialogue,
+                node,
+                next,
+            } => write!(
+                f,
+                "Node '{node}' of dialogue '{dialogue}' has a choice leading to unknown node '{next}'"
+            ),
+            DialogueError::UnreachableNode { dialogue, node } => write!(
+                f,
+                "Node '{node}' of dialogue '{dialogue}' cannot be reached from the start"
+            ),
+            DialogueError::UnknownQuest {
+                dialogue,
+                node,
+                quest,
+            } => write!(
+                f,
+                "Node '{node}' of dialogue '{dialogue}' has a choice with unknown quest '{quest}'"
+            ),
+            DialogueError::UnknownItem {
+                dialogue,
+                node,
+                item,
+            } => write!(
+                f,
+                "Node '{node}' of dialogue '{dialogue}' has a choice with item '{item}', which is not in the world"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DialogueError {}
+
+impl From<serde_json::Error> for DialogueError {
+    fn from(error: serde_json::Error) -> Self {
+        DialogueError::Syntax {
+            message: error.to_string(), // Includes line and column
+        }
+    }
+}
 
-// Probably at some point want to store this in file and load
+// Loaded once from the dialogue file. Entities refer to a dialogue by its id
 pub struct DialogueManager {
     dialogues: HashMap<String, Dialogue>,
 }
 
+impl Default for DialogueManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DialogueManager {
+    // Without dialogues, until the file is loaded
     pub fn new() -> DialogueManager {
         Self {
-            dialogues: Self::load_dialogues(),
+            dialogues: HashMap::new(),
         }
     }
 
-    fn load_dialogues() -> HashMap<String, Dialogue> {
-        let mut dialogues: HashMap<String, Dialogue> = HashMap::new();
+    // Checked as a whole, a broken link would otherwise only show up once someone picks that choice
+    pub fn from_json(bytes: &[u8]) -> Result<DialogueManager, DialogueError> {
+        let raw: RawDialogues = serde_json::from_slice(bytes)?;
+        for (dialogue_id, dialogue) in &raw.dialogues {
+            Self::validate(dialogue_id, dialogue)?;
+        }
+        Ok(Self {
+            dialogues: raw.dialogues.into_iter().collect(),
+        })
+    }
 
-        dialogues.insert(
-            "dennis_intro".to_owned(),
-            Dialogue {
-                text: "Welcome to the exciting world of Kloenk!".to_owned(),
-            },
-        );
+    fn validate(dialogue_id: &str, dialogue: &Dialogue) -> Result<(), DialogueError> {
+        if !dialogue.nodes.contains_key(&dialogue.start) {
+            return Err(DialogueError::UnknownStart {
+                dialogue: dialogue_id.to_owned(),
+                start: dialogue.start.clone(),
+            });
+        }
+        for (node_id, node) in &dialogue.nodes {
+            if let Some(choice) = node
+                .choices
+                .iter()
+                .find(|choice| !dialogue.nodes.contains_key(&choice.next))
+            {
+                return Err(DialogueError::DanglingLink {
+                    dialogue: dialogue_id.to_owned(),
+                    node: node_id.clone(),
+                    next: choice.next.clone(),
+                });
+            }
+        }
 
-        dialogues
+        let mut reached = HashSet::from([dialogue.start.as_str()]);
+        let mut to_visit = VecDeque::from([dialogue.start.as_str()]);
+        while let Some(node_id) = to_visit.pop_front() {
+            for choice in &dialogue.nodes[node_id].choices {
+                if reached.insert(choice.next.as_str()) {
+                    to_visit.push_back(choice.next.as_str());
+                }
+            }
+        }
+        if let Some(node_id) = dialogue
+            .nodes
+            .keys()
+            .find(|node_id| !reached.contains(node_id.as_str()))
+        {
+            return Err(DialogueError::UnreachableNode {
+                dialogue: dialogue_id.to_owned(),
+                node: node_id.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    // Quests and items are named by their id in the other files. Checked once all of them are loaded
+    pub fn check_references(
+        &self,
+        world: &WorldDefinition,
+        quests: &QuestManager,
+    ) -> Result<(), DialogueError> {
+        let entity_ids: HashSet<&str> = world
+            .entities
+            .iter()
+            .filter_map(|entity| entity.id.as_deref())
+            .collect();
+        let mut dialogue_ids: Vec<&String> = self.dialogues.keys().collect();
+        dialogue_ids.sort();
+        for dialogue_id in dialogue_ids {
+            for (node_id, node) in &self.dialogues[dialogue_id].nodes {
+                for choice in &node.choices {
+                    let (items, quest_ids) = choice.references();
+                    if let Some(item) = items.into_iter().find(|item| !entity_ids.contains(item)) {
+                        return Err(DialogueError::UnknownItem {
+                            dialogue: dialogue_id.clone(),
+                            node: node_id.clone(),
+                            item: item.to_owned(),
+                        });
+                    }
+                    if let Some(quest) = quest_ids
+                        .into_iter()
+                        .find(|quest| quests.get_quest(quest).is_none())
+                    {
+                        return Err(DialogueError::UnknownQuest {
+                            dialogue: dialogue_id.clone(),
+                            node: node_id.clone(),
+                            quest: quest.to_owned(),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
     }
 
     pub fn get_dialogue(&self, id: &str) -> Option<&Dialogue> {
         self.dialogues.get(id)
     }
+
+    pub fn get_node(&self, dialogue_id: &str, node_id: &str) -> Option<&DialogueNode> {
+        self.get_dialogue(dialogue_id)?.nodes.get(node_id)
+    }
+
+    pub fn get_choice(
+        &self,
+        dialogue_id: &str,
+        node_id: &str,
+        choice: usize,
+    ) -> Option<&DialogueChoice> {
+        self.get_node(dialogue_id, node_id)?.choices.get(choice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejection(json: &str) -> DialogueError {
+        match DialogueManager::from_json(json.as_bytes()) {
+            Ok(_) => panic!("Dialogues should be rejected: {json}"),
+            Err(error) => error,
+        }
+    }
+
+    #[test]
+    fn the_dialogues_of_the_game_load() {
+        let dialogues = DialogueManager::from_json(include_bytes!("../../assets/dialogues.json"))
+            .expect("Dialogues should load");
+        assert!(dialogues.get_dialogue("dennis_intro").is_some());
+    }
+
+    #[test]
+    fn the_dialogues_of_the_game_only_name_quests_and_items_that_exist() {
+        let dialogues = DialogueManager::from_json(include_bytes!("../../assets/dialogues.json"))
+            .expect("Dialogues should load");
+        let world = WorldDefinition::from_json(include_bytes!("../../assets/world.json"))
+            .expect("World should load");
+        let quests = QuestManager::from_json(include_bytes!("../../assets/quests.json"))
+            .expect("Quests should load");
+        dialogues
+            .check_references(&world, &quests)
+            .unwrap_or_else(|error| panic!("{error}"));
+    }
+
+    #[test]
+    fn unknown_quests_and_items() {
+        let world = WorldDefinition::from_json(br#"{"entities": [{"id": "shield"}]}"#)
+            .expect("World should load");
+        let quests = QuestManager::from_json(
+            br#"{"quests": {"find_the_shield": {"title": "Find the shield", "stages": [
+                {"description": "Find it", "objectives": [
+                    {"text": "Pick it up", "goal": {"action": "pick_up", "item": "shield"}}
+                ]}
+            ]}}}"#,
+        )
+        .expect("Quests should load");
+        let check = |choice: &str| {
+            let json = format!(
+                r#"{{"dialogues": {{"smalltalk": {{"start": "hello", "nodes": {{
+                    "hello": {{"line": "Hello", "choices": [{choice}]}},
+                    "bye": {{"line": "Bye"}}
+                }}}}}}}}"#
+            );
+            DialogueManager::from_json(json.as_bytes())
+                .expect("Dialogues should load")
+                .check_references(&world, &quests)
+        };
+
+        let known = r#"{"text": "Here", "next": "bye",
+            "conditions": [{"condition": "has_item", "item": "shield"}, {"condition": "quest_not_started", "quest": "find_the_shield"}],
+            "effects": [{"effect": "take_item", "item": "shield"}, {"effect": "start_quest", "quest": "find_the_shield"}]}"#;
+        assert!(check(known).is_ok());
+        for choice in [
+            r#"{"text": "Here", "next": "bye", "conditions": [{"condition": "quest_completed", "quest": "find_the_sword"}]}"#,
+            r#"{"text": "Here", "next": "bye", "effects": [{"effect": "start_quest", "quest": "find_the_sword"}]}"#,
+        ] {
+            let error = check(choice).expect_err(choice);
+            assert!(
+                matches!(&error, DialogueError::UnknownQuest { dialogue, node, quest } if dialogue == "smalltalk" && node == "hello" && quest == "find_the_sword"),
+                "{error}"
+            );
+        }
+        for choice in [
+            r#"{"text": "Here", "next": "bye", "conditions": [{"condition": "has_item", "item": "sword"}]}"#,
+            r#"{"text": "Here", "next": "bye", "effects": [{"effect": "give_item", "item": "sword"}]}"#,
+            r#"{"text": "Here", "next": "bye", "effects": [{"effect": "take_item", "item": "sword"}]}"#,
+        ] {
+            let error = check(choice).expect_err(choice);
+            assert!(
+                matches!(&error, DialogueError::UnknownItem { item, .. } if item == "sword"),
+                "{error}"
+            );
+        }
+    }
+
+    #[test]
+    fn conversations_can_go_round_in_circles() {
+        let dialogues = DialogueManager::from_json(
+            br#"{"dialogues": {"smalltalk": {"start": "hello", "nodes": {
+                "hello": {"line": "Hello", "choices": [
+                    {"text": "How are you?", "next": "fine"},
+                    {"text": "Bye", "next": "bye"}
+                ]},
+                "fine": {"line": "Fine", "choices": [{"text": "Again", "next": "hello"}]},
+                "bye": {"line": "Bye"}
+            }}}}"#,
+        )
+        .expect("Dialogues should load");
+
+        assert_eq!(
+            dialogues.get_choice("smalltalk", "fine", 0).unwrap().next,
+            "hello"
+        );
+        assert!(dialogues.get_node("smalltalk", "bye").unwrap().is_end());
+        assert!(dialogues.get_choice("smalltalk", "bye", 0).is_none());
+        assert!(dialogues.get_node("smalltalk", "missing").is_none());
+        assert!(dialogues.get_dialogue("missing").is_none());
+    }
+
+    #[test]
+    fn unknown_start() {
+        let error = rejection(
+            r#"{"dialogues": {"smalltalk": {"start": "hi", "nodes": {"hello": {"line": "Hello"}}}}}"#,
+        );
+        assert!(
+            matches!(&error, DialogueError::UnknownStart { dialogue, start } if dialogue == "smalltalk" && start == "hi"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn dangling_link() {
+        let error = rejection(
+            r#"{"dialogues": {"smalltalk": {"start": "hello", "nodes": {
+                "hello": {"line": "Hello", "choices": [{"text": "Bye", "next": "bye"}]}
+            }}}}"#,
+        );
+        assert!(
+            matches!(&error, DialogueError::DanglingLink { dialogue, node, next } if dialogue == "smalltalk" && node == "hello" && next == "bye"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn unreachable_node() {
+        // The secret node only leads back, nothing leads to it
+        let error = rejection(
+            r#"{"dialogues": {"smalltalk": {"start": "hello", "nodes": {
+                "hello": {"line": "Hello", "choices": [{"text": "Again", "next": "hello"}]},
+                "secret": {"line": "Psst", "choices": [{"text": "Back", "next": "hello"}]}
+            }}}}"#,
+        );
+        assert!(
+            matches!(&error, DialogueError::UnreachableNode { dialogue, node } if dialogue == "smalltalk" && node == "secret"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn unknown_fields_and_bad_syntax() {
+        for json in [
+            r#"{"dialogues": {"smalltalk": {"start": "hello", "nodes": {"hello": {"line": "Hello", "mood": "happy"}}}}}"#,
+            r#"{"dialogues": {"smalltalk": {"start": "hello", "nodes": {"hello": {"line": "Hello", "choices": [
+                {"text": "Dance", "next": "hello", "effects": [{"effect": "dance"}]}
+            ]}}}}}"#,
+            r#"{"dialogues": {"#,
+        ] {
+            assert!(
+                matches!(rejection(json), DialogueError::Syntax { .. }),
+                "{json}"
+            );
+        }
+    }
 }
@@ -1,14 +1,17 @@
+use crate::state::entity::EntityId;
 use crate::state::game_state::GameState;
 use crate::state::input::Input;
 use crate::state::ui_state::{DialogueState, RenderCommand, UIElement, UIState, UserAction};
-use crate::state::update_state::UpdateState;
-use crate::systems::dialogue_manager::DialogueManager;
+use crate::state::update_state::{ActionEffect, ActionRequest, UpdateState};
+use crate::state::viewport::Viewport;
+use crate::systems::dialogue_effect_system::{DialogueChoiceMade, DialogueEffectSystem};
+use crate::systems::dialogue_manager::DialogueChoice;
 use crate::systems::position_manager::PositionManager;
 use cgmath::Point2;
-use std::sync::Arc;
-use winit::window::Window;
 
-const DIALOGUE_RANGE: f32 = 1.5;
+pub const DIALOGUE_RANGE: f32 = 1.5;
+const DIALOGUE_LINE_ROWS: usize = 2; // Room for the line of the NPC to wrap once
+const DIALOGUE_ROW_HEIGHT: f32 = 0.06;
 
 pub struct DialogueSystem {}
 
@@ -19,13 +22,17 @@ impl DialogueSystem {
         input: &Input,
         frame_state: &mut UpdateState,
     ) {
+        let player = game_state
+            .get_entity("player")
+            .expect("Player should exist");
         if input.e_pressed.is_toggled_on()
             && !frame_state.handled_e_click
-            && let Some(near_dialog_interactable) = PositionManager::find_nearest_dialog(game_state)
+            && let Some(near_dialog_interactable) =
+                PositionManager::find_nearest_dialog(game_state, player)
         {
             if !PositionManager::in_range(
                 game_state
-                    .get_position("player")
+                    .get_position(player)
                     .expect("Player position should exist"),
                 game_state
                     .get_position(near_dialog_interactable)
@@ -36,23 +43,55 @@ impl DialogueSystem {
                 return;
             }
 
-            // Open dialogue
-            let dialogue = game_state
-                .dialogue_components
-                .get(near_dialog_interactable)
-                .expect("Dialogue component should exist");
-
-            ui_state.dialogue_state = DialogueState::Npc {
-                render_position: input.mouse_position_ui,
-                npc_entity_id: near_dialog_interactable.to_owned(),
-                dialogue_id: dialogue.dialogue_id.clone(),
-            };
+            Self::open_dialogue(
+                game_state,
+                ui_state,
+                input,
+                frame_state,
+                near_dialog_interactable,
+            );
             frame_state.handled_e_click = true;
         }
     }
 
+    // Range is up to the caller to check
+    pub fn open_dialogue(
+        game_state: &GameState,
+        ui_state: &mut UIState,
+        input: &Input,
+        frame_state: &mut UpdateState,
+        npc: EntityId,
+    ) {
+        let dialogue_id = &game_state
+            .dialogue_components
+            .get(&npc)
+            .expect("Dialogue component should exist")
+            .dialogue_id;
+        let Some(dialogue) = ui_state.dialogues.get_dialogue(dialogue_id) else {
+            #[cfg(feature = "debug-logging")]
+            log::error!("Dialogue {dialogue_id} does not exist");
+            return;
+        };
+
+        ui_state.dialogue_state = DialogueState::Npc {
+            render_position: input.mouse_position_ui,
+            npc_entity_id: npc,
+            dialogue_id: dialogue_id.clone(),
+            node_id: dialogue.start.clone(),
+        };
+        // Quests can ask to talk to someone. Online the server keeps track of those
+        if frame_state.is_online {
+            frame_state.talked_to.push(npc);
+        } else {
+            frame_state
+                .action_effects
+                .push(ActionEffect::TalkedTo { npc });
+        }
+    }
+
+    // Before click to move, so clicking a choice does not also walk the player to wherever is behind the window
     pub fn display_dialogue(
-        window: &Arc<Window>,
+        viewport: &Viewport,
         game_state: &GameState,
         ui_state: &mut UIState,
         input: &Input,
@@ -64,11 +103,46 @@ impl DialogueSystem {
             render_position,
             npc_entity_id,
             dialogue_id,
+            node_id,
         } = &ui_state.dialogue_state
         {
+            let Some(node) = ui_state.dialogues.get_node(dialogue_id, node_id) else {
+                ui_state.dialogue_state = DialogueState::Closed;
+                return;
+            };
+            let player = game_state
+                .get_entity("player")
+                .expect("Player should exist");
+            // Choices keep their index in the node, that is how the server knows which one was picked
+            let mut choices: Vec<(String, Option<(usize, &DialogueChoice)>)> = node
+                .choices
+                .iter()
+                .enumerate()
+                .filter(|(_, choice)| {
+                    DialogueEffectSystem::conditions_hold(game_state, player, &choice.conditions)
+                })
+                .enumerate()
+                .map(|(shown_index, (index, choice))| {
+                    (
+                        format!("{}. {}", shown_index + 1, choice.text),
+                        Some((index, choice)),
+                    )
+                })
+                .collect();
+            // The last node only lets the player leave, and so does a node where none of the choices are possible
+            if choices.is_empty() {
+                choices.push(("Leave".to_owned(), None));
+            }
+
+            // The line of the NPC on top, one row per choice below it
+            let rows = (DIALOGUE_LINE_ROWS + choices.len()) as f32;
+            let half_height = rows * DIALOGUE_ROW_HEIGHT / 2.0;
             let dialogue_rect = UIElement::new_rect(
-                Point2::new(render_position.x + 0.01, render_position.y + 0.05),
-                Point2::new(0.16, 0.06),
+                Point2::new(
+                    render_position.x + 0.01,
+                    render_position.y - 0.01 + half_height,
+                ),
+                Point2::new(0.2, half_height),
             );
             let dialogue_render_command = RenderCommand::Model {
                 layer: 150,
@@ -77,24 +151,59 @@ impl DialogueSystem {
             };
             dialogue_render_commands.push(dialogue_render_command);
 
-            match frame_state.gui.button_handle(window, dialogue_rect, input) {
-                UserAction::None => {}
-                UserAction::Hover => {}
-                UserAction::LeftClick => {}
-                UserAction::RightClick => {}
-            }
-
-            let dialogue_manager = DialogueManager::new();
-            let dialogue_text = dialogue_manager.get_dialogue(dialogue_id).unwrap();
-
+            let line_bottom = DIALOGUE_LINE_ROWS as f32 / rows;
             let dialogue_text_render_command = frame_state.gui.build_text_render_command(
                 300,
-                dialogue_rect,
-                &dialogue_text.text,
+                dialogue_rect.inner_rect(Point2::new(0.02, 0.02), Point2::new(0.88, line_bottom)),
+                &node.line,
                 [0.8, 0.8, 0.0],
             );
             dialogue_render_commands.push(dialogue_text_render_command);
 
+            for (index, (text, choice)) in choices.iter().enumerate() {
+                let top = (DIALOGUE_LINE_ROWS + index) as f32 / rows;
+                let choice_rect = dialogue_rect
+                    .inner_rect(Point2::new(0.02, top), Point2::new(0.98, top + 1.0 / rows));
+                let mut text_color = [0.8, 0.8, 0.8];
+                match frame_state.gui.button_handle(viewport, choice_rect, input) {
+                    UserAction::None | UserAction::RightClick => {}
+                    UserAction::Hover => text_color = [0.8, 0.8, 0.0],
+                    UserAction::LeftClick => {
+                        if !frame_state.handled_left_click {
+                            new_dialogue_state = Some(match choice {
+                                Some((choice_index, choice)) => {
+                                    if !choice.effects.is_empty() {
+                                        frame_state.action_requests.push(
+                                            ActionRequest::DialogueChoice {
+                                                choice: DialogueChoiceMade {
+                                                    npc: *npc_entity_id,
+                                                    node_id: node_id.clone(),
+                                                    choice: *choice_index,
+                                                },
+                                                effects: choice.effects.clone(),
+                                            },
+                                        );
+                                    }
+                                    DialogueState::Npc {
+                                        render_position: *render_position,
+                                        npc_entity_id: *npc_entity_id,
+                                        dialogue_id: dialogue_id.clone(),
+                                        node_id: choice.next.clone(),
+                                    }
+                                }
+                                None => DialogueState::Closed,
+                            });
+                            frame_state.handled_left_click = true;
+                        }
+                    }
+                }
+                let choice_text_render_command =
+                    frame_state
+                        .gui
+                        .build_text_render_command(300, choice_rect, text, text_color);
+                dialogue_render_commands.push(choice_text_render_command);
+            }
+
             let close_button_rect =
                 dialogue_rect.inner_rect_maintain_ratio_x(Point2::new(0.9, 0.05), 0.10);
 
@@ -106,7 +215,7 @@ impl DialogueSystem {
             dialogue_render_commands.push(close_button_render_command);
             match frame_state
                 .gui
-                .button_handle(window, close_button_rect, input)
+                .button_handle(viewport, close_button_rect, input)
             {
                 UserAction::None | UserAction::RightClick => {}
                 UserAction::Hover => {
@@ -117,15 +226,6 @@ impl DialogueSystem {
                         model_id: "close_button_hover".to_owned(),
                     };
                     dialogue_render_commands.push(close_button_hover_render_command);
-                    match frame_state
-                        .gui
-                        .button_handle(window, close_button_rect, input)
-                    {
-                        UserAction::None | UserAction::Hover | UserAction::RightClick => {}
-                        UserAction::LeftClick => {
-                            new_dialogue_state = Some(DialogueState::Closed);
-                        }
-                    }
                 }
                 UserAction::LeftClick => {
                     if !frame_state.handled_left_click {
@@ -135,12 +235,21 @@ impl DialogueSystem {
                 }
             }
 
+            // Clicks on the rest of the window are not meant for the world behind it
+            if let UserAction::LeftClick =
+                frame_state
+                    .gui
+                    .button_handle(viewport, dialogue_rect, input)
+            {
+                frame_state.handled_left_click = true;
+            }
+
             if !PositionManager::in_range(
                 game_state
-                    .get_position("player")
+                    .get_position(player)
                     .expect("Player position should exist"),
                 game_state
-                    .get_position(npc_entity_id)
+                    .get_position(*npc_entity_id)
                     .expect("Interacted NPC position should exist"),
                 DIALOGUE_RANGE,
             ) {
@@ -148,15 +257,6 @@ impl DialogueSystem {
             }
         }
         if let Some(new_state) = new_dialogue_state {
-            match new_state {
-                DialogueState::Closed => {}
-                DialogueState::Npc { .. } => {
-                    frame_state
-                        .gui
-                        .render_commands
-                        .append(&mut dialogue_render_commands);
-                }
-            }
             ui_state.dialogue_state = new_state;
         }
         frame_state
@@ -0,0 +1,380 @@
+use crate::state::components::ItemShape;
+use std::cmp::Reverse;
+
+const BITS_PER_WORD: usize = u64::BITS as usize;
+
+// Which cells of a grid are taken, one bit per cell so any size works. Spots are given as (column, row) from the top left
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GridContainer {
+    columns: u8,
+    rows: u8,
+    occupied: Vec<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placement {
+    FirstFit, // The first free spot going row by row
+    BestFit,  // The spot touching the most taken cells and edges, so the fewest gaps are left
+}
+
+impl GridContainer {
+    pub fn new(columns: u8, rows: u8) -> GridContainer {
+        let cells = usize::from(columns) * usize::from(rows);
+        Self {
+            columns,
+            rows,
+            occupied: vec![0; cells.div_ceil(BITS_PER_WORD)],
+        }
+    }
+
+    fn index(&self, column: u8, row: u8) -> usize {
+        usize::from(row) * usize::from(self.columns) + usize::from(column)
+    }
+
+    // Cells outside of the grid count as taken
+    pub fn is_occupied(&self, column: u8, row: u8) -> bool {
+        if column >= self.columns || row >= self.rows {
+            return true;
+        }
+        let index = self.index(column, row);
+        self.occupied[index / BITS_PER_WORD] & (1 << (index % BITS_PER_WORD)) != 0
+    }
+
+    pub fn is_inside(&self, spot: (u8, u8), shape: &ItemShape) -> bool {
+        u16::from(spot.0) + u16::from(shape.width) <= u16::from(self.columns)
+            && u16::from(spot.1) + u16::from(shape.height) <= u16::from(self.rows)
+    }
+
+    pub fn fits(&self, spot: (u8, u8), shape: &ItemShape) -> bool {
+        self.is_inside(spot, shape)
+            && Self::cells(spot, shape).all(|(column, row)| !self.is_occupied(column, row))
+    }
+
+    // Whatever was there before. Cells outside of the grid are skipped, so an item that no longer fits does not take up anything that is not there
+    pub fn occupy(&mut self, spot: (u8, u8), shape: &ItemShape) {
+        for (column, row) in Self::cells(spot, shape) {
+            if column < self.columns && row < self.rows {
+                let index = self.index(column, row);
+                self.occupied[index / BITS_PER_WORD] |= 1 << (index % BITS_PER_WORD);
+            }
+        }
+    }
+
+    // Saturating, cells past the largest spot are not there for any grid
+    fn cells(spot: (u8, u8), shape: &ItemShape) -> impl Iterator<Item = (u8, u8)> {
+        let (column, row) = spot;
+        (row..row.saturating_add(shape.height))
+            .flat_map(move |y| (column..column.saturating_add(shape.width)).map(move |x| (x, y)))
+    }
+
+    pub fn find_spot(&self, shape: &ItemShape, placement: Placement) -> Option<(u8, u8)> {
+        let mut free_spots = (0..self.rows)
+            .flat_map(|row| (0..self.columns).map(move |column| (column, row)))
+            .filter(|spot| self.fits(*spot, shape));
+        match placement {
+            Placement::FirstFit => free_spots.next(),
+            // The first one going row by row wins a tie
+            Placement::BestFit => free_spots.max_by_key(|(column, row)| {
+                (
+                    self.contact((*column, *row), shape),
+                    Reverse((*row, *column)),
+                )
+            }),
+        }
+    }
+
+    // How many cells around the shape at that spot are taken or outside of the grid
+    fn contact(&self, spot: (u8, u8), shape: &ItemShape) -> usize {
+        let (column, row) = (i16::from(spot.0), i16::from(spot.1));
+        let (width, height) = (i16::from(shape.width), i16::from(shape.height));
+        let above_and_below =
+            (column..column + width).flat_map(|x| [(x, row - 1), (x, row + height)]);
+        let left_and_right =
+            (row..row + height).flat_map(|y| [(column - 1, y), (column + width, y)]);
+        above_and_below
+            .chain(left_and_right)
+            .filter(|(x, y)| match (u8::try_from(*x), u8::try_from(*y)) {
+                (Ok(x), Ok(y)) => self.is_occupied(x, y),
+                _ => true,
+            })
+            .count()
+    }
+
+    // Packs the shapes into the top left of an empty grid, largest first. Each may be turned when that puts it further up. Gives per shape where it goes and whether it is turned, or nothing when they do not all fit this way
+    pub fn arrange(columns: u8, rows: u8, shapes: &[ItemShape]) -> Option<Vec<((u8, u8), bool)>> {
+        let mut order: Vec<usize> = (0..shapes.len()).collect();
+        order.sort_by_key(|index| {
+            let shape = &shapes[*index];
+            let area = u16::from(shape.width) * u16::from(shape.height);
+            (Reverse(area), Reverse(shape.width.max(shape.height)))
+        });
+
+        let mut container = GridContainer::new(columns, rows);
+        let mut arrangement = vec![((0, 0), false); shapes.len()];
+        for index in order {
+            let shape = &shapes[index];
+            let orientations: &[bool] = if shape.is_square() {
+                &[false]
+            } else {
+                &[false, true]
+            };
+            let (spot, is_rotated) = orientations
+                .iter()
+                .filter_map(|is_rotated| {
+                    container
+                        .find_spot(&shape.rotated(*is_rotated), Placement::FirstFit)
+                        .map(|spot| (spot, *is_rotated))
+                })
+                .min_by_key(|((column, row), _)| (*row, *column))?;
+            container.occupy(spot, &shape.rotated(is_rotated));
+            arrangement[index] = (spot, is_rotated);
+        }
+        Some(arrangement)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::HashSet;
+
+    fn shape(width: u8, height: u8) -> ItemShape {
+        ItemShape { width, height }
+    }
+
+    // The same grid as a set of taken cells, to check the bits against
+    struct Model {
+        columns: u8,
+        rows: u8,
+        occupied: HashSet<(u8, u8)>,
+    }
+
+    impl Model {
+        fn occupy(&mut self, spot: (u8, u8), shape: &ItemShape) {
+            for row in u16::from(spot.1)..u16::from(spot.1) + u16::from(shape.height) {
+                for column in u16::from(spot.0)..u16::from(spot.0) + u16::from(shape.width) {
+                    if column < u16::from(self.columns) && row < u16::from(self.rows) {
+                        self.occupied.insert((column as u8, row as u8));
+                    }
+                }
+            }
+        }
+
+        fn is_free(&self, column: i32, row: i32) -> bool {
+            (0..i32::from(self.columns)).contains(&column)
+                && (0..i32::from(self.rows)).contains(&row)
+                && !self.occupied.contains(&(column as u8, row as u8))
+        }
+
+        fn fits(&self, spot: (u8, u8), shape: &ItemShape) -> bool {
+            let (column, row) = (i32::from(spot.0), i32::from(spot.1));
+            (row..row + i32::from(shape.height))
+                .all(|y| (column..column + i32::from(shape.width)).all(|x| self.is_free(x, y)))
+        }
+
+        fn contact(&self, spot: (u8, u8), shape: &ItemShape) -> usize {
+            let (column, row) = (i32::from(spot.0), i32::from(spot.1));
+            let (width, height) = (i32::from(shape.width), i32::from(shape.height));
+            let mut around = Vec::new();
+            for x in column..column + width {
+                around.push((x, row - 1));
+                around.push((x, row + height));
+            }
+            for y in row..row + height {
+                around.push((column - 1, y));
+                around.push((column + width, y));
+            }
+            around
+                .into_iter()
+                .filter(|(x, y)| !self.is_free(*x, *y))
+                .count()
+        }
+
+        fn spots(&self) -> impl Iterator<Item = (u8, u8)> {
+            let columns = self.columns;
+            (0..self.rows).flat_map(move |row| (0..columns).map(move |column| (column, row)))
+        }
+    }
+
+    // Non-square, larger than the 12 by 12 of the inventory and up to the largest a u8 allows
+    fn size() -> impl Strategy<Value = (u8, u8)> {
+        prop_oneof![
+            (1_u8..=40, 1_u8..=40),
+            (250_u8..=255, 1_u8..=6),
+            (1_u8..=6, 250_u8..=255),
+        ]
+    }
+
+    fn item_shape() -> impl Strategy<Value = ItemShape> {
+        (1_u8..=5, 1_u8..=5).prop_map(|(width, height)| shape(width, height))
+    }
+
+    // Where a shape was put. Spots are allowed to go past the edge of the grid
+    type Taken = ((u8, u8), ItemShape);
+
+    fn grid() -> impl Strategy<Value = (u8, u8, Vec<Taken>)> {
+        size().prop_flat_map(|(columns, rows)| {
+            let spot = (0..=columns.saturating_add(2), 0..=rows.saturating_add(2));
+            (
+                Just(columns),
+                Just(rows),
+                prop::collection::vec((spot, item_shape()), 0..12),
+            )
+        })
+    }
+
+    fn build(columns: u8, rows: u8, taken: &[Taken]) -> (GridContainer, Model) {
+        let mut container = GridContainer::new(columns, rows);
+        let mut model = Model {
+            columns,
+            rows,
+            occupied: HashSet::new(),
+        };
+        for (spot, shape) in taken {
+            container.occupy(*spot, shape);
+            model.occupy(*spot, shape);
+        }
+        (container, model)
+    }
+
+    proptest! {
+        #[test]
+        fn cells_are_taken_exactly_where_shapes_were_put(
+            (columns, rows, taken) in grid(),
+            shape in item_shape(),
+        ) {
+            let (container, model) = build(columns, rows, &taken);
+            for row in 0..=rows.saturating_add(1) {
+                for column in 0..=columns.saturating_add(1) {
+                    prop_assert_eq!(
+                        container.is_occupied(column, row),
+                        !model.is_free(i32::from(column), i32::from(row))
+                    );
+                }
+            }
+            for row in 0..=rows.saturating_add(1) {
+                for column in 0..=columns.saturating_add(1) {
+                    prop_assert_eq!(
+                        container.fits((column, row), &shape),
+                        model.fits((column, row), &shape)
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn first_fit_is_the_first_free_spot_row_by_row(
+            (columns, rows, taken) in grid(),
+            shape in item_shape(),
+        ) {
+            let (container, model) = build(columns, rows, &taken);
+            let expected = model.spots().find(|spot| model.fits(*spot, &shape));
+            prop_assert_eq!(container.find_spot(&shape, Placement::FirstFit), expected);
+        }
+
+        #[test]
+        fn best_fit_leaves_the_fewest_gaps(
+            (columns, rows, taken) in grid(),
+            shape in item_shape(),
+        ) {
+            let (container, model) = build(columns, rows, &taken);
+            let first_fit = container.find_spot(&shape, Placement::FirstFit);
+            let best_fit = container.find_spot(&shape, Placement::BestFit);
+            prop_assert_eq!(first_fit.is_none(), best_fit.is_none());
+            if let Some(best_fit) = best_fit {
+                prop_assert!(model.fits(best_fit, &shape));
+                let most_contact = model
+                    .spots()
+                    .filter(|spot| model.fits(*spot, &shape))
+                    .map(|spot| model.contact(spot, &shape))
+                    .max();
+                prop_assert_eq!(Some(model.contact(best_fit, &shape)), most_contact);
+            }
+        }
+
+        #[test]
+        fn arranged_shapes_stay_inside_and_never_overlap(
+            (columns, rows) in size(),
+            shapes in prop::collection::vec(item_shape(), 0..16),
+        ) {
+            let arrangement = GridContainer::arrange(columns, rows, &shapes);
+            let area: usize = shapes
+                .iter()
+                .map(|shape| usize::from(shape.width) * usize::from(shape.height))
+                .sum();
+            if area > usize::from(columns) * usize::from(rows) {
+                prop_assert!(arrangement.is_none());
+            }
+            if let Some(arrangement) = arrangement {
+                prop_assert_eq!(arrangement.len(), shapes.len());
+                let mut model = Model {
+                    columns,
+                    rows,
+                    occupied: HashSet::new(),
+                };
+                for (shape, (spot, is_rotated)) in shapes.iter().zip(arrangement) {
+                    prop_assert!(!is_rotated || !shape.is_square());
+                    let placed = shape.rotated(is_rotated);
+                    prop_assert!(model.fits(spot, &placed));
+                    model.occupy(spot, &placed);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn best_fit_fills_a_hole_that_first_fit_skips_past() {
+        // Only the top left two cells and the bottom right corner are free
+        let mut container = GridContainer::new(5, 3);
+        container.occupy((2, 0), &shape(3, 1));
+        container.occupy((0, 1), &shape(5, 1));
+        container.occupy((0, 2), &shape(4, 1));
+
+        assert_eq!(
+            container.find_spot(&shape(1, 1), Placement::FirstFit),
+            Some((0, 0))
+        );
+        assert_eq!(
+            container.find_spot(&shape(1, 1), Placement::BestFit),
+            Some((4, 2))
+        );
+        assert_eq!(
+            container.find_spot(&shape(2, 1), Placement::BestFit),
+            Some((0, 0))
+        );
+        assert_eq!(container.find_spot(&shape(1, 2), Placement::BestFit), None);
+    }
+
+    #[test]
+    fn spots_near_the_largest_grid_edge() {
+        let mut container = GridContainer::new(255, 255);
+        assert!(container.fits((0, 0), &shape(255, 255)));
+        assert!(container.fits((254, 254), &shape(1, 1)));
+        assert!(!container.is_inside((254, 254), &shape(2, 1)));
+        assert!(!container.fits((255, 0), &shape(1, 1)));
+        assert!(container.is_occupied(255, 255));
+
+        // Only the part inside is taken, the rest would go past 255
+        container.occupy((253, 253), &shape(5, 5));
+        assert!(container.is_occupied(254, 254));
+        assert!(container.is_occupied(253, 253));
+        assert!(!container.is_occupied(252, 253));
+        assert_eq!(
+            container.find_spot(&shape(3, 3), Placement::FirstFit),
+            Some((0, 0))
+        );
+        assert_eq!(
+            container.find_spot(&shape(255, 253), Placement::FirstFit),
+            Some((0, 0))
+        );
+        assert_eq!(
+            container.find_spot(&shape(255, 254), Placement::FirstFit),
+            None
+        );
+        assert_eq!(
+            GridContainer::arrange(255, 1, &[shape(1, 200), shape(55, 1)]),
+            Some(vec![((0, 0), true), ((200, 0), false)])
+        );
+    }
+}
@@ -2,15 +2,14 @@ use crate::state::game_state::GameState;
 use crate::state::input::Input;
 use crate::state::ui_state::{RenderCommand, UIElement, UserAction};
 use crate::state::update_state::UpdateState;
+use crate::state::viewport::Viewport;
 use cgmath::Point2;
-use std::sync::Arc;
-use winit::window::Window;
 
 pub struct HealthSystem {}
 
 impl HealthSystem {
     pub fn display_health(
-        window: &Arc<Window>,
+        viewport: &Viewport,
         game_state: &GameState,
         input: &Input,
         frame_state: &mut UpdateState,
@@ -28,7 +27,7 @@ impl HealthSystem {
             .push(dialogue_render_command);
         match frame_state
             .gui
-            .button_handle(window, health_rect_outside, input)
+            .button_handle(viewport, health_rect_outside, input)
         {
             UserAction::None => {}
             UserAction::Hover => {}
@@ -36,7 +35,10 @@ impl HealthSystem {
             UserAction::RightClick => {}
         }
 
-        let player_health = game_state.health_components.get("player").unwrap();
+        let player = game_state
+            .get_entity("player")
+            .expect("Player should exist");
+        let player_health = game_state.health_components.get(&player).unwrap();
         let percentage_health = player_health.hitpoints as f32 / player_health.max_hitpoints as f32;
         let health_bar_width = 0.90;
         let percentage_health_bar = percentage_health * health_bar_width;
@@ -57,7 +59,7 @@ impl HealthSystem {
 
         match frame_state
             .gui
-            .button_handle(window, health_rect_inside, input)
+            .button_handle(viewport, health_rect_inside, input)
         {
             UserAction::None => {}
             UserAction::Hover => {}
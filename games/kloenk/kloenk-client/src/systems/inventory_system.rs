@@ -1,18 +1,23 @@
+use crate::state::components::{ItemShape, Storage};
 use crate::state::game_state::GameState;
 use crate::state::input::Input;
 use crate::state::ui_state::MenuState::{Closed, InventoryAction};
-use crate::state::ui_state::{RenderCommand, UIElement, UIState, UserAction};
+use crate::state::ui_state::{
+    DragState, RenderCommand, SCREEN_REFERENCE_HEIGHT, SCREEN_REFERENCE_WIDTH, UIElement, UIState,
+    UserAction,
+};
 use crate::state::update_state::{ActionEffect, ActionRequest, UpdateState};
-use crate::systems::item_placement_system::ItemPlacementSystem;
-use cgmath::Point2;
-use std::sync::Arc;
-use winit::window::Window;
+use crate::state::viewport::Viewport;
+use crate::systems::click_to_move_system::ClickToMoveSystem;
+use crate::systems::item_transaction_system::{ItemTransaction, ItemTransactionSystem};
+use crate::systems::storage_manager::StorageManager;
+use cgmath::{Point2, Vector2};
 
 pub struct InventorySystem {}
 
 impl InventorySystem {
     pub fn handle_inventory(
-        window: &Arc<Window>,
+        viewport: &Viewport,
         game_state: &mut GameState,
         ui_state: &mut UIState,
         input: &mut Input,
@@ -24,48 +29,67 @@ impl InventorySystem {
         }
 
         if !inventory_window.is_visible {
+            ui_state.drag_state = DragState::Idle;
             return;
         }
 
-        inventory_window.rect.update(&window.inner_size());
+        inventory_window.rect.update(&viewport.size());
         frame_state
             .gui
             .add_color_command(100, &inventory_window.rect, "black");
-        let inventory_ecs = game_state.get_storage("player").unwrap();
+        let player = game_state
+            .get_entity("player")
+            .expect("Player should exist");
+        let inventory_rect = inventory_window.rect;
+        let inventory_ecs = game_state.get_storage(player).unwrap().clone();
+        let dragged_item = match ui_state.drag_state {
+            DragState::Item { item, .. } => Some(item),
+            DragState::Idle => None,
+        };
 
-        let inventory_items = game_state.get_in_storages("player");
+        let inventory_items = game_state.get_in_storages(player);
 
         // TODO besides rendering, we can stop checking user input? mouse click can only be on one location? Maybe this should be done by looping over all entities
         for (entity, in_storage) in &inventory_items {
-            let storable = game_state.storable_components.get(entity.as_str()).unwrap();
-            let item_image = game_state.get_graphics_inventory(entity).unwrap();
-
-            let left = in_storage.position_x as f32 / inventory_ecs.number_of_columns as f32;
-            let right = left + storable.shape.width as f32 / inventory_ecs.number_of_columns as f32;
-            let top = in_storage.position_y as f32 / inventory_ecs.number_of_rows as f32;
-            let bottom = top + storable.shape.height as f32 / inventory_ecs.number_of_rows as f32;
-
-            let image_element = inventory_window
-                .rect
-                .inner_rect(Point2::new(left, top), Point2::new(right, bottom));
-            let inventory_item_command = RenderCommand::Model {
-                layer: 150,
-                ui_element: image_element,
-                model_id: item_image.material_id.clone(),
-            };
-            frame_state.gui.render_commands.push(inventory_item_command);
+            let shape = StorageManager::get_shape_in_storage(game_state, *entity).unwrap();
+            let item_image = game_state.get_graphics_inventory(*entity).unwrap();
 
-            match frame_state.gui.button_handle(window, image_element, input) {
+            let spot = (in_storage.position_x, in_storage.position_y);
+            let image_element = Self::cells_rect(&inventory_rect, &inventory_ecs, spot, &shape);
+            // Where the dragged item was stays marked until it is put down
+            if dragged_item == Some(*entity) {
+                frame_state
+                    .gui
+                    .add_color_command(150, &image_element, "grey");
+                continue;
+            }
+            frame_state
+                .gui
+                .render_commands
+                .push(Self::item_render_command(
+                    150,
+                    image_element,
+                    &item_image.material_id,
+                    in_storage.is_rotated,
+                ));
+
+            match frame_state
+                .gui
+                .button_handle(viewport, image_element, input)
+            {
                 UserAction::None | UserAction::Hover => {}
                 UserAction::LeftClick => {
                     if frame_state.handled_left_click {
                         continue;
                     }
-                    frame_state
-                        .action_requests
-                        .push(ActionRequest::ItemPlacement {
-                            entity: (*entity).clone(),
-                        });
+                    // Held where it was grabbed. Letting go without moving it drops it, like a click always did
+                    let cursor =
+                        Self::cursor_in_cells(viewport, &inventory_rect, &inventory_ecs, input);
+                    ui_state.drag_state = DragState::Item {
+                        item: *entity,
+                        is_rotated: in_storage.is_rotated,
+                        grab_offset: cursor - Vector2::new(f32::from(spot.0), f32::from(spot.1)),
+                    };
                     frame_state.handled_left_click = true;
                 }
                 UserAction::RightClick => {
@@ -75,28 +99,242 @@ impl InventorySystem {
 
                     ui_state.menu_state = InventoryAction {
                         render_position: input.mouse_position_ui,
-                        item: (*entity).clone(),
+                        item: *entity,
                     };
 
                     frame_state.handled_right_click = true;
                 }
             }
         }
+
+        Self::handle_drag(
+            viewport,
+            game_state,
+            ui_state,
+            input,
+            frame_state,
+            inventory_rect,
+            &inventory_ecs,
+        );
+    }
+
+    // The dragged item snaps to the cells under it, on green when it can be put there. Let go outside of any window, it is put down in the world
+    fn handle_drag(
+        viewport: &Viewport,
+        game_state: &mut GameState,
+        ui_state: &mut UIState,
+        input: &Input,
+        frame_state: &mut UpdateState,
+        mut inventory_rect: UIElement,
+        inventory: &Storage,
+    ) {
+        let DragState::Item {
+            item,
+            is_rotated,
+            grab_offset,
+        } = &mut ui_state.drag_state
+        else {
+            return;
+        };
+        let item = *item;
+        let player = game_state
+            .get_entity("player")
+            .expect("Player should exist");
+        // Online someone else might have gotten to it first
+        let Some(from) = game_state
+            .in_storage_components
+            .get(&item)
+            .filter(|in_storage| in_storage.storage_entity == player)
+        else {
+            ui_state.drag_state = DragState::Idle;
+            return;
+        };
+        let from_spot = (from.position_x, from.position_y);
+        let was_rotated = from.is_rotated;
+
+        let item_shape = game_state.storable_components[&item].shape.clone();
+        if input.r_pressed.is_toggled_on() && !item_shape.is_square() {
+            *is_rotated = !*is_rotated;
+            let shape = item_shape.rotated(*is_rotated);
+            *grab_offset = Vector2::new(f32::from(shape.width), f32::from(shape.height)) / 2.0;
+        }
+        let (is_rotated, grab_offset) = (*is_rotated, *grab_offset);
+        let shape = item_shape.rotated(is_rotated);
+        let model_id = game_state
+            .get_graphics_inventory(item)
+            .expect("Inventory item should have an image")
+            .material_id
+            .clone();
+
+        let is_over_inventory = inventory_rect.contains(input.mouse_position_ui, viewport);
+        let spot = Self::drag_spot(viewport, &inventory_rect, inventory, input, grab_offset);
+
+        match spot {
+            Some(spot) if is_over_inventory => {
+                let ghost_rect = Self::cells_rect(&inventory_rect, inventory, spot, &shape);
+                let is_possible =
+                    ItemTransactionSystem::check_move(game_state, player, item, spot, is_rotated)
+                        .is_ok();
+                let color = if is_possible { "green" } else { "blood_red" };
+                frame_state.gui.add_color_command(155, &ghost_rect, color);
+                frame_state
+                    .gui
+                    .render_commands
+                    .push(Self::item_render_command(
+                        160, ghost_rect, &model_id, is_rotated,
+                    ));
+            }
+            _ => {
+                // Same size as in the inventory, but following the cursor freely
+                let cell_size = Vector2::new(
+                    inventory_rect.width
+                        / SCREEN_REFERENCE_WIDTH
+                        / f32::from(inventory.number_of_columns),
+                    inventory_rect.height
+                        / SCREEN_REFERENCE_HEIGHT
+                        / f32::from(inventory.number_of_rows),
+                );
+                let half_size = Vector2::new(
+                    f32::from(shape.width) * cell_size.x,
+                    f32::from(shape.height) * cell_size.y,
+                ) / 2.0;
+                let center = input.mouse_position_ui + half_size
+                    - Vector2::new(grab_offset.x * cell_size.x, grab_offset.y * cell_size.y);
+                let ghost_rect = UIElement::new_rect(center, Point2::new(half_size.x, half_size.y));
+                frame_state
+                    .gui
+                    .render_commands
+                    .push(Self::item_render_command(
+                        160, ghost_rect, &model_id, is_rotated,
+                    ));
+            }
+        }
+
+        if !input.left_mouse_clicked.is_toggled_off() {
+            return;
+        }
+        ui_state.drag_state = DragState::Idle;
+        if is_over_inventory {
+            match spot {
+                Some(spot) if spot == from_spot && is_rotated == was_rotated => frame_state
+                    .action_requests
+                    .push(ActionRequest::ItemPlacement { entity: item }),
+                Some(spot) => {
+                    ItemTransactionSystem::request(
+                        game_state,
+                        frame_state,
+                        player,
+                        ItemTransaction::Move {
+                            item,
+                            spot,
+                            is_rotated,
+                        },
+                    );
+                }
+                None => {}
+            }
+            return;
+        }
+        // Let go over another window, which is probably not meant as putting it down behind it
+        if ui_state.windows.values_mut().any(|ui_window| {
+            ui_window.is_visible && ui_window.rect.contains(input.mouse_position_ui, viewport)
+        }) {
+            return;
+        }
+        if let Some(ground) = ClickToMoveSystem::find_clicked_ground(game_state, input, player) {
+            ItemTransactionSystem::request(
+                game_state,
+                frame_state,
+                player,
+                ItemTransaction::DropAt {
+                    item,
+                    position: Point2::new(ground.x, ground.z),
+                },
+            );
+        }
+    }
+
+    // In cells from the top left of the inventory, can be outside of it
+    fn cursor_in_cells(
+        viewport: &Viewport,
+        inventory_rect: &UIElement,
+        inventory: &Storage,
+        input: &Input,
+    ) -> Vector2<f32> {
+        let left = inventory_rect.scaled_anchor_x + inventory_rect.scaled_x;
+        let top = inventory_rect.scaled_anchor_y + inventory_rect.scaled_y;
+        let cursor_x = input.mouse_position_ui.x * viewport.width as f32;
+        let cursor_y = input.mouse_position_ui.y * viewport.height as f32;
+        Vector2::new(
+            (cursor_x - left) / inventory_rect.scaled_width
+                * f32::from(inventory.number_of_columns),
+            (cursor_y - top) / inventory_rect.scaled_height * f32::from(inventory.number_of_rows),
+        )
+    }
+
+    // The cell the top left of the dragged item snaps to, none when that is left of or above the inventory
+    fn drag_spot(
+        viewport: &Viewport,
+        inventory_rect: &UIElement,
+        inventory: &Storage,
+        input: &Input,
+        grab_offset: Vector2<f32>,
+    ) -> Option<(u8, u8)> {
+        let top_left =
+            Self::cursor_in_cells(viewport, inventory_rect, inventory, input) - grab_offset;
+        (top_left.x.round() >= 0.0 && top_left.y.round() >= 0.0)
+            .then(|| (top_left.x.round() as u8, top_left.y.round() as u8))
+    }
+
+    fn cells_rect(
+        inventory_rect: &UIElement,
+        inventory: &Storage,
+        spot: (u8, u8),
+        shape: &ItemShape,
+    ) -> UIElement {
+        let columns = f32::from(inventory.number_of_columns);
+        let rows = f32::from(inventory.number_of_rows);
+        let left = f32::from(spot.0) / columns;
+        let right = left + f32::from(shape.width) / columns;
+        let top = f32::from(spot.1) / rows;
+        let bottom = top + f32::from(shape.height) / rows;
+        inventory_rect.inner_rect(Point2::new(left, top), Point2::new(right, bottom))
+    }
+
+    fn item_render_command(
+        layer: u32,
+        ui_element: UIElement,
+        model_id: &str,
+        is_rotated: bool,
+    ) -> RenderCommand {
+        if is_rotated {
+            RenderCommand::RotatedModel {
+                layer,
+                ui_element,
+                model_id: model_id.to_owned(),
+            }
+        } else {
+            RenderCommand::Model {
+                layer,
+                ui_element,
+                model_id: model_id.to_owned(),
+            }
+        }
     }
 
     pub fn display_inventory_item_menu(
-        window: &Arc<Window>,
+        viewport: &Viewport,
         game_state: &mut GameState,
         ui_state: &mut UIState,
         input: &Input,
         frame_state: &mut UpdateState,
     ) {
-        Self::handle_inventory_menu_state(window, game_state, ui_state, input, frame_state);
+        Self::handle_inventory_menu_state(viewport, game_state, ui_state, input, frame_state);
     }
 
     // TODO maybe some gui.start_window() and commit() to create whole windows in place? too much logic around now. since we only know at the end if we should render the window or not. could have been closed
     fn handle_inventory_menu_state(
-        window: &Arc<Window>,
+        viewport: &Viewport,
         game_state: &mut GameState,
         ui_state: &mut UIState,
         input: &Input,
@@ -111,9 +349,10 @@ impl InventorySystem {
         } = &ui_state.menu_state.clone()
         // I guess this is fine, might not need to think about pattern to update enum in match while borrowing
         {
+            // Three rows: drop, sort and examine
             let menu_rect = UIElement::new_rect(
-                Point2::new(render_position.x + 0.015, render_position.y + 0.030),
-                Point2::new(0.065, 0.05),
+                Point2::new(render_position.x + 0.015, render_position.y + 0.055),
+                Point2::new(0.065, 0.075),
             );
             let menu_render_command = RenderCommand::Model {
                 layer: 200,
@@ -124,7 +363,7 @@ impl InventorySystem {
 
             let drop_button_rect = menu_rect.inner_rect(
                 Point2::new(0.01, 0.01),
-                Point2::new(0.99, 0.49),
+                Point2::new(0.99, 0.32),
             );
 
             let drop_button_render_command = RenderCommand::Model {
@@ -137,7 +376,7 @@ impl InventorySystem {
             let mut text_color = [0.8, 0.8, 0.8];
             match frame_state
                 .gui
-                .button_handle(window, drop_button_rect, input)
+                .button_handle(viewport, drop_button_rect, input)
             {
                 UserAction::None => {}
                 UserAction::Hover => {
@@ -147,10 +386,14 @@ impl InventorySystem {
                     if frame_state.handled_left_click {
                         return;
                     }
-                    ItemPlacementSystem::place_item(
+                    let player = game_state
+                        .get_entity("player")
+                        .expect("Player should exist");
+                    ItemTransactionSystem::request(
                         game_state,
-                        &mut frame_state.action_effects,
-                        item,
+                        frame_state,
+                        player,
+                        ItemTransaction::Drop { item: *item },
                     );
                     // TODO Should we not just be able to return here? no need to evaluate examine as we will close after this action. Nothing needs rendered
                     ui_state.menu_state = Closed;
@@ -162,16 +405,60 @@ impl InventorySystem {
 
             let drop_item_text_render_command = frame_state.gui.build_text_render_command(
                 300,
-                menu_rect.inner_rect(Point2::new(0.01, 0.01), Point2::new(0.99, 0.49)),
+                drop_button_rect,
                 "Drop item",
                 text_color,
             );
             inventory_render_commands.push(drop_item_text_render_command);
 
+            // Sort button, for the whole inventory
+            let sort_button_rect = menu_rect.inner_rect(
+                Point2::new(0.01, 0.34),
+                Point2::new(0.99, 0.65),
+            );
+            inventory_render_commands.push(RenderCommand::Model {
+                layer: 200,
+                ui_element: sort_button_rect,
+                model_id: "black_square".to_owned(),
+            });
+            let mut text_color = [0.8, 0.8, 0.8];
+            match frame_state
+                .gui
+                .button_handle(viewport, sort_button_rect, input)
+            {
+                UserAction::None | UserAction::RightClick => {}
+                UserAction::Hover => {
+                    text_color = [0.8, 0.8, 0.0];
+                }
+                UserAction::LeftClick => {
+                    if frame_state.handled_left_click {
+                        return;
+                    }
+                    let player = game_state
+                        .get_entity("player")
+                        .expect("Player should exist");
+                    ItemTransactionSystem::request(
+                        game_state,
+                        frame_state,
+                        player,
+                        ItemTransaction::Sort,
+                    );
+                    ui_state.menu_state = Closed;
+                    frame_state.handled_left_click = true;
+                    return;
+                }
+            }
+            inventory_render_commands.push(frame_state.gui.build_text_render_command(
+                300,
+                sort_button_rect,
+                "Sort inventory",
+                text_color,
+            ));
+
             // Examine button
             if game_state.description_components.contains_key(item) {
                 let examine_button_rect = menu_rect.inner_rect(
-                    Point2::new(0.01, 0.51),
+                    Point2::new(0.01, 0.67),
                     Point2::new(0.99, 0.99),
                 );
                 let examine_render_command = RenderCommand::Model {
@@ -184,7 +471,7 @@ impl InventorySystem {
                 let mut text_color = [0.8, 0.8, 0.8];
                 match frame_state
                     .gui
-                    .button_handle(window, examine_button_rect, input)
+                    .button_handle(viewport, examine_button_rect, input)
                 {
                     UserAction::None | UserAction::RightClick => {}
                     UserAction::Hover => {
@@ -207,8 +494,7 @@ impl InventorySystem {
 
                 let examine_text_render_command = frame_state.gui.build_text_render_command(
                     300,
-                    menu_rect
-                        .inner_rect(Point2::new(0.01, 0.51), Point2::new(0.99, 0.99)),
+                    examine_button_rect,
                     "Examine item",
                     text_color,
                 );
@@ -223,3 +509,184 @@ impl InventorySystem {
             .append(&mut inventory_render_commands);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::entity::EntityId;
+    use crate::state::world_definition::{
+        EntityDefinition, ItemShapeDefinition, StorageDefinition,
+    };
+    use crate::systems::item_transaction_system::TransactionRejection;
+
+    // Not the reference size, so the scaling to the screen is part of it
+    const VIEWPORT: Viewport = Viewport {
+        width: 1280,
+        height: 800,
+    };
+    const INVENTORY: Storage = Storage {
+        number_of_rows: 8,
+        number_of_columns: 8,
+    };
+
+    fn inventory_rect() -> UIElement {
+        let mut inventory_rect = UIState::new().windows["inventory"].rect;
+        inventory_rect.update(&VIEWPORT.size());
+        inventory_rect
+    }
+
+    // The cursor over a point of the inventory, given in cells from its top left
+    fn cursor_at(x: f32, y: f32) -> Input {
+        let inventory_rect = inventory_rect();
+        let left = inventory_rect.scaled_anchor_x + inventory_rect.scaled_x;
+        let top = inventory_rect.scaled_anchor_y + inventory_rect.scaled_y;
+        let mut input = Input::new();
+        input.mouse_position_ui = Point2::new(
+            (left + x / f32::from(INVENTORY.number_of_columns) * inventory_rect.scaled_width)
+                / VIEWPORT.width as f32,
+            (top + y / f32::from(INVENTORY.number_of_rows) * inventory_rect.scaled_height)
+                / VIEWPORT.height as f32,
+        );
+        input
+    }
+
+    // Grabbed in the middle, as after turning it
+    fn drag_spot(x: f32, y: f32, shape: &ItemShape) -> Option<(u8, u8)> {
+        let grab_offset = Vector2::new(f32::from(shape.width), f32::from(shape.height)) / 2.0;
+        InventorySystem::drag_spot(
+            &VIEWPORT,
+            &inventory_rect(),
+            &INVENTORY,
+            &cursor_at(x, y),
+            grab_offset,
+        )
+    }
+
+    // The 1x2 shield at the top left of the 8x8 inventory of the player
+    fn world() -> (GameState, EntityId, EntityId) {
+        let mut game_state = GameState::empty();
+        let player = game_state.load_entity(&EntityDefinition {
+            id: Some("player".to_owned()),
+            storage: Some(StorageDefinition {
+                number_of_rows: INVENTORY.number_of_rows,
+                number_of_columns: INVENTORY.number_of_columns,
+            }),
+            ..EntityDefinition::default()
+        });
+        let shield = game_state.load_entity(&EntityDefinition {
+            id: Some("shield".to_owned()),
+            storable: Some(ItemShapeDefinition {
+                width: 1,
+                height: 2,
+            }),
+            ..EntityDefinition::default()
+        });
+        game_state.create_in_storage(player, shield, (0, 0));
+        (game_state, player, shield)
+    }
+
+    #[test]
+    fn the_cursor_is_found_in_cells_also_outside_of_the_inventory() {
+        for (x, y) in [(0.0, 0.0), (2.5, 3.25), (8.0, 8.0), (-1.5, 9.0)] {
+            let cells = InventorySystem::cursor_in_cells(
+                &VIEWPORT,
+                &inventory_rect(),
+                &INVENTORY,
+                &cursor_at(x, y),
+            );
+            assert!(
+                (cells.x - x).abs() < 1e-3 && (cells.y - y).abs() < 1e-3,
+                "{cells:?} instead of ({x}, {y})"
+            );
+        }
+    }
+
+    #[test]
+    fn cells_cover_the_shield_up_to_the_edges_of_the_inventory() {
+        let inventory_rect = inventory_rect();
+        let (width, height) = (inventory_rect.width, inventory_rect.height);
+        let shield = ItemShape {
+            width: 1,
+            height: 2,
+        };
+        let covers = |spot: (u8, u8), is_rotated: bool, x: f32, y: f32| {
+            let mut cells_rect = InventorySystem::cells_rect(
+                &inventory_rect,
+                &INVENTORY,
+                spot,
+                &shield.rotated(is_rotated),
+            );
+            cells_rect.contains(cursor_at(x, y).mouse_position_ui, &VIEWPORT)
+        };
+
+        let cells_rect = InventorySystem::cells_rect(&inventory_rect, &INVENTORY, (7, 6), &shield);
+        assert!((cells_rect.anchor_offset_x + cells_rect.width - width).abs() < 1e-3);
+        assert!((cells_rect.anchor_offset_y + cells_rect.height - height).abs() < 1e-3);
+        assert!(covers((7, 6), false, 7.5, 7.9));
+        assert!(covers((7, 6), false, 7.5, 6.1));
+        assert!(!covers((7, 6), false, 6.9, 7.5));
+        assert!(!covers((7, 6), false, 7.5, 5.9));
+
+        let cells_rect =
+            InventorySystem::cells_rect(&inventory_rect, &INVENTORY, (6, 7), &shield.rotated(true));
+        assert!((cells_rect.anchor_offset_x + cells_rect.width - width).abs() < 1e-3);
+        assert!((cells_rect.anchor_offset_y + cells_rect.height - height).abs() < 1e-3);
+        assert!(covers((6, 7), true, 6.1, 7.5));
+        assert!(covers((6, 7), true, 7.9, 7.5));
+        assert!(!covers((6, 7), true, 6.5, 6.9));
+    }
+
+    #[test]
+    fn the_shield_fits_against_the_edges_turned_or_not() {
+        let (game_state, player, shield) = world();
+        let shape = game_state.storable_components[&shield].shape.clone();
+        let check = |x: f32, y: f32, is_rotated: bool| {
+            let spot = drag_spot(x, y, &shape.rotated(is_rotated))?;
+            Some((
+                spot,
+                ItemTransactionSystem::check_move(&game_state, player, shield, spot, is_rotated),
+            ))
+        };
+
+        // Bottom right, standing up and lying down
+        assert_eq!(check(7.5, 7.0, false), Some(((7, 6), Ok(None))));
+        assert_eq!(check(7.0, 7.5, true), Some(((6, 7), Ok(None))));
+        // A row or column further sticks out of the inventory
+        assert_eq!(
+            check(7.5, 7.6, false),
+            Some(((7, 7), Err(TransactionRejection::SpotTaken)))
+        );
+        assert_eq!(
+            check(7.6, 7.5, true),
+            Some(((7, 7), Err(TransactionRejection::SpotTaken)))
+        );
+        // Top left snaps to the nearest cell, until it would be past the edge
+        assert_eq!(check(0.4, 0.9, false), Some(((0, 0), Ok(None))));
+        assert_eq!(check(0.9, 0.4, true), Some(((0, 0), Ok(None))));
+        assert_eq!(check(-0.1, 1.0, false), None);
+        assert_eq!(check(1.0, -0.1, true), None);
+    }
+
+    #[test]
+    fn the_shield_swaps_when_what_is_in_the_way_fits_where_it_was() {
+        let (mut game_state, player, shield) = world();
+        let apple = game_state.load_entity(&EntityDefinition {
+            id: Some("apple".to_owned()),
+            storable: Some(ItemShapeDefinition {
+                width: 1,
+                height: 1,
+            }),
+            ..EntityDefinition::default()
+        });
+        game_state.create_in_storage(player, apple, (1, 0));
+        let check = |spot, is_rotated| {
+            ItemTransactionSystem::check_move(&game_state, player, shield, spot, is_rotated)
+        };
+
+        assert_eq!(check((1, 0), false), Ok(Some(apple)));
+        // Lying down the shield would cover where the apple has to go
+        assert_eq!(check((0, 0), true), Err(TransactionRejection::SpotTaken));
+        assert_eq!(check((1, 1), false), Ok(None));
+        assert_eq!(check((0, 1), true), Ok(None));
+    }
+}
@@ -1,6 +1,10 @@
+use crate::state::entity::EntityId;
 use crate::state::game_state::GameState;
 use crate::state::input::Input;
 use crate::state::update_state::{ActionEffect, UpdateState};
+use crate::systems::item_transaction_system::{
+    ItemTransaction, ItemTransactionSystem, TransactionRejection,
+};
 use crate::systems::position_manager::PositionManager;
 use crate::systems::storage_manager::StorageManager;
 
@@ -14,15 +18,13 @@ impl ItemPickupSystem {
         input: &Input,
         frame_state: &mut UpdateState,
     ) {
-        let player = "player";
+        let player = game_state
+            .get_entity("player")
+            .expect("Player should exist");
 
         if input.e_pressed.is_toggled_on() && !frame_state.handled_e_click {
-            let near_pickup = PositionManager::find_nearest_pickup(
-                &game_state.position_components,
-                &game_state.storable_components,
-                &game_state.entities,
-                player,
-            );
+            let near_pickup =
+                PositionManager::find_nearest_pickup(game_state, player, ITEM_PICKUP_RANGE);
 
             if near_pickup.is_none() {
                 frame_state
@@ -30,7 +32,14 @@ impl ItemPickupSystem {
                     .push(ActionEffect::PickupNoItemInRange); // Might not want to show this, just ignore cause there may be other actions to handle
                 return;
             }
-            if Self::item_pickup(game_state, frame_state, &near_pickup.unwrap()) {
+            if ItemTransactionSystem::request(
+                game_state,
+                frame_state,
+                player,
+                ItemTransaction::Pickup {
+                    item: near_pickup.unwrap(),
+                },
+            ) {
                 frame_state.handled_e_click = true;
             }
         }
@@ -50,60 +59,51 @@ impl ItemPickupSystem {
         }
 
         if let Some(nearest_object) = frame_state.get_nearest_object_on_cursor() {
-            let nearest_object_clone = nearest_object.to_owned();
-            Self::item_pickup(game_state, frame_state, &nearest_object_clone);
+            let player = game_state
+                .get_entity("player")
+                .expect("Player should exist");
+            ItemTransactionSystem::request(
+                game_state,
+                frame_state,
+                player,
+                ItemTransaction::Pickup {
+                    item: nearest_object,
+                },
+            );
             frame_state.handled_left_click = true;
         }
     }
 
     pub fn item_pickup(
         game_state: &mut GameState,
-        frame_state: &mut UpdateState,
-        near_pickup: &str,
-    ) -> bool {
-        let pickup = game_state.storable_components.get(near_pickup);
-        if pickup.is_none() {
-            frame_state
-                .action_effects
-                .push(ActionEffect::PickupItemNotStorable);
-            return false;
+        player: EntityId,
+        near_pickup: EntityId,
+    ) -> Result<(), TransactionRejection> {
+        if !game_state.storable_components.contains_key(&near_pickup) {
+            return Err(TransactionRejection::NotStorable);
         }
 
-        let item_position = game_state.get_position(near_pickup);
-        if item_position.is_none() {
-            frame_state
-                .action_effects
-                .push(ActionEffect::PickupNoItemInRange);
-            return false;
-        }
+        // Items in storage have no position, those are not for us to take
+        let Some(item_position) = game_state.get_position(near_pickup) else {
+            return Err(TransactionRejection::OutOfRange);
+        };
 
-        let player = "player";
-        if !PositionManager::in_range(
-            game_state.get_position(player).unwrap(),
-            item_position.unwrap(),
-            ITEM_PICKUP_RANGE,
-        ) {
-            frame_state
-                .action_effects
-                .push(ActionEffect::PickupNoItemInRange);
-            return false;
+        if !game_state.get_position(player).is_some_and(|position| {
+            PositionManager::in_range(position, item_position, ITEM_PICKUP_RANGE)
+        }) {
+            return Err(TransactionRejection::OutOfRange);
         }
 
-        let inventory = game_state.get_storage(player).unwrap();
+        let inventory = game_state
+            .get_storage(player)
+            .ok_or(TransactionRejection::NoInventorySpace)?;
         let inventory_items = StorageManager::get_in_storage(game_state, player);
-        if !StorageManager::has_space(game_state, inventory, &inventory_items, near_pickup) {
-            frame_state
-                .action_effects
-                .push(ActionEffect::PickupNoInventorySpace);
-            return false;
-        }
         let empty_spot =
             StorageManager::find_empty_spot(game_state, inventory, &inventory_items, near_pickup)
-                .unwrap();
+                .ok_or(TransactionRejection::NoInventorySpace)?;
 
         game_state.remove_position(near_pickup);
-        game_state.remove_hitbox(near_pickup);
         game_state.create_in_storage(player, near_pickup, empty_spot);
-        true
+        Ok(())
     }
 }
@@ -1,79 +1,92 @@
-use crate::state::components::{Entity, Hitbox};
+use crate::state::entity::EntityId;
 use crate::state::game_state::GameState;
-use crate::state::update_state::ActionEffect;
 use crate::systems::collision_manager::CollisionManager;
-use cgmath::{ElementWise, Point3};
+use crate::systems::item_transaction_system::{ItemTransactionSystem, TransactionRejection};
+use crate::systems::position_manager::PositionManager;
+use cgmath::{Point2, Point3};
+
+pub const ITEM_PLACE_RANGE: f32 = 2.5;
+pub const ITEM_PLACE_HEIGHT: f32 = 0.25; // Above the ground the player stands on
 
 pub struct ItemPlacementSystem {}
 impl ItemPlacementSystem {
     pub fn place_item(
         game_state: &mut GameState,
-        action_effects: &mut Vec<ActionEffect>,
-        item_unwrap: &str,
-    ) {
-        let storage_component = game_state.in_storage_components.get_mut(item_unwrap);
-        if storage_component.is_none() {
-            #[cfg(feature = "debug-logging")]
-            log::error!("Tried to place item that's not in inventory"); // Interesting to maybe send this to server to keep track of
-            action_effects.push(ActionEffect::PlaceItemNotInInventory);
-            return;
-        }
+        player: EntityId,
+        item_unwrap: EntityId,
+    ) -> Result<(), TransactionRejection> {
+        let player_position = game_state
+            .get_position(player)
+            .ok_or(TransactionRejection::NonPlaceable)?;
+        let position = Point2::new(player_position.x - 1.1, player_position.z - 1.1);
+        Self::place_item_at(game_state, player, item_unwrap, position)
+    }
+
+    // Items are put down on the ground the player stands on, so only the xz position is chosen
+    pub fn place_item_at(
+        game_state: &mut GameState,
+        player: EntityId,
+        item_unwrap: EntityId,
+        position: Point2<f32>,
+    ) -> Result<(), TransactionRejection> {
+        ItemTransactionSystem::check_in_inventory(game_state, player, item_unwrap)?;
 
-        let player_position = game_state.get_position("player").unwrap();
+        let player_position = game_state
+            .get_position(player)
+            .ok_or(TransactionRejection::NonPlaceable)?;
         let placed_position = Point3 {
-            x: player_position.x - 1.1,
-            y: player_position.y + 0.25,
-            z: player_position.z - 1.1,
+            x: position.x,
+            y: player_position.y + ITEM_PLACE_HEIGHT,
+            z: position.y,
         };
+        if !PositionManager::in_range(player_position, &placed_position, ITEM_PLACE_RANGE) {
+            return Err(TransactionRejection::PlaceOutOfRange);
+        }
 
         if !Self::is_placeable_area(game_state, &placed_position) {
-            action_effects.push(ActionEffect::PlaceItemNonPlaceable);
-            return;
+            return Err(TransactionRejection::NonPlaceable);
         }
 
-        // Generate a dynamic hitbox for the item to be placed
-        let item_hitbox_min = placed_position.sub_element_wise(Point3::new(0.26, 0.26, 0.26));
-        let item_hitbox_max = placed_position.add_element_wise(Point3::new(0.26, 0.26, 0.26));
-        let item_hitbox = Hitbox {
-            box_corner_min: item_hitbox_min,
-            box_corner_max: item_hitbox_max,
-        };
-
-        let colliding_entities: Vec<Entity> = game_state
-            .entities
-            .iter()
-            .filter(|entity| game_state.hitbox_components.contains_key(entity.as_str()))
-            .filter(|entity| game_state.position_components.contains_key(entity.as_str()))
-            .filter(|entity| *entity != "player")
-            .filter(|entity| {
-                CollisionManager::check_collision(
-                    game_state.get_hitbox(entity.as_str()).unwrap(),
-                    &item_hitbox,
+        // Items without a collider cannot collide with anything
+        if let Some(item_collider) = game_state.get_world_collider_at(item_unwrap, placed_position)
+        {
+            let item_bounds = item_collider.bounds();
+            let colliding_entities: Vec<EntityId> = game_state
+                .get_entities_in_area(
+                    Point2::new(item_bounds.box_corner_min.x, item_bounds.box_corner_min.z),
+                    Point2::new(item_bounds.box_corner_max.x, item_bounds.box_corner_max.z),
                 )
-            })
-            .cloned()
-            .collect();
-        if !colliding_entities.is_empty() {
-            action_effects.push(ActionEffect::PlaceItemCollidingItem);
-            return;
+                .iter()
+                .filter(|entity| **entity != player && **entity != item_unwrap)
+                .filter(|entity| {
+                    game_state
+                        .get_world_collider(**entity)
+                        .is_some_and(|collider| {
+                            CollisionManager::check_collider_collision(&collider, &item_collider)
+                        })
+                })
+                .copied()
+                .collect();
+            if !colliding_entities.is_empty() {
+                return Err(TransactionRejection::Colliding);
+            }
         }
 
-        action_effects.push(ActionEffect::PlaceItemSucceeded);
         game_state.create_position(item_unwrap, placed_position);
-        game_state.create_hitbox(item_unwrap, item_hitbox);
         game_state.remove_in_storage(item_unwrap);
+        Ok(())
     }
 
     fn is_placeable_area(game_state: &GameState, desired_position: &Point3<f32>) -> bool {
         game_state
-            .entities
+            .get_entities_at(Point2::new(desired_position.x, desired_position.z))
             .iter()
-            .filter(|entity| game_state.surface_components.contains(entity.as_str()))
+            .filter(|entity| game_state.surface_components.contains(entity))
             .filter(|entity| {
                 CollisionManager::check_in_dimension(
                     desired_position.x,
                     0.0,
-                    game_state.get_position(entity.as_str()).unwrap().x,
+                    game_state.get_position(**entity).unwrap().x,
                     0.5,
                 )
             }) // Assume 0.5 as half tile
@@ -81,7 +94,7 @@ impl ItemPlacementSystem {
                 CollisionManager::check_in_dimension(
                     desired_position.z,
                     0.0,
-                    game_state.get_position(entity).unwrap().z,
+                    game_state.get_position(*entity).unwrap().z,
                     0.5,
                 )
             }) // Assume 0.5 as half tile
@@ -0,0 +1,701 @@
+use crate::state::entity::EntityId;
+use crate::state::game_state::GameState;
+use crate::state::update_state::{ActionEffect, UpdateState};
+use crate::systems::item_pickup_system::ItemPickupSystem;
+use crate::systems::item_placement_system::ItemPlacementSystem;
+use crate::systems::position_manager::PositionManager;
+use crate::systems::storage_manager::StorageManager;
+use cgmath::Point2;
+use serde::{Deserialize, Serialize};
+
+pub const TRADE_RANGE: f32 = 2.0;
+
+// Everything that moves items between the world and storages. Playing online the server decides whether it happens, so items cannot be duplicated
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ItemTransaction {
+    Pickup {
+        item: EntityId,
+    },
+    Drop {
+        item: EntityId,
+    },
+    // Onto the ground at a spot near the player, given on the xz plane
+    DropAt {
+        item: EntityId,
+        position: Point2<f32>,
+    },
+    // To another spot in the inventory, given as (column, row). Swaps with the one item in the way when that fits where this one was
+    Move {
+        item: EntityId,
+        spot: (u8, u8),
+        is_rotated: bool,
+    },
+    // Hands an item from the inventory to someone or something nearby that can store it
+    Trade {
+        item: EntityId,
+        recipient: EntityId,
+    },
+    // Packs the whole inventory into the top left
+    Sort,
+}
+
+// Why a transaction did not go through. Nothing changed when it is rejected
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum TransactionRejection {
+    NotStorable,
+    OutOfRange,
+    NoInventorySpace,
+    NotInInventory,
+    NonPlaceable,
+    PlaceOutOfRange,
+    Colliding,
+    SpotTaken,
+    RecipientNotAStorage,
+    RecipientIsAPlayer,
+    RecipientOutOfRange,
+    NoRecipientSpace,
+    NoTidierArrangement,
+}
+
+impl TransactionRejection {
+    pub fn action_effect(self) -> ActionEffect {
+        match self {
+            TransactionRejection::NotStorable => ActionEffect::PickupItemNotStorable,
+            TransactionRejection::OutOfRange => ActionEffect::PickupNoItemInRange,
+            TransactionRejection::NoInventorySpace => ActionEffect::PickupNoInventorySpace,
+            TransactionRejection::NotInInventory => ActionEffect::PlaceItemNotInInventory,
+            TransactionRejection::NonPlaceable => ActionEffect::PlaceItemNonPlaceable,
+            TransactionRejection::PlaceOutOfRange => ActionEffect::PlaceItemOutOfRange,
+            TransactionRejection::Colliding => ActionEffect::PlaceItemCollidingItem,
+            TransactionRejection::SpotTaken => ActionEffect::MoveItemSpotTaken,
+            TransactionRejection::RecipientNotAStorage => ActionEffect::TradeRecipientNotAStorage,
+            TransactionRejection::RecipientIsAPlayer => ActionEffect::TradeRecipientIsAPlayer,
+            TransactionRejection::RecipientOutOfRange => ActionEffect::TradeRecipientOutOfRange,
+            TransactionRejection::NoRecipientSpace => ActionEffect::TradeNoRecipientSpace,
+            TransactionRejection::NoTidierArrangement => ActionEffect::SortItemsDoNotFit,
+        }
+    }
+}
+
+pub struct ItemTransactionSystem {}
+
+impl ItemTransactionSystem {
+    // Applied right away when playing alone. Online it is sent to the server, and we see the result in the next snapshots
+    // Returns false when we already know it is rejected
+    pub fn request(
+        game_state: &mut GameState,
+        frame_state: &mut UpdateState,
+        player: EntityId,
+        transaction: ItemTransaction,
+    ) -> bool {
+        if frame_state.is_online {
+            frame_state.item_transactions.push(transaction);
+            return true;
+        }
+        let result = Self::execute(game_state, player, &transaction);
+        frame_state
+            .action_effects
+            .extend(Self::action_effect(&transaction, result));
+        result.is_ok()
+    }
+
+    // Checks everything before changing anything. Other players can send any entity id, so nothing is assumed about the item
+    pub fn execute(
+        game_state: &mut GameState,
+        player: EntityId,
+        transaction: &ItemTransaction,
+    ) -> Result<(), TransactionRejection> {
+        match transaction {
+            ItemTransaction::Pickup { item } => {
+                ItemPickupSystem::item_pickup(game_state, player, *item)
+            }
+            ItemTransaction::Drop { item } => {
+                ItemPlacementSystem::place_item(game_state, player, *item)
+            }
+            ItemTransaction::DropAt { item, position } => {
+                ItemPlacementSystem::place_item_at(game_state, player, *item, *position)
+            }
+            ItemTransaction::Move {
+                item,
+                spot,
+                is_rotated,
+            } => Self::move_item(game_state, player, *item, *spot, *is_rotated),
+            ItemTransaction::Trade { item, recipient } => {
+                Self::trade(game_state, player, *item, *recipient)
+            }
+            ItemTransaction::Sort => {
+                if StorageManager::sort(game_state, player) {
+                    Ok(())
+                } else {
+                    Err(TransactionRejection::NoTidierArrangement)
+                }
+            }
+        }
+    }
+
+    // What the player gets to see of the outcome
+    pub fn action_effect(
+        transaction: &ItemTransaction,
+        result: Result<(), TransactionRejection>,
+    ) -> Option<ActionEffect> {
+        match (transaction, result) {
+            (_, Err(rejection)) => Some(rejection.action_effect()),
+            (ItemTransaction::Pickup { item }, Ok(())) => {
+                Some(ActionEffect::PickupSucceeded { item: *item })
+            }
+            (ItemTransaction::Drop { item } | ItemTransaction::DropAt { item, .. }, Ok(())) => {
+                Some(ActionEffect::PlaceItemSucceeded { item: *item })
+            }
+            (ItemTransaction::Trade { .. }, Ok(())) => Some(ActionEffect::TradeSucceeded),
+            (ItemTransaction::Move { .. } | ItemTransaction::Sort, Ok(())) => None,
+        }
+    }
+
+    fn move_item(
+        game_state: &mut GameState,
+        player: EntityId,
+        item: EntityId,
+        spot: (u8, u8),
+        is_rotated: bool,
+    ) -> Result<(), TransactionRejection> {
+        let swapped = Self::check_move(game_state, player, item, spot, is_rotated)?;
+
+        if let Some(swapped) = swapped {
+            let from = game_state
+                .in_storage_components
+                .get(&item)
+                .expect("Moved item should be in storage");
+            let (from_spot, swapped_is_rotated) = (
+                (from.position_x, from.position_y),
+                game_state.in_storage_components[&swapped].is_rotated,
+            );
+            game_state.create_in_storage_rotated(player, swapped, from_spot, swapped_is_rotated);
+        }
+        game_state.create_in_storage_rotated(player, item, spot, is_rotated);
+        Ok(())
+    }
+
+    // Also used to preview a move while dragging. Gives the item it would be swapped with, if any
+    pub fn check_move(
+        game_state: &GameState,
+        player: EntityId,
+        item: EntityId,
+        spot: (u8, u8),
+        is_rotated: bool,
+    ) -> Result<Option<EntityId>, TransactionRejection> {
+        Self::check_in_inventory(game_state, player, item)?;
+        let inventory = game_state
+            .get_storage(player)
+            .ok_or(TransactionRejection::NotInInventory)?;
+        let inventory_items = StorageManager::get_in_storage(game_state, player);
+        let shape = game_state.storable_components[&item]
+            .shape
+            .rotated(is_rotated);
+        if StorageManager::fits_at(
+            game_state,
+            inventory,
+            &inventory_items,
+            &[item],
+            &shape,
+            spot,
+        ) {
+            return Ok(None);
+        }
+
+        // The other item goes where this one was, without turning. Both have to fit without overlapping each other
+        let in_the_way =
+            StorageManager::get_in_the_way(game_state, &inventory_items, &[item], &shape, spot);
+        let [other] = in_the_way[..] else {
+            return Err(TransactionRejection::SpotTaken);
+        };
+        let from = &game_state.in_storage_components[&item];
+        let from_spot = (from.position_x, from.position_y);
+        let other_shape = StorageManager::get_shape_in_storage(game_state, other)
+            .ok_or(TransactionRejection::SpotTaken)?;
+        let both = [item, other];
+        if StorageManager::fits_at(game_state, inventory, &inventory_items, &both, &shape, spot)
+            && StorageManager::fits_at(
+                game_state,
+                inventory,
+                &inventory_items,
+                &both,
+                &other_shape,
+                from_spot,
+            )
+            && !StorageManager::overlaps(spot, &shape, from_spot, &other_shape)
+        {
+            Ok(Some(other))
+        } else {
+            Err(TransactionRejection::SpotTaken)
+        }
+    }
+
+    fn trade(
+        game_state: &mut GameState,
+        player: EntityId,
+        item: EntityId,
+        recipient: EntityId,
+    ) -> Result<(), TransactionRejection> {
+        Self::check_in_inventory(game_state, player, item)?;
+        if recipient == player || !game_state.is_alive(recipient) {
+            return Err(TransactionRejection::RecipientNotAStorage);
+        }
+        // Players are who a camera follows. Nobody fills their inventory for them, they pick up what is dropped for them
+        if game_state.get_camera_target(recipient).is_some() {
+            return Err(TransactionRejection::RecipientIsAPlayer);
+        }
+        let storage = game_state
+            .get_storage(recipient)
+            .ok_or(TransactionRejection::RecipientNotAStorage)?;
+        let in_range = match (
+            game_state.get_position(player),
+            game_state.get_position(recipient),
+        ) {
+            (Some(position), Some(recipient_position)) => {
+                PositionManager::in_range(position, recipient_position, TRADE_RANGE)
+            }
+            _ => false,
+        };
+        if !in_range {
+            return Err(TransactionRejection::RecipientOutOfRange);
+        }
+        let recipient_items = StorageManager::get_in_storage(game_state, recipient);
+        let empty_spot =
+            StorageManager::find_empty_spot(game_state, storage, &recipient_items, item)
+                .ok_or(TransactionRejection::NoRecipientSpace)?;
+
+        game_state.create_in_storage(recipient, item, empty_spot);
+        Ok(())
+    }
+
+    // Only the items of the player themselves
+    pub fn check_in_inventory(
+        game_state: &GameState,
+        player: EntityId,
+        item: EntityId,
+    ) -> Result<(), TransactionRejection> {
+        let in_storage = game_state.in_storage_components.get(&item);
+        if in_storage.is_none_or(|in_storage| in_storage.storage_entity != player)
+            || !game_state.storable_components.contains_key(&item)
+        {
+            return Err(TransactionRejection::NotInInventory);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::world_definition::{
+        CameraTargetDefinition, ColliderDefinition, EntityDefinition, ItemShapeDefinition,
+        StorageDefinition,
+    };
+    use cgmath::Point3;
+
+    const CAMERA: CameraTargetDefinition = CameraTargetDefinition {
+        distance: 10.0,
+        rotation_x_degrees: 45.0,
+        rotation_y_degrees: 45.0,
+    };
+
+    fn storage(columns: u8, rows: u8) -> Option<StorageDefinition> {
+        Some(StorageDefinition {
+            number_of_rows: rows,
+            number_of_columns: columns,
+        })
+    }
+
+    fn small_box() -> Option<ColliderDefinition> {
+        Some(ColliderDefinition::Box {
+            min_offset: [-0.2, 0.0, -0.2],
+            max_offset: [0.2, 0.5, 0.2],
+        })
+    }
+
+    // The player with a 4x4 inventory in the middle of a 3x3 floor, a chest next to them and another player close by
+    fn world() -> (GameState, EntityId) {
+        let mut game_state = GameState::empty();
+        for x in -1..=1 {
+            for z in -1..=1 {
+                game_state.load_entity(&EntityDefinition {
+                    position: Some([x as f32, 0.0, z as f32]),
+                    surface: true,
+                    ..EntityDefinition::default()
+                });
+            }
+        }
+        let player = game_state.load_entity(&EntityDefinition {
+            id: Some("player".to_owned()),
+            position: Some([0.0, 0.0, 0.0]),
+            storage: storage(4, 4),
+            camera_target: Some(CAMERA),
+            ..EntityDefinition::default()
+        });
+        game_state.load_entity(&EntityDefinition {
+            id: Some("chest".to_owned()),
+            position: Some([1.0, 0.0, 1.0]),
+            storage: storage(2, 1),
+            collider: Some(ColliderDefinition::Box {
+                min_offset: [-0.5, 0.0, -0.5],
+                max_offset: [0.5, 1.0, 0.5],
+            }),
+            ..EntityDefinition::default()
+        });
+        game_state.load_entity(&EntityDefinition {
+            id: Some("far_chest".to_owned()),
+            position: Some([5.0, 0.0, 0.0]),
+            storage: storage(2, 1),
+            ..EntityDefinition::default()
+        });
+        game_state.load_entity(&EntityDefinition {
+            id: Some("player_2".to_owned()),
+            position: Some([-1.0, 0.0, 0.0]),
+            storage: storage(4, 4),
+            camera_target: Some(CAMERA),
+            ..EntityDefinition::default()
+        });
+        (game_state, player)
+    }
+
+    fn item(game_state: &mut GameState, name: &str, width: u8, height: u8) -> EntityId {
+        game_state.load_entity(&EntityDefinition {
+            id: Some(name.to_owned()),
+            storable: Some(ItemShapeDefinition { width, height }),
+            collider: small_box(),
+            ..EntityDefinition::default()
+        })
+    }
+
+    fn stored_at(
+        game_state: &mut GameState,
+        storage: &str,
+        name: &str,
+        width: u8,
+        height: u8,
+        spot: (u8, u8),
+    ) -> EntityId {
+        let item = item(game_state, name, width, height);
+        let storage = game_state.get_entity(storage).unwrap();
+        game_state.create_in_storage(storage, item, spot);
+        item
+    }
+
+    fn entity(game_state: &GameState, name: &str) -> EntityId {
+        game_state.get_entity(name).unwrap()
+    }
+
+    fn spot(game_state: &GameState, item: EntityId) -> (EntityId, (u8, u8), bool) {
+        let in_storage = &game_state.in_storage_components[&item];
+        (
+            in_storage.storage_entity,
+            (in_storage.position_x, in_storage.position_y),
+            in_storage.is_rotated,
+        )
+    }
+
+    fn execute(
+        game_state: &mut GameState,
+        player: EntityId,
+        transaction: ItemTransaction,
+    ) -> Result<(), TransactionRejection> {
+        let before = game_state.to_saved();
+        let result = ItemTransactionSystem::execute(game_state, player, &transaction);
+        if result.is_err() {
+            assert_eq!(
+                format!("{:?}", game_state.to_saved()),
+                format!("{before:?}"),
+                "{transaction:?} was rejected but changed the game state"
+            );
+        }
+        result
+    }
+
+    #[test]
+    fn pickups_are_rejected_for_what_cannot_be_taken() {
+        let (mut game_state, player) = world();
+        let chest = entity(&game_state, "chest");
+        assert_eq!(
+            execute(
+                &mut game_state,
+                player,
+                ItemTransaction::Pickup { item: chest }
+            ),
+            Err(TransactionRejection::NotStorable)
+        );
+
+        let far_coin = item(&mut game_state, "far_coin", 1, 1);
+        game_state.create_position(far_coin, Point3::new(2.0, 0.0, 0.0));
+        assert_eq!(
+            execute(
+                &mut game_state,
+                player,
+                ItemTransaction::Pickup { item: far_coin }
+            ),
+            Err(TransactionRejection::OutOfRange)
+        );
+
+        // Stored items have no position, even in a chest right next to us
+        let chest_coin = stored_at(&mut game_state, "chest", "chest_coin", 1, 1, (0, 0));
+        assert_eq!(
+            execute(
+                &mut game_state,
+                player,
+                ItemTransaction::Pickup { item: chest_coin }
+            ),
+            Err(TransactionRejection::OutOfRange)
+        );
+
+        let coin = item(&mut game_state, "coin", 1, 1);
+        game_state.create_position(coin, Point3::new(1.0, 0.0, 0.0));
+        stored_at(&mut game_state, "player", "crate", 4, 4, (0, 0));
+        assert_eq!(
+            execute(
+                &mut game_state,
+                player,
+                ItemTransaction::Pickup { item: coin }
+            ),
+            Err(TransactionRejection::NoInventorySpace)
+        );
+    }
+
+    #[test]
+    fn picked_up_items_leave_the_world_for_the_inventory() {
+        let (mut game_state, player) = world();
+        let shield = item(&mut game_state, "shield", 1, 2);
+        game_state.create_position(shield, Point3::new(1.0, 0.0, 0.0));
+
+        assert_eq!(
+            execute(
+                &mut game_state,
+                player,
+                ItemTransaction::Pickup { item: shield }
+            ),
+            Ok(())
+        );
+        assert_eq!(game_state.get_position(shield), None);
+        assert_eq!(spot(&game_state, shield), (player, (0, 0), false));
+    }
+
+    #[test]
+    fn drops_are_rejected_away_from_free_floor_nearby() {
+        let (mut game_state, player) = world();
+        let not_ours = stored_at(&mut game_state, "player_2", "not_ours", 1, 1, (0, 0));
+        let in_chest = stored_at(&mut game_state, "chest", "in_chest", 1, 1, (0, 0));
+        for item in [not_ours, in_chest] {
+            assert_eq!(
+                execute(&mut game_state, player, ItemTransaction::Drop { item }),
+                Err(TransactionRejection::NotInInventory)
+            );
+        }
+
+        let coin = stored_at(&mut game_state, "player", "coin", 1, 1, (0, 0));
+        let drop_at = |x: f32, z: f32| ItemTransaction::DropAt {
+            item: coin,
+            position: Point2::new(x, z),
+        };
+        assert_eq!(
+            execute(&mut game_state, player, drop_at(2.5, 0.0)),
+            Err(TransactionRejection::PlaceOutOfRange)
+        );
+        // In range, but past the edge of the floor
+        assert_eq!(
+            execute(&mut game_state, player, drop_at(0.0, -2.0)),
+            Err(TransactionRejection::NonPlaceable)
+        );
+        assert_eq!(
+            execute(&mut game_state, player, drop_at(1.0, 1.0)),
+            Err(TransactionRejection::Colliding)
+        );
+
+        assert_eq!(
+            execute(
+                &mut game_state,
+                player,
+                ItemTransaction::Drop { item: coin }
+            ),
+            Ok(())
+        );
+        assert!(!game_state.in_storage_components.contains_key(&coin));
+        assert_eq!(
+            game_state.get_position(coin),
+            Some(&Point3::new(-1.1, 0.25, -1.1))
+        );
+    }
+
+    #[test]
+    fn moves_go_to_free_spots_in_the_inventory_only() {
+        let (mut game_state, player) = world();
+        let shield = stored_at(&mut game_state, "player", "shield", 1, 2, (0, 0));
+        let move_to = |spot, is_rotated| ItemTransaction::Move {
+            item: shield,
+            spot,
+            is_rotated,
+        };
+
+        assert_eq!(
+            execute(&mut game_state, player, move_to((3, 3), false)),
+            Err(TransactionRejection::SpotTaken)
+        );
+        assert_eq!(
+            execute(&mut game_state, player, move_to((3, 0), true)),
+            Err(TransactionRejection::SpotTaken)
+        );
+        assert_eq!(
+            execute(&mut game_state, player, move_to((2, 3), true)),
+            Ok(())
+        );
+        assert_eq!(spot(&game_state, shield), (player, (2, 3), true));
+
+        let not_ours = stored_at(&mut game_state, "player_2", "not_ours", 1, 1, (0, 0));
+        assert_eq!(
+            execute(
+                &mut game_state,
+                player,
+                ItemTransaction::Move {
+                    item: not_ours,
+                    spot: (1, 1),
+                    is_rotated: false,
+                },
+            ),
+            Err(TransactionRejection::NotInInventory)
+        );
+        assert_eq!(spot(&game_state, not_ours).1, (0, 0));
+    }
+
+    #[test]
+    fn moves_swap_with_the_one_item_in_the_way_when_both_fit() {
+        let (mut game_state, player) = world();
+        let shield = stored_at(&mut game_state, "player", "shield", 1, 2, (0, 0));
+        let coin = stored_at(&mut game_state, "player", "coin", 1, 1, (2, 0));
+        let move_shield = |spot, is_rotated| ItemTransaction::Move {
+            item: shield,
+            spot,
+            is_rotated,
+        };
+
+        assert_eq!(
+            ItemTransactionSystem::check_move(&game_state, player, shield, (2, 0), false),
+            Ok(Some(coin))
+        );
+        assert_eq!(
+            execute(&mut game_state, player, move_shield((2, 0), false)),
+            Ok(())
+        );
+        assert_eq!(spot(&game_state, shield), (player, (2, 0), false));
+        assert_eq!(spot(&game_state, coin), (player, (0, 0), false));
+
+        // One down, the shield would cover the spot it leaves for the coin
+        game_state.create_in_storage(player, shield, (0, 1));
+        assert_eq!(
+            execute(&mut game_state, player, move_shield((0, 0), false)),
+            Err(TransactionRejection::SpotTaken)
+        );
+
+        // Two items in the way
+        let gem = stored_at(&mut game_state, "player", "gem", 1, 1, (3, 1));
+        game_state.create_in_storage(player, coin, (3, 0));
+        assert_eq!(
+            execute(&mut game_state, player, move_shield((3, 0), false)),
+            Err(TransactionRejection::SpotTaken)
+        );
+        assert_eq!(spot(&game_state, gem).1, (3, 1));
+    }
+
+    #[test]
+    fn moves_do_not_swap_what_would_not_fit_where_the_item_was() {
+        let (mut game_state, player) = world();
+        let coin = stored_at(&mut game_state, "player", "coin", 1, 1, (3, 3));
+        stored_at(&mut game_state, "player", "bag", 2, 2, (0, 0));
+        assert_eq!(
+            execute(
+                &mut game_state,
+                player,
+                ItemTransaction::Move {
+                    item: coin,
+                    spot: (0, 0),
+                    is_rotated: false,
+                },
+            ),
+            Err(TransactionRejection::SpotTaken)
+        );
+    }
+
+    #[test]
+    fn trades_only_go_to_storages_nearby_that_are_not_players() {
+        let (mut game_state, player) = world();
+        let coin = stored_at(&mut game_state, "player", "coin", 1, 1, (0, 0));
+        let trade_to = |recipient| ItemTransaction::Trade {
+            item: coin,
+            recipient,
+        };
+
+        let in_chest = stored_at(&mut game_state, "chest", "in_chest", 1, 1, (0, 0));
+        assert_eq!(
+            execute(
+                &mut game_state,
+                player,
+                ItemTransaction::Trade {
+                    item: in_chest,
+                    recipient: player,
+                },
+            ),
+            Err(TransactionRejection::NotInInventory)
+        );
+
+        let player_2 = entity(&game_state, "player_2");
+        let far_chest = entity(&game_state, "far_chest");
+        let floor = game_state.get_entities_at(Point2::new(0.0, 0.0))[0];
+        let gone = game_state.spawn();
+        game_state.despawn(gone);
+        for recipient in [player, floor, coin, gone] {
+            assert_eq!(
+                execute(&mut game_state, player, trade_to(recipient)),
+                Err(TransactionRejection::RecipientNotAStorage)
+            );
+        }
+        assert_eq!(
+            execute(&mut game_state, player, trade_to(player_2)),
+            Err(TransactionRejection::RecipientIsAPlayer)
+        );
+        assert_eq!(
+            execute(&mut game_state, player, trade_to(far_chest)),
+            Err(TransactionRejection::RecipientOutOfRange)
+        );
+
+        let chest = entity(&game_state, "chest");
+        assert_eq!(execute(&mut game_state, player, trade_to(chest)), Ok(()));
+        assert_eq!(spot(&game_state, coin), (chest, (1, 0), false));
+
+        let gem = stored_at(&mut game_state, "player", "gem", 1, 1, (0, 0));
+        assert_eq!(
+            execute(
+                &mut game_state,
+                player,
+                ItemTransaction::Trade {
+                    item: gem,
+                    recipient: chest,
+                },
+            ),
+            Err(TransactionRejection::NoRecipientSpace)
+        );
+    }
+
+    #[test]
+    fn sorting_keeps_inventories_that_would_not_fit_sorted() {
+        let (mut game_state, player) = world();
+        // More than fits, as when a save from a bigger inventory is loaded
+        stored_at(&mut game_state, "player", "bag", 4, 4, (0, 0));
+        stored_at(&mut game_state, "player", "coin", 1, 1, (0, 0));
+        assert_eq!(
+            execute(&mut game_state, player, ItemTransaction::Sort),
+            Err(TransactionRejection::NoTidierArrangement)
+        );
+
+        game_state.despawn(entity(&game_state, "coin"));
+        assert_eq!(
+            execute(&mut game_state, player, ItemTransaction::Sort),
+            Ok(())
+        );
+    }
+}
@@ -1,19 +1,25 @@
 pub mod camera_system;
 mod chat_system;
+mod click_to_move_system;
 mod close_menu_system;
 mod collision_manager;
-mod command_handle_system;
-mod dialogue_manager;
-mod dialogue_system;
+pub mod command_handle_system;
+pub mod dialogue_effect_system;
+pub mod dialogue_manager;
+pub mod dialogue_system;
 pub mod game_system;
+pub mod grid_container;
 mod health_system;
 mod inventory_system;
 pub mod item_pickup_system;
 pub mod item_placement_system;
-pub mod monitor_change_system;
+pub mod item_transaction_system;
 pub mod movement_system;
 pub mod object_detection_system;
 mod object_selection_system;
-mod position_manager;
+mod path_manager;
+pub mod position_manager;
+pub mod quest_manager;
+pub mod quest_system;
 mod storage_manager;
 mod utility;
@@ -1,109 +1,218 @@
-use crate::state::components::{Entity, Hitbox, Rotation};
+use crate::state::audio::AudioSink;
+use crate::state::components::{Rotation, WorldCollider};
+use crate::state::entity::EntityId;
 use crate::state::game_state::GameState;
 use crate::state::input::Input;
+use crate::state::time::Time;
+use crate::state::update_state::UpdateState;
 use crate::systems::collision_manager::CollisionManager;
-use cgmath::{InnerSpace, Point3, Vector2};
-use hydrox::AudioSystem;
+use crate::systems::position_manager::PositionManager;
+use cgmath::{InnerSpace, Point2, Point3, Vector2};
+use serde::{Deserialize, Serialize};
 use std::f32::consts::PI;
 use std::ops::Sub;
 
-pub const BASE_SPEED: f32 = 0.01;
-pub const CHARACTER_ROTATION_SPEED_DEGREES: f32 = 5.0;
+pub const BASE_SPEED: f32 = 0.6; // Units per second
+pub const CHARACTER_ROTATION_SPEED_DEGREES: f32 = 300.0; // Per second
+const MAX_MOVEMENT_STEP: f32 = 0.05; // Smaller than the thinnest collider, so a fast move cannot skip over an object
+const MIN_SLIDE_FRACTION: f32 = 0.01; // Less of the step than this counts as blocked, so walking straight into a wall does not slide by a rounding error of the angle
 
 pub struct MovementSystem {}
 
+// What a character does for one tick. The client sends this to the server every tick, so both move the character the same way
+#[derive(Clone, Copy, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct MovementInput {
+    pub direction: Option<f32>, // Same angle convention as desired_angle, None to stand still
+    pub is_running: bool,
+    pub max_distance: Option<f32>, // Set when walking a path, so we stop on the waypoint instead of walking past it
+}
+
 impl MovementSystem {
     pub fn resolve_movement(
+        time: &Time,
         game_state: &mut GameState,
         input: &Input,
-        audio_system: &mut AudioSystem,
+        frame_state: &mut UpdateState,
+        audio_sink: &mut dyn AudioSink,
     ) {
-        let mut movement_speed: f32 = BASE_SPEED;
-        if input.left_shift_pressed.is_pressed {
-            movement_speed *= 2.5;
+        let player = game_state
+            .get_entity("player")
+            .expect("Player should exist");
+        let movement_input = Self::movement_input(game_state, player, input);
+        Self::move_character(time, game_state, player, &movement_input, audio_sink);
+        frame_state.movement_input = movement_input;
+    }
+
+    // Walks in the direction of the pressed keys, or along the movement path when there are none
+    pub fn movement_input(
+        game_state: &mut GameState,
+        player: EntityId,
+        input: &Input,
+    ) -> MovementInput {
+        let is_running = input.left_shift_pressed.is_pressed;
+        if let Some(direction) = Self::get_desired_angle(input) {
+            game_state.path_components.remove(&player); // Keyboard takes over from click to move
+            return MovementInput {
+                direction: Some(direction),
+                is_running,
+                max_distance: None,
+            };
+        }
+        match Self::next_path_step(game_state, player) {
+            Some((direction, distance_to_waypoint)) => MovementInput {
+                direction: Some(direction),
+                is_running,
+                max_distance: Some(distance_to_waypoint),
+            },
+            None => MovementInput {
+                direction: None,
+                is_running,
+                max_distance: None,
+            },
         }
+    }
 
-        let angle_option = Self::get_desired_angle(input);
-        if angle_option.is_none() {
+    // Used for every player on the server, and to replay inputs the server did not confirm yet on the client
+    pub fn move_character(
+        time: &Time,
+        game_state: &mut GameState,
+        player: EntityId,
+        movement_input: &MovementInput,
+        audio_sink: &mut dyn AudioSink,
+    ) {
+        let Some(angle) = movement_input.direction else {
             return;
+        };
+        let mut movement_speed: f32 = BASE_SPEED * time.delta_seconds();
+        if movement_input.is_running {
+            movement_speed *= 2.5;
+        }
+        if let Some(max_distance) = movement_input.max_distance {
+            movement_speed = movement_speed.min(max_distance);
         }
 
-        let angle = angle_option.unwrap();
-
-        let player_position = game_state.get_position("player").unwrap();
+        let start_position = *game_state.get_position(player).unwrap();
+        let movement = Vector2::new(movement_speed * angle.sin(), movement_speed * angle.cos());
         let desired_position = Point3 {
-            x: player_position.x + movement_speed * angle.sin(),
-            y: player_position.y,
-            z: player_position.z + movement_speed * angle.cos(),
+            x: start_position.x + movement.x,
+            y: start_position.y,
+            z: start_position.z + movement.y,
         };
 
-        let player_hitbox = game_state.get_hitbox("player").unwrap();
+        let steps = (movement.magnitude() / MAX_MOVEMENT_STEP).ceil().max(1.0);
+        let step = movement / steps;
+        let mut position = start_position;
+        for _ in 0..steps as u32 {
+            match Self::resolve_step(game_state, player, position, step) {
+                Some(next_position) => position = next_position,
+                None => break,
+            }
+        }
 
-        let desired_player_hitbox = Hitbox {
-            box_corner_min: Point3::new(
-                player_hitbox.box_corner_min.x + movement_speed * angle.sin(),
-                player_hitbox.box_corner_min.y,
-                player_hitbox.box_corner_min.z + movement_speed * angle.cos(),
-            ),
-            box_corner_max: Point3::new(
-                player_hitbox.box_corner_max.x + movement_speed * angle.sin(),
-                player_hitbox.box_corner_max.y,
-                player_hitbox.box_corner_max.z + movement_speed * angle.cos(),
-            ),
-        };
-        if Self::is_walkable(game_state, &desired_position)
-            && !Self::is_colliding(&desired_player_hitbox, game_state, audio_system)
-        {
-            Self::update_rotation(game_state, desired_position);
-            game_state.remove_position("player");
-            game_state
-                .position_components
-                .insert("player".to_owned(), desired_position);
-            Self::update_hitbox(game_state, desired_player_hitbox);
+        if position == start_position {
+            // Only bonk into objects, not into the edge of the world
+            let blocked_collider = game_state
+                .get_world_collider_at(player, desired_position)
+                .expect("Player collider should exist");
+            if Self::is_colliding(player, &blocked_collider, game_state) {
+                audio_sink.play_sound("bonk"); // TODO add check for is_active in audio hydrox
+            }
+            game_state.path_components.remove(&player); // Something moved in the way, stop walking the path
+            return;
+        }
+
+        Self::update_rotation(time, game_state, player, desired_position); // Face where we want to go, even when sliding along something
+        game_state.create_position(player, position);
+    }
+
+    // Tries the full step first. When blocked, moves along a single axis so we slide along obstacles and tile edges instead of stopping dead
+    fn resolve_step(
+        game_state: &GameState,
+        player: EntityId,
+        position: Point3<f32>,
+        step: Vector2<f32>,
+    ) -> Option<Point3<f32>> {
+        let candidates = [
+            Vector2::new(step.x, step.y),
+            Vector2::new(step.x, 0.0),
+            Vector2::new(0.0, step.y),
+        ];
+        candidates
+            .into_iter()
+            .filter(|candidate| candidate.magnitude() > step.magnitude() * MIN_SLIDE_FRACTION)
+            .map(|candidate| {
+                Point3::new(
+                    position.x + candidate.x,
+                    position.y,
+                    position.z + candidate.y,
+                )
+            })
+            .find(|candidate_position| Self::can_move_to(game_state, player, candidate_position))
+    }
+
+    pub fn can_move_to(
+        game_state: &GameState,
+        player: EntityId,
+        desired_position: &Point3<f32>,
+    ) -> bool {
+        let desired_player_collider = game_state
+            .get_world_collider_at(player, *desired_position)
+            .expect("Player collider should exist");
+        Self::is_walkable(game_state, desired_position)
+            && !Self::is_colliding(player, &desired_player_collider, game_state)
+    }
+
+    // Skips waypoints we are already standing on. Angle is in the same convention as get_desired_angle
+    fn next_path_step(game_state: &mut GameState, player: EntityId) -> Option<(f32, f32)> {
+        let position = *game_state.get_position(player).unwrap();
+        let path = game_state.path_components.get_mut(&player)?;
+        while let Some(waypoint) = path.waypoints.front() {
+            let distance = PositionManager::distance_2d(&position, waypoint);
+            if distance > 0.001 {
+                let angle = (waypoint.x - position.x).atan2(waypoint.z - position.z);
+                return Some((angle, distance));
+            }
+            path.waypoints.pop_front();
         }
+        None
     }
 
     fn is_walkable(game_state: &GameState, desired_position: &Point3<f32>) -> bool {
         game_state
-            .entities
+            .get_entities_at(Point2::new(desired_position.x, desired_position.z))
             .iter()
-            .filter(|e| game_state.surface_components.contains(e.as_str()))
-            .any(|e| {
-                Self::check_walkable(
-                    desired_position,
-                    game_state.position_components.get(e.as_str()).unwrap(),
-                )
-            })
+            .filter(|e| game_state.surface_components.contains(e))
+            .any(|e| Self::check_walkable(desired_position, game_state.get_position(*e).unwrap()))
     }
 
     fn is_colliding(
-        desired_player_hitbox: &Hitbox,
+        player: EntityId,
+        desired_player_collider: &WorldCollider,
         game_state: &GameState,
-        audio_system: &mut AudioSystem,
     ) -> bool {
-        let interactable_entities: Vec<&Entity> = game_state
-            .entities
-            .iter()
-            .filter(|entity| {
-                entity.as_str() != "player"
-                    && game_state.get_hitbox(entity.as_str()).is_some()
-                    && game_state.get_position(entity.as_str()).is_some()
-            })
+        let desired_bounds = desired_player_collider.bounds();
+        let interactable_entities: Vec<EntityId> = game_state
+            .get_entities_in_area(
+                Point2::new(
+                    desired_bounds.box_corner_min.x,
+                    desired_bounds.box_corner_min.z,
+                ),
+                Point2::new(
+                    desired_bounds.box_corner_max.x,
+                    desired_bounds.box_corner_max.z,
+                ),
+            )
+            .into_iter()
+            .filter(|entity| *entity != player)
             .collect();
 
         for entity in interactable_entities {
-            let entity_hitbox = game_state.get_hitbox(entity).unwrap();
-
-            if CollisionManager::check_collision(desired_player_hitbox, entity_hitbox) {
-                // audio_system.play_sound("bonk"); // TODO add check for is_active in audio hydrox
-                audio_system.play_sound("bonk"); // TODO add check for is_active in audio hydrox
-
-                // #[allow(irrefutable_let_patterns)]
-                //
-                // if let AudioState::Loaded(audio_system) = audio_state {
-                //     audio_system.play_sound("bonk");
-                // } // else audio not loaded
+            let Some(entity_collider) = game_state.get_world_collider(entity) else {
+                continue;
+            };
 
+            if CollisionManager::check_collider_collision(desired_player_collider, &entity_collider)
+            {
                 return true;
             }
         }
@@ -125,12 +234,17 @@ impl MovementSystem {
         is_walkable_x && is_walkable_z
     }
 
-    fn update_rotation(game_state: &mut GameState, desired_position: Point3<f32>) {
+    fn update_rotation(
+        time: &Time,
+        game_state: &mut GameState,
+        player: EntityId,
+        desired_position: Point3<f32>,
+    ) {
         let old_rotation = game_state
-            .get_rotation("player")
+            .get_rotation(player)
             .expect("Old rotation should be present");
         let player_position = game_state
-            .get_position("player")
+            .get_position(player)
             .expect("player position should exist");
 
         let direction_3d = desired_position.sub(player_position);
@@ -139,28 +253,23 @@ impl MovementSystem {
             Vector2::new(0.0, 1.0),
             Vector2::new(direction_3d.x, direction_3d.z),
         );
+        // The short way around, also from rotations of more than a full turn such as the world file can give
         let mut rotation_difference = new_rotation - old_rotation.degrees_y;
-        if rotation_difference < 180.0 {
-            rotation_difference += 360.0;
-        }
-        if rotation_difference > 180.0 {
-            rotation_difference -= 360.0;
+        if rotation_difference.abs() > 180.0 {
+            rotation_difference = (rotation_difference + 180.0).rem_euclid(360.0) - 180.0;
         }
-        let rotation_difference_clamped = rotation_difference.clamp(
-            -CHARACTER_ROTATION_SPEED_DEGREES,
-            CHARACTER_ROTATION_SPEED_DEGREES,
-        );
-        let used_rotation = if rotation_difference_clamped < CHARACTER_ROTATION_SPEED_DEGREES
-            && rotation_difference_clamped > -CHARACTER_ROTATION_SPEED_DEGREES
+        let max_rotation = CHARACTER_ROTATION_SPEED_DEGREES * time.delta_seconds();
+        let rotation_difference_clamped = rotation_difference.clamp(-max_rotation, max_rotation);
+        let used_rotation = if rotation_difference_clamped < max_rotation
+            && rotation_difference_clamped > -max_rotation
         {
             new_rotation
         } else {
             old_rotation.degrees_y + rotation_difference_clamped
         };
 
-        game_state.rotation_components.remove("player");
-        game_state.rotation_components.insert(
-            "player".to_owned(),
+        game_state.set_rotation(
+            player,
             Rotation {
                 degrees_y: used_rotation,
             },
@@ -177,36 +286,38 @@ impl MovementSystem {
         -f32::atan2(determinant, angle).to_degrees()
     }
 
-    fn update_hitbox(game_state: &mut GameState, new_hitbox: Hitbox) {
-        game_state.hitbox_components.remove("player");
-        game_state
-            .hitbox_components
-            .insert("player".to_owned(), new_hitbox);
+    fn get_desired_angle(input: &Input) -> Option<f32> {
+        Self::desired_angle(
+            input.w_pressed.is_pressed,
+            input.s_pressed.is_pressed,
+            input.a_pressed.is_pressed,
+            input.d_pressed.is_pressed,
+        )
     }
 
     // Assumes for now Z-positive is 0 degrees
-    fn get_desired_angle(input: &Input) -> Option<f32> {
+    fn desired_angle(forward: bool, backward: bool, left: bool, right: bool) -> Option<f32> {
         let mut x: f32 = 0.0;
         let mut z: f32 = 0.0;
 
-        if input.w_pressed.is_pressed {
+        if forward {
             x -= 1.0;
             z -= 1.0;
         }
 
-        if input.s_pressed.is_pressed {
+        if backward {
             // angle = 4
             // x5.0;
             x += 1.0;
             z += 1.0;
         }
 
-        if input.a_pressed.is_pressed {
+        if left {
             x += 1.0;
             z -= 1.0;
         }
 
-        if input.d_pressed.is_pressed {
+        if right {
             x -= 1.0;
             z += 1.0;
         }
@@ -219,3 +330,362 @@ impl MovementSystem {
         Some((angle + 2.0 * PI) % (2.0 * PI))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::audio::RecordedAudio;
+    use crate::state::components::{Collider, MovementPath};
+    use cgmath::{MetricSpace, Vector3};
+    use std::collections::VecDeque;
+    use std::f32::consts::FRAC_PI_4;
+    use winit::event::ElementState;
+    use winit::keyboard::KeyCode;
+
+    const PLAYER_RADIUS: f32 = 0.1;
+    const EAST: f32 = PI / 2.0; // +x, see desired_angle
+    const NORTH_EAST: f32 = FRAC_PI_4; // +x and +z
+
+    // Floor tiles from -2 to 2 on both axes, so the walkable area goes from -2.5 to 2.5
+    fn floor() -> GameState {
+        let mut game_state = GameState::empty();
+        for x in -2..=2 {
+            for z in -2..=2 {
+                let tile = game_state.spawn();
+                game_state.surface_components.insert(tile);
+                game_state.create_position(tile, Point3::new(x as f32, 0.0, z as f32));
+            }
+        }
+        game_state
+    }
+
+    fn spawn_player(game_state: &mut GameState, position: Point3<f32>) -> EntityId {
+        let player = game_state.spawn_named("player");
+        game_state.collider_components.insert(
+            player,
+            Collider::Cylinder {
+                radius: PLAYER_RADIUS,
+                height: 1.0,
+            },
+        );
+        game_state.set_rotation(player, Rotation { degrees_y: 0.0 });
+        game_state.create_position(player, position);
+        player
+    }
+
+    // A wall along the z axis, from min_x to max_x
+    fn spawn_wall(game_state: &mut GameState, min_x: f32, max_x: f32) {
+        let wall = game_state.spawn();
+        game_state.collider_components.insert(
+            wall,
+            Collider::Box {
+                min_offset: Vector3::new(min_x, 0.0, -3.0),
+                max_offset: Vector3::new(max_x, 1.0, 3.0),
+            },
+        );
+        game_state.create_position(wall, Point3::new(0.0, 0.0, 0.0));
+    }
+
+    fn walk(
+        time: &Time,
+        game_state: &mut GameState,
+        player: EntityId,
+        direction: f32,
+        ticks: u32,
+    ) -> RecordedAudio {
+        let movement_input = MovementInput {
+            direction: Some(direction),
+            is_running: true,
+            max_distance: None,
+        };
+        let mut audio = RecordedAudio::default();
+        for _ in 0..ticks {
+            MovementSystem::move_character(time, game_state, player, &movement_input, &mut audio);
+        }
+        audio
+    }
+
+    fn position(game_state: &GameState, player: EntityId) -> Point3<f32> {
+        *game_state.get_position(player).unwrap()
+    }
+
+    #[test]
+    fn slides_along_a_collider_blocking_one_axis() {
+        let mut game_state = floor();
+        spawn_wall(&mut game_state, 0.5, 0.6);
+        let player = spawn_player(&mut game_state, Point3::new(0.0, 0.0, -2.0));
+
+        let audio = walk(&Time::new(60), &mut game_state, player, NORTH_EAST, 120);
+
+        let position = position(&game_state, player);
+        let touching_wall = 0.5 - PLAYER_RADIUS;
+        assert!(
+            position.x <= touching_wall,
+            "went into the wall: {position:?}"
+        );
+        assert!(
+            position.x > touching_wall - 0.05,
+            "stopped before the wall: {position:?}"
+        );
+        // Running diagonally covers 1.5 units on each axis in two seconds, without sliding z would stop at the wall too
+        assert!(
+            position.z > -0.6,
+            "did not slide along the wall: {position:?}"
+        );
+        assert!(audio.played_sounds.is_empty(), "bonked while sliding");
+    }
+
+    #[test]
+    fn walking_straight_into_a_collider_stops_and_bonks() {
+        let mut game_state = floor();
+        spawn_wall(&mut game_state, 0.5, 0.6);
+        let player = spawn_player(&mut game_state, Point3::new(0.0, 0.0, 0.0));
+
+        let audio = walk(&Time::new(60), &mut game_state, player, EAST, 120);
+
+        let position = position(&game_state, player);
+        assert!(
+            position.x <= 0.5 - PLAYER_RADIUS,
+            "went into the wall: {position:?}"
+        );
+        assert!(
+            position.z.abs() < 0.001,
+            "slid while walking straight: {position:?}"
+        );
+        assert!(audio.played_sounds.iter().all(|sound| sound == "bonk"));
+        assert!(!audio.played_sounds.is_empty(), "did not bonk");
+    }
+
+    #[test]
+    fn stays_on_the_tiles_and_slides_along_their_edge() {
+        let mut game_state = floor();
+        let player = spawn_player(&mut game_state, Point3::new(1.0, 0.0, -2.0));
+        let time = Time::new(60);
+
+        for _ in 0..400 {
+            walk(&time, &mut game_state, player, NORTH_EAST, 1);
+            let position = position(&game_state, player);
+            assert!(
+                position.x <= 2.5 && position.z <= 2.5,
+                "walked off the tiles: {position:?}"
+            );
+        }
+
+        // Reaches the east edge first, then follows it north into the corner
+        let position = position(&game_state, player);
+        assert!(position.x > 2.45, "stopped before the edge: {position:?}");
+        assert!(
+            position.z > 2.45,
+            "did not slide along the edge: {position:?}"
+        );
+
+        let audio = walk(&time, &mut game_state, player, NORTH_EAST, 10);
+        assert!(
+            audio.played_sounds.is_empty(),
+            "bonked into the edge of the world"
+        );
+    }
+
+    #[test]
+    fn fast_movement_does_not_pass_through_a_thin_collider() {
+        let mut game_state = floor();
+        spawn_wall(&mut game_state, 0.7, 0.72);
+        let player = spawn_player(&mut game_state, Point3::new(0.0, 0.0, 0.0));
+        // One tick per second, so running covers 1.5 units in one move: it would land past the wall in a single step
+        let time = Time::new(1);
+
+        walk(&time, &mut game_state, player, EAST, 1);
+
+        let position = position(&game_state, player);
+        assert!(
+            position.x <= 0.7 - PLAYER_RADIUS,
+            "passed through the wall: {position:?}"
+        );
+        assert!(
+            position.x > 0.7 - PLAYER_RADIUS - MAX_MOVEMENT_STEP,
+            "stopped too early: {position:?}"
+        );
+    }
+
+    fn press(keys: &[KeyCode]) -> Input {
+        let mut input = Input::new();
+        for key in keys {
+            input.update(*key, ElementState::Pressed);
+        }
+        input
+    }
+
+    // Like the game loop does, with the same input held down every tick
+    fn play(time: &Time, game_state: &mut GameState, input: &Input, ticks: u32) -> UpdateState {
+        let mut frame_state = UpdateState::new();
+        let mut audio = RecordedAudio::default();
+        for _ in 0..ticks {
+            frame_state.new_update();
+            MovementSystem::resolve_movement(time, game_state, input, &mut frame_state, &mut audio);
+        }
+        frame_state
+    }
+
+    fn rotation(game_state: &GameState, player: EntityId) -> f32 {
+        game_state.get_rotation(player).unwrap().degrees_y
+    }
+
+    // Same angle, whatever the number of turns
+    fn assert_facing(game_state: &GameState, player: EntityId, degrees: f32) {
+        let rotation = rotation(game_state, player);
+        let difference = (rotation - degrees).rem_euclid(360.0);
+        assert!(
+            difference.min(360.0 - difference) < 0.01,
+            "facing {rotation} instead of {degrees}"
+        );
+    }
+
+    #[test]
+    fn keys_move_and_turn_the_player_along_the_diagonals_of_the_camera() {
+        let diagonal = 0.5_f32.sqrt();
+        // The camera looks along the diagonal, so each key moves along both axes
+        let cases = [
+            (
+                vec![KeyCode::KeyW],
+                Vector2::new(-diagonal, -diagonal),
+                -135.0,
+            ),
+            (vec![KeyCode::KeyS], Vector2::new(diagonal, diagonal), 45.0),
+            (
+                vec![KeyCode::KeyA],
+                Vector2::new(-diagonal, diagonal),
+                -45.0,
+            ),
+            (
+                vec![KeyCode::KeyD],
+                Vector2::new(diagonal, -diagonal),
+                135.0,
+            ),
+            (
+                vec![KeyCode::KeyW, KeyCode::KeyD],
+                Vector2::new(0.0, -1.0),
+                180.0,
+            ),
+            (
+                vec![KeyCode::KeyS, KeyCode::KeyA],
+                Vector2::new(0.0, 1.0),
+                0.0,
+            ),
+            (
+                vec![KeyCode::KeyS, KeyCode::KeyD],
+                Vector2::new(1.0, 0.0),
+                90.0,
+            ),
+        ];
+        let time = Time::new(60);
+        for (keys, direction, degrees) in cases {
+            let mut game_state = floor();
+            let player = spawn_player(&mut game_state, Point3::new(0.0, 0.0, 0.0));
+
+            let frame_state = play(&time, &mut game_state, &press(&keys), 60);
+
+            let position = position(&game_state, player);
+            let expected = direction * BASE_SPEED;
+            assert!(
+                Vector2::new(position.x, position.z).distance(expected) < 0.001,
+                "{keys:?} went to {position:?}"
+            );
+            assert!(frame_state.movement_input.direction.is_some());
+            assert!(!frame_state.movement_input.is_running);
+            assert_facing(&game_state, player, degrees);
+        }
+    }
+
+    #[test]
+    fn opposite_keys_cancel_out() {
+        let time = Time::new(60);
+        for keys in [
+            vec![],
+            vec![KeyCode::KeyW, KeyCode::KeyS],
+            vec![KeyCode::KeyA, KeyCode::KeyD],
+            vec![KeyCode::KeyW, KeyCode::KeyA, KeyCode::KeyS, KeyCode::KeyD],
+        ] {
+            let mut game_state = floor();
+            let player = spawn_player(&mut game_state, Point3::new(0.0, 0.0, 0.0));
+
+            let frame_state = play(&time, &mut game_state, &press(&keys), 30);
+
+            assert_eq!(position(&game_state, player), Point3::new(0.0, 0.0, 0.0));
+            assert_eq!(frame_state.movement_input.direction, None, "{keys:?}");
+            assert_facing(&game_state, player, 0.0);
+        }
+    }
+
+    #[test]
+    fn holding_shift_runs() {
+        let time = Time::new(60);
+        let mut game_state = floor();
+        let player = spawn_player(&mut game_state, Point3::new(0.0, 0.0, -2.0));
+
+        let frame_state = play(
+            &time,
+            &mut game_state,
+            &press(&[KeyCode::KeyS, KeyCode::KeyA, KeyCode::ShiftLeft]),
+            60,
+        );
+
+        assert!(frame_state.movement_input.is_running);
+        let position = position(&game_state, player);
+        assert!(
+            (position.z - (-2.0 + BASE_SPEED * 2.5)).abs() < 0.001,
+            "{position:?}"
+        );
+    }
+
+    #[test]
+    fn turns_the_short_way_around_at_the_rotation_speed() {
+        let time = Time::new(60);
+        let per_tick = CHARACTER_ROTATION_SPEED_DEGREES / 60.0;
+        // Two full turns, as a world file might have it. Facing south-west means turning through 180 degrees is the short way to north
+        for start in [0.0, 720.0, -720.0, 170.0] {
+            let mut game_state = floor();
+            let player = spawn_player(&mut game_state, Point3::new(0.0, 0.0, 0.0));
+            game_state.set_rotation(player, Rotation { degrees_y: start });
+            let input = press(&[KeyCode::KeyS]);
+
+            play(&time, &mut game_state, &input, 1);
+
+            let target: f32 = 45.0;
+            let turned = if (target - start).rem_euclid(360.0) < 180.0 {
+                per_tick
+            } else {
+                -per_tick
+            };
+            assert_facing(&game_state, player, start + turned);
+            play(&time, &mut game_state, &input, 60);
+            assert_facing(&game_state, player, target);
+        }
+    }
+
+    #[test]
+    fn keys_take_over_from_click_to_move() {
+        let time = Time::new(60);
+        let mut game_state = floor();
+        let player = spawn_player(&mut game_state, Point3::new(0.0, 0.0, 0.0));
+        game_state.path_components.insert(
+            player,
+            MovementPath {
+                waypoints: VecDeque::from([Point3::new(0.1, 0.0, 0.0), Point3::new(0.1, 0.0, 1.0)]),
+                on_arrival: None,
+            },
+        );
+
+        // Without keys the path is walked, stopping on the waypoint instead of walking past it
+        let frame_state = play(&time, &mut game_state, &press(&[]), 20);
+        let position = position(&game_state, player);
+        assert!(
+            (position.x - 0.1).abs() < 0.001 && position.z > 0.0,
+            "{position:?}"
+        );
+        assert!(frame_state.movement_input.max_distance.is_some());
+
+        let frame_state = play(&time, &mut game_state, &press(&[KeyCode::KeyW]), 1);
+        assert!(!game_state.path_components.contains_key(&player));
+        assert_eq!(frame_state.movement_input.max_distance, None);
+    }
+}
@@ -1,18 +1,18 @@
-use crate::state::components::{Entity, Hitbox};
+use crate::state::components::Hitbox;
+use crate::state::entity::EntityId;
 use crate::state::game_state::GameState;
 use crate::state::input::Input;
 use crate::state::update_state::{ActionEffect, UpdateState};
 use crate::systems::position_manager::PositionManager;
 use cgmath::num_traits::Float;
-use cgmath::{InnerSpace, Point3, Vector3, Vector4};
+use cgmath::{InnerSpace, Point2, Point3, Vector2, Vector3, Vector4};
 // use itertools::Itertools;
 
 #[derive(Debug)]
-struct Ray {
-    origin: Point3<f32>,
-    #[allow(dead_code)] // Other algorithm choice might use direction
-    direction: Vector3<f32>,
-    direction_inverted: Vector3<f32>,
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+    pub direction_inverted: Vector3<f32>,
 }
 
 pub struct ObjectDetectionSystem {}
@@ -34,7 +34,42 @@ impl ObjectDetectionSystem {
         input: &mut Input,
         frame_state: &mut UpdateState,
     ) {
-        let camera = game_state.get_camera_mut("camera_3d").unwrap();
+        let ray = Self::get_cursor_ray(game_state, input);
+
+        frame_state.objects_on_cursor = Vec::new(); // Statement only needed as long as we run this method twice per frame
+        let candidates = game_state.get_entities_along_ray(
+            Point2::new(ray.origin.x, ray.origin.z),
+            Vector2::new(ray.direction.x, ray.direction.z),
+        );
+        for entity in candidates {
+            if let Some(hitbox) = game_state.get_hitbox(entity)
+                && Self::intersection(&ray, &hitbox)
+            {
+                frame_state.add_object_on_cursor(entity);
+            }
+        }
+
+        let found_objects_text = frame_state
+            .get_objects_on_cursor()
+            .iter()
+            .map(|entity| {
+                game_state
+                    .get_name(*entity)
+                    .map_or_else(|| entity.to_string(), str::to_owned)
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        frame_state
+            .action_effects
+            .push(ActionEffect::ItemSelected { found_objects_text });
+    }
+
+    // Uses the camera matrices of the last camera update
+    pub fn get_cursor_ray(game_state: &GameState, input: &Input) -> Ray {
+        let camera_3d = game_state
+            .get_entity("camera_3d")
+            .expect("Camera should exist");
+        let camera = game_state.get_camera(camera_3d).unwrap();
 
         let ray_clip_near = Vector4::new(
             input.mouse_position_ndc.x,
@@ -63,43 +98,34 @@ impl ObjectDetectionSystem {
 
         let ray_direction_inverted = (1.0 / ray_world).normalize();
 
-        let ray = Ray {
+        Ray {
             origin: point_near_normalized, // Not camera origin! (orthographic)
             direction: ray_world,
             direction_inverted: ray_direction_inverted,
-        };
-
-        frame_state.objects_on_cursor = Vec::new(); // Statement only needed as long as we run this method twice per frame
-        for (entity, hitbox) in &game_state.hitbox_components {
-            if Self::intersection(&ray, hitbox) {
-                frame_state.add_object_on_cursor(entity);
-            }
         }
-
-        let found_objects_text = frame_state.get_objects_on_cursor().join(", ");
-        frame_state
-            .action_effects
-            .push(ActionEffect::ItemSelected { found_objects_text });
     }
 
     fn set_nearest_object(game_state: &GameState, frame_state: &mut UpdateState) {
+        let player = game_state
+            .get_entity("player")
+            .expect("Player should exist");
         let player_position = game_state
-            .get_position("player")
+            .get_position(player)
             .expect("Player position should exist");
-        let nearest_object: Option<Entity> = frame_state
+        let nearest_object: Option<EntityId> = frame_state
             .get_objects_on_cursor()
             .iter()
-            .filter(|entity| !(*entity).eq("player"))
+            .filter(|entity| **entity != player)
             .min_by(|a, b| {
-                let a_pos = game_state.get_position(a).unwrap();
-                let b_pos = game_state.get_position(b).unwrap();
+                let a_pos = game_state.get_position(**a).unwrap();
+                let b_pos = game_state.get_position(**b).unwrap();
                 let a_dist = PositionManager::distance_3d(a_pos, player_position);
                 let b_dist = PositionManager::distance_3d(b_pos, player_position);
                 a_dist
                     .partial_cmp(&b_dist)
                     .expect("Distances must be comparable")
             })
-            .cloned();
+            .copied();
         // .map(|entity| {
         //     let object_position = game_state.get_position(entity).unwrap();
         //     let distance = PositionManager::distance_3d(object_position, player_position);
@@ -1,19 +1,18 @@
-use crate::state::components::Entity;
+use crate::state::entity::EntityId;
 use crate::state::game_state::GameState;
 use crate::state::input::Input;
 use crate::state::ui_state::MenuState::Closed;
 use crate::state::ui_state::{MenuState, RenderCommand, UIElement, UIState, UserAction};
 use crate::state::update_state::{ActionEffect, UpdateState};
-use crate::systems::item_pickup_system::ItemPickupSystem;
+use crate::state::viewport::Viewport;
+use crate::systems::item_transaction_system::{ItemTransaction, ItemTransactionSystem};
 use cgmath::Point2;
-use std::sync::Arc;
-use winit::window::Window;
 
 pub struct ObjectSelectionSystem();
 
 impl ObjectSelectionSystem {
     pub fn handle_object_selection(
-        window: &Arc<Window>,
+        viewport: &Viewport,
         game_state: &mut GameState,
         ui_state: &mut UIState,
         input: &Input,
@@ -24,10 +23,9 @@ impl ObjectSelectionSystem {
         if Self::should_open_menu(input, frame_state, selected_objects) {
             ui_state.menu_state = MenuState::WorldAction {
                 render_position: input.mouse_position_ui,
-                item: selected_objects
+                item: *selected_objects
                     .first()
-                    .expect("Selected objects is not empty")
-                    .clone(),
+                    .expect("Selected objects is not empty"),
             };
             frame_state.handled_right_click = true;
         }
@@ -43,7 +41,7 @@ impl ObjectSelectionSystem {
                 Point2::new(render_position.x + 0.015, render_position.y + 0.03),
                 Point2::new(0.065, 0.05),
             );
-            object_selection_menu_rect.update(&window.inner_size());
+            object_selection_menu_rect.update(&viewport.size());
             let object_selection_menu_render_command = RenderCommand::Model {
                 layer: 100,
                 ui_element: object_selection_menu_rect,
@@ -66,14 +64,22 @@ impl ObjectSelectionSystem {
             let mut text_color = [0.8, 0.8, 0.8];
             match frame_state
                 .gui
-                .button_handle(window, pickup_menu_rect, input)
+                .button_handle(viewport, pickup_menu_rect, input)
             {
                 UserAction::None | UserAction::RightClick => {}
                 UserAction::LeftClick => {
                     if frame_state.handled_left_click {
                         return;
                     }
-                    ItemPickupSystem::item_pickup(game_state, frame_state, item);
+                    let player = game_state
+                        .get_entity("player")
+                        .expect("Player should exist");
+                    ItemTransactionSystem::request(
+                        game_state,
+                        frame_state,
+                        player,
+                        ItemTransaction::Pickup { item: *item },
+                    );
                     new_menu_state = &Closed;
                     frame_state.handled_left_click = true;
                     // TODO just return
@@ -104,7 +110,7 @@ impl ObjectSelectionSystem {
             let mut text_color = [0.8, 0.8, 0.8];
             match frame_state
                 .gui
-                .button_handle(window, examine_menu_rect, input)
+                .button_handle(viewport, examine_menu_rect, input)
             {
                 UserAction::None | UserAction::RightClick => {}
                 UserAction::LeftClick => {
@@ -138,7 +144,7 @@ impl ObjectSelectionSystem {
     fn should_open_menu(
         input: &Input,
         frame_state: &UpdateState,
-        selected_objects: &[Entity],
+        selected_objects: &[EntityId],
     ) -> bool {
         if !input.right_mouse_clicked.is_toggled_on() {
             return false;
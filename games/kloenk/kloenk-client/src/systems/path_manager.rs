@@ -0,0 +1,171 @@
+use crate::state::entity::EntityId;
+use crate::state::game_state::GameState;
+use cgmath::{Point2, Point3};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+const TILE_CLEARANCE: f32 = 0.3; // Part of the tile around its center that needs to be free of colliders to walk over it
+const MAX_SEARCHED_TILES: usize = 10_000;
+
+type Tile = (i32, i32);
+
+// A* over the walkable surface tiles. Tiles are centered on whole coordinates
+pub struct PathManager {}
+
+impl PathManager {
+    // Returns the tile centers to walk over, not including the tile the walker is standing on
+    // The ignored entity does not block tiles, so we can find a path towards something we want to interact with
+    pub fn find_path(
+        game_state: &GameState,
+        walker: EntityId,
+        destination: Point3<f32>,
+        ignored: Option<EntityId>,
+    ) -> Option<Vec<Point3<f32>>> {
+        let start_position = game_state.get_position(walker)?;
+        let start = Self::tile_at(start_position.x, start_position.z);
+        let goal = Self::tile_at(destination.x, destination.z);
+
+        let walkable_tiles: HashSet<Tile> = game_state
+            .surface_components
+            .iter()
+            .filter_map(|tile| game_state.get_position(*tile))
+            .map(|position| Self::tile_at(position.x, position.z))
+            .collect();
+
+        let mut blocked_cache: HashMap<Tile, bool> = HashMap::new();
+        let mut is_open = |tile: Tile| {
+            walkable_tiles.contains(&tile)
+                && !*blocked_cache
+                    .entry(tile)
+                    .or_insert_with(|| Self::is_blocked(game_state, tile, walker, ignored))
+        };
+        if !is_open(goal) {
+            return None;
+        }
+
+        let mut open_set = BinaryHeap::new();
+        let mut came_from: HashMap<Tile, Tile> = HashMap::new();
+        let mut cost_so_far: HashMap<Tile, u32> = HashMap::new();
+        open_set.push(Reverse((Self::heuristic(start, goal), start)));
+        cost_so_far.insert(start, 0);
+
+        while let Some(Reverse((_, current))) = open_set.pop() {
+            if current == goal {
+                return Some(Self::reconstruct_path(
+                    &came_from,
+                    start,
+                    goal,
+                    start_position.y,
+                ));
+            }
+            if cost_so_far.len() > MAX_SEARCHED_TILES {
+                return None;
+            }
+
+            let current_cost = cost_so_far[&current];
+            for (dx, dz) in [
+                (1, 0),
+                (-1, 0),
+                (0, 1),
+                (0, -1),
+                (1, 1),
+                (1, -1),
+                (-1, 1),
+                (-1, -1),
+            ] {
+                let neighbour = (current.0 + dx, current.1 + dz);
+                if !is_open(neighbour) {
+                    continue;
+                }
+                let is_diagonal = dx != 0 && dz != 0;
+                // Do not cut corners, we would walk into whatever blocks the tile next to us
+                if is_diagonal
+                    && (!is_open((current.0 + dx, current.1))
+                        || !is_open((current.0, current.1 + dz)))
+                {
+                    continue;
+                }
+
+                let step_cost = if is_diagonal {
+                    DIAGONAL_COST
+                } else {
+                    STRAIGHT_COST
+                };
+                let new_cost = current_cost + step_cost;
+                if cost_so_far
+                    .get(&neighbour)
+                    .is_none_or(|known_cost| new_cost < *known_cost)
+                {
+                    cost_so_far.insert(neighbour, new_cost);
+                    came_from.insert(neighbour, current);
+                    open_set.push(Reverse((
+                        new_cost + Self::heuristic(neighbour, goal),
+                        neighbour,
+                    )));
+                }
+            }
+        }
+
+        None
+    }
+
+    pub fn tile_at(x: f32, z: f32) -> Tile {
+        (x.round() as i32, z.round() as i32)
+    }
+
+    fn is_blocked(
+        game_state: &GameState,
+        tile: Tile,
+        walker: EntityId,
+        ignored: Option<EntityId>,
+    ) -> bool {
+        let min = Point2::new(
+            tile.0 as f32 - TILE_CLEARANCE,
+            tile.1 as f32 - TILE_CLEARANCE,
+        );
+        let max = Point2::new(
+            tile.0 as f32 + TILE_CLEARANCE,
+            tile.1 as f32 + TILE_CLEARANCE,
+        );
+        game_state
+            .get_entities_in_area(min, max)
+            .into_iter()
+            .filter(|entity| *entity != walker && Some(*entity) != ignored)
+            .filter_map(|entity| game_state.get_hitbox(entity))
+            .any(|hitbox| {
+                hitbox.box_corner_min.x < max.x
+                    && hitbox.box_corner_max.x > min.x
+                    && hitbox.box_corner_min.z < max.y
+                    && hitbox.box_corner_max.z > min.y
+            })
+    }
+
+    // Octile distance, never overestimates with our step costs
+    fn heuristic(from: Tile, to: Tile) -> u32 {
+        let dx = from.0.abs_diff(to.0);
+        let dz = from.1.abs_diff(to.1);
+        STRAIGHT_COST * dx.max(dz) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dz)
+    }
+
+    fn reconstruct_path(
+        came_from: &HashMap<Tile, Tile>,
+        start: Tile,
+        goal: Tile,
+        y: f32,
+    ) -> Vec<Point3<f32>> {
+        let mut tiles = vec![goal];
+        let mut current = goal;
+        while current != start {
+            current = came_from[&current];
+            tiles.push(current);
+        }
+        tiles.pop(); // Already standing on the start tile
+        tiles
+            .iter()
+            .rev()
+            .map(|tile| Point3::new(tile.0 as f32, y, tile.1 as f32))
+            .collect()
+    }
+}
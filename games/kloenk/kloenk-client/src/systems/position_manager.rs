@@ -1,60 +1,60 @@
-use crate::state::components::{Entity, Storable};
+use crate::state::entity::EntityId;
 use crate::state::game_state::GameState;
 use cgmath::num_traits::ToPrimitive;
-use cgmath::Point3;
-use std::collections::HashMap;
+use cgmath::{Point2, Point3};
 
 pub struct PositionManager {}
 
 impl PositionManager {
+    // Only looks within range, anything further away could not be picked up anyway
     pub fn find_nearest_pickup(
-        positions: &HashMap<Entity, Point3<f32>>,
-        storables: &HashMap<Entity, Storable>,
-        entities: &[Entity],
-        entity: &str,
-    ) -> Option<Entity> {
-        entities
-            .iter()
-            .filter(|e| storables.contains_key(e.as_str()))
-            .filter(|e| positions.contains_key(e.as_str()))
+        game_state: &GameState,
+        entity: EntityId,
+        range: f32,
+    ) -> Option<EntityId> {
+        let position = game_state.get_position(entity).unwrap();
+        game_state
+            .get_entities_in_area(
+                Point2::new(position.x - range, position.z - range),
+                Point2::new(position.x + range, position.z + range),
+            )
+            .into_iter()
+            .filter(|e| game_state.storable_components.contains_key(e))
+            .filter(|e| game_state.get_position(*e).is_some())
             .min_by_key(|e| {
-                Self::distance_2d(
-                    positions.get(entity).unwrap(),
-                    positions.get(e.as_str()).unwrap(),
-                )
+                Self::distance_2d(position, game_state.get_position(*e).unwrap())
                     .round()
                     .to_u32()
             })
-            .cloned()
     }
 
     pub fn distance_2d(position1: &Point3<f32>, position2: &Point3<f32>) -> f32 {
         ((position2.x - position1.x).powi(2) + (position2.z - position1.z).powi(2)).sqrt()
     }
 
-    pub fn find_nearest_dialog(game_state: &GameState) -> Option<&str> {
+    pub fn find_nearest_dialog(game_state: &GameState, player: EntityId) -> Option<EntityId> {
         game_state
             .entities
             .iter()
             .filter(|e| {
-                game_state.position_components.contains_key(e.as_str())
-                    && game_state.dialogue_components.contains_key(e.as_str())
+                game_state.position_components.contains_key(e)
+                    && game_state.dialogue_components.contains_key(e)
             })
             .min_by_key(|e| {
                 Self::distance_2d(
                     game_state
                         .position_components
-                        .get("player")
+                        .get(&player)
                         .expect("Player position should exist"),
                     game_state
                         .position_components
-                        .get(e.as_str())
+                        .get(e)
                         .expect("NPC dialogue position should exist"),
                 )
                     .round()
                     .to_u32()
             })
-            .map(String::as_str)
+            .copied()
     }
 
     pub fn distance_3d(point1: &Point3<f32>, point2: &Point3<f32>) -> f32 {
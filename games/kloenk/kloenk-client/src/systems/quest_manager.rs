@@ -0,0 +1,167 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+// A quest is done stage by stage, a stage once all of its objectives are. The rewards are given when the last stage is done
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Quest {
+    pub title: String,
+    pub stages: Vec<QuestStage>,
+    #[serde(default)]
+    pub rewards: Vec<QuestReward>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct QuestStage {
+    pub description: String,
+    pub objectives: Vec<QuestObjective>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct QuestObjective {
+    pub text: String,
+    pub goal: ObjectiveGoal,
+    #[serde(default = "QuestObjective::default_count")]
+    pub count: u32, // How often the goal has to be reached
+}
+
+impl QuestObjective {
+    fn default_count() -> u32 {
+        1
+    }
+}
+
+// Reached through what the player does, as told by the action effects. Entities are referred to by their id in the world file, or the template they were made from
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "action", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ObjectiveGoal {
+    PickUp {
+        item: String,
+    },
+    Drop {
+        item: String,
+        surface: Option<String>, // By the name of the surface grid, anywhere when left out
+    },
+    TalkTo {
+        npc: String,
+    },
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "reward", rename_all = "snake_case", deny_unknown_fields)]
+pub enum QuestReward {
+    Heal { hitpoints: u32 },
+    MaxHealth { hitpoints: u32 }, // Heals by the same amount
+    SetFlag { flag: String },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawQuests {
+    quests: BTreeMap<String, Quest>,
+}
+
+#[derive(Debug)]
+pub enum QuestError {
+    Syntax { message: String },
+    NoStages { quest: String },
+    NoObjectives { quest: String, stage: usize },
+    ZeroCount { quest: String, stage: usize },
+}
+
+impl fmt::Display for QuestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuestError::Syntax { message } => write!(f, "Invalid quest file: {message}"),
+            QuestError::NoStages { quest } => write!(f, "Quest '{quest}' has no stages"),
+            QuestError::NoObjectives { quest, stage } => {
+                write!(f, "Stage {stage} of quest '{quest}' has no objectives")
+            }
+            QuestError::ZeroCount { quest, stage } => write!(
+                f,
+                "Stage {stage} of quest '{quest}' has an objective with a count of 0"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QuestError {}
+
+impl From<serde_json::Error> for QuestError {
+    fn from(error: serde_json::Error) -> Self {
+        QuestError::Syntax {
+            message: error.to_string(), // Includes line and column
+        }
+    }
+}
+
+// Loaded once from the quest file. Players refer to a quest by its id
+pub struct QuestManager {
+    quests: HashMap<String, Quest>,
+}
+
+impl Default for QuestManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QuestManager {
+    // Without quests, until the file is loaded
+    pub fn new() -> QuestManager {
+        Self {
+            quests: HashMap::new(),
+        }
+    }
+
+    // A quest that cannot be finished would otherwise only show up once someone gets stuck in it
+    pub fn from_json(bytes: &[u8]) -> Result<QuestManager, QuestError> {
+        let raw: RawQuests = serde_json::from_slice(bytes)?;
+        for (quest_id, quest) in &raw.quests {
+            Self::validate(quest_id, quest)?;
+        }
+        Ok(Self {
+            quests: raw.quests.into_iter().collect(),
+        })
+    }
+
+    // Stages are counted from 1, like the designers do
+    fn validate(quest_id: &str, quest: &Quest) -> Result<(), QuestError> {
+        if quest.stages.is_empty() {
+            return Err(QuestError::NoStages {
+                quest: quest_id.to_owned(),
+            });
+        }
+        for (index, stage) in quest.stages.iter().enumerate() {
+            if stage.objectives.is_empty() {
+                return Err(QuestError::NoObjectives {
+                    quest: quest_id.to_owned(),
+                    stage: index + 1,
+                });
+            }
+            if stage
+                .objectives
+                .iter()
+                .any(|objective| objective.count == 0)
+            {
+                return Err(QuestError::ZeroCount {
+                    quest: quest_id.to_owned(),
+                    stage: index + 1,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_quest(&self, id: &str) -> Option<&Quest> {
+        self.quests.get(id)
+    }
+
+    // The title when the quest is known, otherwise the id is better than nothing
+    pub fn title<'a>(&'a self, id: &'a str) -> &'a str {
+        self.get_quest(id).map_or(id, |quest| quest.title.as_str())
+    }
+}
@@ -0,0 +1,519 @@
+use crate::state::components::QuestProgress;
+use crate::state::entity::EntityId;
+use crate::state::game_state::GameState;
+use crate::state::input::Input;
+use crate::state::ui_state::{UIState, UserAction};
+use crate::state::update_state::{ActionEffect, UpdateState};
+use crate::state::viewport::Viewport;
+use crate::systems::quest_manager::{ObjectiveGoal, QuestManager, QuestReward};
+use cgmath::Point2;
+
+pub struct QuestSystem {}
+
+impl QuestSystem {
+    // Returns false when the player already started it before
+    pub fn start(game_state: &mut GameState, player: EntityId, quest: &str) -> bool {
+        let quest_log = game_state.quest_components.entry(player).or_default();
+        if quest_log.quests.contains_key(quest) {
+            return false;
+        }
+        quest_log
+            .quests
+            .insert(quest.to_owned(), QuestProgress::default());
+        true
+    }
+
+    pub fn has_started(game_state: &GameState, player: EntityId, quest: &str) -> bool {
+        game_state
+            .quest_components
+            .get(&player)
+            .is_some_and(|quest_log| quest_log.quests.contains_key(quest))
+    }
+
+    pub fn has_completed(game_state: &GameState, player: EntityId, quest: &str) -> bool {
+        game_state
+            .quest_components
+            .get(&player)
+            .and_then(|quest_log| quest_log.quests.get(quest))
+            .is_some_and(|progress| progress.is_completed)
+    }
+
+    // Saves can be older than the quest file. Progress in a stage the quest no longer has starts over in its last stage
+    pub fn fit_progress(game_state: &mut GameState, quests: &QuestManager) {
+        for quest_log in game_state.quest_components.values_mut() {
+            for (quest_id, progress) in &mut quest_log.quests {
+                let Some(quest) = quests.get_quest(quest_id) else {
+                    continue; // Kept, the quest might come back
+                };
+                if progress.stage >= quest.stages.len() {
+                    progress.stage = quest.stages.len() - 1;
+                    progress.objective_counts.clear();
+                }
+            }
+        }
+    }
+
+    // Goes over what the actions of the player led to, and returns the quest stages that got done by it
+    // Where the game state is owned: alone that is the client, online the server
+    pub fn track(
+        game_state: &mut GameState,
+        quests: &QuestManager,
+        player: EntityId,
+        action_effects: &[ActionEffect],
+    ) -> Vec<ActionEffect> {
+        let Some(quest_log) = game_state.quest_components.get(&player) else {
+            return Vec::new();
+        };
+        let active_quests: Vec<String> = quest_log
+            .quests
+            .iter()
+            .filter(|(_, progress)| !progress.is_completed)
+            .map(|(quest, _)| quest.clone())
+            .collect();
+
+        let mut quest_effects = Vec::new();
+        for quest_id in active_quests {
+            let Some(quest) = quests.get_quest(&quest_id) else {
+                continue;
+            };
+            for action_effect in action_effects {
+                let progress = &game_state.quest_components[&player].quests[&quest_id];
+                let Some(stage) = quest.stages.get(progress.stage) else {
+                    break; // Left alone until the progress is fit to the quest again
+                };
+                let objectives = &stage.objectives;
+                let reached: Vec<usize> = objectives
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, objective)| {
+                        Self::reaches(game_state, &objective.goal, action_effect)
+                    })
+                    .map(|(index, _)| index)
+                    .collect();
+                if reached.is_empty() {
+                    continue;
+                }
+
+                let progress = game_state
+                    .quest_components
+                    .get_mut(&player)
+                    .and_then(|quest_log| quest_log.quests.get_mut(&quest_id))
+                    .expect("Quest progress should exist");
+                progress.objective_counts.resize(objectives.len(), 0);
+                for index in reached {
+                    progress.objective_counts[index] =
+                        (progress.objective_counts[index] + 1).min(objectives[index].count);
+                }
+                let stage_done = objectives
+                    .iter()
+                    .zip(&progress.objective_counts)
+                    .all(|(objective, count)| *count >= objective.count);
+                if !stage_done {
+                    continue;
+                }
+
+                if progress.stage + 1 < quest.stages.len() {
+                    progress.stage += 1;
+                    progress.objective_counts.clear();
+                    quest_effects.push(ActionEffect::QuestStageCompleted {
+                        quest: quest_id.clone(),
+                    });
+                } else {
+                    progress.is_completed = true;
+                    for reward in &quest.rewards {
+                        Self::reward(game_state, player, reward);
+                    }
+                    quest_effects.push(ActionEffect::QuestCompleted {
+                        quest: quest_id.clone(),
+                    });
+                    break;
+                }
+            }
+        }
+        quest_effects
+    }
+
+    fn reaches(game_state: &GameState, goal: &ObjectiveGoal, action_effect: &ActionEffect) -> bool {
+        match (goal, action_effect) {
+            (ObjectiveGoal::PickUp { item }, ActionEffect::PickupSucceeded { item: picked_up }) => {
+                Self::is(game_state, *picked_up, item)
+            }
+            (
+                ObjectiveGoal::Drop { item, surface },
+                ActionEffect::PlaceItemSucceeded { item: dropped },
+            ) => {
+                Self::is(game_state, *dropped, item)
+                    && surface
+                        .as_ref()
+                        .is_none_or(|surface| Self::is_on_surface(game_state, *dropped, surface))
+            }
+            (ObjectiveGoal::TalkTo { npc }, ActionEffect::TalkedTo { npc: talked_to }) => {
+                Self::is(game_state, *talked_to, npc)
+            }
+            _ => false,
+        }
+    }
+
+    // By id, or by the template it was made from
+    fn is(game_state: &GameState, entity: EntityId, id: &str) -> bool {
+        game_state.get_name(entity) == Some(id)
+            || game_state
+                .kind_components
+                .get(&entity)
+                .is_some_and(|kind| kind == id)
+    }
+
+    fn is_on_surface(game_state: &GameState, entity: EntityId, surface: &str) -> bool {
+        let Some(position) = game_state.get_position(entity) else {
+            return false;
+        };
+        let point = Point2::new(position.x, position.z);
+        game_state
+            .get_entities_at(point)
+            .into_iter()
+            .filter(|candidate| game_state.surface_components.contains(candidate))
+            .any(|tile| Self::is(game_state, tile, surface))
+    }
+
+    fn reward(game_state: &mut GameState, player: EntityId, reward: &QuestReward) {
+        match reward {
+            QuestReward::Heal { hitpoints } => {
+                if let Some(health) = game_state.health_components.get_mut(&player) {
+                    health.hitpoints = health
+                        .hitpoints
+                        .saturating_add(*hitpoints)
+                        .min(health.max_hitpoints);
+                }
+            }
+            QuestReward::MaxHealth { hitpoints } => {
+                if let Some(health) = game_state.health_components.get_mut(&player) {
+                    health.max_hitpoints = health.max_hitpoints.saturating_add(*hitpoints);
+                    health.hitpoints = health.hitpoints.saturating_add(*hitpoints);
+                }
+            }
+            QuestReward::SetFlag { flag } => game_state.set_flag(player, flag),
+        }
+    }
+
+    pub fn display_quest_log(
+        viewport: &Viewport,
+        game_state: &GameState,
+        ui_state: &mut UIState,
+        input: &Input,
+        frame_state: &mut UpdateState,
+    ) {
+        let quest_window = ui_state
+            .windows
+            .get_mut("quests")
+            .expect("Quest window should exist");
+        if input.l_pressed.is_toggled_on() {
+            quest_window.is_visible = !quest_window.is_visible;
+        }
+        if !quest_window.is_visible {
+            return;
+        }
+
+        let quest_window_rect = quest_window.rect;
+        frame_state
+            .gui
+            .add_color_command(100, &quest_window_rect, "black");
+        // Clicks on the window are not meant for the world behind it
+        if let UserAction::LeftClick =
+            frame_state
+                .gui
+                .button_handle(viewport, quest_window_rect, input)
+        {
+            frame_state.handled_left_click = true;
+        }
+
+        let player = game_state
+            .get_entity("player")
+            .expect("Player should exist");
+        frame_state.gui.text_render(
+            300,
+            quest_window_rect.inner_rect(Point2::new(0.04, 0.04), Point2::new(0.96, 0.96)),
+            &Self::quest_log_text(game_state, &ui_state.quests, player),
+            [0.8, 0.8, 0.0],
+        );
+    }
+
+    // Active quests first with what is left to do, completed ones only by their title
+    fn quest_log_text(game_state: &GameState, quests: &QuestManager, player: EntityId) -> String {
+        let Some(quest_log) = game_state
+            .quest_components
+            .get(&player)
+            .filter(|quest_log| !quest_log.quests.is_empty())
+        else {
+            return "No quests yet. Talk to people to find some.".to_owned();
+        };
+
+        let mut lines = Vec::new();
+        for (quest_id, progress) in &quest_log.quests {
+            let Some(quest) = quests.get_quest(quest_id) else {
+                continue;
+            };
+            if progress.is_completed {
+                continue;
+            }
+            let Some(stage) = quest.stages.get(progress.stage) else {
+                continue;
+            };
+            lines.push(quest.title.clone());
+            lines.push(format!("  {}", stage.description));
+            for (index, objective) in stage.objectives.iter().enumerate() {
+                let count = progress.objective_counts.get(index).copied().unwrap_or(0);
+                lines.push(format!(
+                    "  - {} ({count}/{})",
+                    objective.text, objective.count
+                ));
+            }
+        }
+        for (quest_id, progress) in &quest_log.quests {
+            if progress.is_completed {
+                lines.push(format!("{} (completed)", quests.title(quest_id)));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::components::QuestLog;
+    use crate::state::save_game::SaveGame;
+    use crate::state::world_definition::{EntityDefinition, HealthDefinition};
+
+    // Two coins to pick up, then back to Dennis
+    const QUESTS: &[u8] = br#"{"quests": {"collect_coins": {"title": "Coins", "stages": [
+        {"description": "Dennis lost his coins", "objectives": [
+            {"text": "Pick up coins", "goal": {"action": "pick_up", "item": "coin"}, "count": 2}
+        ]},
+        {"description": "Bring them back", "objectives": [
+            {"text": "Talk to Dennis", "goal": {"action": "talk_to", "npc": "Dennis"}}
+        ]}
+    ], "rewards": [
+        {"reward": "heal", "hitpoints": 10},
+        {"reward": "max_health", "hitpoints": 20},
+        {"reward": "set_flag", "flag": "coin_finder"}
+    ]}}}"#;
+
+    struct World {
+        game_state: GameState,
+        quests: QuestManager,
+        player: EntityId,
+        dennis: EntityId,
+        coins: [EntityId; 3], // Made from the coin template
+        sword: EntityId,
+    }
+
+    fn world() -> World {
+        let mut game_state = GameState::empty();
+        let player = game_state.load_entity(&EntityDefinition {
+            id: Some("player".to_owned()),
+            health: Some(HealthDefinition {
+                hitpoints: 50,
+                max_hitpoints: 100,
+            }),
+            ..EntityDefinition::default()
+        });
+        let dennis = game_state.spawn_named("Dennis");
+        let coin = EntityDefinition {
+            template: Some("coin".to_owned()),
+            ..EntityDefinition::default()
+        };
+        let coins = [(); 3].map(|()| game_state.load_entity(&coin));
+        let sword = game_state.spawn_named("sword");
+        World {
+            game_state,
+            quests: QuestManager::from_json(QUESTS).expect("Quests should load"),
+            player,
+            dennis,
+            coins,
+            sword,
+        }
+    }
+
+    impl World {
+        fn track(&mut self, action_effects: &[ActionEffect]) -> Vec<String> {
+            QuestSystem::track(
+                &mut self.game_state,
+                &self.quests,
+                self.player,
+                action_effects,
+            )
+            .iter()
+            .map(|quest_effect| format!("{quest_effect:?}"))
+            .collect()
+        }
+
+        fn progress(&self) -> &QuestProgress {
+            &self.game_state.quest_components[&self.player].quests["collect_coins"]
+        }
+
+        fn log(&self) -> String {
+            QuestSystem::quest_log_text(&self.game_state, &self.quests, self.player)
+        }
+    }
+
+    fn picked_up(item: EntityId) -> ActionEffect {
+        ActionEffect::PickupSucceeded { item }
+    }
+
+    #[test]
+    fn quests_are_only_tracked_once_started() {
+        let mut world = world();
+        assert!(world.track(&[picked_up(world.coins[0])]).is_empty());
+        assert!(
+            !world
+                .game_state
+                .quest_components
+                .contains_key(&world.player)
+        );
+        assert_eq!(world.log(), "No quests yet. Talk to people to find some.");
+
+        assert!(QuestSystem::start(
+            &mut world.game_state,
+            world.player,
+            "collect_coins"
+        ));
+        assert!(!QuestSystem::start(
+            &mut world.game_state,
+            world.player,
+            "collect_coins"
+        ));
+        assert!(QuestSystem::has_started(
+            &world.game_state,
+            world.player,
+            "collect_coins"
+        ));
+        assert!(!QuestSystem::has_completed(
+            &world.game_state,
+            world.player,
+            "collect_coins"
+        ));
+        assert_eq!(world.progress(), &QuestProgress::default());
+    }
+
+    #[test]
+    fn stages_advance_once_all_objectives_are_reached_and_rewards_come_last() {
+        let mut world = world();
+        QuestSystem::start(&mut world.game_state, world.player, "collect_coins");
+
+        // Other items, and talking to Dennis before it is time, do not count
+        assert!(
+            world
+                .track(&[
+                    picked_up(world.sword),
+                    ActionEffect::TalkedTo { npc: world.dennis }
+                ])
+                .is_empty()
+        );
+        assert!(world.track(&[picked_up(world.coins[0])]).is_empty());
+        assert_eq!(world.progress().objective_counts, [1]);
+        assert_eq!(
+            world.log(),
+            "Coins\n  Dennis lost his coins\n  - Pick up coins (1/2)"
+        );
+
+        assert_eq!(
+            world.track(&[picked_up(world.coins[1]), picked_up(world.coins[2])]),
+            [r#"QuestStageCompleted { quest: "collect_coins" }"#]
+        );
+        assert_eq!(world.progress().stage, 1);
+        assert!(
+            world.progress().objective_counts.is_empty(),
+            "The third coin counted for the next stage"
+        );
+        let health = &world.game_state.health_components[&world.player];
+        assert_eq!((health.hitpoints, health.max_hitpoints), (50, 100));
+
+        assert_eq!(
+            world.track(&[ActionEffect::TalkedTo { npc: world.dennis }]),
+            [r#"QuestCompleted { quest: "collect_coins" }"#]
+        );
+        assert!(world.progress().is_completed);
+        assert!(QuestSystem::has_completed(
+            &world.game_state,
+            world.player,
+            "collect_coins"
+        ));
+        // Healed by 10 and then another 20 along with the maximum
+        let health = &world.game_state.health_components[&world.player];
+        assert_eq!((health.hitpoints, health.max_hitpoints), (80, 120));
+        assert!(world.game_state.has_flag(world.player, "coin_finder"));
+        assert_eq!(world.log(), "Coins (completed)");
+
+        // Nothing more to get out of it
+        assert!(
+            world
+                .track(&[ActionEffect::TalkedTo { npc: world.dennis }])
+                .is_empty()
+        );
+        assert_eq!(
+            world.game_state.health_components[&world.player].hitpoints,
+            80
+        );
+    }
+
+    #[test]
+    fn progress_is_kept_through_saving_and_loading() {
+        let mut world = world();
+        QuestSystem::start(&mut world.game_state, world.player, "collect_coins");
+        world.track(&[picked_up(world.coins[0])]);
+
+        world.game_state = SaveGame::deserialize(&SaveGame::serialize(&world.game_state))
+            .expect("Save should load");
+        QuestSystem::fit_progress(&mut world.game_state, &world.quests);
+        assert_eq!(world.progress().objective_counts, [1]);
+        assert_eq!(
+            world.track(&[picked_up(world.coins[1])]),
+            [r#"QuestStageCompleted { quest: "collect_coins" }"#]
+        );
+    }
+
+    #[test]
+    fn progress_past_the_stages_of_the_quest_is_fit_back_into_them() {
+        let mut world = world();
+        let progress = |stage, is_completed| QuestProgress {
+            stage,
+            objective_counts: vec![1, 1],
+            is_completed,
+        };
+        world.game_state.quest_components.insert(
+            world.player,
+            QuestLog {
+                quests: [
+                    ("collect_coins".to_owned(), progress(5, false)),
+                    ("removed_quest".to_owned(), progress(5, false)),
+                ]
+                .into(),
+            },
+        );
+
+        // Not followed and not shown until fit, but nothing breaks either
+        assert!(
+            world
+                .track(&[ActionEffect::TalkedTo { npc: world.dennis }])
+                .is_empty()
+        );
+        assert_eq!(world.log(), "");
+
+        QuestSystem::fit_progress(&mut world.game_state, &world.quests);
+        assert_eq!(
+            world.progress(),
+            &QuestProgress {
+                stage: 1,
+                ..QuestProgress::default()
+            }
+        );
+        assert_eq!(
+            world.game_state.quest_components[&world.player].quests["removed_quest"],
+            progress(5, false)
+        );
+        assert_eq!(
+            world.track(&[ActionEffect::TalkedTo { npc: world.dennis }]),
+            [r#"QuestCompleted { quest: "collect_coins" }"#]
+        );
+    }
+}
@@ -1,101 +1,145 @@
-use crate::state::components::{Entity, ItemShape, Storage};
+use crate::state::components::{ItemShape, Storage};
+use crate::state::entity::EntityId;
 use crate::state::game_state::GameState;
+use crate::systems::grid_container::{GridContainer, Placement};
 
 pub struct StorageManager {}
 
 impl StorageManager {
-    pub fn has_space(
-        game_state: &GameState,
-        storage: &Storage,
-        in_storage_entities: &Vec<Entity>,
-        near_pickup: &str,
-    ) -> bool {
-        Self::find_empty_spot(game_state, storage, in_storage_entities, near_pickup).is_some()
-    }
-
     pub fn find_empty_spot(
         game_state: &GameState,
         storage: &Storage,
-        in_storage_entities: &Vec<Entity>,
-        near_pickup: &str,
+        in_storage_entities: &[EntityId],
+        near_pickup: EntityId,
     ) -> Option<(u8, u8)> {
-        let dynamic_storage =
-            Self::generate_dynamic_storage_space(game_state, storage, in_storage_entities);
+        let container = Self::generate_container(game_state, storage, in_storage_entities);
         let item_shape = &game_state
             .storable_components
-            .get(near_pickup)
+            .get(&near_pickup)
             .unwrap()
             .shape;
-        let mut padded_storage = vec![vec![true; 12]; 12];
-        for x in 0..dynamic_storage.len() {
-            for y in 0..dynamic_storage.len() {
-                padded_storage[y][x] = dynamic_storage[y][x];
-            }
-        }
-
-        for row in 0..storage.number_of_rows {
-            for column in 0..storage.number_of_columns {
-                if Self::check_empty_spot(&padded_storage, row, column, item_shape) {
-                    return Some((column, row));
-                }
-            }
-        }
-        None
+        container.find_spot(item_shape, Placement::FirstFit)
     }
 
-    fn check_empty_spot(
-        padded_storage: &[Vec<bool>],
-        row: u8,
-        column: u8,
+    // The ignored items are not in the way, so an item can be moved to a spot overlapping where it is now
+    pub fn fits_at(
+        game_state: &GameState,
+        storage: &Storage,
+        in_storage_entities: &[EntityId],
+        ignored: &[EntityId],
         shape: &ItemShape,
+        spot: (u8, u8),
     ) -> bool {
-        for x in column..column + shape.width {
-            for y in row..row + shape.height {
-                if padded_storage[y as usize][x as usize] {
-                    return false;
-                }
-            }
+        let other_entities: Vec<EntityId> = in_storage_entities
+            .iter()
+            .filter(|entity| !ignored.contains(entity))
+            .copied()
+            .collect();
+        Self::generate_container(game_state, storage, &other_entities).fits(spot, shape)
+    }
+
+    // Packs the items into the top left, largest first, turning them where that helps. Nothing is moved when they would not all fit that way
+    pub fn sort(game_state: &mut GameState, storage_entity: EntityId) -> bool {
+        let Some(storage) = game_state.get_storage(storage_entity) else {
+            return false;
+        };
+        let items = Self::get_in_storage(game_state, storage_entity);
+        let shapes: Vec<ItemShape> = items
+            .iter()
+            .map(|item| game_state.storable_components[item].shape.clone())
+            .collect();
+        let Some(arrangement) =
+            GridContainer::arrange(storage.number_of_columns, storage.number_of_rows, &shapes)
+        else {
+            return false;
+        };
+        for (item, (spot, is_rotated)) in items.into_iter().zip(arrangement) {
+            game_state.create_in_storage_rotated(storage_entity, item, spot, is_rotated);
         }
         true
     }
 
-    fn generate_dynamic_storage_space(
+    // The items, besides the ignored ones, that take up any of the cells the shape would at that spot
+    pub fn get_in_the_way(
         game_state: &GameState,
-        storage: &Storage,
-        in_storage_entities: &Vec<Entity>,
-    ) -> Vec<Vec<bool>> {
-        let mut storage_spots =
-            vec![vec![false; storage.number_of_rows.into()]; storage.number_of_columns.into()];
+        in_storage_entities: &[EntityId],
+        ignored: &[EntityId],
+        shape: &ItemShape,
+        spot: (u8, u8),
+    ) -> Vec<EntityId> {
+        in_storage_entities
+            .iter()
+            .filter(|entity| !ignored.contains(entity))
+            .filter(|entity| {
+                let in_storage = game_state
+                    .in_storage_components
+                    .get(entity)
+                    .expect("Stored entity should be in storage");
+                Self::get_shape_in_storage(game_state, **entity).is_some_and(|entity_shape| {
+                    Self::overlaps(
+                        spot,
+                        shape,
+                        (in_storage.position_x, in_storage.position_y),
+                        &entity_shape,
+                    )
+                })
+            })
+            .copied()
+            .collect()
+    }
 
+    pub fn overlaps(
+        spot: (u8, u8),
+        shape: &ItemShape,
+        other_spot: (u8, u8),
+        other_shape: &ItemShape,
+    ) -> bool {
+        let (column, row) = (u16::from(spot.0), u16::from(spot.1));
+        let (other_column, other_row) = (u16::from(other_spot.0), u16::from(other_spot.1));
+        column < other_column + u16::from(other_shape.width)
+            && other_column < column + u16::from(shape.width)
+            && row < other_row + u16::from(other_shape.height)
+            && other_row < row + u16::from(shape.height)
+    }
+
+    // Turned when it lies sideways
+    pub fn get_shape_in_storage(game_state: &GameState, item: EntityId) -> Option<ItemShape> {
+        let storable = game_state.storable_components.get(&item)?;
+        let is_rotated = game_state
+            .in_storage_components
+            .get(&item)
+            .is_some_and(|in_storage| in_storage.is_rotated);
+        Some(storable.shape.rotated(is_rotated))
+    }
+
+    fn generate_container(
+        game_state: &GameState,
+        storage: &Storage,
+        in_storage_entities: &[EntityId],
+    ) -> GridContainer {
+        let mut container = GridContainer::new(storage.number_of_columns, storage.number_of_rows);
         for in_storage_entity in in_storage_entities {
             let in_storage = game_state
                 .in_storage_components
                 .get(in_storage_entity)
                 .unwrap();
-            let storable = game_state
-                .storable_components
-                .get(in_storage_entity)
-                .unwrap();
-            for x in in_storage.position_x..in_storage.position_x + storable.shape.width {
-                for y in in_storage.position_y..in_storage.position_y + storable.shape.height {
-                    storage_spots[y as usize][x as usize] = true;
-                }
-            }
+            let shape = Self::get_shape_in_storage(game_state, *in_storage_entity).unwrap();
+            container.occupy((in_storage.position_x, in_storage.position_y), &shape);
         }
-        storage_spots
+        container
     }
 
-    pub fn get_in_storage(game_state: &GameState, entity: &str) -> Vec<Entity> {
+    pub fn get_in_storage(game_state: &GameState, entity: EntityId) -> Vec<EntityId> {
         game_state
             .entities
             .iter()
             .filter(|e| {
                 game_state
                     .in_storage_components
-                    .get(e.as_str())
+                    .get(e)
                     .is_some_and(|comp| comp.storage_entity == entity)
             })
-            .cloned()
+            .copied()
             .collect()
     }
 }
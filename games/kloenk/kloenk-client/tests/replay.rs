@@ -0,0 +1,71 @@
+// Replays a recorded session and checks the game ends up in the same state. When the world or game logic changes on purpose, record it again:
+// cargo run --no-default-features --features headless --bin kloenk-headless -- scripts/pickup_shield.json --record tests/fixtures/pickup_shield.recording.json
+use kloenk::headless::{DEFAULT_VIEWPORT, HeadlessGame};
+use kloenk::state::recording::InputRecording;
+use kloenk::state::world_definition::WorldDefinition;
+use kloenk::systems::dialogue_manager::DialogueManager;
+use kloenk::systems::quest_manager::QuestManager;
+
+const RECORDING: &[u8] = include_bytes!("fixtures/pickup_shield.recording.json");
+
+fn new_game() -> HeadlessGame {
+    let world = WorldDefinition::from_json(include_bytes!("../assets/world.json"))
+        .expect("World should load");
+    let mut game = HeadlessGame::new(&world, DEFAULT_VIEWPORT);
+    game.ui_state.dialogues =
+        DialogueManager::from_json(include_bytes!("../assets/dialogues.json"))
+            .expect("Dialogues should load");
+    game.ui_state.quests = QuestManager::from_json(include_bytes!("../assets/quests.json"))
+        .expect("Quests should load");
+    game
+}
+
+fn recording() -> InputRecording {
+    InputRecording::from_json(RECORDING).expect("Recording should load")
+}
+
+#[test]
+fn replay_ends_in_the_recorded_state() {
+    let recording = recording();
+    let ticks = recording.ticks.len();
+    let recorded_hash = recording
+        .ticks
+        .last()
+        .expect("Recording should have ticks")
+        .state_hash;
+    let mut game = new_game();
+
+    if let Err(divergence) = game.replay(recording) {
+        panic!("{divergence}");
+    }
+
+    assert_eq!(game.time.tick(), ticks as u64);
+    assert_eq!(game.game_state.state_hash(), recorded_hash);
+    let player = game
+        .game_state
+        .get_entity("player")
+        .expect("Player should exist");
+    let shield = game
+        .game_state
+        .get_entity("shield")
+        .expect("Shield should exist");
+    assert!(
+        game.game_state
+            .get_in_storages(player)
+            .contains_key(&shield)
+    );
+}
+
+#[test]
+fn replay_reports_the_first_tick_that_differs() {
+    let mut recording = recording();
+    let tampered_tick = recording.ticks.len() / 2;
+    recording.ticks[tampered_tick].state_hash ^= 1;
+
+    let divergence = new_game()
+        .replay(recording)
+        .expect_err("Tampered recording should diverge");
+
+    assert_eq!(divergence.tick, tampered_tick);
+    assert_eq!(divergence.expected_hash ^ 1, divergence.actual_hash);
+}
@@ -0,0 +1,204 @@
+use kloenk::net::protocol::MAX_CHAT_MESSAGE_LENGTH;
+
+// Whole words only, so names and words that happen to contain one are left alone
+const BLOCKED_WORDS: &[&str] = &[
+    "arse",
+    "asshole",
+    "bastard",
+    "bitch",
+    "bullshit",
+    "crap",
+    "cunt",
+    "dick",
+    "fuck",
+    "fucked",
+    "fucker",
+    "fucking",
+    "klootzak",
+    "kut",
+    "lul",
+    "motherfucker",
+    "piss",
+    "shit",
+    "shitty",
+    "slut",
+    "tering",
+    "twat",
+    "wanker",
+    "whore",
+];
+const CHAT_BURST: f32 = 5.0; // Messages a player can send one right after the other
+const CHAT_MESSAGES_PER_SECOND: f32 = 0.5; // And after those, how fast they can keep going
+
+#[derive(Debug, PartialEq)]
+pub enum ChatRejection {
+    Empty,
+    TooLong,
+    TooFast,
+}
+
+impl ChatRejection {
+    // Told to the sender, nobody else gets to see the message
+    pub fn notice(&self) -> Option<String> {
+        match self {
+            ChatRejection::Empty => None,
+            ChatRejection::TooLong => Some(format!(
+                "Message not sent, it is longer than {MAX_CHAT_MESSAGE_LENGTH} characters"
+            )),
+            ChatRejection::TooFast => Some(
+                "Message not sent, you are sending messages too fast. Wait a moment".to_owned(),
+            ),
+        }
+    }
+}
+
+// A token bucket per player, so nobody floods the chat. Time is counted in server ticks
+pub struct ChatRateLimit {
+    tokens: f32,
+    last_tick: u64,
+}
+
+impl ChatRateLimit {
+    pub fn new(tick: u64) -> Self {
+        ChatRateLimit {
+            tokens: CHAT_BURST,
+            last_tick: tick,
+        }
+    }
+
+    // Only messages that are sent use up a token
+    pub fn take(&mut self, tick: u64, ticks_per_second: u32) -> Result<(), ChatRejection> {
+        let seconds = tick.saturating_sub(self.last_tick) as f32 / ticks_per_second as f32;
+        self.tokens = (self.tokens + seconds * CHAT_MESSAGES_PER_SECOND).min(CHAT_BURST);
+        self.last_tick = tick;
+        if self.tokens < 1.0 {
+            return Err(ChatRejection::TooFast);
+        }
+        self.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+pub struct ChatFilter {}
+
+impl ChatFilter {
+    // Clients are not trusted to do any of this themselves
+    pub fn filter(text: &str) -> Result<String, ChatRejection> {
+        let text: String = text
+            .trim()
+            .chars()
+            .filter(|character| !character.is_control())
+            .collect();
+        if text.is_empty() {
+            return Err(ChatRejection::Empty);
+        }
+        if text.chars().count() > MAX_CHAT_MESSAGE_LENGTH {
+            return Err(ChatRejection::TooLong);
+        }
+        Ok(Self::censor(&text))
+    }
+
+    fn censor(text: &str) -> String {
+        let mut censored = String::with_capacity(text.len());
+        let mut word = String::new();
+        for character in text.chars() {
+            if character.is_alphanumeric() {
+                word.push(character);
+                continue;
+            }
+            Self::push_word(&mut censored, &word);
+            word.clear();
+            censored.push(character);
+        }
+        Self::push_word(&mut censored, &word);
+        censored
+    }
+
+    fn push_word(censored: &mut String, word: &str) {
+        if BLOCKED_WORDS.contains(&word.to_lowercase().as_str()) {
+            censored.extend(word.chars().map(|_| '*'));
+        } else {
+            censored.push_str(word);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocked_words_are_starred_whatever_their_case() {
+        assert_eq!(
+            ChatFilter::filter("What the Fuck, SHIT!"),
+            Ok("What the ****, ****!".to_owned())
+        );
+    }
+
+    #[test]
+    fn words_containing_blocked_words_are_left_alone() {
+        let text = "Scunthorpe has a classic shitake dickens";
+        assert_eq!(ChatFilter::filter(text), Ok(text.to_owned()));
+    }
+
+    #[test]
+    fn whitespace_around_and_control_characters_are_dropped() {
+        assert_eq!(
+            ChatFilter::filter("  hi\u{7}\u{1b}[2J there\n "),
+            Ok("hi[2J there".to_owned())
+        );
+    }
+
+    #[test]
+    fn empty_messages_are_rejected_without_a_notice() {
+        for text in ["", "   ", "\u{7}\n\t"] {
+            assert_eq!(ChatFilter::filter(text), Err(ChatRejection::Empty));
+        }
+        assert_eq!(ChatRejection::Empty.notice(), None);
+    }
+
+    #[test]
+    fn length_is_counted_in_characters() {
+        let longest = "é".repeat(MAX_CHAT_MESSAGE_LENGTH);
+        assert_eq!(ChatFilter::filter(&longest), Ok(longest.clone()));
+        assert_eq!(
+            ChatFilter::filter(&format!("{longest}é")),
+            Err(ChatRejection::TooLong)
+        );
+        assert!(ChatRejection::TooLong.notice().is_some());
+    }
+
+    #[test]
+    fn chat_is_limited_to_a_burst_and_then_a_steady_rate() {
+        let ticks_per_second = 60;
+        let mut rate_limit = ChatRateLimit::new(100);
+        for _ in 0..CHAT_BURST as u32 {
+            assert_eq!(rate_limit.take(100, ticks_per_second), Ok(()));
+        }
+        assert_eq!(
+            rate_limit.take(101, ticks_per_second),
+            Err(ChatRejection::TooFast)
+        );
+        assert!(ChatRejection::TooFast.notice().is_some());
+
+        let ticks_per_message = (ticks_per_second as f32 / CHAT_MESSAGES_PER_SECOND) as u64;
+        assert_eq!(
+            rate_limit.take(100 + ticks_per_message / 2, ticks_per_second),
+            Err(ChatRejection::TooFast)
+        );
+        assert_eq!(
+            rate_limit.take(100 + ticks_per_message + 1, ticks_per_second),
+            Ok(())
+        );
+
+        // Being quiet for a long time does not save up more than a burst
+        let much_later = 100 + 100 * ticks_per_message;
+        for _ in 0..CHAT_BURST as u32 {
+            assert_eq!(rate_limit.take(much_later, ticks_per_second), Ok(()));
+        }
+        assert_eq!(
+            rate_limit.take(much_later, ticks_per_second),
+            Err(ChatRejection::TooFast)
+        );
+    }
+}
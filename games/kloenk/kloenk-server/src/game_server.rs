@@ -0,0 +1,1008 @@
+use crate::chat_filter::{ChatFilter, ChatRateLimit};
+use crate::interest::InterestManager;
+use crate::network::{ConnectionId, NetworkEvent, NetworkServer};
+use cgmath::{Point2, Point3};
+use kloenk::net::protocol::{ClientMessage, PROTOCOL_VERSION, PlayerAction, ServerMessage};
+use kloenk::net::snapshot::SnapshotEncoder;
+use kloenk::state::audio::SilentAudio;
+use kloenk::state::chat::{ChatChannel, ChatMessage, ChatSource};
+use kloenk::state::entity::EntityId;
+use kloenk::state::game_state::GameState;
+use kloenk::state::time::{DEFAULT_TICKS_PER_SECOND, Time};
+use kloenk::state::update_state::{ActionEffect, EntityCommand};
+use kloenk::state::world_definition::{EntityDefinition, WorldDefinition};
+use kloenk::systems::command_handle_system::CommandHandleSystem;
+use kloenk::systems::dialogue_effect_system::DialogueEffectSystem;
+use kloenk::systems::dialogue_manager::DialogueManager;
+use kloenk::systems::dialogue_system::DIALOGUE_RANGE;
+use kloenk::systems::item_placement_system::{ITEM_PLACE_HEIGHT, ItemPlacementSystem};
+use kloenk::systems::item_transaction_system::{ItemTransaction, ItemTransactionSystem};
+use kloenk::systems::movement_system::{MovementInput, MovementSystem};
+use kloenk::systems::position_manager::PositionManager;
+use kloenk::systems::quest_manager::QuestManager;
+use kloenk::systems::quest_system::QuestSystem;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::time::Duration;
+
+const PLAYER_TEMPLATE_ID: &str = "player";
+const MAX_PLAYER_NAME_LENGTH: usize = 20;
+const SPAWN_SEARCH_RINGS: u32 = 6;
+const DROP_SEARCH_RINGS: u32 = 5; // Stays within ITEM_PLACE_RANGE
+const SEARCH_STEP: f32 = 0.5;
+const MAX_QUEUED_MOVEMENT_INPUTS: usize = 30; // Half a second. A client further ahead loses its oldest inputs, and gets corrected
+const MAX_ITEM_TRANSACTIONS_PER_INPUT: usize = 4; // A player clicks a few items in a tick at most
+const TRAFFIC_LOG_INTERVAL_SECONDS: u64 = 60;
+const LOCAL_CHAT_RANGE: f32 = 15.0;
+
+struct PlayerInput {
+    sequence: u32,
+    movement: MovementInput,
+    item_transactions: Vec<ItemTransaction>,
+}
+
+// Where a player is in a conversation. Only choices of that node are taken, so sending the same choice again does not repeat its effects
+struct Conversation {
+    npc: EntityId,
+    dialogue_id: String,
+    node_id: String,
+}
+
+struct Player {
+    name: String,
+    entity: EntityId,
+    inputs: VecDeque<PlayerInput>, // One is applied every tick
+    last_received_input: Option<u32>,
+    last_processed_input: Option<u32>,
+    known_entities: HashSet<EntityId>, // What the client was told about, and not told is gone since
+    snapshots: SnapshotEncoder,
+    joined_at_tick: u64,
+    chat_rate_limit: ChatRateLimit,
+    conversation: Option<Conversation>, // Since the player last talked to someone
+}
+
+// Owns the one true game state. Clients send what they want to do, the server decides what happens and tells everyone
+pub struct GameServer {
+    time: Time,
+    game_state: GameState,
+    dialogues: DialogueManager, // To check and apply the choices players make
+    quests: QuestManager,       // To follow the progress players make
+    player_template: EntityDefinition,
+    waiting_for_hello: HashSet<ConnectionId>,
+    players: BTreeMap<ConnectionId, Player>, // Ordered, so players are always updated in the order they joined
+    next_player_number: u64,
+    entity_commands: Vec<EntityCommand>, // Applied at the end of the tick, like on the client
+}
+
+impl GameServer {
+    // The player in the world definition is used as template for every player that joins, it is not spawned itself
+    pub fn new(world: &WorldDefinition, dialogues: DialogueManager, quests: QuestManager) -> Self {
+        let player_template = world
+            .entities
+            .iter()
+            .find(|entity| entity.id.as_deref() == Some(PLAYER_TEMPLATE_ID))
+            .expect("World should define a player")
+            .clone();
+        let world_without_player = WorldDefinition {
+            entities: world
+                .entities
+                .iter()
+                .filter(|entity| entity.id.as_deref() != Some(PLAYER_TEMPLATE_ID))
+                .cloned()
+                .collect(),
+        };
+
+        GameServer {
+            time: Time::new(DEFAULT_TICKS_PER_SECOND),
+            game_state: GameState::new(&world_without_player),
+            dialogues,
+            quests,
+            player_template,
+            waiting_for_hello: HashSet::new(),
+            players: BTreeMap::new(),
+            next_player_number: 0,
+            entity_commands: Vec::new(),
+        }
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        self.time.delta()
+    }
+
+    pub fn handle_event(&mut self, network: &mut NetworkServer, event: NetworkEvent) {
+        match event {
+            NetworkEvent::Connected(connection) => {
+                self.waiting_for_hello.insert(connection);
+            }
+            NetworkEvent::Message(
+                connection,
+                ClientMessage::Hello {
+                    protocol_version,
+                    name,
+                },
+            ) => {
+                if !self.waiting_for_hello.remove(&connection) {
+                    return; // Already playing
+                }
+                if protocol_version != PROTOCOL_VERSION {
+                    network.send(connection, &ServerMessage::Rejected {
+                        reason: format!(
+                            "Server runs protocol version {PROTOCOL_VERSION}, please update your game"
+                        ),
+                    });
+                    network.disconnect(connection);
+                    return;
+                }
+                self.join(network, connection, &name);
+            }
+            NetworkEvent::Message(connection, ClientMessage::Action(action)) => {
+                self.handle_action(network, connection, action);
+            }
+            NetworkEvent::Message(connection, ClientMessage::Ack { tick }) => {
+                if let Some(player) = self.players.get_mut(&connection) {
+                    player.snapshots.acknowledge(tick);
+                }
+            }
+            NetworkEvent::Disconnected(connection) => {
+                self.waiting_for_hello.remove(&connection);
+                self.leave(connection);
+            }
+        }
+    }
+
+    fn join(&mut self, network: &mut NetworkServer, connection: ConnectionId, name: &str) {
+        self.next_player_number += 1;
+        let name = self.player_name(name);
+
+        let mut definition = self.player_template.clone();
+        definition.id = Some(format!("player_{}", self.next_player_number));
+        let entity = self.game_state.load_entity(&definition);
+        self.move_to_free_spot(entity);
+        log::info!("{name} joined on connection {connection}");
+
+        let known_entities =
+            InterestManager::entities_in_view(&self.game_state, entity, &HashSet::new());
+        let mut snapshots = SnapshotEncoder::new();
+        let entities = snapshots.start(self.time.tick(), &self.game_state, &known_entities);
+        network.send(
+            connection,
+            &ServerMessage::Welcome {
+                player: entity,
+                tick: self.time.tick(),
+                ticks_per_second: self.time.ticks_per_second(),
+                entities,
+            },
+        );
+        let notice = format!("You are playing as {name}");
+        Self::send_chat(network, connection, ChatMessage::notice(&notice));
+        self.players.insert(
+            connection,
+            Player {
+                name,
+                entity,
+                inputs: VecDeque::new(),
+                last_received_input: None,
+                last_processed_input: None,
+                known_entities,
+                snapshots,
+                joined_at_tick: self.time.tick(),
+                chat_rate_limit: ChatRateLimit::new(self.time.tick()),
+                conversation: None,
+            },
+        );
+    }
+
+    // Whispers are addressed by name, so a name is a single word and no two players have the same one, ignoring case
+    // Taken names get a number added
+    fn player_name(&self, requested: &str) -> String {
+        let name = match requested
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join("_")
+        {
+            name if name.is_empty() => format!("Player{}", self.next_player_number),
+            name => name,
+        };
+        let is_taken = |candidate: &str| {
+            self.players
+                .values()
+                .any(|player| player.name.eq_ignore_ascii_case(candidate))
+        };
+        (1..)
+            .map(|number: u32| {
+                let suffix = if number == 1 {
+                    String::new()
+                } else {
+                    number.to_string()
+                };
+                let length = MAX_PLAYER_NAME_LENGTH - suffix.len();
+                name.chars().take(length).chain(suffix.chars()).collect()
+            })
+            .find(|candidate: &String| !is_taken(candidate))
+            .expect("Some number should be free")
+    }
+
+    // Players spawn at the same spot, but cannot walk out of each other. Look around for the nearest place that is free
+    fn move_to_free_spot(&mut self, entity: EntityId) {
+        let spawn_position = *self
+            .game_state
+            .get_position(entity)
+            .expect("Player template should have a position");
+        let free_spot = Self::spots_around(spawn_position, SPAWN_SEARCH_RINGS)
+            .find(|candidate| MovementSystem::can_move_to(&self.game_state, entity, candidate));
+        match free_spot {
+            Some(free_spot) => self.game_state.create_position(entity, free_spot),
+            None => log::warn!("No free spot found to spawn player, they might be stuck"),
+        }
+    }
+
+    // The center first, then rings of 8 spots further and further away
+    fn spots_around(center: Point3<f32>, rings: u32) -> impl Iterator<Item = Point3<f32>> {
+        (0..rings).flat_map(move |ring| {
+            let distance = ring as f32 * SEARCH_STEP;
+            let directions = if ring == 0 { 1 } else { 8 };
+            (0..directions).map(move |direction| {
+                let angle = direction as f32 * std::f32::consts::FRAC_PI_4;
+                Point3::new(
+                    center.x + distance * angle.cos(),
+                    center.y,
+                    center.z + distance * angle.sin(),
+                )
+            })
+        })
+    }
+
+    // TODO keep the player around, so they can continue their quests when they come back
+    fn leave(&mut self, connection: ConnectionId) {
+        if let Some(player) = self.players.remove(&connection) {
+            log::info!("{} left", player.name);
+            self.drop_inventory(player.entity);
+            self.game_state.despawn(player.entity);
+        }
+    }
+
+    // Despawning the player would take their items with them. They are put down around where the player stood instead, for anyone to pick up
+    fn drop_inventory(&mut self, player: EntityId) {
+        let Some(center) = self.game_state.get_position(player).copied() else {
+            return;
+        };
+        let mut items: Vec<EntityId> = self
+            .game_state
+            .get_in_storages(player)
+            .into_keys()
+            .collect();
+        items.sort();
+        for item in items {
+            let is_placed = Self::spots_around(center, DROP_SEARCH_RINGS).any(|spot| {
+                ItemPlacementSystem::place_item_at(
+                    &mut self.game_state,
+                    player,
+                    item,
+                    Point2::new(spot.x, spot.z),
+                )
+                .is_ok()
+            });
+            if !is_placed {
+                // Where the player stood is free once they are gone. Better on top of another item than lost
+                self.game_state.remove_in_storage(item);
+                self.game_state.create_position(
+                    item,
+                    Point3::new(center.x, center.y + ITEM_PLACE_HEIGHT, center.z),
+                );
+            }
+        }
+    }
+
+    fn handle_action(
+        &mut self,
+        network: &mut NetworkServer,
+        connection: ConnectionId,
+        action: PlayerAction,
+    ) {
+        let Some(player) = self.players.get_mut(&connection) else {
+            return; // Has to say hello first
+        };
+        let entity = player.entity;
+
+        let mut action_effects = Vec::new();
+        match action {
+            PlayerAction::Move {
+                sequence,
+                movement,
+                mut item_transactions,
+            } => {
+                if player
+                    .last_received_input
+                    .is_some_and(|last_received_input| sequence <= last_received_input)
+                {
+                    return;
+                }
+                player.last_received_input = Some(sequence);
+                item_transactions.truncate(MAX_ITEM_TRANSACTIONS_PER_INPUT);
+                player.inputs.push_back(PlayerInput {
+                    sequence,
+                    movement: Self::validate_movement(movement),
+                    item_transactions,
+                });
+                if player.inputs.len() > MAX_QUEUED_MOVEMENT_INPUTS {
+                    player.inputs.pop_front();
+                }
+            }
+            PlayerAction::Examine { entity: examined } => {
+                if let Some(description) = self.game_state.description_components.get(&examined) {
+                    action_effects.push(ActionEffect::Examine {
+                        text: description.text.clone(),
+                    });
+                }
+            }
+            PlayerAction::Talk { npc } => {
+                if let Some(dialogue) = self.game_state.dialogue_components.get(&npc)
+                    && self.is_in_range(entity, npc, DIALOGUE_RANGE)
+                {
+                    let dialogue_id = dialogue.dialogue_id.clone();
+                    network.send(
+                        connection,
+                        &ServerMessage::DialogueStarted {
+                            npc,
+                            dialogue_id: dialogue_id.clone(),
+                        },
+                    );
+                    let conversation =
+                        self.dialogues
+                            .get_dialogue(&dialogue_id)
+                            .map(|dialogue| Conversation {
+                                npc,
+                                node_id: dialogue.start.clone(),
+                                dialogue_id,
+                            });
+                    if let Some(player) = self.players.get_mut(&connection) {
+                        player.conversation = conversation;
+                    }
+                    action_effects.push(ActionEffect::TalkedTo { npc });
+                }
+            }
+            PlayerAction::DialogueChoice {
+                npc,
+                node_id,
+                choice,
+            } => {
+                action_effects = self.dialogue_choice(connection, npc, &node_id, choice);
+            }
+            PlayerAction::Chat { channel, text } => {
+                match ChatFilter::filter(&text).and_then(|text| {
+                    player
+                        .chat_rate_limit
+                        .take(self.time.tick(), self.time.ticks_per_second())
+                        .map(|()| text)
+                }) {
+                    Ok(text) => {
+                        let from = player.name.clone();
+                        self.chat(network, connection, &from, channel, text);
+                    }
+                    Err(rejection) => {
+                        if let Some(notice) = rejection.notice() {
+                            Self::send_chat(network, connection, ChatMessage::notice(&notice));
+                        }
+                    }
+                }
+            }
+        }
+
+        let quest_effects =
+            QuestSystem::track(&mut self.game_state, &self.quests, entity, &action_effects);
+        action_effects.extend(quest_effects);
+        if !action_effects.is_empty() {
+            network.send(
+                connection,
+                &ServerMessage::ActionEffects {
+                    effects: action_effects,
+                },
+            );
+        }
+    }
+
+    // Only a choice of the node the player is at counts. The player goes on to the next node like the client does,
+    // also when the choice is not available anymore and its effects are not applied
+    fn dialogue_choice(
+        &mut self,
+        connection: ConnectionId,
+        npc: EntityId,
+        node_id: &str,
+        choice: usize,
+    ) -> Vec<ActionEffect> {
+        let player = &self.players[&connection];
+        let entity = player.entity;
+        let Some(conversation) = player
+            .conversation
+            .as_ref()
+            .filter(|conversation| conversation.npc == npc && conversation.node_id == node_id)
+        else {
+            return vec![ActionEffect::DialogueChoiceUnavailable];
+        };
+        let Some(choice) = self
+            .dialogues
+            .get_choice(&conversation.dialogue_id, node_id, choice)
+        else {
+            return Vec::new();
+        };
+        if let Some(conversation) = self
+            .players
+            .get_mut(&connection)
+            .and_then(|player| player.conversation.as_mut())
+        {
+            conversation.node_id = choice.next.clone();
+        }
+        if !self.is_in_range(entity, npc, DIALOGUE_RANGE)
+            || !DialogueEffectSystem::conditions_hold(&self.game_state, entity, &choice.conditions)
+        {
+            return vec![ActionEffect::DialogueChoiceUnavailable];
+        }
+
+        choice
+            .effects
+            .iter()
+            .filter_map(|effect| {
+                DialogueEffectSystem::apply(
+                    &mut self.game_state,
+                    entity,
+                    npc,
+                    effect,
+                    &mut self.entity_commands,
+                )
+            })
+            .collect()
+    }
+
+    // Whispers are sent back to who sent them, so they show up in their history like every other message
+    fn chat(
+        &self,
+        network: &mut NetworkServer,
+        connection: ConnectionId,
+        from: &str,
+        channel: ChatChannel,
+        text: String,
+    ) {
+        log::debug!("{from} in {channel:?}: {text}");
+        match channel {
+            ChatChannel::Local => {
+                let sender = self.players[&connection].entity;
+                let nearby_connections: Vec<ConnectionId> = self
+                    .players
+                    .iter()
+                    .filter(|(_, player)| self.is_in_range(sender, player.entity, LOCAL_CHAT_RANGE))
+                    .map(|(connection, _)| *connection)
+                    .collect();
+                let message = ChatMessage {
+                    source: ChatSource::Local {
+                        from: from.to_owned(),
+                    },
+                    text,
+                };
+                network.broadcast(&nearby_connections, &ServerMessage::Chat { message });
+            }
+            ChatChannel::Global => {
+                let message = ChatMessage {
+                    source: ChatSource::Global {
+                        from: from.to_owned(),
+                    },
+                    text,
+                };
+                network.broadcast(&self.connections(), &ServerMessage::Chat { message });
+            }
+            ChatChannel::Whisper { player } => {
+                let Some((recipient_connection, recipient)) = self
+                    .players
+                    .iter()
+                    .find(|(_, recipient)| recipient.name.eq_ignore_ascii_case(&player))
+                else {
+                    let notice = format!("Nobody called {player} is playing right now");
+                    Self::send_chat(network, connection, ChatMessage::notice(&notice));
+                    return;
+                };
+                let whisper_from = ChatMessage {
+                    source: ChatSource::WhisperFrom {
+                        from: from.to_owned(),
+                    },
+                    text: text.clone(),
+                };
+                Self::send_chat(network, *recipient_connection, whisper_from);
+                let whisper_to = ChatMessage {
+                    source: ChatSource::WhisperTo {
+                        to: recipient.name.clone(),
+                    },
+                    text,
+                };
+                Self::send_chat(network, connection, whisper_to);
+            }
+        }
+    }
+
+    fn send_chat(network: &mut NetworkServer, connection: ConnectionId, message: ChatMessage) {
+        network.send(connection, &ServerMessage::Chat { message });
+    }
+
+    // The client picks the direction, but the server decides how fast it moves
+    fn validate_movement(movement: MovementInput) -> MovementInput {
+        MovementInput {
+            direction: movement.direction.filter(|direction| direction.is_finite()),
+            is_running: movement.is_running,
+            max_distance: movement
+                .max_distance
+                .filter(|max_distance| max_distance.is_finite() && *max_distance >= 0.0),
+        }
+    }
+
+    fn is_in_range(&self, entity: EntityId, other: EntityId, range: f32) -> bool {
+        match (
+            self.game_state.get_position(entity),
+            self.game_state.get_position(other),
+        ) {
+            (Some(position), Some(other_position)) => {
+                PositionManager::in_range(position, other_position, range)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn tick(&mut self, network: &mut NetworkServer) {
+        for (connection, player) in &mut self.players {
+            // Without input the player stands still, their input might just be late
+            let Some(input) = player.inputs.pop_front() else {
+                continue;
+            };
+            MovementSystem::move_character(
+                &self.time,
+                &mut self.game_state,
+                player.entity,
+                &input.movement,
+                &mut SilentAudio {},
+            );
+            // After moving, so items picked up on arriving at them are in range like they were for the player
+            let mut action_effects: Vec<ActionEffect> = input
+                .item_transactions
+                .iter()
+                .filter_map(|transaction| {
+                    let result = ItemTransactionSystem::execute(
+                        &mut self.game_state,
+                        player.entity,
+                        transaction,
+                    );
+                    ItemTransactionSystem::action_effect(transaction, result)
+                })
+                .collect();
+            let quest_effects = QuestSystem::track(
+                &mut self.game_state,
+                &self.quests,
+                player.entity,
+                &action_effects,
+            );
+            action_effects.extend(quest_effects);
+            if !action_effects.is_empty() {
+                network.send(
+                    *connection,
+                    &ServerMessage::ActionEffects {
+                        effects: action_effects,
+                    },
+                );
+            }
+            player.last_processed_input = Some(input.sequence);
+        }
+        // Before the snapshots, so players hear about them this tick
+        CommandHandleSystem::apply_entity_commands(
+            &mut self.game_state,
+            std::mem::take(&mut self.entity_commands),
+        );
+        self.time.advance();
+
+        let tick = self.time.tick();
+        for (connection, player) in &mut self.players {
+            let snapshot = Self::snapshot(&self.game_state, tick, player);
+            network.send(*connection, &snapshot);
+        }
+        let traffic_log_interval_ticks =
+            TRAFFIC_LOG_INTERVAL_SECONDS * u64::from(self.time.ticks_per_second());
+        if tick.is_multiple_of(traffic_log_interval_ticks) {
+            self.log_traffic(network);
+        }
+    }
+
+    // Everything in view of the player, as changes to the last snapshot they acknowledged
+    fn snapshot(game_state: &GameState, tick: u64, player: &mut Player) -> ServerMessage {
+        let in_view =
+            InterestManager::entities_in_view(game_state, player.entity, &player.known_entities);
+        let delta = player.snapshots.encode(tick, game_state, &in_view);
+        player.known_entities = in_view;
+        ServerMessage::Snapshot {
+            tick,
+            last_processed_input: player.last_processed_input,
+            delta,
+        }
+    }
+
+    fn log_traffic(&self, network: &NetworkServer) {
+        for (connection, player) in &self.players {
+            let Some(traffic) = network.traffic(*connection) else {
+                continue;
+            };
+            let ticks_played = (self.time.tick() - player.joined_at_tick).max(1);
+            let seconds_played = ticks_played as f64 / f64::from(self.time.ticks_per_second());
+            log::info!(
+                "{} receives {:.0} bytes per second, knows about {} entities",
+                player.name,
+                traffic.bytes_sent as f64 / seconds_played,
+                player.known_entities.len()
+            );
+        }
+    }
+
+    fn connections(&self) -> Vec<ConnectionId> {
+        self.players.keys().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat_filter::ChatRejection;
+    use cgmath::MetricSpace;
+    use kloenk::headless::{DEFAULT_VIEWPORT, HeadlessGame};
+    use kloenk::net::WebSocketTransport;
+    use kloenk::net::client::NetworkClient;
+    use kloenk::net::connection::{Connection, ConnectionState};
+    use kloenk::state::update_state::UpdateState;
+    use kloenk::systems::camera_system::CameraSystem;
+    use std::thread;
+
+    const MAX_BYTES_PER_TICK: u64 = 64; // Under 4 kB per second
+    const MEASURED_TICKS: u64 = 120;
+
+    fn world() -> WorldDefinition {
+        WorldDefinition::from_json(include_bytes!("../../kloenk-client/assets/world.json"))
+            .expect("World should load")
+    }
+
+    fn start() -> (GameServer, NetworkServer) {
+        let dialogues =
+            DialogueManager::from_json(include_bytes!("../../kloenk-client/assets/dialogues.json"))
+                .expect("Dialogues should load");
+        let quests =
+            QuestManager::from_json(include_bytes!("../../kloenk-client/assets/quests.json"))
+                .expect("Quests should load");
+        let network = NetworkServer::bind("127.0.0.1:0").expect("Server should start");
+        (GameServer::new(&world(), dialogues, quests), network)
+    }
+
+    fn connect(network: &NetworkServer, name: &str) -> HeadlessGame {
+        let mut client = HeadlessGame::new(&world(), DEFAULT_VIEWPORT);
+        client.network_client = Some(NetworkClient::new(Connection::new(
+            Box::new(WebSocketTransport::new(
+                &network.local_address().to_string(),
+            )),
+            name,
+        )));
+        client
+    }
+
+    fn step(server: &mut GameServer, network: &mut NetworkServer, clients: &mut [HeadlessGame]) {
+        for event in network.poll() {
+            server.handle_event(network, event);
+        }
+        server.tick(network);
+        network.flush();
+        for client in clients {
+            client.tick();
+        }
+        thread::sleep(Duration::from_millis(1)); // Gives the sockets a moment to deliver
+    }
+
+    fn is_connected(client: &HeadlessGame) -> bool {
+        client
+            .network_client
+            .as_ref()
+            .is_some_and(|network_client| {
+                network_client.connection_state() == &ConnectionState::Connected
+            })
+    }
+
+    #[test]
+    fn players_only_hear_about_what_is_near_within_a_bandwidth_budget() {
+        let (mut server, mut network) = start();
+        let distances = [0.0, 3.0, 30.0, 60.0];
+        let mut clients: Vec<HeadlessGame> = (0..distances.len())
+            .map(|number| connect(&network, &format!("Player {number}")))
+            .collect();
+        for _ in 0..1000 {
+            if server.players.len() == distances.len() && clients.iter().all(is_connected) {
+                break;
+            }
+            step(&mut server, &mut network, &mut clients);
+        }
+        assert!(clients.iter().all(is_connected), "Not everyone joined");
+
+        // In the order they joined, which is not necessarily the order of the clients
+        let players: Vec<(ConnectionId, EntityId)> = server
+            .players
+            .iter()
+            .map(|(connection, player)| (*connection, player.entity))
+            .collect();
+        for ((_, entity), distance) in players.iter().zip(distances) {
+            server
+                .game_state
+                .create_position(*entity, Point3::new(distance, 0.5, 0.0));
+        }
+        for _ in 0..60 {
+            step(&mut server, &mut network, &mut clients);
+        }
+
+        // Everyone keeps moving, so every snapshot has something in it
+        let bytes_before: Vec<u64> = players
+            .iter()
+            .map(|(connection, _)| network.traffic(*connection).unwrap().bytes_sent)
+            .collect();
+        for _ in 0..MEASURED_TICKS {
+            for (_, entity) in &players {
+                let mut position = *server.game_state.get_position(*entity).unwrap();
+                position.z += 0.01;
+                server.game_state.create_position(*entity, position);
+            }
+            step(&mut server, &mut network, &mut clients);
+        }
+        for ((connection, _), bytes_before) in players.iter().zip(bytes_before) {
+            let bytes_per_tick =
+                (network.traffic(*connection).unwrap().bytes_sent - bytes_before) / MEASURED_TICKS;
+            assert!(
+                bytes_per_tick <= MAX_BYTES_PER_TICK,
+                "{bytes_per_tick} bytes per tick"
+            );
+        }
+
+        let far = CameraSystem::max_view_distance() * 2.0;
+        for client in &clients {
+            let own_entity = client.game_state.get_entity("player").unwrap();
+            let own_position = *client.game_state.get_position(own_entity).unwrap();
+            for (_, entity) in &players {
+                let server_position = *server.game_state.get_position(*entity).unwrap();
+                let is_near = server_position.distance(own_position) < far;
+                assert_eq!(
+                    client.game_state.is_alive(*entity),
+                    is_near,
+                    "Player at {server_position:?} seen from {own_position:?}"
+                );
+            }
+            for (entity, position) in &client.game_state.position_components {
+                assert!(
+                    position.distance(own_position) < far,
+                    "{entity:?} at {position:?} replicated to {own_position:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn entity_commands_are_applied_at_the_end_of_the_tick() {
+        let (mut server, mut network) = start();
+        let sword = server.game_state.get_entity("sword1").unwrap();
+        server.entity_commands.push(EntityCommand::Spawn {
+            definition: Box::new(EntityDefinition {
+                id: Some("gift".to_owned()),
+                position: Some([1.0, 0.5, 1.0]),
+                ..EntityDefinition::default()
+            }),
+        });
+        server
+            .entity_commands
+            .push(EntityCommand::Despawn { entity: sword });
+        assert_eq!(server.game_state.get_entity("gift"), None);
+        assert!(server.game_state.is_alive(sword));
+
+        server.tick(&mut network);
+
+        assert!(server.game_state.get_entity("gift").is_some());
+        assert!(!server.game_state.is_alive(sword));
+        assert!(server.entity_commands.is_empty());
+    }
+
+    // One at a time, so they join in this order
+    fn join(
+        server: &mut GameServer,
+        network: &mut NetworkServer,
+        clients: &mut Vec<HeadlessGame>,
+        name: &str,
+    ) {
+        clients.push(connect(network, name));
+        for _ in 0..1000 {
+            if server.players.len() == clients.len() && clients.iter().all(is_connected) {
+                return;
+            }
+            step(server, network, clients);
+        }
+        panic!("{name} did not join");
+    }
+
+    fn names(server: &GameServer) -> Vec<&str> {
+        server
+            .players
+            .values()
+            .map(|player| player.name.as_str())
+            .collect()
+    }
+
+    fn whisper(client: &mut HeadlessGame, to: &str, text: &str) {
+        let mut frame_state = UpdateState::new();
+        let channel = ChatChannel::Whisper {
+            player: to.to_owned(),
+        };
+        frame_state.chat_messages.push((channel, text.to_owned()));
+        client.network_client.as_mut().unwrap().send(&frame_state);
+    }
+
+    // Leaves out the notices telling players their name
+    fn chat(client: &HeadlessGame) -> Vec<ChatMessage> {
+        client
+            .ui_state
+            .chat
+            .messages()
+            .iter()
+            .filter(|message| !message.text.starts_with("You are playing as"))
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn names_are_single_words_and_unique() {
+        let (mut server, mut network) = start();
+        let mut clients = Vec::new();
+        let long_name = "A".repeat(MAX_PLAYER_NAME_LENGTH + 5);
+        for name in [
+            "Alice",
+            "alice",
+            "Alice2",
+            "  Jan  de Vries ",
+            "",
+            &long_name,
+            &long_name,
+        ] {
+            join(&mut server, &mut network, &mut clients, name);
+        }
+        assert_eq!(
+            names(&server),
+            [
+                "Alice",
+                "alice2",
+                "Alice22",
+                "Jan_de_Vries",
+                "Player5",
+                &long_name[..MAX_PLAYER_NAME_LENGTH],
+                &format!("{}2", &long_name[..MAX_PLAYER_NAME_LENGTH - 1]),
+            ]
+        );
+
+        step(&mut server, &mut network, &mut clients);
+        let notice = ChatMessage::notice("You are playing as alice2");
+        assert!(clients[1].ui_state.chat.messages().contains(&notice));
+    }
+
+    #[test]
+    fn whispers_only_reach_the_player_with_that_name() {
+        let (mut server, mut network) = start();
+        let mut clients = Vec::new();
+        for name in ["Alice", "alice", "Bob"] {
+            join(&mut server, &mut network, &mut clients, name);
+        }
+
+        whisper(&mut clients[2], "ALICE2", "Psst");
+        whisper(&mut clients[2], "Carol", "Anyone?");
+        for _ in 0..20 {
+            step(&mut server, &mut network, &mut clients);
+        }
+
+        assert!(chat(&clients[0]).is_empty(), "The first Alice overheard");
+        assert_eq!(
+            chat(&clients[1]),
+            [ChatMessage {
+                source: ChatSource::WhisperFrom {
+                    from: "Bob".to_owned()
+                },
+                text: "Psst".to_owned(),
+            }]
+        );
+        assert_eq!(
+            chat(&clients[2]),
+            [
+                ChatMessage {
+                    source: ChatSource::WhisperTo {
+                        to: "alice2".to_owned()
+                    },
+                    text: "Psst".to_owned(),
+                },
+                ChatMessage::notice("Nobody called Carol is playing right now"),
+            ]
+        );
+    }
+
+    #[test]
+    fn dialogue_choices_are_only_taken_from_where_the_player_is_in_the_conversation() {
+        let (mut server, mut network) = start();
+        let mut clients = Vec::new();
+        join(&mut server, &mut network, &mut clients, "Alice");
+        let (connection, entity) = server
+            .players
+            .iter()
+            .map(|(connection, player)| (*connection, player.entity))
+            .next()
+            .unwrap();
+        let dennis = server.game_state.get_entity("Dennis").unwrap();
+        server
+            .game_state
+            .create_position(entity, Point3::new(-3.0, 0.5, 1.0));
+        server
+            .game_state
+            .health_components
+            .get_mut(&entity)
+            .unwrap()
+            .hitpoints = 10;
+        let choice = |node_id: &str, choice: usize| PlayerAction::DialogueChoice {
+            npc: dennis,
+            node_id: node_id.to_owned(),
+            choice,
+        };
+        let mut act = |server: &mut GameServer, action: PlayerAction| {
+            server.handle_action(&mut network, connection, action);
+            server.game_state.health_components[&entity].hitpoints
+        };
+
+        // Without talking to Dennis first
+        assert_eq!(act(&mut server, choice("patch_up", 0)), 10);
+
+        act(&mut server, PlayerAction::Talk { npc: dennis });
+        assert_eq!(act(&mut server, choice("patch_up", 0)), 10, "Skipped ahead");
+        assert_eq!(act(&mut server, choice("welcome", 5)), 10);
+        assert_eq!(act(&mut server, choice("patch_up", 0)), 35);
+        for _ in 0..3 {
+            assert_eq!(act(&mut server, choice("patch_up", 0)), 35, "Replayed");
+        }
+
+        // Going round the conversation is how it is done
+        assert_eq!(act(&mut server, choice("patched_up", 0)), 35);
+        assert_eq!(act(&mut server, choice("welcome", 5)), 35);
+        assert_eq!(act(&mut server, choice("patch_up", 0)), 60);
+    }
+
+    #[test]
+    fn players_chatting_too_fast_are_told_and_not_heard() {
+        let (mut server, mut network) = start();
+        let mut clients = Vec::new();
+        for name in ["Alice", "Bob"] {
+            join(&mut server, &mut network, &mut clients, name);
+        }
+
+        for number in 0..7 {
+            whisper(&mut clients[1], "Alice", &format!("Spam {number}"));
+        }
+        for _ in 0..20 {
+            step(&mut server, &mut network, &mut clients);
+        }
+        let texts = |client: &HeadlessGame| -> Vec<String> {
+            chat(client)
+                .into_iter()
+                .map(|message| message.text)
+                .collect()
+        };
+        let too_fast = ChatRejection::TooFast.notice().unwrap();
+        assert_eq!(
+            texts(&clients[0]),
+            ["Spam 0", "Spam 1", "Spam 2", "Spam 3", "Spam 4"]
+        );
+        assert_eq!(texts(&clients[1])[5..], [too_fast.clone(), too_fast]);
+
+        // The limit is per player, and fills up again over time
+        whisper(&mut clients[0], "Bob", "Calm down");
+        for _ in 0..2 * server.time.ticks_per_second() {
+            step(&mut server, &mut network, &mut clients);
+        }
+        whisper(&mut clients[1], "Alice", "Sorry");
+        for _ in 0..20 {
+            step(&mut server, &mut network, &mut clients);
+        }
+        assert_eq!(texts(&clients[0])[5..], ["Calm down", "Sorry"]);
+    }
+}
@@ -0,0 +1,61 @@
+use cgmath::Point2;
+use kloenk::state::entity::EntityId;
+use kloenk::state::game_state::GameState;
+use kloenk::systems::camera_system::CameraSystem;
+use kloenk::systems::position_manager::PositionManager;
+use std::collections::HashSet;
+
+const VIEW_MARGIN: f32 = 1.0; // Positions are centers, large entities are in view before their center is
+const LEAVE_MARGIN: f32 = 2.0; // Otherwise entities at the edge of view would be spawned and despawned over and over
+
+// Decides what each player gets told about. Anything they cannot see is left out, which saves bandwidth and does not hand out what is hidden
+pub struct InterestManager {}
+
+impl InterestManager {
+    // What the player can see from where they stand, with what is needed to make sense of it:
+    // what is stored in the entities in view, and entities that are not in the world at all, such as cameras
+    // Entities the player already knows about stay in view a little longer before they are despawned
+    pub fn entities_in_view(
+        game_state: &GameState,
+        player: EntityId,
+        known_entities: &HashSet<EntityId>,
+    ) -> HashSet<EntityId> {
+        let mut in_view: HashSet<EntityId> = game_state
+            .entities
+            .iter()
+            .filter(|entity| {
+                game_state.get_position(**entity).is_none()
+                    && !game_state.in_storage_components.contains_key(entity)
+            })
+            .copied()
+            .collect();
+        in_view.insert(player);
+
+        if let Some(center) = game_state.get_position(player) {
+            let view_distance = CameraSystem::max_view_distance() + VIEW_MARGIN;
+            let leave_distance = view_distance + LEAVE_MARGIN;
+            let min = Point2::new(center.x - leave_distance, center.z - leave_distance);
+            let max = Point2::new(center.x + leave_distance, center.z + leave_distance);
+            for entity in game_state.get_entities_in_area(min, max) {
+                let Some(position) = game_state.get_position(entity) else {
+                    continue;
+                };
+                let distance = PositionManager::distance_2d(center, position);
+                if distance <= view_distance
+                    || (distance <= leave_distance && known_entities.contains(&entity))
+                {
+                    in_view.insert(entity);
+                }
+            }
+        }
+
+        let stored_in_view: Vec<EntityId> = game_state
+            .in_storage_components
+            .iter()
+            .filter(|(_, in_storage)| in_view.contains(&in_storage.storage_entity))
+            .map(|(entity, _)| *entity)
+            .collect();
+        in_view.extend(stored_in_view);
+        in_view
+    }
+}
@@ -0,0 +1,4 @@
+pub mod chat_filter;
+pub mod game_server;
+pub mod interest;
+pub mod network;
@@ -0,0 +1,78 @@
+use kloenk::net::protocol::DEFAULT_SERVER_ADDRESS;
+use kloenk::state::world_definition::WorldDefinition;
+use kloenk::systems::dialogue_manager::DialogueManager;
+use kloenk::systems::quest_manager::QuestManager;
+use kloenk_server::game_server::GameServer;
+use kloenk_server::network::NetworkServer;
+use std::process::ExitCode;
+use std::time::Instant;
+
+const USAGE: &str = "Usage: kloenk-server [--address <host:port>] [--world <world.json>] [--dialogues <dialogues.json>] [--quests <quests.json>]";
+const MAX_CATCH_UP_TICKS: u32 = 5;
+
+fn main() -> ExitCode {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let mut address = DEFAULT_SERVER_ADDRESS.to_owned();
+    let mut world_path = "../kloenk-client/assets/world.json".to_owned();
+    let mut dialogues_path = "../kloenk-client/assets/dialogues.json".to_owned();
+    let mut quests_path = "../kloenk-client/assets/quests.json".to_owned();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let Some(value) = args.next() else {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        };
+        match arg.as_str() {
+            "--address" => address = value,
+            "--world" => world_path = value,
+            "--dialogues" => dialogues_path = value,
+            "--quests" => quests_path = value,
+            _ => {
+                eprintln!("{USAGE}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let world_data = std::fs::read(&world_path)
+        .unwrap_or_else(|error| panic!("World file {world_path} could not be read: {error}"));
+    let world = WorldDefinition::from_json(&world_data)
+        .unwrap_or_else(|error| panic!("Failed to load world file {world_path}: {error}"));
+    let dialogues_data = std::fs::read(&dialogues_path).unwrap_or_else(|error| {
+        panic!("Dialogue file {dialogues_path} could not be read: {error}")
+    });
+    let dialogues = DialogueManager::from_json(&dialogues_data)
+        .unwrap_or_else(|error| panic!("Failed to load dialogue file {dialogues_path}: {error}"));
+    let quests_data = std::fs::read(&quests_path)
+        .unwrap_or_else(|error| panic!("Quest file {quests_path} could not be read: {error}"));
+    let quests = QuestManager::from_json(&quests_data)
+        .unwrap_or_else(|error| panic!("Failed to load quest file {quests_path}: {error}"));
+    dialogues
+        .check_references(&world, &quests)
+        .unwrap_or_else(|error| panic!("Failed to load dialogue file {dialogues_path}: {error}"));
+    let mut game_server = GameServer::new(&world, dialogues, quests);
+    let mut network = NetworkServer::bind(&address)
+        .unwrap_or_else(|error| panic!("Could not listen on {address}: {error}"));
+    log::info!("Listening on {}", network.local_address());
+
+    // Same fixed tick as the client. When we fall too far behind we skip ahead instead of trying to catch up
+    let tick_duration = game_server.tick_duration();
+    let mut next_tick = Instant::now();
+    loop {
+        for event in network.poll() {
+            game_server.handle_event(&mut network, event);
+        }
+        game_server.tick(&mut network);
+        network.flush();
+
+        next_tick += tick_duration;
+        let now = Instant::now();
+        if next_tick > now {
+            std::thread::sleep(next_tick - now);
+        } else if now - next_tick > tick_duration * MAX_CATCH_UP_TICKS {
+            log::warn!("Server is running behind, skipping ticks");
+            next_tick = now;
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use kloenk::net::connection::TrafficStats;
+use kloenk::net::protocol::{ClientMessage, ServerMessage};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::thread;
+use std::time::Duration;
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::{Error, Message, WebSocket};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_CLIENT_MESSAGE_SIZE: usize = 64 * 1024; // Players only send small actions
+const MAX_WRITE_BUFFER_SIZE: usize = 16 * 1024 * 1024; // Clients that cannot keep up are dropped instead of buffering forever
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct ConnectionId(u64);
+
+impl std::fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+pub enum NetworkEvent {
+    Connected(ConnectionId),
+    Message(ConnectionId, ClientMessage),
+    Disconnected(ConnectionId),
+}
+
+// Accepts WebSocket connections in the background. The game loop polls all connections once per tick, so no game state is shared between threads
+pub struct NetworkServer {
+    local_address: SocketAddr,
+    new_connections: Receiver<WebSocket<TcpStream>>,
+    connections: HashMap<ConnectionId, WebSocket<TcpStream>>,
+    traffic: HashMap<ConnectionId, TrafficStats>,
+    next_connection_id: u64,
+}
+
+impl NetworkServer {
+    pub fn bind(address: &str) -> std::io::Result<NetworkServer> {
+        let listener = TcpListener::bind(address)?;
+        let local_address = listener.local_addr()?;
+        let (sender, new_connections) = channel();
+        thread::spawn(move || Self::accept_connections(&listener, &sender));
+
+        Ok(NetworkServer {
+            local_address,
+            new_connections,
+            connections: HashMap::new(),
+            traffic: HashMap::new(),
+            next_connection_id: 0,
+        })
+    }
+
+    pub fn local_address(&self) -> SocketAddr {
+        self.local_address
+    }
+
+    // Counts whole messages, without WebSocket and TCP overhead
+    pub fn traffic(&self, connection: ConnectionId) -> Option<TrafficStats> {
+        self.traffic.get(&connection).copied()
+    }
+
+    fn accept_connections(listener: &TcpListener, sender: &Sender<WebSocket<TcpStream>>) {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let sender = sender.clone();
+            // Handshake on its own thread, a slow client should not hold up everyone else
+            thread::spawn(move || {
+                if let Some(websocket) = Self::handshake(stream) {
+                    // Only fails when the server is shutting down
+                    let _ = sender.send(websocket);
+                }
+            });
+        }
+    }
+
+    fn handshake(stream: TcpStream) -> Option<WebSocket<TcpStream>> {
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).ok()?;
+        stream.set_nodelay(true).ok()?; // Small messages every tick, waiting to fill up packets only adds latency
+        let config = WebSocketConfig::default()
+            .max_message_size(Some(MAX_CLIENT_MESSAGE_SIZE))
+            .max_write_buffer_size(MAX_WRITE_BUFFER_SIZE);
+        match tungstenite::accept_with_config(stream, Some(config)) {
+            Ok(websocket) => {
+                websocket.get_ref().set_read_timeout(None).ok()?;
+                websocket.get_ref().set_nonblocking(true).ok()?;
+                Some(websocket)
+            }
+            Err(error) => {
+                log::debug!("WebSocket handshake failed: {error}");
+                None
+            }
+        }
+    }
+
+    // Everything received since the last poll. Connections that closed or sent something we do not understand are dropped
+    pub fn poll(&mut self) -> Vec<NetworkEvent> {
+        let mut events = Vec::new();
+        while let Ok(websocket) = self.new_connections.try_recv() {
+            let connection = ConnectionId(self.next_connection_id);
+            self.next_connection_id += 1;
+            self.connections.insert(connection, websocket);
+            self.traffic.insert(connection, TrafficStats::default());
+            events.push(NetworkEvent::Connected(connection));
+        }
+
+        let mut closed_connections = Vec::new();
+        for (connection, websocket) in &mut self.connections {
+            let traffic = self.traffic.entry(*connection).or_default();
+            loop {
+                match websocket.read() {
+                    Ok(Message::Binary(bytes)) => match ClientMessage::decode(&bytes) {
+                        Ok(message) => {
+                            traffic.record_received(bytes.len());
+                            events.push(NetworkEvent::Message(*connection, message));
+                        }
+                        Err(error) => {
+                            log::warn!("Dropping connection {connection}: {error}");
+                            closed_connections.push(*connection);
+                            break;
+                        }
+                    },
+                    Ok(Message::Close(_)) => {
+                        closed_connections.push(*connection);
+                        break;
+                    }
+                    Ok(_) => {} // Pings are answered by tungstenite itself
+                    Err(Error::Io(error)) if error.kind() == ErrorKind::WouldBlock => break,
+                    Err(_) => {
+                        closed_connections.push(*connection);
+                        break;
+                    }
+                }
+            }
+        }
+
+        for connection in closed_connections {
+            self.remove(connection);
+            events.push(NetworkEvent::Disconnected(connection));
+        }
+        events
+    }
+
+    // Queued until flush. A connection that fails here shows up as disconnected on the next poll
+    pub fn send(&mut self, connection: ConnectionId, message: &ServerMessage) {
+        let Some(websocket) = self.connections.get_mut(&connection) else {
+            return;
+        };
+        let bytes = message.encode();
+        self.traffic
+            .entry(connection)
+            .or_default()
+            .record_sent(bytes.len());
+        Self::write(websocket, connection, Message::binary(bytes));
+    }
+
+    pub fn broadcast(&mut self, connections: &[ConnectionId], message: &ServerMessage) {
+        let bytes = message.encode();
+        for connection in connections {
+            let Some(websocket) = self.connections.get_mut(connection) else {
+                continue;
+            };
+            self.traffic
+                .entry(*connection)
+                .or_default()
+                .record_sent(bytes.len());
+            Self::write(websocket, *connection, Message::binary(bytes.clone()));
+        }
+    }
+
+    fn write(websocket: &mut WebSocket<TcpStream>, connection: ConnectionId, message: Message) {
+        match websocket.write(message) {
+            Ok(()) => {}
+            Err(Error::Io(error)) if error.kind() == ErrorKind::WouldBlock => {} // Message is queued, goes out on flush
+            Err(error) => {
+                log::warn!("Failed to send to connection {connection}: {error}");
+                Self::shutdown(websocket);
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        for websocket in self.connections.values_mut() {
+            match websocket.flush() {
+                Ok(()) => {}
+                Err(Error::Io(error)) if error.kind() == ErrorKind::WouldBlock => {} // Rest goes out on the next flush
+                Err(_) => Self::shutdown(websocket),
+            }
+        }
+    }
+
+    pub fn disconnect(&mut self, connection: ConnectionId) {
+        if let Some(mut websocket) = self.remove(connection) {
+            let _ = websocket.close(None);
+            let _ = websocket.flush();
+        }
+    }
+
+    fn remove(&mut self, connection: ConnectionId) -> Option<WebSocket<TcpStream>> {
+        if let Some(traffic) = self.traffic.remove(&connection) {
+            log::debug!(
+                "Connection {connection} closed after sending {} bytes in {} messages and receiving {} bytes in {} messages",
+                traffic.bytes_sent,
+                traffic.messages_sent,
+                traffic.bytes_received,
+                traffic.messages_received
+            );
+        }
+        self.connections.remove(&connection)
+    }
+
+    // Makes the next read fail, so the connection gets cleaned up in poll like any other closed connection
+    fn shutdown(websocket: &mut WebSocket<TcpStream>) {
+        let _ = websocket.get_ref().shutdown(std::net::Shutdown::Both);
+    }
+}
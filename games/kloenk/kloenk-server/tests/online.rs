@@ -0,0 +1,175 @@
+// Runs a server on localhost with headless clients connected to it over real sockets. Server and clients take turns ticking on the test thread
+use cgmath::MetricSpace;
+use kloenk::headless::{DEFAULT_VIEWPORT, HeadlessGame, InputScript};
+use kloenk::net::WebSocketTransport;
+use kloenk::net::client::NetworkClient;
+use kloenk::net::connection::{Connection, ConnectionState};
+use kloenk::state::entity::EntityId;
+use kloenk::state::world_definition::WorldDefinition;
+use kloenk::systems::dialogue_manager::DialogueManager;
+use kloenk::systems::quest_manager::QuestManager;
+use kloenk_server::game_server::GameServer;
+use kloenk_server::network::NetworkServer;
+use std::thread;
+use std::time::Duration;
+
+const MAX_STEPS: u32 = 2000;
+
+fn world() -> WorldDefinition {
+    WorldDefinition::from_json(include_bytes!("../../kloenk-client/assets/world.json"))
+        .expect("World should load")
+}
+
+struct TestServer {
+    game_server: GameServer,
+    network: NetworkServer,
+}
+
+impl TestServer {
+    fn start() -> TestServer {
+        let dialogues =
+            DialogueManager::from_json(include_bytes!("../../kloenk-client/assets/dialogues.json"))
+                .expect("Dialogues should load");
+        let quests =
+            QuestManager::from_json(include_bytes!("../../kloenk-client/assets/quests.json"))
+                .expect("Quests should load");
+        TestServer {
+            game_server: GameServer::new(&world(), dialogues, quests),
+            network: NetworkServer::bind("127.0.0.1:0").expect("Server should start"),
+        }
+    }
+
+    fn connect(&self, name: &str) -> HeadlessGame {
+        let address = self.network.local_address().to_string();
+        let mut game = HeadlessGame::new(&world(), DEFAULT_VIEWPORT);
+        game.network_client = Some(NetworkClient::new(Connection::new(
+            Box::new(WebSocketTransport::new(&address)),
+            name,
+        )));
+        game
+    }
+
+    // One tick of the server, then one of every client
+    fn step(&mut self, clients: &mut [&mut HeadlessGame]) {
+        for event in self.network.poll() {
+            self.game_server.handle_event(&mut self.network, event);
+        }
+        self.game_server.tick(&mut self.network);
+        self.network.flush();
+        for client in clients {
+            client.tick();
+        }
+        thread::sleep(Duration::from_millis(1)); // Gives the sockets a moment to deliver
+    }
+
+    fn run_ticks(&mut self, clients: &mut [&mut HeadlessGame], ticks: u32) {
+        for _ in 0..ticks {
+            self.step(clients);
+        }
+    }
+
+    fn run_until(
+        &mut self,
+        clients: &mut [&mut HeadlessGame],
+        what: &str,
+        condition: impl Fn(&[&mut HeadlessGame]) -> bool,
+    ) {
+        for _ in 0..MAX_STEPS {
+            if condition(clients) {
+                return;
+            }
+            self.step(clients);
+        }
+        panic!("Gave up waiting until {what}");
+    }
+}
+
+fn is_connected(client: &HeadlessGame) -> bool {
+    client
+        .network_client
+        .as_ref()
+        .is_some_and(|network_client| {
+            network_client.connection_state() == &ConnectionState::Connected
+        })
+}
+
+// Our own player goes by "player", the others by the id the server gave them
+fn own_player(client: &HeadlessGame) -> EntityId {
+    client
+        .game_state
+        .get_entity("player")
+        .expect("Player should exist")
+}
+
+fn input(client: &mut HeadlessGame, action: &str, key: &str) {
+    let script = format!(r#"{{"steps": [{{"action": "{action}", "key": "{key}"}}]}}"#);
+    let script = InputScript::from_json(script.as_bytes()).expect("Script should be valid");
+    client.run_script(&script).expect("Script should run");
+}
+
+#[test]
+fn players_see_each_other_and_the_items_left_behind() {
+    let mut server = TestServer::start();
+    let mut alice = server.connect("Alice");
+    server.run_until(&mut [&mut alice], "Alice joined", |clients| {
+        is_connected(clients[0])
+    });
+    let mut bob = server.connect("Bob");
+    server.run_until(&mut [&mut alice, &mut bob], "Bob joined", |clients| {
+        clients.iter().all(|client| is_connected(client))
+    });
+    let alice_entity = own_player(&alice);
+    assert!(
+        bob.game_state.is_alive(alice_entity),
+        "Bob does not see Alice"
+    );
+
+    // Same as scripts/pickup_shield.json: walk up to the shield and pick it up
+    input(&mut alice, "press", "KeyW");
+    server.run_ticks(&mut [&mut alice, &mut bob], 240);
+    input(&mut alice, "release", "KeyW");
+    input(&mut alice, "press", "KeyE");
+    server.run_ticks(&mut [&mut alice, &mut bob], 1);
+    input(&mut alice, "release", "KeyE");
+    server.run_until(
+        &mut [&mut alice, &mut bob],
+        "Bob sees Alice carry the shield",
+        |clients| {
+            let bob = &clients[1];
+            bob.game_state
+                .get_entity("shield")
+                .and_then(|shield| bob.game_state.in_storage_components.get(&shield))
+                .is_some_and(|in_storage| in_storage.storage_entity == alice_entity)
+        },
+    );
+
+    // Bob sees Alice where she sees herself
+    server.run_ticks(&mut [&mut alice, &mut bob], 30);
+    let alice_position = *alice.game_state.get_position(alice_entity).unwrap();
+    let seen_position = *bob.game_state.get_position(alice_entity).unwrap();
+    assert!(
+        alice_position.distance(seen_position) < 0.01,
+        "Alice is at {alice_position:?}, Bob sees her at {seen_position:?}"
+    );
+
+    drop(alice);
+    server.run_until(
+        &mut [&mut bob],
+        "Bob sees the shield on the ground",
+        |clients| {
+            let bob = &clients[0];
+            let Some(shield) = bob.game_state.get_entity("shield") else {
+                return false;
+            };
+            !bob.game_state.is_alive(alice_entity)
+                && !bob.game_state.in_storage_components.contains_key(&shield)
+                && bob.game_state.get_position(shield).is_some()
+        },
+    );
+    let shield = bob.game_state.get_entity("shield").unwrap();
+    let shield_position = *bob.game_state.get_position(shield).unwrap();
+    assert!(
+        shield_position.distance(alice_position) < 2.5,
+        "Shield dropped at {shield_position:?}, far from where Alice left at {alice_position:?}"
+    );
+}